@@ -7,12 +7,23 @@
 #include "redis-reader.hpp"
 #include "wifi-events.hpp"
 
+#ifndef BEETLE_VERSION
+#define BEETLE_VERSION "dev"
+#endif
+
+#ifndef ARDUINO_BOARD
+#define ARDUINO_BOARD "esp32"
+#endif
+
 namespace redisevents {
 extern const uint8_t redis_root_ca[] asm(
     "_binary_embeds_redis_host_root_ca_pem_start");
 
 constexpr static const uint32_t MAX_ID_SIZE = 36;
-constexpr static const uint8_t OUTBOUND_BUFFER_SIZE = 200;
+constexpr static const uint32_t MAX_SECRET_SIZE = 65;
+constexpr static const uint32_t MAX_RENDER_ID_SIZE = 65;
+constexpr static const uint16_t OUTBOUND_BUFFER_SIZE = 448;
+constexpr static const uint16_t CHECK_IN_BUFFER_SIZE = 384;
 
 template <std::size_t T>
 class Events final {
@@ -49,6 +60,13 @@ class Events final {
   constexpr static const char REDIS_REGISTRATION_POP[] =
       "*2\r\n$4\r\nLPOP\r\n$4\r\nob:r\r\n";
 
+  constexpr static const uint8_t PNG_SIGNATURE[] = {0x89, 'P',  'N',  'G',
+                                                   '\r', '\n', 0x1a, '\n'};
+
+  // The keyword of the png text chunk holding the id of the render, including
+  // the null separator that follows it.
+  constexpr static const char RENDER_ID_KEYWORD[] = "beetle:render_id";
+
   constexpr static const char REDIS_AUTH_FAILURE[] =
       "WRONGPASS invalid username-password pair or user is disabled.";
 
@@ -66,9 +84,14 @@ class Events final {
     explicit Context(std::shared_ptr<RedisConfig> config)
         : config(config),
           device_id((char *)malloc(sizeof(char) * MAX_ID_SIZE)),
+          device_secret((char *)malloc(sizeof(char) * MAX_SECRET_SIZE)),
+          last_render_id((char *)malloc(sizeof(char) * MAX_RENDER_ID_SIZE)),
           outbound((char *)malloc(sizeof(char) * OUTBOUND_BUFFER_SIZE)),
-          device_id_len(0) {
+          device_id_len(0),
+          device_secret_len(0) {
       memset(device_id, '\0', MAX_ID_SIZE);
+      memset(device_secret, '\0', MAX_SECRET_SIZE);
+      memset(last_render_id, '\0', MAX_RENDER_ID_SIZE);
     }
 
     ~Context() {
@@ -80,6 +103,14 @@ class Events final {
         free(device_id);
       }
       device_id = nullptr;
+      if (device_secret != nullptr) {
+        free(device_secret);
+      }
+      device_secret = nullptr;
+      if (last_render_id != nullptr) {
+        free(last_render_id);
+      }
+      last_render_id = nullptr;
     }
 
     // No copies; the context is passed around using a `std::shared_ptr`
@@ -101,11 +132,123 @@ class Events final {
     // Some memory allocated for holding a device id.
     char *device_id;
 
+    // Some memory allocated for holding the secret we authenticate with.
+    char *device_secret;
+
+    // Some memory allocated for holding the id of the last render received.
+    char *last_render_id;
+
     // Some memory allocated for holding our outbound messages.
     char *outbound;
 
     // The length of our id.
     uint8_t device_id_len;
+
+    // The length of our secret.
+    uint8_t device_secret_len;
+
+    /**
+     * Credentials handed out by the registrar hold the device id and secret
+     * separated by a colon. Entries from older servers only hold the id, which
+     * doubles as the password. Both are persisted for the next connection.
+     */
+    void store_credentials(const char *entry, size_t len) {
+      const char *separator = (const char *)memchr(entry, ':', len);
+      size_t id_len = separator != nullptr ? separator - entry : len;
+      const char *secret = separator != nullptr ? separator + 1 : entry;
+      size_t secret_len = separator != nullptr ? len - id_len - 1 : len;
+
+      device_id_len = std::min(id_len, (size_t)MAX_ID_SIZE - 1);
+      device_secret_len = std::min(secret_len, (size_t)MAX_SECRET_SIZE - 1);
+
+      memset(device_id, '\0', MAX_ID_SIZE);
+      memset(device_secret, '\0', MAX_SECRET_SIZE);
+      memcpy(device_id, entry, device_id_len);
+      memcpy(device_secret, secret, device_secret_len);
+
+      preferences.putString("device-id", device_id);
+      preferences.putString("device-secret", device_secret);
+    }
+
+    // Removes any persisted credentials, forcing a fresh registration.
+    void clear_credentials(void) {
+      preferences.remove("device-id");
+      preferences.remove("device-secret");
+    }
+
+    /**
+     * Rendered images carry the id of their render in a png text chunk. When
+     * the payload is a png holding one, it is kept around to be reported back
+     * with our next check-in. Anything else leaves the last id as-is.
+     */
+    void remember_render_id(const uint8_t *payload, size_t len) {
+      if (len < sizeof(PNG_SIGNATURE) ||
+          memcmp(payload, PNG_SIGNATURE, sizeof(PNG_SIGNATURE)) != 0) {
+        return;
+      }
+
+      size_t offset = sizeof(PNG_SIGNATURE);
+      size_t keyword_len = sizeof(RENDER_ID_KEYWORD);
+
+      while (offset + 12 <= len) {
+        const uint8_t *header = payload + offset;
+        size_t chunk_len = ((size_t)header[0] << 24) |
+                           ((size_t)header[1] << 16) |
+                           ((size_t)header[2] << 8) | (size_t)header[3];
+
+        if (chunk_len > len - offset - 12) {
+          return;
+        }
+
+        const uint8_t *data = header + 8;
+        if (memcmp(header + 4, "tEXt", 4) == 0 && chunk_len > keyword_len &&
+            memcmp(data, RENDER_ID_KEYWORD, keyword_len) == 0) {
+          size_t id_len = std::min(chunk_len - keyword_len,
+                                   (size_t)MAX_RENDER_ID_SIZE - 1);
+          memset(last_render_id, '\0', MAX_RENDER_ID_SIZE);
+          memcpy(last_render_id, data + keyword_len, id_len);
+          log_i("received render '%s'", last_render_id);
+          return;
+        }
+
+        offset += chunk_len + 12;
+      }
+    }
+
+    /**
+     * Prepares the push of our versioned check-in onto the incoming queue,
+     * including whatever telemetry we are able to measure and the id of the
+     * render on our display.
+     */
+    void prepare_check_in(uint32_t time) {
+      char render[MAX_RENDER_ID_SIZE + 24];
+      memset(render, '\0', sizeof(render));
+      if (strlen(last_render_id) > 0) {
+        snprintf(render, sizeof(render), ",\"last_render_id\":\"%s\"",
+                 last_render_id);
+      }
+
+      char check_in[CHECK_IN_BUFFER_SIZE];
+      int check_in_len = snprintf(
+          check_in, CHECK_IN_BUFFER_SIZE,
+          "{\"beetle:kind\":\"v1\",\"beetle:content\":{\"id\":\"%s\","
+          "\"telemetry\":{\"firmware_version\":\"%s\",\"board\":\"%s\","
+          "\"free_heap\":%u,\"wifi_rssi\":%d,\"uptime_seconds\":%u%s}}}",
+          device_id, BEETLE_VERSION, ARDUINO_BOARD, ESP.getFreeHeap(),
+          WiFi.RSSI(), time / 1000, render);
+
+      memset(outbound, '\0', OUTBOUND_BUFFER_SIZE);
+      sprintf(outbound, "*3\r\n$5\r\nRPUSH\r\n$4\r\nob:i\r\n$%d\r\n%s\r\n",
+              check_in_len, check_in);
+    }
+
+    // Writes an `AUTH` using the device id and secret.
+    void write_auth(void) {
+      memset(outbound, '\0', OUTBOUND_BUFFER_SIZE);
+      sprintf(outbound, "*3\r\n$4\r\nAUTH\r\n$%d\r\n%s\r\n$%d\r\n%s\r\n",
+              device_id_len, device_id, device_secret_len, device_secret);
+      client.print(outbound);
+    }
   };
 
   /**
@@ -131,8 +274,8 @@ class Events final {
 
     /**
      * This message will attempt to read from our connected `WiFiClientSecure`
-     * instance, expecting to find a device id that will immediately be used in
-     * a fresh `AUTH` request.
+     * instance, expecting to find a device id and secret that will immediately
+     * be used in a fresh `AUTH` request.
      */
     std::pair<std::variant<Disconnected, Connected>, std::optional<RedisEvent>>
     read_id(Connected connected) {
@@ -143,22 +286,13 @@ class Events final {
         if (std::holds_alternative<RedisRead>(event)) {
           auto read_event = std::get<RedisRead>(event);
 
-          context->device_id_len = read_event.size;
-          memcpy(context->device_id, buffer->data(), read_event.size);
-
-          context->preferences.putString("device-id", context->device_id);
+          context->store_credentials((char *)buffer->data(), read_event.size);
 
-          log_i("read %d bytes during id request: '%s'", context->device_id_len,
+          log_i("read %d bytes during id request: '%s'", read_event.size,
                 context->device_id);
 
-          memset(context->outbound, '\0', OUTBOUND_BUFFER_SIZE);
-
-          sprintf(context->outbound,
-                  "*3\r\n$4\r\nAUTH\r\n$%d\r\n%s\r\n$%d\r\n%s\r\n",
-                  context->device_id_len, context->device_id,
-                  context->device_id_len, context->device_id);
-          context->client.print(context->outbound);
-          log_i("wrote auth: '%s'", context->outbound);
+          context->write_auth();
+          log_i("wrote auth for '%s'", context->device_id);
           connected.authorization_stage =
               AuthorizationStage::AuthorizationAttempted;
 
@@ -216,7 +350,7 @@ class Events final {
                                     : AuthorizationStage::FullyAuthorized;
           } else if (strcmp((char *)buffer->data(), REDIS_AUTH_FAILURE) == 0) {
             log_e("failed authenticating using current credentials");
-            context->preferences.remove("device-id");
+            context->clear_credentials();
           } else {
             log_e("unrecognized response from redis - %s", buffer->data());
           }
@@ -270,17 +404,32 @@ class Events final {
       buffer->fill('\0');
       memset(context->outbound, '\0', OUTBOUND_BUFFER_SIZE);
 
-      // If we have a stored id, try using it for an `AUTH`
+      // If we have a stored id, try using it for an `AUTH`. Devices registered
+      // before secrets were introduced use their id as their password.
       if (stored_id_len > 0) {
         log_i("device id loaded from non-volatile memory: '%s'",
               context->device_id);
         context->device_id_len = stored_id_len - 1;
-        sprintf(context->outbound,
-                "*3\r\n$4\r\nAUTH\r\n$%d\r\n%s\r\n$%d\r\n%s\r\n",
-                context->device_id_len, context->device_id,
-                context->device_id_len, context->device_id);
-        context->client.print(context->outbound);
-        log_d("wrote auth: '%s'; clearing internal buffer", context->outbound);
+
+        size_t stored_secret_len =
+            context->preferences.isKey("device-secret")
+                ? context->preferences.getString("device-secret",
+                                                 context->device_secret,
+                                                 MAX_SECRET_SIZE)
+                : 0;
+
+        if (stored_secret_len > 0) {
+          context->device_secret_len = stored_secret_len - 1;
+        } else {
+          memset(context->device_secret, '\0', MAX_SECRET_SIZE);
+          memcpy(context->device_secret, context->device_id,
+                 context->device_id_len);
+          context->device_secret_len = context->device_id_len;
+        }
+
+        context->write_auth();
+        log_d("wrote auth for '%s'; clearing internal buffer",
+              context->device_id);
         connected.authorization_stage =
             AuthorizationStage::AuthorizationAttempted;
         return std::make_pair(connected, IdentificationReceived{});
@@ -366,15 +515,32 @@ class Events final {
 
             auto read_result = std::get<RedisRead>(event);
 
-            connected.state = ReceivingPop{payload_count, position + 1};
+            // The first element of a `BLPOP` response is the key the value
+            // came from; new credentials arrive on their own queue.
+            bool credentials =
+                std::get_if<ReceivingPop>(&connected.state)->credentials ||
+                (position == 0 &&
+                 strstr((char *)buffer->data(), ":credentials") != nullptr);
+
+            connected.state =
+                ReceivingPop{payload_count, position + 1, 0, 0, credentials};
 
             log_i("received read event of size %d on payload item %d",
                   read_result.size, payload_count);
 
+            if (had_payload && position + 1 == payload_count && credentials) {
+              connected.state = NotReceiving{true};
+              context->store_credentials((char *)buffer->data(),
+                                         read_result.size);
+              log_i("stored rotated credentials for '%s'", context->device_id);
+              return std::make_pair(connected, std::nullopt);
+            }
+
             if (had_payload && position + 1 == payload_count) {
               connected.state = NotReceiving{true};
               log_i("finished all array elements, last size: %d (of %d)",
                     read_result.size, T);
+              context->remember_render_id(buffer->data(), read_result.size);
               RedisEvent event = PayloadReceived{(uint32_t)read_result.size};
               return std::make_pair(connected, event);
             }
@@ -413,13 +579,15 @@ class Events final {
 
         if (sending_heartbeat) {
           connected.state = ReceivingHeartbeatAck{};
-          sprintf(context->outbound,
-                  "*3\r\n$5\r\nRPUSH\r\n$4\r\nob:i\r\n$%d\r\n%s\r\n",
-                  context->device_id_len, context->device_id);
+          context->prepare_check_in(time);
         } else {
+          // Rotated credentials are checked before messages so they are
+          // picked up as soon as possible.
           connected.state = ReceivingPop{};
           sprintf(context->outbound,
-                  "*3\r\n$5\r\nBLPOP\r\n$%d\r\nob:%s\r\n$1\r\n5\r\n",
+                  "*4\r\n$5\r\nBLPOP\r\n$%d\r\nob:%s:credentials\r\n$%d\r\nob:"
+                  "%s\r\n$1\r\n5\r\n",
+                  context->device_id_len + 15, context->device_id,
                   context->device_id_len + 3, context->device_id);
         }
 
@@ -523,6 +691,7 @@ class Events final {
     int32_t payload_position = 0;
     uint32_t timeout_start = 0;
     uint32_t pending_reads = 0;
+    bool credentials = false;
   };
 
   struct NotReceiving final {
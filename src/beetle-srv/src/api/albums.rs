@@ -0,0 +1,205 @@
+//! Defines the routes used to add, remove and reorder the photos retained for a device. Photos in
+//! the album are displayed by the slideshow rendering state.
+
+use crate::{registrar, rendering, schema};
+use serde::{Deserialize, Serialize};
+
+/// The url query supported when adding photos.
+#[derive(Debug, Default, Deserialize)]
+struct AddQuery {
+  /// How the photo should be placed on the display.
+  fit: Option<rendering::ImageFit>,
+}
+
+/// The payload for removing a photo.
+#[derive(Debug, Deserialize)]
+struct RemovePayload {
+  /// The id of the photo.
+  id: String,
+}
+
+/// The payload for moving a photo.
+#[derive(Debug, Deserialize)]
+struct MovePayload {
+  /// The id of the photo.
+  id: String,
+  /// The position the photo should be moved to.
+  index: usize,
+}
+
+/// The schema of responses sent after queuing an album change.
+#[derive(Debug, Serialize)]
+struct AlbumChangeResponse {
+  /// The id of the job queued.
+  id: String,
+}
+
+/// A single photo, as it is exposed from the api. This omits where the file lives on disc.
+#[derive(Debug, Serialize)]
+struct AlbumPhotoResponse {
+  /// The id of the photo.
+  id: String,
+  /// How the photo is placed on the display.
+  fit: Option<rendering::ImageFit>,
+  /// When the photo was added.
+  added_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// The schema of responses sent from the album lookup api.
+#[derive(Debug, Serialize)]
+struct AlbumResponse {
+  /// The photos, in the order they will be displayed.
+  photos: Vec<AlbumPhotoResponse>,
+}
+
+/// Returns the device id from the request url after verifying the current user is allowed to
+/// perform the action on it.
+async fn authorized_device(
+  request: &tide::Request<super::worker::Worker>,
+  action: registrar::DeviceAction,
+) -> tide::Result<String> {
+  let worker = request.state();
+  let user = worker.request_authority(request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let device_id = request.param("device_id")?.to_string();
+  worker.require_device_action(&user.oid, &device_id, action).await?;
+
+  Ok(device_id)
+}
+
+/// Queues the album change, responding with the id of the job.
+async fn queue_change(
+  worker: &super::worker::Worker,
+  device_id: String,
+  change: registrar::album::DeviceAlbumChange,
+) -> tide::Result {
+  let id = worker
+    .queue_job_kind(registrar::RegistrarJobKind::MutateDeviceAlbum(
+      registrar::album::DeviceAlbumRequest { device_id, change },
+    ))
+    .await?;
+
+  tide::Body::from_json(&AlbumChangeResponse { id }).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-album
+///
+/// Returns the photos in a device album.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let device_id = authorized_device(&request, registrar::DeviceAction::View).await?;
+
+  let photos = request
+    .state()
+    .device_state_collection()?
+    .find_one(bson::doc! { "device_id": &device_id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load device state for '{device_id}' album - {error}");
+      tide::Error::from_str(500, "bad-lookup")
+    })?
+    .map(|state| state.album)
+    .unwrap_or_default()
+    .into_iter()
+    .map(|photo| AlbumPhotoResponse {
+      id: photo.id,
+      fit: photo.fit,
+      added_at: photo.added_at,
+    })
+    .collect();
+
+  tide::Body::from_json(&AlbumResponse { photos }).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-album
+///
+/// Writes an uploaded jpeg/png to the album storage and queues a job to add it to the album. Photos
+/// from moderated senders are held until the owner approves them.
+pub(super) async fn add(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+  let device_id = request.param("device_id")?.to_string();
+  let record = worker
+    .require_device_action(&user.oid, &device_id, registrar::DeviceAction::SendContent)
+    .await?;
+  let content = registrar::moderation::ScreenedContent::Opaque;
+  let verdict = super::jobs::moderate(worker, record.as_ref(), &device_id, &user.oid, content).await?;
+
+  let query = request.query::<AddQuery>().unwrap_or_default();
+
+  let content_type = request
+    .content_type()
+    .ok_or_else(|| tide::Error::from_str(422, "missing content-type"))?;
+
+  let image_kind = match content_type.essence() {
+    image_kind @ "image/jpeg" | image_kind @ "image/png" => image_kind,
+    other => {
+      log::warn!("strange album content type - '{other}'");
+      return Err(tide::Error::from_str(422, "bad-content-type"));
+    }
+  };
+
+  let web_configuration = &request.state().web_configuration;
+  let storage_dest = web_configuration
+    .album_file_storage
+    .as_ref()
+    .map(std::path::PathBuf::from)
+    .unwrap_or_else(|| std::path::Path::new(&web_configuration.temp_file_storage).join("albums"))
+    .join(&device_id);
+
+  let location = super::jobs::write_upload(&mut request, storage_dest, image_kind)
+    .await?
+    .to_string_lossy()
+    .to_string();
+
+  if verdict == registrar::moderation::ModerationVerdict::Hold {
+    let content = schema::DevicePendingContent::AlbumPhoto {
+      location,
+      fit: query.fit,
+    };
+    return super::jobs::hold(request.state(), device_id, &user, content).await;
+  }
+
+  let change = registrar::album::DeviceAlbumChange::Add {
+    location,
+    fit: query.fit,
+  };
+
+  queue_change(request.state(), device_id, change).await
+}
+
+/// Route: device-album/remove
+///
+/// Queues a job to remove a photo from the album.
+pub(super) async fn remove(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let device_id = authorized_device(&request, registrar::DeviceAction::ManageAlbum).await?;
+  let payload = request.body_json::<RemovePayload>().await.map_err(|error| {
+    log::warn!("bad album remove payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+
+  let change = registrar::album::DeviceAlbumChange::Remove(payload.id);
+  queue_change(request.state(), device_id, change).await
+}
+
+/// Route: device-album/move
+///
+/// Queues a job to move a photo to a new position in the album.
+pub(super) async fn reorder(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let device_id = authorized_device(&request, registrar::DeviceAction::ManageAlbum).await?;
+  let payload = request.body_json::<MovePayload>().await.map_err(|error| {
+    log::warn!("bad album move payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+
+  let change = registrar::album::DeviceAlbumChange::Move {
+    id: payload.id,
+    index: payload.index,
+  };
+  queue_change(request.state(), device_id, change).await
+}
@@ -0,0 +1,155 @@
+//! Defines the routes used by users to choose how they are told about their devices going offline,
+//! and to look through the connectivity events of a device.
+
+use crate::{registrar, schema};
+use async_std::stream::StreamExt;
+use serde::Deserialize;
+
+/// The most amount of events returned from the event api.
+const MAX_EVENTS: i64 = 50;
+
+/// The payload for looking up a device by id.
+#[derive(Debug, Deserialize)]
+struct LookupQuery {
+  /// The id of a device in question.
+  id: String,
+}
+
+/// Returns true when the value looks enough like an email address to hand to the smtp server.
+fn valid_email(email: &str) -> bool {
+  matches!(email.split_once('@'), Some((local, domain)) if !local.is_empty() && domain.contains('.'))
+    && !email.chars().any(|c| c.is_whitespace() || c == '<' || c == '>')
+}
+
+/// Returns true when the value is an http(s) url.
+pub(super) fn valid_webhook_url(url: &str) -> bool {
+  http_types::Url::parse(url)
+    .map(|url| matches!(url.scheme(), "http" | "https"))
+    .unwrap_or_default()
+}
+
+/// Route: alert-preferences
+///
+/// Returns the notification channels of the current user.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let user = request.state().request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  tide::Body::from_json(&user.alert_preferences.unwrap_or_default())
+    .map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: alert-preferences
+///
+/// Replaces the notification channels of the current user.
+pub(super) async fn update(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let preferences = request
+    .body_json::<schema::UserAlertPreferences>()
+    .await
+    .map_err(|error| {
+      log::warn!("bad alert preferences payload - {error}");
+      tide::Error::from_str(422, "bad-request")
+    })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  if !preferences.email.as_deref().map(valid_email).unwrap_or(true) {
+    return Err(tide::Error::from_str(422, "bad-email"));
+  }
+
+  if !preferences
+    .webhook_url
+    .as_deref()
+    .map(valid_webhook_url)
+    .unwrap_or(true)
+  {
+    return Err(tide::Error::from_str(422, "bad-webhook-url"));
+  }
+
+  if let Some(url) = preferences.webhook_url.as_deref() {
+    registrar::webhooks::public_destination(url).await.map_err(|error| {
+      log::warn!("rejected webhook url for '{}' - {error}", user.oid);
+      tide::Error::from_str(422, "bad-webhook-url")
+    })?;
+  }
+
+  let serialized = bson::to_bson(&preferences).map_err(|error| {
+    log::warn!("unable to serialize alert preferences - {error}");
+    tide::Error::from_str(500, "server-error")
+  })?;
+
+  worker
+    .users_collection()?
+    .update_one(
+      bson::doc! { "oid": &user.oid },
+      bson::doc! { "$set": { "alert_preferences": serialized } },
+      None,
+    )
+    .await
+    .map_err(|error| {
+      log::warn!("unable to store alert preferences for '{}' - {error}", user.oid);
+      tide::Error::from_str(500, "server-error")
+    })?;
+
+  tide::Body::from_json(&preferences).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-events
+///
+/// Returns the most recent connectivity events of a device, newest first.
+pub(super) async fn events(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+  let query = request.query::<LookupQuery>()?;
+
+  worker
+    .require_device_action(&user.oid, &query.id, registrar::DeviceAction::View)
+    .await?;
+
+  let mut cursor = worker
+    .device_event_collection()?
+    .find(
+      bson::doc! { "device_id": &query.id },
+      mongodb::options::FindOptions::builder()
+        .sort(bson::doc! { "at": -1 })
+        .limit(MAX_EVENTS)
+        .build(),
+    )
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load events for '{}' - {error}", query.id);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  let mut events = vec![];
+  while let Some(event) = cursor.next().await {
+    match event {
+      Ok(event) => events.push(event),
+      Err(error) => log::warn!("unable to parse event for '{}' - {error}", query.id),
+    }
+  }
+
+  tide::Body::from_json(&events).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{valid_email, valid_webhook_url};
+
+  #[test]
+  fn test_validation() {
+    assert!(valid_email("owner@example.com"));
+    assert!(!valid_email("owner"));
+    assert!(!valid_email("owner@example.com>\r\nRCPT TO:<other@example.com"));
+    assert!(valid_webhook_url("https://example.com/hooks/beetle"));
+    assert!(!valid_webhook_url("ftp://example.com"));
+  }
+}
@@ -3,6 +3,9 @@
 /// Google apis.
 pub mod google;
 
+/// Generic OpenID Connect providers.
+pub mod oidc;
+
 /// The flags that will be used to set our cookie when not using https.
 #[cfg(debug_assertions)]
 const COOKIE_SET_FLAGS: &str = "Max-Age=86400; Path=/; SameSite=Strict; HttpOnly";
@@ -18,12 +21,56 @@ const COOKIE_CLEAR_FLAGS: &str = "Max-Age: 0; Path=/; SameSite=Strict; HttpOnly"
 #[cfg(not(debug_assertions))]
 const COOKIE_CLEAR_FLAGS: &str = "Max-Age: 0; Path=/; SameSite=Strict; HttpOnly; Secure";
 
+/// Returns the `Set-Cookie` header value holding the session token.
+pub(super) fn session_cookie(worker: &super::worker::Worker, jwt: &str) -> String {
+  format!(
+    "{}={}; {}; Domain={}",
+    &worker.web_configuration.session_cookie, jwt, COOKIE_SET_FLAGS, &worker.web_configuration.cookie_domain,
+  )
+}
+
+/// Creates a session for the user, redirecting them to the ui with the session cookie.
+pub(super) async fn login(request: &tide::Request<super::worker::Worker>, user_id: &str) -> tide::Result {
+  let worker = request.state();
+  let user_agent = request
+    .header("User-Agent")
+    .map(|values| values.last().as_str().to_string());
+  let session = super::sessions::create(worker, user_id, user_agent.as_deref()).await?;
+  let jwt =
+    super::claims::Claims::for_session(user_id, &session.id).encode(&worker.web_configuration.session_secret)?;
+
+  log::info!("sending session cookie through redirect");
+  Ok(
+    tide::Response::builder(302)
+      .header("Set-Cookie", session_cookie(worker, &jwt))
+      .header("Location", &worker.web_configuration.ui_redirect)
+      .build(),
+  )
+}
+
+/// Returns the session claims held in the cookie of the request, if they are valid.
+pub(super) fn request_claims(request: &tide::Request<super::worker::Worker>) -> Option<super::claims::Claims> {
+  let worker = request.state();
+  request
+    .cookie(&worker.web_configuration.session_cookie)
+    .and_then(|cookie| super::claims::Claims::decode(cookie.value(), &worker.web_configuration.session_secret).ok())
+}
+
 /// Route: logout
 ///
-/// A simple redirect with a cookie-clearing header.
+/// Revokes the current session, redirecting with a cookie-clearing header.
 pub async fn logout(request: tide::Request<super::worker::Worker>) -> tide::Result {
   let worker = request.state();
 
+  if let Some(super::claims::Claims {
+    oid, sid: Some(sid), ..
+  }) = request_claims(&request)
+  {
+    if let Err(error) = super::sessions::revoke(worker, &oid, &sid).await {
+      log::warn!("unable to revoke session of '{oid}' during logout - {error}");
+    }
+  }
+
   log::debug!("redirecting user with logout cookie");
 
   let cookie = format!(
@@ -38,6 +85,33 @@ pub async fn logout(request: tide::Request<super::worker::Worker>) -> tide::Resu
   Ok(response)
 }
 
+/// Route: refresh
+///
+/// Issues a new session cookie for the current session, pushing back its expiration. Only valid,
+/// unrevoked sessions can be refreshed.
+pub async fn refresh(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let (oid, sid) = match request_claims(&request) {
+    Some(super::claims::Claims {
+      oid, sid: Some(sid), ..
+    }) => (oid, sid),
+    _ => return Err(tide::Error::from_str(401, "missing-session")),
+  };
+
+  if super::sessions::authenticate(worker, &sid, &oid).await?.is_none() {
+    return Err(tide::Error::from_str(401, "missing-session"));
+  }
+
+  let jwt = super::claims::Claims::for_session(&oid, &sid).encode(&worker.web_configuration.session_secret)?;
+  log::debug!("refreshed session cookie for '{oid}'");
+
+  Ok(
+    tide::Response::builder(204)
+      .header("Set-Cookie", session_cookie(worker, &jwt))
+      .build(),
+  )
+}
+
 /// Route: identify
 ///
 /// This route attempts to load the user information from our db based on the session cookied
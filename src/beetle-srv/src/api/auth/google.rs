@@ -1,13 +1,16 @@
 //! This module is currently in the process of replacing the Auth0-based module defined in the
 //! parent directory. Some of the code in here is repetetive while that is being phases out.
 
-use crate::{registrar, schema};
+use crate::registrar;
 use anyhow::Context;
 
 /// This value is how auth0 "tags" ids during its oauth handshake. It will be added for all users
 /// authenticating through google for backwards-compatibility.
 const GOOGLE_ID_PREFIX: &str = "google-oauth2|";
 
+/// The provider id google identities are linked to users with.
+const GOOGLE_PROVIDER_ID: &str = "google";
+
 /// Route: google redirect start.
 pub async fn redirect(request: tide::Request<crate::api::Worker>) -> tide::Result {
   let mut url = url::Url::parse("https://accounts.google.com/o/oauth2/v2/auth").with_context(|| "bad")?;
@@ -61,48 +64,28 @@ pub async fn complete(request: tide::Request<crate::api::Worker>) -> tide::Resul
     error
   })?;
 
-  let normalized_id = format!("{GOOGLE_ID_PREFIX}{}", userinfo.id);
-  let query = bson::doc! { "oid": &normalized_id };
-  let options = mongodb::options::FindOneAndUpdateOptions::builder()
-    .upsert(true)
-    .return_document(mongodb::options::ReturnDocument::After)
-    .build();
-
-  // TODO: this got a little bit messy when names were introduced: we're attempting to upsert a
-  // user if it doesnt exist, but _always_ updating the `name` that came back from google user
-  // info.
-  let state = schema::User {
-    oid: normalized_id.clone(),
-    picture: userinfo.picture.clone(),
+  let identity = crate::vendor::oidc::Identity {
+    subject: userinfo.id.clone(),
+    email: userinfo.email.as_ref().map(|email| email.to_lowercase()),
+    email_verified: userinfo.verified_email.unwrap_or_default(),
     name: Some(userinfo.name.clone()),
-    ..Default::default()
+    picture: Some(userinfo.picture.clone()),
   };
-
-  log::debug!("loaded user info - '{}' ({:?})", state.oid, state.name);
-
-  let upsert_doc = bson::to_bson(&state).map_err(|error| {
-    log::warn!("unable to serialize new user - {:?}", error);
-    tide::Error::from_str(500, "user-failure")
-  })?;
-
-  let users = worker.users_collection()?;
-  let user = users
-    .find_one_and_update(query.clone(), bson::doc! { "$setOnInsert": upsert_doc }, options)
-    .await
-    .map_err(|error| {
-      log::warn!("unable to create new user - {:?}", error);
-      tide::Error::from_str(500, "user-failure")
-    })?
-    .ok_or_else(|| tide::Error::from_str(404, "missing-user"))?;
+  let user = super::oidc::link(worker, GOOGLE_PROVIDER_ID, GOOGLE_ID_PREFIX, &identity).await?;
 
   // TODO(name-migration): remove this after some time has passed for stabilization. We weren't
   // originally recording names until we started rendering events with user names.
-  users
-    .update_one(query, bson::doc! { "$set": { "name": state.name } }, None)
+  worker
+    .users_collection()?
+    .update_one(
+      bson::doc! { "oid": &user.oid },
+      bson::doc! { "$set": { "name": &userinfo.name } },
+      None,
+    )
     .await?;
 
   log::debug!("loaded user from database - '{}'", user.oid);
-  let jwt = crate::api::claims::Claims::for_user(&user.oid).encode(&worker.web_configuration.session_secret)?;
+  let response = super::login(&request, &user.oid).await?;
 
   if let Err(error) = worker
     .queue_job_kind(registrar::RegistrarJobKind::UserAccessTokenRefresh {
@@ -114,21 +97,5 @@ pub async fn complete(request: tide::Request<crate::api::Worker>) -> tide::Resul
     log::warn!("unable to queued refresh token request - {error}");
   }
 
-  // Create a session and redirect them to the ui.
-  let cookie = format!(
-    "{}={}; {}; Domain={}",
-    &worker.web_configuration.session_cookie,
-    jwt,
-    super::COOKIE_SET_FLAGS,
-    &worker.web_configuration.cookie_domain,
-  );
-
-  log::info!("sending cookie through redirect - '{cookie}'");
-
-  let response = tide::Response::builder(302)
-    .header("Set-Cookie", cookie)
-    .header("Location", &worker.web_configuration.ui_redirect)
-    .build();
-
   Ok(response)
 }
@@ -0,0 +1,202 @@
+//! Logging in through the OpenID Connect providers listed in the web configuration. Accounts are
+//! linked to users by provider and subject; the first time an account is seen, it is linked to the
+//! user that already has its email address verified, if any.
+
+use serde::{Deserialize, Serialize};
+
+/// How long users have to finish logging in at the provider.
+const STATE_TTL_SECONDS: u64 = 60 * 10;
+
+/// The query parameters the provider redirects back with.
+#[derive(Debug, Deserialize)]
+struct CompletionQuery {
+  /// The code to exchange.
+  code: String,
+  /// The state the user was sent with.
+  state: String,
+}
+
+/// A provider, as it is listed for the ui.
+#[derive(Debug, Serialize)]
+struct ProviderResponse<'a> {
+  /// The id of the provider.
+  id: &'a str,
+  /// The name of the provider.
+  name: &'a str,
+}
+
+/// Returns the redis key a login state is stored under.
+fn state_key(state: &str) -> String {
+  format!("{}:{state}", crate::constants::OIDC_STATE_PREFIX)
+}
+
+/// Returns the configuration of the provider named in the route.
+fn provider(request: &tide::Request<crate::api::Worker>) -> tide::Result<&crate::config::OidcProviderConfiguration> {
+  let id = request.param("provider")?;
+  request
+    .state()
+    .web_configuration
+    .oidc_providers
+    .iter()
+    .find(|provider| provider.id == id)
+    .ok_or_else(|| {
+      log::warn!("login attempted with unknown provider '{id}'");
+      tide::Error::from_str(404, "missing-provider")
+    })
+}
+
+/// Returns the user the identity belongs to, creating or linking one as needed. New users are given
+/// an id made of the prefix and the subject.
+pub(super) async fn link(
+  worker: &crate::api::Worker,
+  provider: &str,
+  prefix: &str,
+  identity: &crate::vendor::oidc::Identity,
+) -> tide::Result<crate::schema::User> {
+  let users = worker.users_collection()?;
+  let oid = format!("{prefix}{}", identity.subject);
+  let record = crate::schema::UserIdentity {
+    provider: provider.to_string(),
+    subject: identity.subject.clone(),
+    email: identity.email.clone(),
+  };
+  let serialized = bson::to_bson(&record)?;
+  let verified_email = identity.email.as_ref().filter(|_| identity.email_verified);
+
+  // Users that have logged in with this account before. Users created before accounts were linked
+  // are only known by their id.
+  let mut candidates = vec![
+    bson::doc! { "identities": { "$elemMatch": { "provider": provider, "subject": &identity.subject } } },
+    bson::doc! { "oid": &oid },
+  ];
+
+  // Otherwise, the user whose email address matches one verified by the provider.
+  if let Some(email) = verified_email {
+    candidates.push(bson::doc! { "verified_emails": email });
+  }
+
+  for query in candidates {
+    let Some(user) = users.find_one(query, None).await? else {
+      continue;
+    };
+
+    let mut update = bson::doc! {};
+    if !user
+      .identities
+      .iter()
+      .any(|known| known.provider == provider && known.subject == identity.subject)
+    {
+      log::info!("linking '{provider}' account to user '{}'", user.oid);
+      update.insert("identities", serialized.clone());
+    }
+    if let Some(email) = verified_email {
+      update.insert("verified_emails", email);
+    }
+    if !update.is_empty() {
+      users
+        .update_one(
+          bson::doc! { "oid": &user.oid },
+          bson::doc! { "$addToSet": update },
+          None,
+        )
+        .await?;
+    }
+
+    return Ok(user);
+  }
+
+  log::info!("creating user for new '{provider}' account");
+  let user = crate::schema::User {
+    oid,
+    picture: identity.picture.clone().unwrap_or_default(),
+    name: identity.name.clone(),
+    identities: vec![record],
+    verified_emails: verified_email.into_iter().cloned().collect(),
+    ..Default::default()
+  };
+  users.insert_one(&user, None).await?;
+  Ok(user)
+}
+
+/// Route: oidc providers
+///
+/// Lists the providers users can log in with.
+pub async fn providers(request: tide::Request<crate::api::Worker>) -> tide::Result {
+  let providers = request
+    .state()
+    .web_configuration
+    .oidc_providers
+    .iter()
+    .map(|provider| ProviderResponse {
+      id: &provider.id,
+      name: &provider.name,
+    })
+    .collect::<Vec<ProviderResponse>>();
+
+  tide::Body::from_json(&providers).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: oidc redirect start.
+pub async fn redirect(request: tide::Request<crate::api::Worker>) -> tide::Result {
+  let config = provider(&request)?;
+  let discovery = crate::vendor::oidc::discover(config).await.map_err(|error| {
+    log::error!("unable to discover '{}' - {error}", config.id);
+    tide::Error::from_str(502, "bad-provider")
+  })?;
+
+  let state = crate::identity::create_secret();
+  request
+    .state()
+    .command(&kramer::Command::Strings(kramer::StringCommand::Set(
+      kramer::Arity::One((state_key(&state), config.id.as_str())),
+      Some(std::time::Duration::from_secs(STATE_TTL_SECONDS)),
+      kramer::Insertion::Always,
+    )))
+    .await?;
+
+  let url = crate::vendor::oidc::authorization_url(config, &discovery, &state)?;
+  log::debug!("sending user to '{}' login", config.id);
+  Ok(tide::Redirect::new(url).into())
+}
+
+/// Route: oidc redirect finish.
+pub async fn complete(request: tide::Request<crate::api::Worker>) -> tide::Result {
+  let config = provider(&request)?;
+  let query = request.query::<CompletionQuery>()?;
+  let worker = request.state();
+
+  // The state can only be used once, and only with the provider it was created for.
+  let stored = worker
+    .command(&kramer::Command::Strings::<String, &str>(kramer::StringCommand::Get(
+      kramer::Arity::One(state_key(&query.state)),
+    )))
+    .await?;
+  worker
+    .command(&kramer::Command::Del::<String, &str>(kramer::Arity::One(state_key(
+      &query.state,
+    ))))
+    .await?;
+
+  match stored {
+    kramer::Response::Item(kramer::ResponseValue::String(id)) if id == config.id => (),
+    _ => {
+      log::warn!("login with '{}' completed with unknown state", config.id);
+      return Err(tide::Error::from_str(400, "bad-state"));
+    }
+  }
+
+  let discovery = crate::vendor::oidc::discover(config).await.map_err(|error| {
+    log::error!("unable to discover '{}' - {error}", config.id);
+    tide::Error::from_str(502, "bad-provider")
+  })?;
+  let identity = crate::vendor::oidc::complete(config, &discovery, &query.code)
+    .await
+    .map_err(|error| {
+      log::error!("unable to complete login with '{}' - {error}", config.id);
+      tide::Error::from_str(502, "bad-provider")
+    })?;
+
+  let user = link(worker, &config.id, &format!("{}|", config.id), &identity).await?;
+  log::info!("user '{}' logged in with '{}'", user.oid, config.id);
+  super::login(&request, &user.oid).await
+}
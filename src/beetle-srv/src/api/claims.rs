@@ -8,6 +8,10 @@ pub struct Claims {
   pub exp: usize,
   /// The oauth user id.
   pub oid: String,
+  /// The id of the server-side session. Tokens issued before sessions were persisted do not have
+  /// one, and are no longer accepted.
+  #[serde(default)]
+  pub sid: Option<String>,
 }
 
 impl Claims {
@@ -27,8 +31,8 @@ impl Claims {
       .map(|data| data.claims)
   }
 
-  /// Builds a token payload for a given user id.
-  pub fn for_user<T>(oid: T) -> Self
+  /// Builds a token payload for a given user id and session.
+  pub fn for_session<T>(oid: T, sid: &str) -> Self
   where
     T: std::fmt::Display,
   {
@@ -42,6 +46,7 @@ impl Claims {
     Self {
       exp,
       oid: format!("{}", oid),
+      sid: Some(sid.to_string()),
     }
   }
 
@@ -15,6 +15,16 @@ struct RegistrationPayload {
   device_id: String,
 }
 
+/// The schema of our api to decommission a device.
+#[derive(Debug, Deserialize)]
+struct DecommissionPayload {
+  /// The id of the device.
+  device_id: String,
+  /// When true, the device keeps its id and is sent back to the initial registration state.
+  #[serde(default)]
+  reset_registration: bool,
+}
+
 /// The schema of responses sent from the registration api.
 #[derive(Debug, Serialize)]
 struct RegistrationResponse {
@@ -47,6 +57,15 @@ pub struct DeviceInfoPayload {
 
   /// A list of the most recent messages that have been sent to the device.
   sent_messages: Vec<crate::rendering::queue::QueuedRender<String>>,
+
+  /// The telemetry sent with the most recent check-in.
+  telemetry: Option<schema::DeviceTelemetry>,
+
+  /// The telemetry sent with recent check-ins, oldest first.
+  telemetry_history: Vec<schema::DeviceTelemetry>,
+
+  /// Whether the device is online, if it is being tracked.
+  connectivity: Option<schema::DeviceConnectivity>,
 }
 
 /// Route: authority
@@ -59,10 +78,14 @@ pub async fn authority(request: tide::Request<super::worker::Worker>) -> tide::R
     tide::Error::from_str(404, "missing-user")
   })?;
   let query = request.query::<LookupQuery>()?;
-  let (_, model) = worker.user_access(&user.oid, &query.id).await?.ok_or_else(|| {
-    log::warn!("'{}' has no access to device '{}'", user.oid, query.id);
-    tide::Error::from_str(400, "not-found")
-  })?;
+  // Invite codes are only exposed to owners, through the member api.
+  let model = worker
+    .require_device_action(&user.oid, &query.id, registrar::DeviceAction::View)
+    .await?
+    .map(|record| schema::DeviceAuthorityRecord {
+      invites: vec![],
+      ..record
+    });
   tide::Body::from_json(&model).map(|body| tide::Response::builder(200).body(body).build())
 }
 
@@ -80,10 +103,9 @@ pub async fn info(request: tide::Request<super::worker::Worker>) -> tide::Result
 
   let query = request.query::<LookupQuery>()?;
 
-  if worker.user_access(&user.oid, &query.id).await?.is_none() {
-    log::warn!("'{}' has no access to device '{}'", user.oid, query.id);
-    return Err(tide::Error::from_str(400, "not-found"));
-  }
+  worker
+    .require_device_action(&user.oid, &query.id, registrar::DeviceAction::View)
+    .await?;
 
   log::trace!("user loaded in {}ms", now.elapsed().as_millis());
   now = std::time::Instant::now();
@@ -135,6 +157,10 @@ pub async fn info(request: tide::Request<super::worker::Worker>) -> tide::Result
     // somewhere else. This is important to minimize the exposure of breaking changes in the schema
     // of device diagnostics.
     sent_messages: vec![],
+
+    telemetry: device_diagnostic.telemetry,
+    telemetry_history: device_diagnostic.telemetry_history,
+    connectivity: device_diagnostic.connectivity,
   };
 
   log::trace!("user '{}' fetched device '{}'", user.oid, info.id);
@@ -206,6 +232,37 @@ pub async fn unregister(mut request: tide::Request<super::worker::Worker>) -> ti
   Ok(tide::Response::builder(200).build())
 }
 
+/// Route: decommission
+///
+/// Queues a job to archive everything stored for the device and remove it from every user.
+pub async fn decommission(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<DecommissionPayload>().await.map_err(|error| {
+    log::warn!("invalid decommission payload - {error}");
+    tide::Error::from_str(422, "bad-payload")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("device decommission -> no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  worker
+    .require_device_action(&user.oid, &payload.device_id, registrar::DeviceAction::Decommission)
+    .await?;
+
+  log::info!("user '{}' decommissioning device '{}'", user.oid, payload.device_id);
+  let id = worker
+    .queue_job_kind(registrar::RegistrarJobKind::Decommission(
+      registrar::decommission::DeviceDecommissionRequest {
+        device_id: payload.device_id,
+        reset_registration: payload.reset_registration,
+      },
+    ))
+    .await?;
+
+  tide::Body::from_json(&RegistrationResponse { id }).map(|body| tide::Response::builder(200).body(body).build())
+}
+
 /// Route: register
 ///
 /// This api route will attempt to parse the request payload and register the device id
@@ -0,0 +1,442 @@
+//! Defines the routes used by device owners to create and revoke inbound hooks, as well as the route
+//! those hooks are used with. A hook is a token scoped to a single device, which is sent as part of
+//! the url; requests made with it do not need a session, which makes them usable from scripts and
+//! other services.
+
+use crate::{registrar, schema};
+use async_std::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+
+/// The most amount of hooks a single device can have.
+const MAX_HOOKS: u64 = 10;
+
+/// The rate limit given to hooks created without one, in requests per minute.
+const DEFAULT_RATE_LIMIT: u32 = 10;
+
+/// The highest rate limit a hook can be given, in requests per minute.
+const MAX_RATE_LIMIT: u32 = 120;
+
+/// The longest label a hook can be given.
+const MAX_LABEL_LEN: usize = 64;
+
+/// The payload for looking up a device or a hook by id.
+#[derive(Debug, Deserialize)]
+struct LookupQuery {
+  /// The id of the device or hook in question.
+  id: String,
+}
+
+/// The payload for creating a hook.
+#[derive(Debug, Deserialize)]
+struct HookCreationPayload {
+  /// The id of the device.
+  device_id: String,
+
+  /// A name for the hook.
+  label: String,
+
+  /// What the hook is allowed to do.
+  permissions: Vec<schema::InboundHookPermission>,
+
+  /// The most amount of requests allowed per minute.
+  rate_limit: Option<u32>,
+}
+
+/// A hook as it is returned from the api; the token is only ever returned once, on creation.
+#[derive(Debug, Serialize)]
+struct HookResponse {
+  /// The id of the hook.
+  id: String,
+
+  /// The id of the device.
+  device_id: String,
+
+  /// The name of the hook.
+  label: String,
+
+  /// What the hook is allowed to do.
+  permissions: Vec<schema::InboundHookPermission>,
+
+  /// The most amount of requests allowed per minute.
+  rate_limit: u32,
+
+  /// When the hook was created.
+  created_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When the hook was last used.
+  last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// The token, only present in the response to creation.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  token: Option<String>,
+}
+
+impl From<schema::InboundHook> for HookResponse {
+  fn from(hook: schema::InboundHook) -> Self {
+    Self {
+      id: hook.id,
+      device_id: hook.device_id,
+      label: hook.label,
+      permissions: hook.permissions,
+      rate_limit: hook.rate_limit,
+      created_at: hook.created_at,
+      last_used_at: hook.last_used_at,
+      token: None,
+    }
+  }
+}
+
+/// The content that can be sent with a hook. Images are sent as the raw body of the request instead.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+enum HookPayload {
+  /// Pushes a message onto the device.
+  Message(String),
+
+  /// Turns the lights on or off.
+  Lights(bool),
+
+  /// Renders a layout template of the user that created the hook.
+  Template {
+    /// The id of the template.
+    id: String,
+    /// The values of the placeholders in the template.
+    #[serde(default)]
+    variables: std::collections::HashMap<String, String>,
+  },
+}
+
+impl HookPayload {
+  /// Returns the permission a hook needs to send this payload.
+  fn permission(&self) -> schema::InboundHookPermission {
+    match self {
+      Self::Message(_) => schema::InboundHookPermission::Message,
+      Self::Lights(_) => schema::InboundHookPermission::Lights,
+      Self::Template { .. } => schema::InboundHookPermission::Template,
+    }
+  }
+}
+
+/// The schema of responses sent from the hook api.
+#[derive(Debug, Serialize)]
+struct HookQueueResponse {
+  /// The id of the job or render queued.
+  id: String,
+}
+
+/// Returns an error unless the hook is allowed to do what it is attempting.
+fn require_permission(hook: &schema::InboundHook, permission: schema::InboundHookPermission) -> tide::Result<()> {
+  if hook.permissions.contains(&permission) {
+    return Ok(());
+  }
+
+  log::warn!("hook '{}' attempted {permission:?} without permission", hook.id);
+  Err(tide::Error::from_str(403, "forbidden"))
+}
+
+/// Route: device-hooks
+///
+/// Returns the hooks of a device.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+  let query = request.query::<LookupQuery>()?;
+
+  worker
+    .require_device_action(&user.oid, &query.id, registrar::DeviceAction::ManageHooks)
+    .await?;
+
+  let mut cursor = worker
+    .inbound_hook_collection()?
+    .find(bson::doc! { "device_id": &query.id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load hooks for '{}' - {error}", query.id);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  let mut hooks = vec![];
+  while let Some(hook) = cursor.next().await {
+    match hook {
+      Ok(hook) => hooks.push(HookResponse::from(hook)),
+      Err(error) => log::warn!("unable to parse hook for '{}' - {error}", query.id),
+    }
+  }
+
+  tide::Body::from_json(&hooks).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-hooks
+///
+/// Creates a hook for a device, returning it along with its token.
+pub(super) async fn create(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<HookCreationPayload>().await.map_err(|error| {
+    log::warn!("bad hook payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  worker
+    .require_device_action(&user.oid, &payload.device_id, registrar::DeviceAction::ManageHooks)
+    .await?;
+
+  let label = payload.label.trim().to_string();
+  if label.is_empty() || label.len() > MAX_LABEL_LEN {
+    return Err(tide::Error::from_str(422, "bad-label"));
+  }
+
+  if payload.permissions.is_empty() {
+    return Err(tide::Error::from_str(422, "missing-permissions"));
+  }
+
+  let rate_limit = payload.rate_limit.unwrap_or(DEFAULT_RATE_LIMIT);
+  if rate_limit == 0 || rate_limit > MAX_RATE_LIMIT {
+    return Err(tide::Error::from_str(422, "bad-rate-limit"));
+  }
+
+  let hooks = worker.inbound_hook_collection()?;
+  let existing = hooks
+    .count_documents(bson::doc! { "device_id": &payload.device_id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to count hooks for '{}' - {error}", payload.device_id);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  if existing >= MAX_HOOKS {
+    return Err(tide::Error::from_str(422, "too-many-hooks"));
+  }
+
+  let token = crate::identity::create_secret();
+  let hook = schema::InboundHook {
+    id: uuid::Uuid::new_v4().to_string(),
+    device_id: payload.device_id,
+    user_id: user.oid.clone(),
+    label,
+    token_hash: crate::redis::hash_secret(&token),
+    permissions: payload.permissions,
+    rate_limit,
+    created_at: Some(chrono::Utc::now()),
+    last_used_at: None,
+  };
+
+  hooks.insert_one(&hook, None).await.map_err(|error| {
+    log::warn!("unable to store hook for '{}' - {error}", hook.device_id);
+    tide::Error::from_str(500, "server-error")
+  })?;
+
+  log::info!(
+    "user '{}' created hook '{}' for '{}'",
+    user.oid,
+    hook.id,
+    hook.device_id
+  );
+  let response = HookResponse {
+    token: Some(token),
+    ..HookResponse::from(hook)
+  };
+
+  tide::Body::from_json(&response).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-hooks/revoke
+///
+/// Deletes a hook; its token stops working immediately.
+pub(super) async fn revoke(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<LookupQuery>().await.map_err(|error| {
+    log::warn!("bad hook revocation payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let hooks = worker.inbound_hook_collection()?;
+  let hook = hooks
+    .find_one(bson::doc! { "id": &payload.id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to find hook '{}' - {error}", payload.id);
+      tide::Error::from_str(500, "bad-lookup")
+    })?
+    .ok_or_else(|| tide::Error::from_str(404, "missing-hook"))?;
+
+  worker
+    .require_device_action(&user.oid, &hook.device_id, registrar::DeviceAction::ManageHooks)
+    .await?;
+
+  hooks
+    .delete_one(bson::doc! { "id": &hook.id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to delete hook '{}' - {error}", hook.id);
+      tide::Error::from_str(500, "server-error")
+    })?;
+
+  log::info!(
+    "user '{}' revoked hook '{}' for '{}'",
+    user.oid,
+    hook.id,
+    hook.device_id
+  );
+  Ok(tide::Response::builder(200).build())
+}
+
+/// Route: hooks/:token
+///
+/// Sends content to the device of the hook. Messages and lighting changes are sent as json, while
+/// images are sent as the raw body with an image content type.
+pub(super) async fn send(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let token_hash = crate::redis::hash_secret(request.param("token")?);
+
+  // Scoped to release the borrow of the worker before the body is read.
+  let hook = {
+    let worker = request.state();
+    let hook = worker
+      .inbound_hook_collection()?
+      .find_one(bson::doc! { "token_hash": &token_hash }, None)
+      .await
+      .map_err(|error| {
+        log::warn!("unable to find hook - {error}");
+        tide::Error::from_str(500, "bad-lookup")
+      })?
+      .ok_or_else(|| {
+        log::warn!("request made with unknown hook token");
+        tide::Error::from_str(404, "missing-hook")
+      })?;
+
+    if !worker.hook_within_limit(&hook).await? {
+      log::warn!("hook '{}' has exceeded its rate limit", hook.id);
+      return Err(tide::Error::from_str(429, "rate-limited"));
+    }
+
+    // Hooks act on behalf of whoever created them, and stop working once that user can no longer
+    // send content to the device.
+    worker
+      .require_device_action(&hook.user_id, &hook.device_id, registrar::DeviceAction::SendContent)
+      .await?;
+
+    if let Err(error) = worker
+      .inbound_hook_collection()?
+      .update_one(
+        bson::doc! { "id": &hook.id },
+        bson::doc! { "$set": { "last_used_at": bson::to_bson(&chrono::Utc::now())? } },
+        None,
+      )
+      .await
+    {
+      log::warn!("unable to update last use of hook '{}' - {error}", hook.id);
+    }
+
+    hook
+  };
+
+  let image_kind = request
+    .content_type()
+    .map(|mime| mime.essence().to_string())
+    .filter(|essence| essence == "image/jpeg" || essence == "image/png");
+
+  if let Some(image_kind) = image_kind {
+    require_permission(&hook, schema::InboundHookPermission::Image)?;
+
+    let storage_dest = std::path::PathBuf::from(&request.state().web_configuration.temp_file_storage);
+    let storage_dest = super::jobs::write_upload(&mut request, storage_dest, &image_kind).await?;
+
+    let id = request
+      .state()
+      .queue_job_kind(registrar::RegistrarJobKind::Renders(
+        registrar::jobs::RegistrarRenderKinds::SendImage {
+          location: storage_dest.to_string_lossy().to_string(),
+          device_id: hook.device_id.clone(),
+        },
+      ))
+      .await?;
+
+    log::info!("hook '{}' sent an image to '{}'", hook.id, hook.device_id);
+    return tide::Body::from_json(&HookQueueResponse { id })
+      .map(|body| tide::Response::builder(200).body(body).build());
+  }
+
+  let payload = request.body_json::<HookPayload>().await.map_err(|error| {
+    log::warn!("bad hook request payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  require_permission(&hook, payload.permission())?;
+
+  let worker = request.state();
+  log::info!("hook '{}' sending {payload:?} to '{}'", hook.id, hook.device_id);
+
+  let id = match payload {
+    HookPayload::Message(message) => {
+      worker
+        .queue_job_kind(registrar::RegistrarJobKind::MutateDeviceState(
+          registrar::device_state::DeviceStateTransitionRequest {
+            device_id: hook.device_id.clone(),
+            transition: registrar::device_state::DeviceStateTransition::PushMessage(
+              message,
+              schema::DeviceStateMessageOrigin::User {
+                nickname: hook.label.clone(),
+              },
+            ),
+          },
+        ))
+        .await?
+    }
+    HookPayload::Lights(state) => {
+      let layout: crate::rendering::RenderVariant<String> = match state {
+        true => crate::rendering::RenderVariant::on(),
+        false => crate::rendering::RenderVariant::off(),
+      };
+      worker.queue_render(&hook.device_id, &hook.user_id, layout).await?
+    }
+    HookPayload::Template { id, variables } => {
+      let layout = super::templates::fill_owned(worker, &hook.user_id, &id, &variables).await?;
+      worker
+        .queue_render(
+          &hook.device_id,
+          &hook.user_id,
+          crate::rendering::RenderVariant::layout(layout),
+        )
+        .await?
+    }
+  };
+
+  tide::Body::from_json(&HookQueueResponse { id }).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{require_permission, HookPayload};
+  use crate::schema::{InboundHook, InboundHookPermission};
+
+  #[test]
+  fn test_hook_permissions() {
+    let hook = InboundHook {
+      id: "hook".to_string(),
+      device_id: "device".to_string(),
+      user_id: "user".to_string(),
+      label: "ci".to_string(),
+      token_hash: crate::redis::hash_secret("token"),
+      permissions: vec![InboundHookPermission::Message],
+      rate_limit: 10,
+      created_at: None,
+      last_used_at: None,
+    };
+
+    let message = serde_json::from_str::<HookPayload>(r#"{"beetle:kind":"message","beetle:content":"hi"}"#).unwrap();
+    let lights = serde_json::from_str::<HookPayload>(r#"{"beetle:kind":"lights","beetle:content":true}"#).unwrap();
+
+    assert!(require_permission(&hook, message.permission()).is_ok());
+    assert!(require_permission(&hook, lights.permission()).is_err());
+    assert!(require_permission(&hook, InboundHookPermission::Image).is_err());
+  }
+}
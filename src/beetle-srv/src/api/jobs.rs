@@ -44,6 +44,38 @@ enum QueuePayloadKind {
   /// Renders text.
   Message(String),
 
+  /// Shows the clock with the provided settings, or hides it.
+  Clock(Option<schema::DeviceRenderingStateClock>),
+
+  /// Adds a countdown.
+  Countdown(schema::DeviceRenderingStateCountdownEntry),
+
+  /// Removes the countdown(s) with a given label.
+  RemoveCountdown(String),
+
+  /// Adds an item to the device checklist.
+  ChecklistAdd(String),
+
+  /// Checks the checklist item with the provided id.
+  ChecklistCheck(String),
+
+  /// Unchecks the checklist item with the provided id.
+  ChecklistUncheck(String),
+
+  /// Moves the checklist item with the provided id to a new position.
+  ChecklistMove {
+    /// The id of the checklist item.
+    id: String,
+    /// The position the item should be moved to.
+    index: usize,
+  },
+
+  /// Removes the checklist item with the provided id.
+  ChecklistRemove(String),
+
+  /// Starts cycling through the photos in the device album, every given amount of seconds.
+  Slideshow(u32),
+
   /// Will queue a QR code render for the device.
   Link(String),
 
@@ -68,6 +100,181 @@ enum QueuePayloadKind {
   MakePrivate,
 }
 
+/// Writes the image upload held in the body of the request to a new file inside the provided
+/// directory, returning the full path of the file.
+pub(super) async fn write_upload(
+  request: &mut tide::Request<super::worker::Worker>,
+  mut storage_dest: std::path::PathBuf,
+  image_kind: &str,
+) -> tide::Result<std::path::PathBuf> {
+  let size = request.len().ok_or_else(|| {
+    log::warn!("unable to determine image size from upload");
+    tide::Error::from_str(422, "missing image upload size")
+  })?;
+
+  if (size as u32) > MAX_FILE_SIZE {
+    log::warn!("invalid image upload size: '{size}'");
+    return Err(tide::Error::from_str(422, "image too large"));
+  }
+
+  log::debug!("has image upload of {size} bytes");
+  let mut bytes = request.take_body();
+  async_std::fs::create_dir_all(&storage_dest).await.map_err(|error| {
+    log::error!("unable to ensure file storage dir exists - {error}");
+    tide::Error::from_str(500, "bad")
+  })?;
+  let file_name = uuid::Uuid::new_v4().to_string();
+
+  storage_dest.push(&file_name);
+  storage_dest.set_extension(if image_kind == "image/jpeg" { "jpg" } else { "png" });
+
+  log::info!("writing uploaded file to '{storage_dest:?}");
+  let mut file = async_std::fs::OpenOptions::new()
+    .write(true)
+    .create(true)
+    .open(&storage_dest)
+    .await
+    .map_err(|error| {
+      log::error!("unable to create file for upload - {error}");
+      tide::Error::from_str(500, "bad")
+    })?;
+
+  async_std::io::copy(&mut bytes, &mut file).await.map_err(|error| {
+    log::error!("unable to copy file upload - {error}");
+    tide::Error::from_str(500, "bad")
+  })?;
+
+  Ok(storage_dest)
+}
+
+impl QueuePayloadKind {
+  /// Maps the payload onto the action checked against the user's access level.
+  fn action(&self) -> registrar::DeviceAction {
+    match self {
+      Self::Lights(_)
+      | Self::Message(_)
+      | Self::Clock(_)
+      | Self::Countdown(_)
+      | Self::RemoveCountdown(_)
+      | Self::ChecklistAdd(_)
+      | Self::ChecklistCheck(_)
+      | Self::ChecklistUncheck(_)
+      | Self::ChecklistMove { .. }
+      | Self::ChecklistRemove(_)
+      | Self::Slideshow(_)
+      | Self::Link(_)
+      | Self::Refresh => registrar::DeviceAction::SendContent,
+      Self::Schedule(_) => registrar::DeviceAction::ToggleSchedule,
+      Self::Rename(_) => registrar::DeviceAction::Rename,
+      Self::ClearRender => registrar::DeviceAction::ClearRender,
+      Self::Registration => registrar::DeviceAction::ShowRegistration,
+      Self::MakePublic | Self::MakePrivate => registrar::DeviceAction::ChangeVisibility,
+    }
+  }
+
+  /// Returns the content this payload would put on the display, as it would be held for approval.
+  /// Payloads that do not display anything new (e.g hiding the clock) have none.
+  fn held_content(&self) -> Option<schema::DevicePendingContent> {
+    match self {
+      Self::Message(content) => Some(schema::DevicePendingContent::Message(content.clone())),
+      Self::ChecklistAdd(content) => Some(schema::DevicePendingContent::ChecklistItem(content.clone())),
+      Self::Clock(Some(clock)) => Some(schema::DevicePendingContent::Clock(clock.clone())),
+      Self::Countdown(entry) => Some(schema::DevicePendingContent::Countdown(entry.clone())),
+      Self::Link(link) => Some(schema::DevicePendingContent::Link(link.clone())),
+      Self::Lights(_)
+      | Self::Schedule(_)
+      | Self::Clock(None)
+      | Self::RemoveCountdown(_)
+      | Self::ChecklistCheck(_)
+      | Self::ChecklistUncheck(_)
+      | Self::ChecklistMove { .. }
+      | Self::ChecklistRemove(_)
+      | Self::Slideshow(_)
+      | Self::Rename(_)
+      | Self::Refresh
+      | Self::ClearRender
+      | Self::Registration
+      | Self::MakePublic
+      | Self::MakePrivate => None,
+    }
+  }
+}
+
+/// Returns the origin that will be displayed alongside content sent by the user.
+pub(super) fn user_origin(user: &schema::User) -> schema::DeviceStateMessageOrigin {
+  user
+    .nickname
+    .as_ref()
+    .or(user.name.as_ref())
+    .map(|name| schema::DeviceStateMessageOrigin::User { nickname: name.clone() })
+    .unwrap_or_else(|| schema::DeviceStateMessageOrigin::Unknown)
+}
+
+/// Applies moderation to requests made by users other than the owner of a `Public` device. This
+/// counts the request against the sender's rate limit, and screens any content being sent. Rejected
+/// requests are returned as errors.
+pub(super) async fn moderate(
+  worker: &super::worker::Worker,
+  record: Option<&schema::DeviceAuthorityRecord>,
+  device_id: &str,
+  sender: &str,
+  content: registrar::moderation::ScreenedContent<'_>,
+) -> tide::Result<registrar::moderation::ModerationVerdict> {
+  let record = match record {
+    Some(record) if registrar::moderation::is_moderated(record, sender) => record,
+    _ => return Ok(registrar::moderation::ModerationVerdict::Allow),
+  };
+
+  if !worker.sender_within_limit(device_id, sender).await? {
+    log::warn!("'{sender}' has exceeded their rate limit for device '{device_id}'");
+    return Err(tide::Error::from_str(429, "rate-limited"));
+  }
+
+  let blocked_words = worker
+    .web_configuration
+    .moderation
+    .as_ref()
+    .map(|moderation| moderation.blocked_words.as_slice())
+    .unwrap_or_default();
+
+  match registrar::moderation::screen(record, sender, content, blocked_words) {
+    registrar::moderation::ModerationVerdict::Reject(reason) => {
+      log::warn!("rejecting request from '{sender}' to device '{device_id}' - {reason}");
+      Err(tide::Error::from_str(403, reason))
+    }
+    verdict => Ok(verdict),
+  }
+}
+
+/// Queues a job that holds content from a moderated sender until the owner of the device approves
+/// it, responding with the id of the job.
+pub(super) async fn hold(
+  worker: &super::worker::Worker,
+  device_id: String,
+  user: &schema::User,
+  content: schema::DevicePendingContent,
+) -> tide::Result {
+  log::info!("holding content from '{}' for device '{device_id}' approval", user.oid);
+  let entry = schema::DevicePendingEntry {
+    id: crate::identity::create(),
+    sender: user.oid.clone(),
+    origin: user_origin(user),
+    content,
+    timestamp: Some(chrono::Utc::now()),
+  };
+
+  let id = worker
+    .queue_job_kind(registrar::RegistrarJobKind::Moderation(
+      registrar::moderation::DeviceModerationRequest {
+        device_id,
+        change: registrar::moderation::DeviceModerationChange::Hold(entry),
+      },
+    ))
+    .await?;
+
+  tide::Body::from_json(&QueueResponse { id }).map(|body| tide::Response::builder(202).body(body).build())
+}
+
 /// Route: message
 ///
 /// Sends a message to the device.
@@ -102,56 +309,28 @@ pub async fn queue(mut request: tide::Request<super::worker::Worker>) -> tide::R
         .to_string();
 
       // TODO: borrow scoping...
-      {
+      let verdict = {
         let worker = request.state();
-        if worker.user_access(&user.oid, &device_id).await?.is_none() {
-          return Err(tide::Error::from_str(404, "not-found"));
-        }
-      }
+        let record = worker
+          .require_device_action(&user.oid, &device_id, registrar::DeviceAction::SendContent)
+          .await?;
+        let content = registrar::moderation::ScreenedContent::Opaque;
+        moderate(worker, record.as_ref(), &device_id, &user.oid, content).await?
+      };
 
-      let size = request.len().ok_or_else(|| {
-        log::warn!("unable to determine image size from upload");
-        tide::Error::from_str(422, "missing image upload size")
-      })?;
+      let storage_dest = std::path::PathBuf::from(&request.state().web_configuration.temp_file_storage);
+      let location = write_upload(&mut request, storage_dest, image_kind)
+        .await?
+        .to_string_lossy()
+        .to_string();
 
-      if (size as u32) > MAX_FILE_SIZE {
-        log::warn!("invalid image upload size: '{size}'");
-        return Err(tide::Error::from_str(422, "image too large"));
+      if verdict == registrar::moderation::ModerationVerdict::Hold {
+        let content = schema::DevicePendingContent::Image { location };
+        return hold(request.state(), device_id, &user, content).await;
       }
 
-      log::debug!("has image upload for device queue '{device_id}' of {size} bytes");
-      let mut bytes = request.take_body();
-      let mut storage_dest = std::path::PathBuf::new();
-      storage_dest.push(&request.state().web_configuration.temp_file_storage);
-      async_std::fs::create_dir_all(&storage_dest).await.map_err(|error| {
-        log::error!("unable to ensure temporary file storage dir exists - {error}");
-        tide::Error::from_str(500, "bad")
-      })?;
-      let file_name = uuid::Uuid::new_v4().to_string();
-
-      storage_dest.push(&file_name);
-      storage_dest.set_extension(if image_kind == "image/jpeg" { "jpg" } else { "png" });
-
-      log::info!("writing temporary file to '{storage_dest:?}");
-      let mut file = async_std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(&storage_dest)
-        .await
-        .map_err(|error| {
-          log::error!("unable to create temporary file for upload - {error}");
-          tide::Error::from_str(500, "bad")
-        })?;
-
-      async_std::io::copy(&mut bytes, &mut file).await.map_err(|error| {
-        log::error!("unable to copy file upload - {error}");
-        tide::Error::from_str(500, "bad")
-      })?;
-
-      let job = registrar::RegistrarJobKind::Renders(registrar::jobs::RegistrarRenderKinds::SendImage {
-        location: storage_dest.to_string_lossy().to_string(),
-        device_id,
-      });
+      let job =
+        registrar::RegistrarJobKind::Renders(registrar::jobs::RegistrarRenderKinds::SendImage { location, device_id });
       let worker = request.state();
       let id = worker.queue_job_kind(job).await?;
 
@@ -168,13 +347,22 @@ pub async fn queue(mut request: tide::Request<super::worker::Worker>) -> tide::R
   })?;
 
   let worker = request.state();
-  if worker.user_access(&user.oid, &queue_payload.device_id).await?.is_none() {
-    log::warn!("'{}' has no access to device '{}'", user.oid, queue_payload.device_id);
-    return Err(tide::Error::from_str(400, "not-found"));
-  }
+  let authority_record = worker
+    .require_device_action(&user.oid, &queue_payload.device_id, queue_payload.kind.action())
+    .await?;
 
   log::info!("queue payload request received - {queue_payload:?}");
 
+  if let QueuePayloadKind::Clock(Some(schema::DeviceRenderingStateClock {
+    format: Some(format), ..
+  })) = &queue_payload.kind
+  {
+    if let Err(error) = crate::rendering::components::check_clock_format(format) {
+      log::warn!("rejecting clock for device '{}' - {error}", queue_payload.device_id);
+      return Err(tide::Error::from_str(422, "bad-format"));
+    }
+  }
+
   let device_id = queue_payload.device_id.clone();
 
   log::info!(
@@ -183,6 +371,20 @@ pub async fn queue(mut request: tide::Request<super::worker::Worker>) -> tide::R
     queue_payload.kind
   );
 
+  let held_content = queue_payload.kind.held_content();
+  let screened = held_content
+    .as_ref()
+    .map(registrar::moderation::screened)
+    .unwrap_or(registrar::moderation::ScreenedContent::Nothing);
+
+  let verdict = moderate(worker, authority_record.as_ref(), &device_id, &user.oid, screened).await?;
+
+  // Content held for approval is not sent along; it waits in the device state until the owner
+  // approves it through the moderation api.
+  if let (registrar::moderation::ModerationVerdict::Hold, Some(content)) = (verdict, held_content) {
+    return hold(worker, device_id, &user, content).await;
+  }
+
   let layout = match queue_payload.kind {
     kind @ QueuePayloadKind::MakePublic | kind @ QueuePayloadKind::MakePrivate => {
       let privacy = match kind {
@@ -256,11 +458,7 @@ pub async fn queue(mut request: tide::Request<super::worker::Worker>) -> tide::R
 
     // Attempt to transition the device rendering state, which will cause a re-render.
     QueuePayloadKind::Message(message) => {
-      let origin = user
-        .nickname
-        .or(user.name)
-        .map(|name| schema::DeviceStateMessageOrigin::User { nickname: name })
-        .unwrap_or_else(|| schema::DeviceStateMessageOrigin::Unknown);
+      let origin = user_origin(&user);
 
       let id = worker
         .queue_job_kind(registrar::RegistrarJobKind::MutateDeviceState(
@@ -274,6 +472,75 @@ pub async fn queue(mut request: tide::Request<super::worker::Worker>) -> tide::R
       return tide::Body::from_json(&QueueResponse { id }).map(|body| tide::Response::builder(200).body(body).build());
     }
 
+    QueuePayloadKind::Slideshow(interval_seconds) => {
+      let id = worker
+        .queue_job_kind(registrar::RegistrarJobKind::MutateDeviceState(
+          registrar::device_state::DeviceStateTransitionRequest {
+            device_id,
+            transition: registrar::device_state::DeviceStateTransition::StartSlideshow(interval_seconds),
+          },
+        ))
+        .await?;
+
+      return tide::Body::from_json(&QueueResponse { id }).map(|body| tide::Response::builder(200).body(body).build());
+    }
+
+    // Checklist changes are all device state transitions, which will cause a re-render.
+    kind @ QueuePayloadKind::ChecklistAdd(_)
+    | kind @ QueuePayloadKind::ChecklistCheck(_)
+    | kind @ QueuePayloadKind::ChecklistUncheck(_)
+    | kind @ QueuePayloadKind::ChecklistMove { .. }
+    | kind @ QueuePayloadKind::ChecklistRemove(_) => {
+      let transition = match kind {
+        QueuePayloadKind::ChecklistAdd(content) => {
+          registrar::device_state::DeviceStateTransition::AddChecklistItem(content, user_origin(&user))
+        }
+        QueuePayloadKind::ChecklistCheck(id) => {
+          registrar::device_state::DeviceStateTransition::SetChecklistItemChecked(id, true)
+        }
+        QueuePayloadKind::ChecklistUncheck(id) => {
+          registrar::device_state::DeviceStateTransition::SetChecklistItemChecked(id, false)
+        }
+        QueuePayloadKind::ChecklistMove { id, index } => {
+          registrar::device_state::DeviceStateTransition::MoveChecklistItem(id, index)
+        }
+        QueuePayloadKind::ChecklistRemove(id) => {
+          registrar::device_state::DeviceStateTransition::RemoveChecklistItem(id)
+        }
+        _ => return Ok(tide::Error::from_str(422, "bad transition").into()),
+      };
+
+      let id = worker
+        .queue_job_kind(registrar::RegistrarJobKind::MutateDeviceState(
+          registrar::device_state::DeviceStateTransitionRequest { device_id, transition },
+        ))
+        .await?;
+
+      return tide::Body::from_json(&QueueResponse { id }).map(|body| tide::Response::builder(200).body(body).build());
+    }
+
+    // Timekeeping changes are all device state transitions, which will cause a re-render.
+    kind @ QueuePayloadKind::Clock(_)
+    | kind @ QueuePayloadKind::Countdown(_)
+    | kind @ QueuePayloadKind::RemoveCountdown(_) => {
+      let transition = match kind {
+        QueuePayloadKind::Clock(clock) => registrar::device_state::DeviceStateTransition::SetClock(clock),
+        QueuePayloadKind::Countdown(entry) => registrar::device_state::DeviceStateTransition::PushCountdown(entry),
+        QueuePayloadKind::RemoveCountdown(label) => {
+          registrar::device_state::DeviceStateTransition::RemoveCountdown(label)
+        }
+        _ => return Ok(tide::Error::from_str(422, "bad transition").into()),
+      };
+
+      let id = worker
+        .queue_job_kind(registrar::RegistrarJobKind::MutateDeviceState(
+          registrar::device_state::DeviceStateTransitionRequest { device_id, transition },
+        ))
+        .await?;
+
+      return tide::Body::from_json(&QueueResponse { id }).map(|body| tide::Response::builder(200).body(body).build());
+    }
+
     // TODO: these variants need to go through the device state transition flow; they are currently
     // being written directly to the device render queue here.
     QueuePayloadKind::Lights(true) => crate::rendering::RenderVariant::on(),
@@ -346,3 +613,92 @@ pub async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::QueuePayloadKind;
+  use crate::registrar::AccessLevel;
+
+  #[test]
+  fn test_payload_permissions() {
+    let levels = [
+      AccessLevel::Viewer,
+      AccessLevel::Contributor,
+      AccessLevel::Manager,
+      AccessLevel::Owner,
+    ];
+
+    let content = [false, true, true, true];
+    let management = [false, false, true, true];
+    let ownership = [false, false, false, true];
+
+    let payloads = [
+      (QueuePayloadKind::Lights(true), content),
+      (QueuePayloadKind::Message("hi".into()), content),
+      (QueuePayloadKind::Clock(None), content),
+      (
+        QueuePayloadKind::Countdown(crate::schema::DeviceRenderingStateCountdownEntry {
+          label: "launch".into(),
+          target: chrono::Utc::now(),
+          granularity: None,
+        }),
+        content,
+      ),
+      (QueuePayloadKind::RemoveCountdown("launch".into()), content),
+      (QueuePayloadKind::ChecklistAdd("milk".into()), content),
+      (QueuePayloadKind::ChecklistCheck("id".into()), content),
+      (QueuePayloadKind::ChecklistUncheck("id".into()), content),
+      (
+        QueuePayloadKind::ChecklistMove {
+          id: "id".into(),
+          index: 0,
+        },
+        content,
+      ),
+      (QueuePayloadKind::ChecklistRemove("id".into()), content),
+      (QueuePayloadKind::Slideshow(60), content),
+      (QueuePayloadKind::Link("https://example.com".into()), content),
+      (QueuePayloadKind::Refresh, content),
+      (QueuePayloadKind::Schedule(true), management),
+      (QueuePayloadKind::Rename("kitchen".into()), management),
+      (QueuePayloadKind::ClearRender, management),
+      (QueuePayloadKind::Registration, management),
+      (QueuePayloadKind::MakePublic, ownership),
+      (QueuePayloadKind::MakePrivate, ownership),
+    ];
+
+    for (payload, expected) in payloads {
+      for (level, allowed) in levels.iter().zip(expected) {
+        assert_eq!(level.allows(payload.action()), allowed, "{level:?} sending {payload:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_payload_held_content() {
+    let displayed = [
+      QueuePayloadKind::Message("hi".into()),
+      QueuePayloadKind::ChecklistAdd("milk".into()),
+      QueuePayloadKind::Clock(Some(Default::default())),
+      QueuePayloadKind::Countdown(crate::schema::DeviceRenderingStateCountdownEntry {
+        label: "launch".into(),
+        target: chrono::Utc::now(),
+        granularity: None,
+      }),
+      QueuePayloadKind::Link("https://example.com".into()),
+    ];
+    for payload in displayed {
+      assert!(payload.held_content().is_some(), "{payload:?}");
+    }
+
+    let hidden = [
+      QueuePayloadKind::Clock(None),
+      QueuePayloadKind::RemoveCountdown("launch".into()),
+      QueuePayloadKind::ChecklistCheck("id".into()),
+      QueuePayloadKind::Refresh,
+    ];
+    for payload in hidden {
+      assert!(payload.held_content().is_none(), "{payload:?}");
+    }
+  }
+}
@@ -0,0 +1,69 @@
+//! Defines the routes that help with authoring layouts: the JSON Schema of our layout types, and a
+//! route that checks a layout document without sending it anywhere.
+
+use serde::Deserialize;
+
+/// The query of the schema route.
+#[derive(Debug, Deserialize)]
+struct SchemaQuery {
+  /// Which type to return the schema of. Defaults to `render_layout`.
+  kind: Option<crate::rendering::json_schema::SchemaRoot>,
+}
+
+/// The query of the validation route.
+#[derive(Debug, Deserialize)]
+struct ValidationQuery {
+  /// The width to check against. Defaults to the width of our devices.
+  width: Option<u32>,
+
+  /// The height to check against. Defaults to the height of our devices.
+  height: Option<u32>,
+}
+
+/// Route: layouts/schema
+///
+/// Returns the JSON Schema of one of our layout types.
+pub(super) async fn schema(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let query = request.query::<SchemaQuery>().map_err(|error| {
+    log::warn!("bad layout schema query - {error}");
+    tide::Error::from_str(422, "bad-kind")
+  })?;
+  let root = query
+    .kind
+    .unwrap_or(crate::rendering::json_schema::SchemaRoot::RenderLayout);
+
+  tide::Body::from_json(&crate::rendering::json_schema::document(root))
+    .map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: layouts/validate
+///
+/// Checks a `RenderLayout` document, returning every problem found along with any text that would
+/// be truncated and elements that would not fit on the device.
+pub(super) async fn validate(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let query = request.query::<ValidationQuery>()?;
+  let document = request.body_json::<serde_json::Value>().await.map_err(|error| {
+    log::warn!("bad layout validation payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let dimensions = (
+    query.width.unwrap_or(crate::rendering::DEVICE_DIMENSIONS.0),
+    query.height.unwrap_or(crate::rendering::DEVICE_DIMENSIONS.1),
+  );
+  let report = crate::rendering::validation::report(&document, dimensions);
+  log::info!(
+    "user '{}' validated a layout ({} errors, {} truncated, {} overflows)",
+    user.oid,
+    report.errors.len(),
+    report.truncated.len(),
+    report.overflows.len()
+  );
+
+  tide::Body::from_json(&report).map(|body| tide::Response::builder(200).body(body).build())
+}
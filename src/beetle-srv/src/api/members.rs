@@ -0,0 +1,263 @@
+//! Defines the routes used to invite other users to a device, accept or decline those invites, and
+//! manage the guests of a device.
+
+use crate::{registrar, schema};
+use async_std::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+
+/// The default amount of hours an invite can be accepted for.
+const DEFAULT_INVITE_HOURS: u32 = 72;
+
+/// The longest amount of hours an invite can be accepted for.
+const MAX_INVITE_HOURS: u32 = 24 * 14;
+
+/// The payload for looking up a device by id.
+#[derive(Debug, Deserialize)]
+struct LookupQuery {
+  /// The id of a device in question.
+  id: String,
+}
+
+/// The changes that can be made to the members of a device.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+enum MembershipPayloadKind {
+  /// Creates an invite.
+  Invite {
+    /// The role given to whoever accepts.
+    #[serde(default)]
+    role: schema::DeviceGuestRole,
+    /// How long the invite can be accepted for.
+    expires_in_hours: Option<u32>,
+  },
+  /// Removes an outstanding invite.
+  RevokeInvite(String),
+  /// Removes a guest.
+  Remove(String),
+  /// Removes the current user from the device.
+  Leave,
+}
+
+/// The api used to manage the members of a device.
+#[derive(Debug, Deserialize)]
+struct MembershipPayload {
+  /// The id of the device.
+  device_id: String,
+  /// The change being made.
+  kind: MembershipPayloadKind,
+}
+
+/// The payload used to accept or decline an invite.
+#[derive(Debug, Deserialize)]
+struct InvitePayload {
+  /// The code of the invite.
+  code: String,
+}
+
+/// The schema of responses sent after queuing a membership change.
+#[derive(Debug, Serialize)]
+struct MembershipChangeResponse {
+  /// The id of the job queued.
+  id: String,
+  /// The code of a newly created invite.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  code: Option<String>,
+}
+
+/// A single member, as it is exposed from the api.
+#[derive(Debug, Serialize)]
+struct MemberResponse {
+  /// The id of the user.
+  id: String,
+  /// The name of the user, if we know it.
+  name: Option<String>,
+  /// The access level of the user.
+  level: registrar::AccessLevel,
+}
+
+/// The schema of responses sent from the member lookup api.
+#[derive(Debug, Serialize)]
+struct MembersResponse {
+  /// The owner and guests of the device.
+  members: Vec<MemberResponse>,
+  /// Outstanding invites; these are only visible to the owner.
+  invites: Vec<schema::DeviceInvite>,
+}
+
+/// Returns the current user, failing with a 404 when there is none.
+async fn current_user(request: &tide::Request<super::worker::Worker>) -> tide::Result<schema::User> {
+  request.state().request_authority(request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })
+}
+
+/// Queues the membership change, responding with the id of the job.
+async fn queue_change(
+  worker: &super::worker::Worker,
+  change: registrar::membership::DeviceMembershipChange,
+  code: Option<String>,
+) -> tide::Result {
+  let id = worker
+    .queue_job_kind(registrar::RegistrarJobKind::Membership(change))
+    .await?;
+
+  tide::Body::from_json(&MembershipChangeResponse { id, code })
+    .map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-members
+///
+/// Returns the owner and guests of a device. Only managers and the owner can see who else has
+/// access to a device.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = current_user(&request).await?;
+  let query = request.query::<LookupQuery>()?;
+
+  let record = worker
+    .require_device_action(&user.oid, &query.id, registrar::DeviceAction::ViewMembers)
+    .await?
+    .ok_or_else(|| tide::Error::from_str(404, "not-found"))?;
+
+  let mut levels = match record.authority_model {
+    Some(schema::DeviceAuthorityModel::Exclusive { owner }) => vec![(owner, registrar::AccessLevel::Owner)],
+    Some(schema::DeviceAuthorityModel::Shared { owner, guests, roles })
+    | Some(schema::DeviceAuthorityModel::Public { owner, guests, roles }) => {
+      let mut levels = vec![(owner, registrar::AccessLevel::Owner)];
+      levels.extend(guests.into_iter().map(|guest| {
+        let level = roles.get(&guest).copied().unwrap_or_default().into();
+        (guest, level)
+      }));
+      levels
+    }
+    None => vec![],
+  };
+  levels.dedup_by(|(a, _), (b, _)| a == b);
+
+  let is_owner = levels.first().map(|(owner, _)| owner == &user.oid).unwrap_or_default();
+
+  let ids = levels.iter().map(|(id, _)| id.clone()).collect::<Vec<String>>();
+  let mut names = std::collections::HashMap::with_capacity(ids.len());
+  let mut cursor = worker
+    .users_collection()?
+    .find(bson::doc! { "oid": { "$in": ids } }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load members for '{}' - {error}", query.id);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  while let Some(member) = cursor.next().await {
+    match member {
+      Ok(member) => {
+        names.insert(member.oid, member.nickname.or(member.name));
+      }
+      Err(error) => log::warn!("unable to parse member for '{}' - {error}", query.id),
+    }
+  }
+
+  let members = levels
+    .into_iter()
+    .map(|(id, level)| MemberResponse {
+      name: names.remove(&id).flatten(),
+      id,
+      level,
+    })
+    .collect();
+
+  let now = chrono::Utc::now();
+  let invites = match is_owner {
+    true => record
+      .invites
+      .into_iter()
+      .filter(|invite| invite.expires_at > now)
+      .collect(),
+    false => vec![],
+  };
+
+  tide::Body::from_json(&MembersResponse { members, invites })
+    .map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-members
+///
+/// Queues a job to invite, remove or revoke; or for the current user to leave the device.
+pub(super) async fn update(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<MembershipPayload>().await.map_err(|error| {
+    log::warn!("bad membership payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let user = current_user(&request).await?;
+  let worker = request.state();
+  let device_id = payload.device_id;
+
+  let action = match payload.kind {
+    MembershipPayloadKind::Leave => registrar::DeviceAction::View,
+    _ => registrar::DeviceAction::ManageMembers,
+  };
+  worker.require_device_action(&user.oid, &device_id, action).await?;
+
+  match payload.kind {
+    MembershipPayloadKind::Invite { role, expires_in_hours } => {
+      let hours = expires_in_hours
+        .unwrap_or(DEFAULT_INVITE_HOURS)
+        .clamp(1, MAX_INVITE_HOURS);
+      let code = crate::identity::create();
+      let invite = schema::DeviceInvite {
+        code: code.clone(),
+        role,
+        created_by: user.oid,
+        expires_at: chrono::Utc::now() + chrono::Duration::hours(hours.into()),
+      };
+      let change = registrar::membership::DeviceMembershipChange::CreateInvite { device_id, invite };
+      queue_change(worker, change, Some(code)).await
+    }
+    MembershipPayloadKind::RevokeInvite(code) => {
+      let change = registrar::membership::DeviceMembershipChange::RevokeInvite { device_id, code };
+      queue_change(worker, change, None).await
+    }
+    MembershipPayloadKind::Remove(user_id) => {
+      let change = registrar::membership::DeviceMembershipChange::RemoveMember { device_id, user_id };
+      queue_change(worker, change, None).await
+    }
+    MembershipPayloadKind::Leave => {
+      let change = registrar::membership::DeviceMembershipChange::RemoveMember {
+        device_id,
+        user_id: user.oid,
+      };
+      queue_change(worker, change, None).await
+    }
+  }
+}
+
+/// Route: device-invites/accept
+///
+/// Queues a job to add the current user as a guest on the device the invite was created for.
+pub(super) async fn accept(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<InvitePayload>().await.map_err(|error| {
+    log::warn!("bad invite payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let user = current_user(&request).await?;
+
+  let change = registrar::membership::DeviceMembershipChange::AcceptInvite {
+    code: payload.code,
+    user_id: user.oid,
+  };
+  queue_change(request.state(), change, None).await
+}
+
+/// Route: device-invites/decline
+///
+/// Queues a job to remove the invite.
+pub(super) async fn decline(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<InvitePayload>().await.map_err(|error| {
+    log::warn!("bad invite payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  current_user(&request).await?;
+
+  let change = registrar::membership::DeviceMembershipChange::DeclineInvite { code: payload.code };
+  queue_change(request.state(), change, None).await
+}
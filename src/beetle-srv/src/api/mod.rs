@@ -21,6 +21,39 @@ mod worker;
 /// Routes related to the job result store;
 mod jobs;
 
+/// Routes for managing the photos retained for a device.
+mod albums;
+
+/// Routes used by owners to moderate content sent to their public devices.
+mod moderation;
+
+/// Routes for inviting other users to a device and managing its guests.
+mod members;
+
+/// Routes for handing a device over to a new owner.
+mod transfers;
+
+/// Routes for notification preferences and device connectivity events.
+mod alerts;
+
+/// Routes for registering webhooks and looking through their deliveries.
+mod webhooks;
+
+/// Routes for managing and using the inbound hooks of a device.
+mod hooks;
+
+/// Routes for managing the personal access tokens of a user.
+mod tokens;
+
+/// The server-side session store, and the routes for managing sessions.
+mod sessions;
+
+/// Layout templates saved by users.
+mod templates;
+
+/// Routes that help with authoring layouts.
+mod layouts;
+
 pub use worker::Worker;
 
 /// These configuration definitions makes it easy for the web binary to
@@ -32,6 +65,11 @@ pub use worker::Worker;
 pub struct WebConfiguration {
   /// The location on disc where files should be saved temporarily.
   temp_file_storage: String,
+  /// The location on disc where album photos are retained. Defaults to an `albums` directory
+  /// inside the temporary file storage.
+  album_file_storage: Option<String>,
+  /// Moderation settings for content sent to `Public` devices.
+  moderation: Option<crate::config::ModerationConfiguration>,
   /// The domain to associated cookies with.
   cookie_domain: String,
   /// Where to send folks after the Oauth handshake has completed.
@@ -40,6 +78,9 @@ pub struct WebConfiguration {
   session_secret: String,
   /// The name of our cookie which will house our JWT token.
   session_cookie: String,
+  /// OpenID Connect providers users can log in with, in addition to google.
+  #[serde(default)]
+  oidc_providers: Vec<crate::config::OidcProviderConfiguration>,
 }
 
 /// The web worker configuration.
@@ -95,11 +136,22 @@ pub fn new(worker: worker::Worker) -> tide::Server<worker::Worker> {
   app.at("/auth/g/redirect").get(auth::google::redirect);
   app.at("/auth/g/complete").get(auth::google::complete);
 
+  app.at("/auth/oidc").get(auth::oidc::providers);
+  app.at("/auth/oidc/:provider/redirect").get(auth::oidc::redirect);
+  app.at("/auth/oidc/:provider/complete").get(auth::oidc::complete);
+
   app.at("/auth/identify").get(auth::identify);
   app.at("/auth/logout").get(auth::logout);
+  app.at("/auth/refresh").post(auth::refresh);
+  app.at("/auth/sessions").get(sessions::find);
+  app.at("/auth/sessions/revoke").post(sessions::revoke_one);
+  app.at("/auth/sessions/revoke-all").post(sessions::revoke_all);
+  app.at("/auth/tokens").get(tokens::find).post(tokens::create);
+  app.at("/auth/tokens/revoke").post(tokens::revoke);
 
   app.at("/devices/register").post(devices::register);
   app.at("/devices/unregister").post(devices::unregister);
+  app.at("/devices/decommission").post(devices::decommission);
   app.at("/device-info").get(devices::info);
   app.at("/device-authority").get(devices::authority);
 
@@ -113,8 +165,41 @@ pub fn new(worker: worker::Worker) -> tide::Server<worker::Worker> {
   // this should deprecate the non-scoped route, or make file uploading better.
   app.at("/device-queue/:device_id").post(jobs::queue);
 
+  app.at("/device-album/:device_id").get(albums::find).post(albums::add);
+  app.at("/device-album/:device_id/remove").post(albums::remove);
+  app.at("/device-album/:device_id/move").post(albums::reorder);
+
+  app
+    .at("/device-moderation")
+    .get(moderation::find)
+    .post(moderation::update);
+
+  app.at("/device-members").get(members::find).post(members::update);
+  app.at("/device-invites/accept").post(members::accept);
+  app.at("/device-invites/decline").post(members::decline);
+
+  app.at("/device-transfers").get(transfers::find).post(transfers::update);
+
+  app.at("/alert-preferences").get(alerts::find).post(alerts::update);
+  app.at("/device-events").get(alerts::events);
+
+  app.at("/webhooks").get(webhooks::find).post(webhooks::create);
+  app.at("/webhooks/delete").post(webhooks::delete);
+  app.at("/webhook-deliveries").get(webhooks::deliveries);
+
+  app.at("/device-hooks").get(hooks::find).post(hooks::create);
+  app.at("/device-hooks/revoke").post(hooks::revoke);
+  app.at("/hooks/:token").post(hooks::send);
+
+  app.at("/layouts/schema").get(layouts::schema);
+  app.at("/layouts/validate").post(layouts::validate);
+
+  app.at("/layout-templates").get(templates::find).post(templates::save);
+  app.at("/layout-templates/delete").post(templates::delete);
+  app.at("/layout-templates/render").post(templates::render);
+
   app.at("/jobs").get(jobs::find);
-  app.at("/device-schedules").get(schedules::find);
+  app.at("/device-schedules").get(schedules::find).post(schedules::update);
 
   app.at("/status").get(heartbeat);
   app.at("/*").all(missing);
@@ -0,0 +1,144 @@
+//! Defines the routes used by device owners (and managers) to moderate what other users send to
+//! their `Public` devices.
+
+use crate::{registrar, schema};
+use serde::{Deserialize, Serialize};
+
+/// The payload for looking up a device by id.
+#[derive(Debug, Deserialize)]
+struct LookupQuery {
+  /// The id of a device in question.
+  id: String,
+}
+
+/// The changes an owner can make through the api.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+enum ModerationPayloadKind {
+  /// Approves a pending entry.
+  Approve(String),
+  /// Rejects a pending entry.
+  Reject(String),
+  /// Adds a user to the sender blocklist.
+  BlockSender(String),
+  /// Removes a user from the sender blocklist.
+  UnblockSender(String),
+  /// Turns the approval queue on or off.
+  RequireApproval(bool),
+}
+
+/// The api used to moderate a device.
+#[derive(Debug, Deserialize)]
+struct ModerationPayload {
+  /// The id of the device.
+  device_id: String,
+  /// The change being made.
+  kind: ModerationPayloadKind,
+}
+
+/// The schema of responses sent after queuing a moderation change.
+#[derive(Debug, Serialize)]
+struct ModerationChangeResponse {
+  /// The id of the job queued.
+  id: String,
+}
+
+/// The schema of responses sent from the moderation lookup api.
+#[derive(Debug, Serialize)]
+struct ModerationResponse {
+  /// The current settings.
+  settings: schema::DeviceModerationSettings,
+  /// Everything waiting on approval.
+  pending: Vec<schema::DevicePendingEntry>,
+}
+
+/// Strips held uploads of where they live on disc, leaving only the file name.
+fn redacted(mut entry: schema::DevicePendingEntry) -> schema::DevicePendingEntry {
+  if let schema::DevicePendingContent::Image { location } | schema::DevicePendingContent::AlbumPhoto { location, .. } =
+    &mut entry.content
+  {
+    *location = std::path::Path::new(location.as_str())
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+  }
+  entry
+}
+
+/// Returns the authority record of the device, provided the current user is allowed to moderate it.
+async fn moderated_record(
+  request: &tide::Request<super::worker::Worker>,
+  device_id: &String,
+) -> tide::Result<schema::DeviceAuthorityRecord> {
+  let worker = request.state();
+  let user = worker.request_authority(request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  worker
+    .require_device_action(&user.oid, device_id, registrar::DeviceAction::Moderate)
+    .await?
+    .ok_or_else(|| tide::Error::from_str(404, "not-found"))
+}
+
+/// Route: device-moderation
+///
+/// Returns the moderation settings and pending entries for a device.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let query = request.query::<LookupQuery>()?;
+  let record = moderated_record(&request, &query.id).await?;
+
+  let pending = request
+    .state()
+    .device_state_collection()?
+    .find_one(bson::doc! { "device_id": &query.id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load device state for '{}' moderation - {error}", query.id);
+      tide::Error::from_str(500, "bad-lookup")
+    })?
+    .map(|state| state.pending.into_iter().map(redacted).collect())
+    .unwrap_or_default();
+
+  let response = ModerationResponse {
+    settings: record.moderation,
+    pending,
+  };
+  tide::Body::from_json(&response).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-moderation
+///
+/// Queues a job to apply a moderation change for a device.
+pub(super) async fn update(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<ModerationPayload>().await.map_err(|error| {
+    log::warn!("bad moderation payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  moderated_record(&request, &payload.device_id).await?;
+
+  let change = match payload.kind {
+    ModerationPayloadKind::Approve(id) => registrar::moderation::DeviceModerationChange::Approve(id),
+    ModerationPayloadKind::Reject(id) => registrar::moderation::DeviceModerationChange::Reject(id),
+    ModerationPayloadKind::BlockSender(user_id) => registrar::moderation::DeviceModerationChange::BlockSender(user_id),
+    ModerationPayloadKind::UnblockSender(user_id) => {
+      registrar::moderation::DeviceModerationChange::UnblockSender(user_id)
+    }
+    ModerationPayloadKind::RequireApproval(require) => {
+      registrar::moderation::DeviceModerationChange::RequireApproval(require)
+    }
+  };
+
+  let id = request
+    .state()
+    .queue_job_kind(registrar::RegistrarJobKind::Moderation(
+      registrar::moderation::DeviceModerationRequest {
+        device_id: payload.device_id,
+        change,
+      },
+    ))
+    .await?;
+
+  tide::Body::from_json(&ModerationChangeResponse { id }).map(|body| tide::Response::builder(200).body(body).build())
+}
@@ -1,6 +1,7 @@
 //! This module defines the api routes for updating a device schedule; this is how the ui will
 //! configure the registrar to periodically render various scheduled things to a given device.
 
+use crate::{registrar, schema};
 use serde::{Deserialize, Serialize};
 
 /// Defines the schema of the url query for our lookup.
@@ -10,12 +11,265 @@ struct DeviceScheduleLookupQuery {
   device_id: String,
 }
 
-/// Requests a lookup for a schedule based on a device id.
-pub(super) async fn find(request: tide::Request<super::Worker>) -> tide::Result {
-  super::Worker::require_authority(&request).await?;
+/// The kinds of schedules users can pick from the api. These are resolved against the current
+/// user before they are handed to the registrar.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+enum SchedulePayloadKind {
+  /// Renders the calendar events of the current user.
+  UserEventsBasic,
+}
+
+/// The things schedule entries created through the api can do. These are resolved against the
+/// current user before they are handed to the registrar.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+enum ScheduleEntryPayloadAction {
+  /// Renders a layout.
+  RenderLayout(crate::rendering::RenderLayout<String>),
+
+  /// Adds a message from the current user to the device state.
+  Message(String),
+
+  /// Turns the lights on or off.
+  Lights(bool),
+
+  /// Renders the calendar events of the current user.
+  UserEvents,
+
+  /// Fills in and renders a layout template of the current user.
+  RenderTemplate {
+    /// The id of the template.
+    template_id: String,
+    /// The values of the placeholders in the template.
+    #[serde(default)]
+    variables: std::collections::HashMap<String, String>,
+  },
+}
+
+/// The changes users can make to a schedule through the api.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+enum SchedulePayloadChange {
+  /// Sets what the schedule does; `None` stops it from rendering anything.
+  SetKind(Option<SchedulePayloadKind>),
+
+  /// Sets how often the schedule is run, in seconds; `None` goes back to the default.
+  SetRefreshInterval(Option<u32>),
 
+  /// Runs the schedule right away.
+  Run,
+
+  /// Adds an entry that fires whenever the cron expression matches.
+  AddEntry {
+    /// A five-field cron expression.
+    cron: String,
+    /// The IANA timezone the expression is evaluated in; defaults to utc.
+    timezone: Option<String>,
+    /// What happens when the entry fires.
+    action: Box<ScheduleEntryPayloadAction>,
+  },
+
+  /// Removes the entry with the provided id.
+  RemoveEntry(String),
+
+  /// Removes the schedule entirely.
+  Delete,
+}
+
+/// The api used to change the schedule of a device.
+#[derive(Debug, Deserialize)]
+struct SchedulePayload {
+  /// The id of the device.
+  device_id: String,
+  /// The change being made.
+  change: SchedulePayloadChange,
+}
+
+/// The schema of responses sent after queuing a schedule change.
+#[derive(Debug, Serialize)]
+struct ScheduleChangeResponse {
+  /// The id of the job queued.
+  id: String,
+}
+
+/// The schema of responses sent from the schedule lookup api.
+#[derive(Debug, Serialize)]
+struct ScheduleResponse {
+  /// The id of the device.
+  device_id: String,
+  /// What the schedule does, if anything.
+  kind: Option<schema::DeviceScheduleKind>,
+  /// When the schedule was last run, in milliseconds.
+  last_executed: Option<u64>,
+  /// How often the schedule is run, in seconds, if it differs from the default.
+  refresh_interval_seconds: Option<u32>,
+  /// The entries that fire on their own cron schedule.
+  entries: Vec<schema::DeviceScheduleEntry>,
+  /// When anything on the schedule is due next, in milliseconds.
+  next_run_at: Option<i64>,
+}
+
+/// Route: device-schedules
+///
+/// Returns the schedule of a device. Devices without one are returned with nothing scheduled.
+pub(super) async fn find(request: tide::Request<super::Worker>) -> tide::Result {
   let query = request.query::<DeviceScheduleLookupQuery>()?;
-  log::info!("requesting device schedule - '{query:?}'");
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  worker
+    .require_device_action(&user.oid, &query.device_id, registrar::DeviceAction::View)
+    .await?;
+
+  let schedule = worker
+    .device_schedule_collection()?
+    .find_one(bson::doc! { "device_id": &query.device_id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load device schedule for '{}' - {error}", query.device_id);
+      tide::Error::from_str(500, "bad-lookup")
+    })?
+    .unwrap_or_default();
+
+  let response = ScheduleResponse {
+    device_id: query.device_id,
+    kind: schedule.kind,
+    last_executed: schedule.last_executed,
+    refresh_interval_seconds: schedule.refresh_interval_seconds,
+    entries: schedule.entries,
+    next_run_at: schedule.next_run_at,
+  };
+  tide::Body::from_json(&response).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-schedules
+///
+/// Queues a job to change, run or remove the schedule of a device.
+pub(super) async fn update(mut request: tide::Request<super::Worker>) -> tide::Result {
+  let payload = request.body_json::<SchedulePayload>().await.map_err(|error| {
+    log::warn!("bad schedule payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  worker
+    .require_device_action(&user.oid, &payload.device_id, registrar::DeviceAction::ToggleSchedule)
+    .await?;
+
+  let device_id = payload.device_id;
+  let change = match payload.change {
+    SchedulePayloadChange::Run => {
+      log::info!("user '{}' running schedule for '{device_id}'", user.oid);
+      let id = worker
+        .queue_job_kind(registrar::RegistrarJobKind::RunDeviceSchedule {
+          device_id,
+          refresh_nonce: None,
+        })
+        .await?;
+      return tide::Body::from_json(&ScheduleChangeResponse { id })
+        .map(|body| tide::Response::builder(200).body(body).build());
+    }
+    SchedulePayloadChange::SetKind(kind) => {
+      registrar::device_schedule::DeviceScheduleChange::SetKind(kind.map(|kind| match kind {
+        SchedulePayloadKind::UserEventsBasic => schema::DeviceScheduleKind::UserEventsBasic {
+          user_oid: user.oid.clone(),
+        },
+      }))
+    }
+    SchedulePayloadChange::SetRefreshInterval(Some(seconds))
+      if !(registrar::device_schedule::MIN_REFRESH_INTERVAL_SECONDS
+        ..=registrar::device_schedule::MAX_REFRESH_INTERVAL_SECONDS)
+        .contains(&seconds) =>
+    {
+      log::warn!("invalid schedule refresh interval '{seconds}'");
+      return Err(tide::Error::from_str(422, "bad-interval"));
+    }
+    SchedulePayloadChange::SetRefreshInterval(seconds) => {
+      registrar::device_schedule::DeviceScheduleChange::SetRefreshInterval(seconds)
+    }
+    SchedulePayloadChange::AddEntry { cron, timezone, action } => {
+      if let Err(error) = registrar::cron::CronExpression::parse(&cron) {
+        log::warn!("invalid schedule entry - {error}");
+        return Err(tide::Error::from_str(422, "bad-cron"));
+      }
+
+      if let Some(Err(error)) = timezone.as_deref().map(str::parse::<chrono_tz::Tz>) {
+        log::warn!("invalid schedule entry timezone - {error}");
+        return Err(tide::Error::from_str(422, "bad-timezone"));
+      }
+
+      let action = match *action {
+        ScheduleEntryPayloadAction::RenderLayout(layout) if layout.references_files() => {
+          log::warn!("user '{}' attempted to schedule a layout with files", user.oid);
+          return Err(tide::Error::from_str(422, "bad-layout"));
+        }
+        ScheduleEntryPayloadAction::RenderLayout(layout) => {
+          if let Err(error) = crate::rendering::validation::check(&layout, crate::rendering::DEVICE_DIMENSIONS) {
+            log::warn!("user '{}' attempted to schedule an invalid layout - {error}", user.oid);
+            return Err(tide::Error::from_str(422, "bad-layout"));
+          }
+          schema::DeviceScheduleAction::RenderLayout(layout)
+        }
+        ScheduleEntryPayloadAction::Message(message) => schema::DeviceScheduleAction::PushMessage {
+          message,
+          origin: super::jobs::user_origin(&user),
+        },
+        ScheduleEntryPayloadAction::Lights(on) => schema::DeviceScheduleAction::Lights(on),
+        ScheduleEntryPayloadAction::UserEvents => schema::DeviceScheduleAction::UserEvents {
+          user_oid: user.oid.clone(),
+        },
+        ScheduleEntryPayloadAction::RenderTemplate { template_id, variables } => {
+          // Fill the template now so entries missing variables are rejected up front.
+          super::templates::fill_owned(worker, &user.oid, &template_id, &variables).await?;
+          schema::DeviceScheduleAction::RenderTemplate {
+            user_oid: user.oid.clone(),
+            template_id,
+            variables,
+          }
+        }
+      };
+
+      let existing = worker
+        .device_schedule_collection()?
+        .find_one(bson::doc! { "device_id": &device_id }, None)
+        .await
+        .map_err(|error| {
+          log::warn!("unable to load device schedule for '{device_id}' - {error}");
+          tide::Error::from_str(500, "bad-lookup")
+        })?
+        .map_or(0, |schedule| schedule.entries.len());
+
+      if existing >= registrar::device_schedule::MAX_ENTRIES {
+        return Err(tide::Error::from_str(422, "too-many-entries"));
+      }
+
+      registrar::device_schedule::DeviceScheduleChange::AddEntry(Box::new(schema::DeviceScheduleEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        cron,
+        timezone,
+        action,
+        next_fire_at: None,
+        last_fired_at: None,
+      }))
+    }
+    SchedulePayloadChange::RemoveEntry(id) => registrar::device_schedule::DeviceScheduleChange::RemoveEntry(id),
+    SchedulePayloadChange::Delete => registrar::device_schedule::DeviceScheduleChange::Delete,
+  };
+
+  log::info!("user '{}' changing schedule for '{device_id}' - {change:?}", user.oid);
+  let id = worker
+    .queue_job_kind(registrar::RegistrarJobKind::Schedule(
+      registrar::device_schedule::DeviceScheduleRequest { device_id, change },
+    ))
+    .await?;
 
-  Ok("".into())
+  tide::Body::from_json(&ScheduleChangeResponse { id }).map(|body| tide::Response::builder(200).body(body).build())
 }
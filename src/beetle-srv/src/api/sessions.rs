@@ -0,0 +1,330 @@
+//! Sessions are persisted in redis, with only their id carried in the session cookie. This lets us
+//! revoke them, and lets users see where they are signed in.
+//!
+//! Sessions expire after a day of inactivity, but never last longer than `SESSION_MAX_AGE_DAYS`
+//! regardless of activity. The cookie itself is refreshed through the `auth/refresh` route.
+
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind, Result};
+
+/// How long a session lasts without being used.
+const SESSION_IDLE_SECONDS: i64 = 60 * 60 * 24;
+
+/// How long a session can last, regardless of how often it is used.
+const SESSION_MAX_AGE_DAYS: i64 = 30;
+
+/// How often the last use of a session is written back to redis.
+const SESSION_TOUCH_INTERVAL_SECONDS: i64 = 60 * 5;
+
+/// The longest user agent that is retained on a session.
+const MAX_USER_AGENT_LEN: usize = 200;
+
+/// A session, as it is stored in redis.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(super) struct Session {
+  /// The id of this session, which is held in the cookie.
+  pub(super) id: String,
+
+  /// The id of the user this session belongs to.
+  pub(super) user_id: String,
+
+  /// When the session was created.
+  pub(super) created_at: chrono::DateTime<chrono::Utc>,
+
+  /// When the session was last used.
+  pub(super) last_seen_at: chrono::DateTime<chrono::Utc>,
+
+  /// The user agent the session was created with.
+  pub(super) user_agent: Option<String>,
+}
+
+/// What checking a session against the current time resulted in.
+#[derive(Debug, PartialEq)]
+enum SessionCheck {
+  /// The session is no longer valid.
+  Expired,
+
+  /// The session is valid, but its last use should be written back.
+  Touch,
+
+  /// The session is valid.
+  Valid,
+}
+
+/// A session as it is returned from the api.
+#[derive(Debug, Serialize)]
+struct SessionResponse {
+  /// The session itself.
+  #[serde(flatten)]
+  session: Session,
+
+  /// Whether or not this is the session the request was made with.
+  current: bool,
+}
+
+/// The payload for revoking a session.
+#[derive(Debug, Deserialize)]
+struct RevokePayload {
+  /// The id of the session.
+  id: String,
+}
+
+/// Returns the redis key a session is stored under.
+fn session_key(id: &str) -> String {
+  format!("{}:{id}", crate::constants::SESSION_PREFIX)
+}
+
+/// Returns the redis key holding the ids of every session a user has.
+fn user_sessions_key(user_id: &str) -> String {
+  format!("{}:{user_id}", crate::constants::USER_SESSIONS_PREFIX)
+}
+
+/// Returns whether the session is still valid, and whether its last use is worth writing back.
+fn check(session: &Session, now: chrono::DateTime<chrono::Utc>) -> SessionCheck {
+  if now - session.created_at > chrono::Duration::days(SESSION_MAX_AGE_DAYS)
+    || now - session.last_seen_at > chrono::Duration::seconds(SESSION_IDLE_SECONDS)
+  {
+    return SessionCheck::Expired;
+  }
+
+  match now - session.last_seen_at > chrono::Duration::seconds(SESSION_TOUCH_INTERVAL_SECONDS) {
+    true => SessionCheck::Touch,
+    false => SessionCheck::Valid,
+  }
+}
+
+/// Writes the session to redis, expiring it once it has gone unused for too long.
+async fn store(worker: &super::worker::Worker, session: &Session) -> Result<()> {
+  let serialized = serde_json::to_string(session).map_err(|error| Error::new(ErrorKind::Other, error.to_string()))?;
+
+  worker
+    .command(&kramer::Command::Strings(kramer::StringCommand::Set(
+      kramer::Arity::One((session_key(&session.id), serialized)),
+      Some(std::time::Duration::from_secs(SESSION_IDLE_SECONDS as u64)),
+      kramer::Insertion::Always,
+    )))
+    .await?;
+
+  Ok(())
+}
+
+/// Returns the session stored under the id, if there is one.
+async fn load(worker: &super::worker::Worker, id: &str) -> Result<Option<Session>> {
+  match worker
+    .command(&kramer::Command::Strings::<String, &str>(kramer::StringCommand::Get(
+      kramer::Arity::One(session_key(id)),
+    )))
+    .await?
+  {
+    kramer::Response::Item(kramer::ResponseValue::String(contents)) => serde_json::from_str(&contents)
+      .map(Some)
+      .map_err(|error| Error::new(ErrorKind::Other, format!("bad session - {error}"))),
+    _ => Ok(None),
+  }
+}
+
+/// Creates and stores a new session for the user.
+pub(super) async fn create(worker: &super::worker::Worker, user_id: &str, user_agent: Option<&str>) -> Result<Session> {
+  let now = chrono::Utc::now();
+  let session = Session {
+    id: crate::identity::create_secret(),
+    user_id: user_id.to_string(),
+    created_at: now,
+    last_seen_at: now,
+    user_agent: user_agent.map(|agent| agent.chars().take(MAX_USER_AGENT_LEN).collect()),
+  };
+
+  store(worker, &session).await?;
+  worker
+    .command(&kramer::Command::Sets(kramer::SetCommand::Add(
+      user_sessions_key(user_id),
+      kramer::Arity::One(session.id.as_str()),
+    )))
+    .await?;
+
+  log::info!("created new session for '{user_id}'");
+  Ok(session)
+}
+
+/// Returns the session if it exists, belongs to the user and has not expired. Sessions that are
+/// used are kept alive.
+pub(super) async fn authenticate(worker: &super::worker::Worker, id: &str, user_id: &str) -> Result<Option<Session>> {
+  let Some(mut session) = load(worker, id).await? else {
+    log::warn!("request made with unknown or revoked session");
+    return Ok(None);
+  };
+
+  if session.user_id != user_id {
+    log::warn!("session does not belong to '{user_id}'");
+    return Ok(None);
+  }
+
+  let now = chrono::Utc::now();
+  match check(&session, now) {
+    SessionCheck::Expired => {
+      log::info!("session for '{user_id}' has expired");
+      revoke(worker, user_id, id).await?;
+      return Ok(None);
+    }
+    SessionCheck::Touch => {
+      session.last_seen_at = now;
+      store(worker, &session).await?;
+    }
+    SessionCheck::Valid => (),
+  }
+
+  Ok(Some(session))
+}
+
+/// Removes a session of the user.
+pub(super) async fn revoke(worker: &super::worker::Worker, user_id: &str, id: &str) -> Result<()> {
+  worker
+    .command(&kramer::Command::Del::<String, &str>(kramer::Arity::One(session_key(
+      id,
+    ))))
+    .await?;
+  worker
+    .command(&kramer::Command::Sets(kramer::SetCommand::Rem(
+      user_sessions_key(user_id),
+      kramer::Arity::One(id),
+    )))
+    .await?;
+  Ok(())
+}
+
+/// Returns every active session of the user, cleaning up the ids of those that have expired.
+async fn list(worker: &super::worker::Worker, user_id: &str) -> Result<Vec<Session>> {
+  let ids = match worker
+    .command(&kramer::Command::Sets::<String, &str>(kramer::SetCommand::Members(
+      user_sessions_key(user_id),
+    )))
+    .await?
+  {
+    kramer::Response::Array(values) => values
+      .into_iter()
+      .filter_map(|value| match value {
+        kramer::ResponseValue::String(id) => Some(id),
+        _ => None,
+      })
+      .collect::<Vec<String>>(),
+    _ => vec![],
+  };
+
+  let mut sessions = vec![];
+  for id in ids {
+    match load(worker, &id).await? {
+      Some(session) if check(&session, chrono::Utc::now()) != SessionCheck::Expired => sessions.push(session),
+      _ => revoke(worker, user_id, &id).await?,
+    }
+  }
+
+  sessions.sort_by(|a, b| b.last_seen_at.cmp(&a.last_seen_at));
+  Ok(sessions)
+}
+
+/// Route: auth/sessions
+///
+/// Returns the active sessions of the current user.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+  let current = super::auth::request_claims(&request).and_then(|claims| claims.sid);
+
+  let sessions = list(worker, &user.oid)
+    .await?
+    .into_iter()
+    .map(|session| SessionResponse {
+      current: current.as_deref() == Some(session.id.as_str()),
+      session,
+    })
+    .collect::<Vec<SessionResponse>>();
+
+  tide::Body::from_json(&sessions).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: auth/sessions/revoke
+///
+/// Revokes a single session of the current user.
+pub(super) async fn revoke_one(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<RevokePayload>().await.map_err(|error| {
+    log::warn!("bad session revocation payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let owned = load(worker, &payload.id)
+    .await?
+    .map(|session| session.user_id == user.oid)
+    .unwrap_or_default();
+
+  if !owned {
+    log::warn!("user '{}' attempted to revoke a session that is not theirs", user.oid);
+    return Err(tide::Error::from_str(404, "not-found"));
+  }
+
+  revoke(worker, &user.oid, &payload.id).await?;
+  log::info!("user '{}' revoked a session", user.oid);
+  Ok(tide::Response::builder(200).build())
+}
+
+/// Route: auth/sessions/revoke-all
+///
+/// Revokes every session of the current user, including the one the request was made with.
+pub(super) async fn revoke_all(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let sessions = list(worker, &user.oid).await?;
+  for session in &sessions {
+    revoke(worker, &user.oid, &session.id).await?;
+  }
+
+  log::info!("user '{}' revoked all {} session(s)", user.oid, sessions.len());
+  Ok(tide::Response::builder(200).build())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{check, Session, SessionCheck};
+
+  #[test]
+  fn test_session_check() {
+    let now = chrono::Utc::now();
+    let session = Session {
+      id: "session".to_string(),
+      user_id: "user".to_string(),
+      created_at: now - chrono::Duration::days(2),
+      last_seen_at: now - chrono::Duration::seconds(30),
+      user_agent: None,
+    };
+    assert_eq!(check(&session, now), SessionCheck::Valid);
+
+    let stale = Session {
+      last_seen_at: now - chrono::Duration::minutes(10),
+      ..session.clone()
+    };
+    assert_eq!(check(&stale, now), SessionCheck::Touch);
+
+    let idle = Session {
+      last_seen_at: now - chrono::Duration::days(2),
+      ..session.clone()
+    };
+    assert_eq!(check(&idle, now), SessionCheck::Expired);
+
+    let old = Session {
+      created_at: now - chrono::Duration::days(31),
+      ..session
+    };
+    assert_eq!(check(&old, now), SessionCheck::Expired);
+  }
+}
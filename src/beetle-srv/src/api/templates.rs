@@ -0,0 +1,291 @@
+//! Defines the routes used to save layout templates and render them to devices. Templates are
+//! render layouts with `{{placeholders}}` in their strings, filled in with variables at render time.
+
+use crate::{registrar, schema};
+use async_std::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The most amount of templates a single user can save.
+const MAX_TEMPLATES: u64 = 50;
+
+/// The longest name a template can be given.
+const MAX_NAME_LEN: usize = 64;
+
+/// The longest value a single variable can hold.
+const MAX_VARIABLE_LEN: usize = 512;
+
+/// The payload for looking up or deleting a template by id.
+#[derive(Debug, Deserialize)]
+struct LookupQuery {
+  /// The id of the template.
+  id: String,
+}
+
+/// The payload for saving a template. Templates saved with the id of an existing one replace it.
+#[derive(Debug, Deserialize)]
+struct TemplateSavePayload {
+  /// The id of the template being replaced, if any.
+  id: Option<String>,
+
+  /// A name for the template.
+  name: String,
+
+  /// The layout, with placeholders.
+  layout: crate::rendering::RenderLayout<String>,
+}
+
+/// The payload for rendering a template to a device.
+#[derive(Debug, Deserialize)]
+struct TemplateRenderPayload {
+  /// The id of the template.
+  template_id: String,
+
+  /// The id of the device.
+  device_id: String,
+
+  /// The values of the placeholders in the template.
+  #[serde(default)]
+  variables: HashMap<String, String>,
+}
+
+/// The schema of responses sent after queuing a render.
+#[derive(Debug, Serialize)]
+struct TemplateRenderResponse {
+  /// The id of the render queued.
+  id: String,
+}
+
+/// Returns an error unless every variable is short enough to be rendered.
+pub(super) fn check_variables(variables: &HashMap<String, String>) -> tide::Result<()> {
+  if variables.values().any(|value| value.len() > MAX_VARIABLE_LEN) {
+    return Err(tide::Error::from_str(422, "bad-variables"));
+  }
+
+  Ok(())
+}
+
+/// Loads a template, provided it belongs to the user.
+pub(super) async fn find_owned(
+  worker: &super::worker::Worker,
+  user_id: &str,
+  template_id: &str,
+) -> tide::Result<schema::LayoutTemplate> {
+  worker
+    .layout_template_collection()?
+    .find_one(bson::doc! { "id": template_id, "user_id": user_id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to find template '{template_id}' - {error}");
+      tide::Error::from_str(500, "bad-lookup")
+    })?
+    .ok_or_else(|| tide::Error::from_str(404, "missing-template"))
+}
+
+/// Loads a template and fills it in with the variables.
+pub(super) async fn fill_owned(
+  worker: &super::worker::Worker,
+  user_id: &str,
+  template_id: &str,
+  variables: &HashMap<String, String>,
+) -> tide::Result<crate::rendering::RenderLayout<String>> {
+  check_variables(variables)?;
+  let template = find_owned(worker, user_id, template_id).await?;
+
+  crate::rendering::templates::fill(&template.layout, variables).map_err(|error| {
+    log::warn!("unable to fill template '{template_id}' - {error}");
+    tide::Error::from_str(422, "missing-variables")
+  })
+}
+
+/// Route: layout-templates
+///
+/// Returns the templates saved by the current user.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let mut cursor = worker
+    .layout_template_collection()?
+    .find(bson::doc! { "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load templates for '{}' - {error}", user.oid);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  let mut templates = vec![];
+  while let Some(template) = cursor.next().await {
+    match template {
+      Ok(template) => templates.push(template),
+      Err(error) => log::warn!("unable to parse template for '{}' - {error}", user.oid),
+    }
+  }
+
+  tide::Body::from_json(&templates).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: layout-templates
+///
+/// Saves a template for the current user. The layout is rendered with sample variables before it
+/// is stored, so templates that cannot be rendered are rejected here instead of at render time.
+pub(super) async fn save(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<TemplateSavePayload>().await.map_err(|error| {
+    log::warn!("bad template payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let name = payload.name.trim().to_string();
+  if name.is_empty() || name.len() > MAX_NAME_LEN {
+    return Err(tide::Error::from_str(422, "bad-name"));
+  }
+
+  let variables = crate::rendering::templates::validate(&payload.layout).map_err(|error| {
+    log::warn!("user '{}' attempted to save a bad template - {error}", user.oid);
+    tide::Error::from_str(422, "bad-layout")
+  })?;
+
+  let templates = worker.layout_template_collection()?;
+  let now = chrono::Utc::now();
+
+  if let Some(id) = payload.id {
+    let existing = find_owned(worker, &user.oid, &id).await?;
+    let template = schema::LayoutTemplate {
+      name,
+      layout: payload.layout,
+      variables,
+      updated_at: Some(now),
+      ..existing
+    };
+
+    templates
+      .replace_one(bson::doc! { "id": &id, "user_id": &user.oid }, &template, None)
+      .await
+      .map_err(|error| {
+        log::warn!("unable to replace template '{id}' - {error}");
+        tide::Error::from_str(500, "server-error")
+      })?;
+
+    log::info!("user '{}' updated template '{id}'", user.oid);
+    return tide::Body::from_json(&template).map(|body| tide::Response::builder(200).body(body).build());
+  }
+
+  let existing = templates
+    .count_documents(bson::doc! { "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to count templates for '{}' - {error}", user.oid);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  if existing >= MAX_TEMPLATES {
+    return Err(tide::Error::from_str(422, "too-many-templates"));
+  }
+
+  let template = schema::LayoutTemplate {
+    id: uuid::Uuid::new_v4().to_string(),
+    user_id: user.oid.clone(),
+    name,
+    layout: payload.layout,
+    variables,
+    created_at: Some(now),
+    updated_at: Some(now),
+  };
+
+  templates.insert_one(&template, None).await.map_err(|error| {
+    log::warn!("unable to store template for '{}' - {error}", user.oid);
+    tide::Error::from_str(500, "server-error")
+  })?;
+
+  log::info!("user '{}' created template '{}'", user.oid, template.id);
+  tide::Body::from_json(&template).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: layout-templates/delete
+///
+/// Deletes a template of the current user. Hooks and schedule entries that render it stop working.
+pub(super) async fn delete(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<LookupQuery>().await.map_err(|error| {
+    log::warn!("bad template deletion payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let result = worker
+    .layout_template_collection()?
+    .delete_one(bson::doc! { "id": &payload.id, "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to delete template '{}' - {error}", payload.id);
+      tide::Error::from_str(500, "server-error")
+    })?;
+
+  if result.deleted_count == 0 {
+    return Err(tide::Error::from_str(404, "missing-template"));
+  }
+
+  log::info!("user '{}' deleted template '{}'", user.oid, payload.id);
+  Ok(tide::Response::builder(200).build())
+}
+
+/// Route: layout-templates/render
+///
+/// Fills in a template with the provided variables and queues it for rendering on a device.
+pub(super) async fn render(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<TemplateRenderPayload>().await.map_err(|error| {
+    log::warn!("bad template render payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let record = worker
+    .require_device_action(&user.oid, &payload.device_id, registrar::DeviceAction::SendContent)
+    .await?;
+
+  let layout = fill_owned(worker, &user.oid, &payload.template_id, &payload.variables).await?;
+
+  // Screen the filled layout as a whole; the variables are only part of what ends up on the
+  // display. There is nothing to hold a template render as, so content needing approval is turned
+  // away.
+  let screened = crate::rendering::templates::text(&layout).map_err(|error| {
+    log::warn!("unable to read text of template '{}' - {error}", payload.template_id);
+    tide::Error::from_str(500, "server-error")
+  })?;
+  let content = registrar::moderation::ScreenedContent::Text(&screened);
+  let verdict = super::jobs::moderate(worker, record.as_ref(), &payload.device_id, &user.oid, content).await?;
+  if verdict == registrar::moderation::ModerationVerdict::Hold {
+    return Err(tide::Error::from_str(403, "requires-approval"));
+  }
+
+  log::info!(
+    "user '{}' rendering template '{}' to '{}'",
+    user.oid,
+    payload.template_id,
+    payload.device_id
+  );
+  let id = worker
+    .queue_render(
+      &payload.device_id,
+      &user.oid,
+      crate::rendering::RenderVariant::layout(layout),
+    )
+    .await?;
+
+  tide::Body::from_json(&TemplateRenderResponse { id }).map(|body| tide::Response::builder(200).body(body).build())
+}
@@ -0,0 +1,285 @@
+//! Defines the routes used by users to create, list and revoke their personal access tokens. Tokens
+//! are sent in an `Authorization: Bearer <token>` header, and are accepted by every route that
+//! would otherwise need the session cookie.
+
+use crate::schema;
+use async_std::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+
+/// The most amount of tokens a single user can have.
+const MAX_TOKENS: u64 = 20;
+
+/// The longest name a token can be given.
+const MAX_NAME_LEN: usize = 64;
+
+/// The longest a token can be created for, in days.
+const MAX_EXPIRES_IN_DAYS: u32 = 3650;
+
+/// Tokens are prefixed to make them easy to recognize, e.g when scanning for leaked secrets.
+const TOKEN_PREFIX: &str = "obpat_";
+
+/// The payload for looking up a token by id.
+#[derive(Debug, Deserialize)]
+struct LookupQuery {
+  /// The id of the token in question.
+  id: String,
+}
+
+/// The payload for creating a token.
+#[derive(Debug, Deserialize)]
+struct TokenCreationPayload {
+  /// A name for the token.
+  name: String,
+
+  /// What the token can be used for.
+  scopes: Vec<schema::PersonalAccessTokenScope>,
+
+  /// The amount of days until the token stops working. Tokens without one never expire.
+  expires_in_days: Option<u32>,
+}
+
+/// A token as it is returned from the api; the token itself is only ever returned once, on
+/// creation.
+#[derive(Debug, Serialize)]
+struct TokenResponse {
+  /// The id of the token.
+  id: String,
+
+  /// The name of the token.
+  name: String,
+
+  /// What the token can be used for.
+  scopes: Vec<schema::PersonalAccessTokenScope>,
+
+  /// When the token was created.
+  created_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When the token was last used.
+  last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When the token stops working.
+  expires_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// The token, only present in the response to creation.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  token: Option<String>,
+}
+
+impl From<schema::PersonalAccessToken> for TokenResponse {
+  fn from(token: schema::PersonalAccessToken) -> Self {
+    Self {
+      id: token.id,
+      name: token.name,
+      scopes: token.scopes,
+      created_at: token.created_at,
+      last_used_at: token.last_used_at,
+      expires_at: token.expires_at,
+      token: None,
+    }
+  }
+}
+
+/// Returns the personal access token held in the `Authorization` header of a request, if any.
+pub(super) fn bearer_token(request: &http_types::Request) -> Option<&str> {
+  request
+    .header("Authorization")
+    .map(|values| values.last())
+    .and_then(|value| value.as_str().strip_prefix("Bearer "))
+    .map(str::trim)
+    .filter(|token| !token.is_empty())
+}
+
+/// Returns true if the scopes of a token cover requests made with the method.
+pub(super) fn scope_allows(scopes: &[schema::PersonalAccessTokenScope], method: http_types::Method) -> bool {
+  let required = match method {
+    http_types::Method::Get | http_types::Method::Head | http_types::Method::Options => {
+      schema::PersonalAccessTokenScope::Read
+    }
+    _ => schema::PersonalAccessTokenScope::Write,
+  };
+
+  scopes.contains(&required)
+}
+
+/// Route: auth/tokens
+///
+/// Returns the personal access tokens of the current user.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let mut cursor = worker
+    .personal_access_token_collection()?
+    .find(bson::doc! { "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load tokens for '{}' - {error}", user.oid);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  let mut tokens = vec![];
+  while let Some(token) = cursor.next().await {
+    match token {
+      Ok(token) => tokens.push(TokenResponse::from(token)),
+      Err(error) => log::warn!("unable to parse token for '{}' - {error}", user.oid),
+    }
+  }
+
+  tide::Body::from_json(&tokens).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Returns when a token created now expires, or nothing when the amount of days is out of range.
+fn expires_at(now: chrono::DateTime<chrono::Utc>, days: u32) -> Option<chrono::DateTime<chrono::Utc>> {
+  if days == 0 || days > MAX_EXPIRES_IN_DAYS {
+    return None;
+  }
+
+  now.checked_add_signed(chrono::Duration::days(i64::from(days)))
+}
+
+/// Route: auth/tokens
+///
+/// Creates a personal access token for the current user, returning it once. Tokens cannot be used
+/// to create more tokens.
+pub(super) async fn create(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  if bearer_token(request.as_ref()).is_some() {
+    log::warn!("attempt to create a personal access token with a personal access token");
+    return Err(tide::Error::from_str(403, "session-required"));
+  }
+
+  let payload = request.body_json::<TokenCreationPayload>().await.map_err(|error| {
+    log::warn!("bad token payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let name = payload.name.trim().to_string();
+  if name.is_empty() || name.len() > MAX_NAME_LEN {
+    return Err(tide::Error::from_str(422, "bad-name"));
+  }
+
+  if payload.scopes.is_empty() {
+    return Err(tide::Error::from_str(422, "missing-scopes"));
+  }
+
+  let tokens = worker.personal_access_token_collection()?;
+  let existing = tokens
+    .count_documents(bson::doc! { "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to count tokens for '{}' - {error}", user.oid);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  if existing >= MAX_TOKENS {
+    return Err(tide::Error::from_str(422, "too-many-tokens"));
+  }
+
+  let now = chrono::Utc::now();
+  let expires_at = payload
+    .expires_in_days
+    .map(|days| expires_at(now, days).ok_or_else(|| tide::Error::from_str(422, "bad-expiration")))
+    .transpose()?;
+
+  let token = format!("{TOKEN_PREFIX}{}", crate::identity::create_secret());
+  let record = schema::PersonalAccessToken {
+    id: uuid::Uuid::new_v4().to_string(),
+    user_id: user.oid.clone(),
+    name,
+    token_hash: crate::redis::hash_secret(&token),
+    scopes: payload.scopes,
+    created_at: Some(now),
+    last_used_at: None,
+    expires_at,
+  };
+
+  tokens.insert_one(&record, None).await.map_err(|error| {
+    log::warn!("unable to store token for '{}' - {error}", user.oid);
+    tide::Error::from_str(500, "server-error")
+  })?;
+
+  log::info!("user '{}' created personal access token '{}'", user.oid, record.id);
+  let response = TokenResponse {
+    token: Some(token),
+    ..TokenResponse::from(record)
+  };
+
+  tide::Body::from_json(&response).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: auth/tokens/revoke
+///
+/// Deletes a personal access token of the current user; it stops working immediately.
+pub(super) async fn revoke(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<LookupQuery>().await.map_err(|error| {
+    log::warn!("bad token revocation payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let result = worker
+    .personal_access_token_collection()?
+    .delete_one(bson::doc! { "id": &payload.id, "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to delete token '{}' - {error}", payload.id);
+      tide::Error::from_str(500, "server-error")
+    })?;
+
+  if result.deleted_count == 0 {
+    return Err(tide::Error::from_str(404, "missing-token"));
+  }
+
+  log::info!("user '{}' revoked personal access token '{}'", user.oid, payload.id);
+  Ok(tide::Response::builder(200).build())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{bearer_token, expires_at, scope_allows, MAX_EXPIRES_IN_DAYS};
+  use crate::schema::PersonalAccessTokenScope;
+
+  #[test]
+  fn test_scopes() {
+    let read = [PersonalAccessTokenScope::Read];
+    let both = [PersonalAccessTokenScope::Read, PersonalAccessTokenScope::Write];
+
+    assert!(scope_allows(&read, http_types::Method::Get));
+    assert!(!scope_allows(&read, http_types::Method::Post));
+    assert!(scope_allows(&both, http_types::Method::Post));
+    assert!(!scope_allows(
+      &[PersonalAccessTokenScope::Write],
+      http_types::Method::Get
+    ));
+  }
+
+  #[test]
+  fn test_bearer_token() {
+    let mut request = http_types::Request::get("http://localhost/auth/identify");
+    assert_eq!(bearer_token(&request), None);
+
+    request.insert_header("Authorization", "Bearer obpat_abc");
+    assert_eq!(bearer_token(&request), Some("obpat_abc"));
+  }
+
+  #[test]
+  fn test_expires_at() {
+    let now = chrono::Utc::now();
+    assert_eq!(expires_at(now, 30), Some(now + chrono::Duration::days(30)));
+    assert!(expires_at(now, MAX_EXPIRES_IN_DAYS).is_some());
+    assert_eq!(expires_at(now, 0), None);
+    assert_eq!(expires_at(now, MAX_EXPIRES_IN_DAYS + 1), None);
+    assert_eq!(expires_at(now, u32::MAX), None);
+  }
+}
@@ -0,0 +1,154 @@
+//! Defines the routes used to hand a device over to a new owner. Owners start (or cancel) a
+//! transfer, and the recipient accepts or declines it.
+
+use crate::{registrar, schema};
+use async_std::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+
+/// The default amount of hours a transfer can be accepted for.
+const DEFAULT_TRANSFER_HOURS: u32 = 72;
+
+/// The longest amount of hours a transfer can be accepted for.
+const MAX_TRANSFER_HOURS: u32 = 24 * 14;
+
+/// The changes that can be made to the transfer of a device.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+enum TransferPayloadKind {
+  /// Starts a transfer to another user.
+  Initiate {
+    /// The id of the user that will become the owner.
+    to: String,
+    /// When true, the current owner stays on as a manager.
+    #[serde(default)]
+    keep_access: bool,
+    /// How long the transfer can be accepted for.
+    expires_in_hours: Option<u32>,
+  },
+  /// Cancels a transfer that was started by the current user.
+  Cancel,
+  /// Accepts a transfer to the current user.
+  Accept,
+  /// Declines a transfer to the current user.
+  Decline,
+}
+
+/// The api used to transfer a device.
+#[derive(Debug, Deserialize)]
+struct TransferPayload {
+  /// The id of the device.
+  device_id: String,
+  /// The change being made.
+  kind: TransferPayloadKind,
+}
+
+/// The schema of responses sent after queuing a transfer change.
+#[derive(Debug, Serialize)]
+struct TransferChangeResponse {
+  /// The id of the job queued.
+  id: String,
+}
+
+/// A single transfer, as it is exposed from the api.
+#[derive(Debug, Serialize)]
+struct TransferResponse {
+  /// The id of the device.
+  device_id: String,
+  /// The transfer itself.
+  transfer: schema::DeviceOwnershipTransfer,
+}
+
+/// Route: device-transfers
+///
+/// Returns the pending transfers to and from the current user.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let mut cursor = worker
+    .device_authority_collection()?
+    .find(
+      bson::doc! { "$or": [{ "transfer.to": &user.oid }, { "transfer.from": &user.oid }] },
+      None,
+    )
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load transfers for '{}' - {error}", user.oid);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  let now = chrono::Utc::now();
+  let mut transfers = vec![];
+  while let Some(record) = cursor.next().await {
+    match record {
+      Ok(schema::DeviceAuthorityRecord {
+        device_id,
+        transfer: Some(transfer),
+        ..
+      }) if transfer.expires_at > now => transfers.push(TransferResponse { device_id, transfer }),
+      Ok(_) => continue,
+      Err(error) => log::warn!("unable to parse transfer for '{}' - {error}", user.oid),
+    }
+  }
+
+  tide::Body::from_json(&transfers).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: device-transfers
+///
+/// Queues a job to start, cancel, accept or decline the transfer of a device.
+pub(super) async fn update(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<TransferPayload>().await.map_err(|error| {
+    log::warn!("bad transfer payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+  let device_id = payload.device_id;
+
+  // The recipient does not have access to the device yet; the job itself makes sure the transfer
+  // being accepted or declined was addressed to them.
+  let job = match payload.kind {
+    TransferPayloadKind::Initiate {
+      to,
+      keep_access,
+      expires_in_hours,
+    } => {
+      worker
+        .require_device_action(&user.oid, &device_id, registrar::DeviceAction::TransferOwnership)
+        .await?;
+
+      let hours = expires_in_hours
+        .unwrap_or(DEFAULT_TRANSFER_HOURS)
+        .clamp(1, MAX_TRANSFER_HOURS);
+      let transfer = schema::DeviceOwnershipTransfer {
+        from: user.oid,
+        to,
+        keep_access,
+        expires_at: chrono::Utc::now() + chrono::Duration::hours(hours.into()),
+      };
+      registrar::transfer::DeviceTransferRequest::Initiate { device_id, transfer }
+    }
+    TransferPayloadKind::Cancel | TransferPayloadKind::Decline => {
+      registrar::transfer::DeviceTransferRequest::Withdraw {
+        device_id,
+        user_id: user.oid,
+      }
+    }
+    TransferPayloadKind::Accept => registrar::transfer::DeviceTransferRequest::Accept {
+      device_id,
+      user_id: user.oid,
+    },
+  };
+
+  let id = worker
+    .queue_job_kind(registrar::RegistrarJobKind::Transfer(job))
+    .await?;
+  tide::Body::from_json(&TransferChangeResponse { id }).map(|body| tide::Response::builder(200).body(body).build())
+}
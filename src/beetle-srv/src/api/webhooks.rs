@@ -0,0 +1,248 @@
+//! Defines the routes used by users to register the webhooks that device and job events are posted
+//! to, and to look through the log of deliveries made to them.
+
+use crate::{registrar, schema};
+use async_std::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+
+/// The most amount of webhooks a single user can register.
+const MAX_WEBHOOKS: u64 = 10;
+
+/// The most amount of deliveries returned from the delivery api.
+const MAX_DELIVERIES: i64 = 50;
+
+/// The payload for looking up a webhook by id.
+#[derive(Debug, Deserialize)]
+struct LookupQuery {
+  /// The id of a webhook in question.
+  id: String,
+}
+
+/// The payload for registering a webhook.
+#[derive(Debug, Deserialize)]
+struct WebhookCreationPayload {
+  /// Where events will be posted to.
+  url: String,
+
+  /// The kinds of events to send.
+  events: Vec<schema::WebhookEventKind>,
+
+  /// When not empty, only events for these devices are sent.
+  #[serde(default)]
+  device_ids: Vec<String>,
+}
+
+/// A webhook as it is returned from the api; the secret is only ever returned once, on creation.
+#[derive(Debug, Serialize)]
+struct WebhookResponse {
+  /// The id of the webhook.
+  id: String,
+
+  /// Where events are posted to.
+  url: String,
+
+  /// The kinds of events sent.
+  events: Vec<schema::WebhookEventKind>,
+
+  /// The devices events are limited to.
+  device_ids: Vec<String>,
+
+  /// When the webhook was registered.
+  created_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// The secret used to sign requests, only present in the response to creation.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  secret: Option<String>,
+}
+
+impl From<schema::Webhook> for WebhookResponse {
+  fn from(webhook: schema::Webhook) -> Self {
+    Self {
+      id: webhook.id,
+      url: webhook.url,
+      events: webhook.events,
+      device_ids: webhook.device_ids,
+      created_at: webhook.created_at,
+      secret: None,
+    }
+  }
+}
+
+/// Route: webhooks
+///
+/// Returns the webhooks registered by the current user.
+pub(super) async fn find(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let mut cursor = worker
+    .webhook_collection()?
+    .find(bson::doc! { "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load webhooks for '{}' - {error}", user.oid);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  let mut webhooks = vec![];
+  while let Some(webhook) = cursor.next().await {
+    match webhook {
+      Ok(webhook) => webhooks.push(WebhookResponse::from(webhook)),
+      Err(error) => log::warn!("unable to parse webhook for '{}' - {error}", user.oid),
+    }
+  }
+
+  tide::Body::from_json(&webhooks).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: webhooks
+///
+/// Registers a new webhook for the current user, returning it along with its signing secret.
+pub(super) async fn create(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<WebhookCreationPayload>().await.map_err(|error| {
+    log::warn!("bad webhook payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  if !super::alerts::valid_webhook_url(&payload.url) {
+    return Err(tide::Error::from_str(422, "bad-webhook-url"));
+  }
+
+  registrar::webhooks::public_destination(&payload.url)
+    .await
+    .map_err(|error| {
+      log::warn!("rejected webhook url for '{}' - {error}", user.oid);
+      tide::Error::from_str(422, "bad-webhook-url")
+    })?;
+
+  if payload.events.is_empty() {
+    return Err(tide::Error::from_str(422, "missing-events"));
+  }
+
+  for device_id in &payload.device_ids {
+    worker
+      .require_device_action(&user.oid, device_id, registrar::DeviceAction::View)
+      .await?;
+  }
+
+  let webhooks = worker.webhook_collection()?;
+  let existing = webhooks
+    .count_documents(bson::doc! { "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to count webhooks for '{}' - {error}", user.oid);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  if existing >= MAX_WEBHOOKS {
+    return Err(tide::Error::from_str(422, "too-many-webhooks"));
+  }
+
+  let webhook = schema::Webhook {
+    id: uuid::Uuid::new_v4().to_string(),
+    user_id: user.oid.clone(),
+    url: payload.url,
+    secret: crate::identity::create_secret(),
+    events: payload.events,
+    device_ids: payload.device_ids,
+    created_at: Some(chrono::Utc::now()),
+  };
+
+  webhooks.insert_one(&webhook, None).await.map_err(|error| {
+    log::warn!("unable to store webhook for '{}' - {error}", user.oid);
+    tide::Error::from_str(500, "server-error")
+  })?;
+
+  log::info!("user '{}' registered webhook '{}'", user.oid, webhook.id);
+  let secret = webhook.secret.clone();
+  let response = WebhookResponse {
+    secret: Some(secret),
+    ..WebhookResponse::from(webhook)
+  };
+
+  tide::Body::from_json(&response).map(|body| tide::Response::builder(200).body(body).build())
+}
+
+/// Route: webhooks/delete
+///
+/// Removes a webhook of the current user, along with its deliveries.
+pub(super) async fn delete(mut request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let payload = request.body_json::<LookupQuery>().await.map_err(|error| {
+    log::warn!("bad webhook deletion payload - {error}");
+    tide::Error::from_str(422, "bad-request")
+  })?;
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+
+  let result = worker
+    .webhook_collection()?
+    .delete_one(bson::doc! { "id": &payload.id, "user_id": &user.oid }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to delete webhook '{}' - {error}", payload.id);
+      tide::Error::from_str(500, "server-error")
+    })?;
+
+  if result.deleted_count == 0 {
+    return Err(tide::Error::from_str(404, "missing-webhook"));
+  }
+
+  worker
+    .webhook_delivery_collection()?
+    .delete_many(bson::doc! { "webhook_id": &payload.id }, None)
+    .await
+    .map_err(|error| {
+      log::warn!("unable to delete deliveries of webhook '{}' - {error}", payload.id);
+      tide::Error::from_str(500, "server-error")
+    })?;
+
+  Ok(tide::Response::builder(200).build())
+}
+
+/// Route: webhook-deliveries
+///
+/// Returns the most recent deliveries made to a webhook of the current user, newest first.
+pub(super) async fn deliveries(request: tide::Request<super::worker::Worker>) -> tide::Result {
+  let worker = request.state();
+  let user = worker.request_authority(&request).await?.ok_or_else(|| {
+    log::warn!("no user found");
+    tide::Error::from_str(404, "missing-user")
+  })?;
+  let query = request.query::<LookupQuery>()?;
+
+  let mut cursor = worker
+    .webhook_delivery_collection()?
+    .find(
+      bson::doc! { "webhook_id": &query.id, "user_id": &user.oid },
+      mongodb::options::FindOptions::builder()
+        .sort(bson::doc! { "event.created_at": -1 })
+        .limit(MAX_DELIVERIES)
+        .build(),
+    )
+    .await
+    .map_err(|error| {
+      log::warn!("unable to load deliveries for '{}' - {error}", query.id);
+      tide::Error::from_str(500, "bad-lookup")
+    })?;
+
+  let mut deliveries = vec![];
+  while let Some(delivery) = cursor.next().await {
+    match delivery {
+      Ok(delivery) => deliveries.push(delivery),
+      Err(error) => log::warn!("unable to parse delivery for '{}' - {error}", query.id),
+    }
+  }
+
+  tide::Body::from_json(&deliveries).map(|body| tide::Response::builder(200).body(body).build())
+}
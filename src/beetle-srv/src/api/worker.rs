@@ -3,6 +3,12 @@ use async_std::sync::{Arc, Mutex};
 use serde::Serialize;
 use std::io::{Error, ErrorKind, Result};
 
+/// The default amount of requests a sender can make to a `Public` device per window.
+const DEFAULT_SENDER_RATE_LIMIT: u32 = 10;
+
+/// The default length of the sender rate limiting window.
+const DEFAULT_SENDER_RATE_WINDOW_SECONDS: u64 = 60;
+
 /// The type shared by all web worker requests.
 #[derive(Clone)]
 pub struct Worker {
@@ -149,6 +155,55 @@ impl Worker {
     Ok(id)
   }
 
+  /// Counts a request from the sender to the device against the sender's limit, returning false if
+  /// it has been exceeded. This uses fixed windows.
+  pub(super) async fn sender_within_limit(&self, device_id: &str, sender: &str) -> Result<bool> {
+    let moderation = self.web_configuration.moderation.clone().unwrap_or_default();
+    let limit = moderation.sender_rate_limit.unwrap_or(DEFAULT_SENDER_RATE_LIMIT);
+    let window = moderation
+      .sender_rate_window_seconds
+      .unwrap_or(DEFAULT_SENDER_RATE_WINDOW_SECONDS)
+      .max(1);
+
+    let key = format!("{}:{device_id}:{sender}", crate::constants::MODERATION_RATE_PREFIX);
+    self.within_limit(&key, limit, window).await
+  }
+
+  /// Counts a request made with an inbound hook against its own limit, returning false if it has
+  /// been exceeded. Hook limits are per minute.
+  pub(super) async fn hook_within_limit(&self, hook: &schema::InboundHook) -> Result<bool> {
+    let key = format!("{}:{}", crate::constants::INBOUND_HOOK_RATE_PREFIX, hook.id);
+    self.within_limit(&key, hook.rate_limit, 60).await
+  }
+
+  /// Increments the counter of the current window for the key, returning false once it goes past
+  /// the limit. Each window gets its own counter which expires once the window has passed.
+  async fn within_limit(&self, key: &str, limit: u32, window: u64) -> Result<bool> {
+    let window_index = chrono::Utc::now().timestamp() as u64 / window;
+    let key = format!("{key}:{window_index}");
+
+    self
+      .command(&kramer::Command::Strings(kramer::StringCommand::Set(
+        kramer::Arity::One((&key, "0")),
+        Some(std::time::Duration::from_secs(window)),
+        kramer::Insertion::IfNotExists,
+      )))
+      .await?;
+
+    match self
+      .command(&kramer::Command::Strings::<&String, &str>(kramer::StringCommand::Incr(
+        &key, 1,
+      )))
+      .await?
+    {
+      kramer::Response::Item(kramer::ResponseValue::Integer(count)) => Ok(count <= i64::from(limit)),
+      other => {
+        log::warn!("unexpected response from rate limit counter - {other:?}");
+        Err(Error::new(ErrorKind::Other, "bad-rate-limit-response"))
+      }
+    }
+  }
+
   /// Attempts to execute a command against the redis instance.
   pub(super) async fn command<S, V>(&self, command: &kramer::Command<S, V>) -> Result<kramer::Response>
   where
@@ -171,31 +226,34 @@ impl Worker {
     Err(Error::new(ErrorKind::Other, "unable to connect to redis"))
   }
 
-  /// This is an associated, helper function for routes to require that a request has a valid user
-  /// associated with it. The `Err` will be returned if there is none or an "actual" problem
-  /// happened while fetching.
-  pub(super) async fn require_authority(request: &tide::Request<Self>) -> Result<schema::User> {
-    request
-      .state()
-      .request_authority(request)
-      .await?
-      .ok_or_else(|| Error::new(ErrorKind::Other, "no-user"))
-  }
-
   /// Given a request, this method will attempt to determine what kind of authority we are
-  /// processing with.
-  ///
-  /// TODO: back this with redis for a more secure + controllable session store. For now
-  /// we are ultimately relying on the json web token secret to prevent spoofing.
+  /// processing with. Requests with an `Authorization: Bearer` header are identified by the
+  /// personal access token in it; everything else by the session cookie.
   pub(super) async fn request_authority(&self, request: &tide::Request<Self>) -> Result<Option<schema::User>> {
-    let oid = request
-      .cookie(&self.web_configuration.session_cookie)
-      .and_then(|cook| {
-        log::trace!("found cookie - '{cook:?}'");
-        super::claims::Claims::decode(cook.value(), &self.web_configuration.session_secret).ok()
-      })
-      .map(|claims| claims.oid)
-      .unwrap_or_default();
+    let oid = match super::tokens::bearer_token(request.as_ref()) {
+      Some(token) => match self.token_authority(token, request.method()).await? {
+        Some(oid) => oid,
+        None => return Ok(None),
+      },
+      None => {
+        let claims = request.cookie(&self.web_configuration.session_cookie).and_then(|cook| {
+          log::trace!("found cookie - '{cook:?}'");
+          super::claims::Claims::decode(cook.value(), &self.web_configuration.session_secret).ok()
+        });
+
+        // Every cookie is checked against the session store, so revoked sessions stop working
+        // immediately.
+        match claims {
+          Some(super::claims::Claims {
+            oid, sid: Some(sid), ..
+          }) => match super::sessions::authenticate(self, &sid, &oid).await? {
+            Some(_) => oid,
+            None => return Ok(None),
+          },
+          _ => String::default(),
+        }
+      }
+    };
 
     if oid.is_empty() {
       log::trace!("no user id found in cookies");
@@ -212,6 +270,55 @@ impl Worker {
     })
   }
 
+  /// Returns the id of the user a personal access token acts as, provided the token exists, has not
+  /// expired and has the scope required by the request method.
+  async fn token_authority(&self, token: &str, method: http_types::Method) -> Result<Option<String>> {
+    let tokens = self.personal_access_token_collection()?;
+    let Some(record) = tokens
+      .find_one(bson::doc! { "token_hash": crate::redis::hash_secret(token) }, None)
+      .await
+      .map_err(|error| {
+        log::warn!("unable to find personal access token - {error}");
+        Error::new(ErrorKind::Other, "bad-token-lookup")
+      })?
+    else {
+      log::warn!("request made with unknown personal access token");
+      return Ok(None);
+    };
+
+    let now = chrono::Utc::now();
+    if record
+      .expires_at
+      .map(|expires_at| expires_at <= now)
+      .unwrap_or_default()
+    {
+      log::warn!("request made with expired personal access token '{}'", record.id);
+      return Ok(None);
+    }
+
+    if !super::tokens::scope_allows(&record.scopes, method) {
+      log::warn!("personal access token '{}' is not scoped for {method}", record.id);
+      return Ok(None);
+    }
+
+    let last_used = bson::to_bson(&now).map_err(|error| Error::new(ErrorKind::Other, error.to_string()))?;
+    if let Err(error) = tokens
+      .update_one(
+        bson::doc! { "id": &record.id },
+        bson::doc! { "$set": { "last_used_at": last_used } },
+        None,
+      )
+      .await
+    {
+      log::warn!(
+        "unable to update last use of personal access token '{}' - {error}",
+        record.id
+      );
+    }
+
+    Ok(Some(record.user_id))
+  }
+
   /// Wraps the mongodb client and returns our collection.
   pub(super) fn device_diagnostic_collection(&self) -> Result<mongodb::Collection<schema::DeviceDiagnostic>> {
     Ok(
@@ -223,6 +330,102 @@ impl Worker {
     )
   }
 
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn device_state_collection(&self) -> Result<mongodb::Collection<schema::DeviceState>> {
+    Ok(
+      self
+        .mongo
+        .0
+        .database(&self.mongo.1.database)
+        .collection(&self.mongo.1.collections.device_states),
+    )
+  }
+
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn device_schedule_collection(&self) -> Result<mongodb::Collection<schema::DeviceSchedule>> {
+    Ok(
+      self
+        .mongo
+        .0
+        .database(&self.mongo.1.database)
+        .collection(&self.mongo.1.collections.device_schedules),
+    )
+  }
+
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn device_authority_collection(&self) -> Result<mongodb::Collection<schema::DeviceAuthorityRecord>> {
+    Ok(
+      self
+        .mongo
+        .0
+        .database(&self.mongo.1.database)
+        .collection(&self.mongo.1.collections.device_authorities),
+    )
+  }
+
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn device_event_collection(&self) -> Result<mongodb::Collection<schema::DeviceConnectivityEvent>> {
+    Ok(crate::registrar::connectivity::collection(&self.mongo.0, &self.mongo.1))
+  }
+
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn webhook_collection(&self) -> Result<mongodb::Collection<schema::Webhook>> {
+    Ok(crate::registrar::webhooks::collection(&self.mongo.0, &self.mongo.1))
+  }
+
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn webhook_delivery_collection(&self) -> Result<mongodb::Collection<schema::WebhookDelivery>> {
+    Ok(crate::registrar::webhooks::deliveries_collection(
+      &self.mongo.0,
+      &self.mongo.1,
+    ))
+  }
+
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn inbound_hook_collection(&self) -> Result<mongodb::Collection<schema::InboundHook>> {
+    Ok(
+      self.mongo.0.database(&self.mongo.1.database).collection(
+        self
+          .mongo
+          .1
+          .collections
+          .inbound_hooks
+          .as_deref()
+          .unwrap_or("inbound_hooks"),
+      ),
+    )
+  }
+
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn personal_access_token_collection(&self) -> Result<mongodb::Collection<schema::PersonalAccessToken>> {
+    Ok(
+      self.mongo.0.database(&self.mongo.1.database).collection(
+        self
+          .mongo
+          .1
+          .collections
+          .personal_access_tokens
+          .as_deref()
+          .unwrap_or("personal_access_tokens"),
+      ),
+    )
+  }
+
+  /// Wraps the mongodb client and returns our collection.
+  pub(super) fn layout_template_collection(&self) -> Result<mongodb::Collection<schema::LayoutTemplate>> {
+    Ok(
+      self.mongo.0.database(&self.mongo.1.database).collection(
+        self
+          .mongo
+          .1
+          .collections
+          .layout_templates
+          .as_deref()
+          .unwrap_or("layout_templates"),
+      ),
+    )
+  }
+
   /// Wraps the mongodb client and returns our collection.
   pub(super) fn users_collection(&self) -> Result<mongodb::Collection<schema::User>> {
     Ok(
@@ -250,6 +453,32 @@ impl Worker {
     crate::registrar::user_access(&self.mongo.0, &self.mongo.1, &user_id, &device_id).await
   }
 
+  /// Loads the access a user has to a device, requiring that it allows the action. The authority
+  /// record is returned for routes that need it. Users without any access are told the device does
+  /// not exist.
+  pub(super) async fn require_device_action<D>(
+    &self,
+    user_id: &str,
+    device_id: D,
+    action: crate::registrar::DeviceAction,
+  ) -> tide::Result<Option<schema::DeviceAuthorityRecord>>
+  where
+    D: AsRef<str>,
+  {
+    let device_id = device_id.as_ref();
+    let (level, record) = self.user_access(user_id, device_id).await?.ok_or_else(|| {
+      log::warn!("'{user_id}' has no access to device '{device_id}'");
+      tide::Error::from_str(404, "not-found")
+    })?;
+
+    if !level.allows(action) {
+      log::warn!("'{user_id}' ({level:?}) is not allowed to perform {action:?} on device '{device_id}'");
+      return Err(tide::Error::from_str(403, "forbidden"));
+    }
+
+    Ok(record)
+  }
+
   /// Attempts to aquire a lock, filling the contents with either a new connection, or just
   /// re-using the existing one.
   async fn get_redis_lock(&self) -> Result<async_std::sync::MutexGuard<'_, Option<crate::redis::RedisConnection>>> {
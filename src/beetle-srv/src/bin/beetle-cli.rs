@@ -41,12 +41,25 @@ enum CommandLineCommand {
   /// Creates request for an layout render and queues it.
   SendLayout(cli::SendLayoutCommand),
 
+  /// Prints the JSON Schema of a layout type; this does not need the config file.
+  LayoutSchema(cli::LayoutSchemaCommand),
+
+  /// Checks a layout file for errors, truncated text and overflowing elements; this does not need
+  /// the config file.
+  ValidateLayout(cli::ValidateLayoutCommand),
+
+  /// Uploads a ttf or otf font that layouts can refer to by name.
+  UploadFont(cli::UploadFontCommand),
+
   /// Creates request for a qr code render and queues it.
   SendScannable(cli::SendScannableCommand),
 
   /// Resets the device registration state so the renderer will send a new registration qr code.
   ResetRegistration(cli::SingleDeviceCommand),
 
+  /// Rotates the secret a device uses to authenticate with redis.
+  RotateCredentials(cli::RotateCredentialsCommand),
+
   /// Turns the lights on.
   Darken(cli::SingleDeviceCommand),
 
@@ -56,6 +69,9 @@ enum CommandLineCommand {
   /// Prints the length of a device message queue.
   PrintItems(cli::SingleDeviceCommand),
 
+  /// Talks to the web api with a personal access token; this does not need the config file.
+  Api(cli::ApiCommand),
+
   /// Do migration things.
   Migrate {
     /// The operation
@@ -84,6 +100,9 @@ async fn run(config: cli::CommandLineConfig, command: CommandLineCommand) -> io:
   println!("  mongofb: {}", config.mongo.url);
   println!("==");
   match command {
+    CommandLineCommand::Api(cmd) => cli::run_api(cmd).await,
+    CommandLineCommand::LayoutSchema(cmd) => cli::print_schema(cmd).await,
+    CommandLineCommand::ValidateLayout(cmd) => cli::validate_layout(cmd).await,
     CommandLineCommand::Migrate { kind } => cli::migrate::run(&config, kind).await,
     CommandLineCommand::DropCollections => {
       let mongo = beetle::mongo::connect_mongo(&config.mongo).await?;
@@ -128,10 +147,12 @@ async fn run(config: cli::CommandLineConfig, command: CommandLineCommand) -> io:
 
       Ok(())
     }
+    CommandLineCommand::RotateCredentials(cmd) => cli::rotate_credentials(&config, cmd).await,
     CommandLineCommand::PrintItems(cmd) => cli::print_queue_size(&config, cmd).await,
     CommandLineCommand::SendImage(cmd) => cli::send_image(&config, cmd).await,
     CommandLineCommand::SendLayout(cmd) => cli::send_layout(&config, cmd).await,
     CommandLineCommand::SendScannable(cmd) => cli::send_scannable(&config, cmd).await,
+    CommandLineCommand::UploadFont(cmd) => cli::upload_font(&config, cmd).await,
     CommandLineCommand::ResetRegistration(cmd) => {
       log::info!("resetting device '{}' to force qr code", cmd.id);
       let mongo = beetle::mongo::connect_mongo(&config.mongo).await?;
@@ -170,6 +191,14 @@ fn main() -> io::Result<()> {
   log::info!("environment + logger ready.");
 
   let options = CommandLineOptions::parse();
+
+  match options.command {
+    CommandLineCommand::Api(command) => return async_std::task::block_on(cli::run_api(command)),
+    CommandLineCommand::LayoutSchema(command) => return async_std::task::block_on(cli::print_schema(command)),
+    CommandLineCommand::ValidateLayout(command) => return async_std::task::block_on(cli::validate_layout(command)),
+    _ => (),
+  }
+
   let contents = std::fs::read_to_string(&options.config).map_err(|error| {
     io::Error::new(
       io::ErrorKind::Other,
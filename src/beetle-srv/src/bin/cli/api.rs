@@ -0,0 +1,118 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::io;
+
+/// The environment variable read when no token is provided as an argument.
+const TOKEN_ENV_VAR: &str = "BEETLE_API_TOKEN";
+
+/// Talks to the web api as a normal user, authenticated with a personal access token. Unlike the
+/// rest of the cli, this does not need redis or mongo credentials.
+#[derive(Parser, Deserialize, PartialEq, Debug)]
+pub struct ApiCommand {
+  /// The base url of the web api, e.g `https://beetle.example.com/api`.
+  #[arg(short = 'u', long)]
+  url: String,
+
+  /// A personal access token. Falls back to the `BEETLE_API_TOKEN` environment variable.
+  #[arg(short = 't', long)]
+  token: Option<String>,
+
+  /// What to do.
+  #[command(subcommand)]
+  action: ApiAction,
+}
+
+/// The things that can be done through the api.
+#[derive(PartialEq, clap::Subcommand, Deserialize, Debug)]
+enum ApiAction {
+  /// Prints the user the token belongs to.
+  Whoami,
+
+  /// Prints information about a device.
+  DeviceInfo(super::SingleDeviceCommand),
+
+  /// Sends a message to a device.
+  Message {
+    /// The id of a device.
+    #[arg(short = 'd', long)]
+    id: String,
+    /// The message to send.
+    #[arg(short = 'm', long)]
+    message: String,
+  },
+
+  /// Turns the lights of a device on or off.
+  Lights {
+    /// The id of a device.
+    #[arg(short = 'd', long)]
+    id: String,
+    /// Turns the lights off instead of on.
+    #[arg(long)]
+    off: bool,
+  },
+
+  /// Prints the result of a job.
+  Job {
+    /// The id of the job.
+    #[arg(short = 'j', long)]
+    id: String,
+  },
+}
+
+/// Sends the request with the token attached, printing the json response.
+async fn execute(request: surf::RequestBuilder, token: &str) -> io::Result<()> {
+  let mut response = request
+    .header("Authorization", format!("Bearer {token}"))
+    .await
+    .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("request failed - {error}")))?;
+
+  let body = response
+    .body_string()
+    .await
+    .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("unable to read response - {error}")))?;
+
+  if !response.status().is_success() {
+    return Err(io::Error::new(
+      io::ErrorKind::Other,
+      format!("api responded with {} - {body}", response.status()),
+    ));
+  }
+
+  match serde_json::from_str::<serde_json::Value>(&body) {
+    Ok(value) => println!("{}", serde_json::to_string_pretty(&value).unwrap_or(body)),
+    Err(_) => println!("{body}"),
+  }
+
+  Ok(())
+}
+
+/// Runs the api command.
+pub async fn run_api(command: ApiCommand) -> io::Result<()> {
+  let token = command
+    .token
+    .or_else(|| std::env::var(TOKEN_ENV_VAR).ok())
+    .ok_or_else(|| {
+      io::Error::new(
+        io::ErrorKind::Other,
+        format!("please provide a token with --token or {TOKEN_ENV_VAR}"),
+      )
+    })?;
+  let base = command.url.trim_end_matches('/');
+
+  let request = match command.action {
+    ApiAction::Whoami => Ok(surf::get(format!("{base}/auth/identify"))),
+    ApiAction::DeviceInfo(device) => surf::get(format!("{base}/device-info")).query(&[("id", device.id)]),
+    ApiAction::Job { id } => surf::get(format!("{base}/jobs")).query(&[("id", id)]),
+    ApiAction::Message { id, message } => surf::post(format!("{base}/device-queue")).body_json(&serde_json::json!({
+      "device_id": id,
+      "kind": { "beetle:kind": "message", "beetle:content": message },
+    })),
+    ApiAction::Lights { id, off } => surf::post(format!("{base}/device-queue")).body_json(&serde_json::json!({
+      "device_id": id,
+      "kind": { "beetle:kind": "lights", "beetle:content": !off },
+    })),
+  }
+  .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("unable to build request - {error}")))?;
+
+  execute(request, &token).await
+}
@@ -0,0 +1,41 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::io;
+
+/// Rotates the secret a device uses to authenticate with redis.
+#[derive(Parser, Deserialize, PartialEq, Debug)]
+pub struct RotateCredentialsCommand {
+  /// The id of a device.
+  #[arg(short = 'd', long)]
+  id: String,
+  /// Revokes the previous secret. This should be run once the device has picked up its new one.
+  #[arg(long)]
+  finalize: bool,
+}
+
+/// Rotates (or finalizes the rotation of) a device's credentials.
+pub async fn rotate_credentials(
+  config: &super::CommandLineConfig,
+  command: RotateCredentialsCommand,
+) -> io::Result<()> {
+  let mut stream = beetle::redis::connect(&config.redis).await?;
+  let mongo = beetle::mongo::connect_mongo(&config.mongo).await?;
+  let credentials = beetle::registrar::credentials::collection(&mongo, &config.mongo);
+
+  let result = match command.finalize {
+    true => beetle::registrar::credentials::finalize(&mut stream, &credentials, &command.id).await,
+    false => beetle::registrar::credentials::rotate(&mut stream, &credentials, &command.id).await,
+  };
+
+  result.map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+
+  match command.finalize {
+    true => println!("previous credentials for '{}' revoked", command.id),
+    false => println!(
+      "new credentials queued for '{}'; finalize once the device has picked them up",
+      command.id
+    ),
+  }
+
+  Ok(())
+}
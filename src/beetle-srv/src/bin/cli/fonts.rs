@@ -0,0 +1,37 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::io;
+
+/// Uploads a ttf or otf font that layouts can refer to by name.
+#[derive(Parser, Deserialize, PartialEq, Debug)]
+pub struct UploadFontCommand {
+  /// The name layouts will refer to the font by; replaces any font with the same name.
+  #[arg(short = 'n', long)]
+  name: String,
+  /// The path to the font file.
+  #[arg(short = 'i', long)]
+  font_file: String,
+}
+
+/// Reads the font file and stores it for renderers to pick up.
+pub async fn upload_font(config: &super::CommandLineConfig, command: UploadFontCommand) -> io::Result<()> {
+  let bytes = async_std::fs::read(&command.font_file).await.map_err(|error| {
+    io::Error::new(
+      error.kind(),
+      format!("unable to read font file '{}' - {error}", command.font_file),
+    )
+  })?;
+
+  let mongo = beetle::mongo::connect_mongo(&config.mongo).await?;
+  let fonts = beetle::rendering::fonts::collection(&mongo, &config.mongo);
+  let font = beetle::rendering::fonts::upload(&fonts, &command.name, bytes).await?;
+
+  println!(
+    "font '{}' uploaded ({} bytes, sha256 {})",
+    font.name,
+    font.data.bytes.len(),
+    font.digest
+  );
+
+  Ok(())
+}
@@ -0,0 +1,109 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::io;
+
+/// The types a schema can be printed for.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, clap::ValueEnum)]
+pub enum SchemaKind {
+  /// The layouts read by `send-layout`.
+  RenderLayout,
+  /// A layout or lighting change, as it is queued.
+  RenderVariant,
+  /// The text component.
+  StylizedMessage,
+}
+
+/// Prints the JSON Schema of a layout type.
+#[derive(Parser, Deserialize, PartialEq, Debug)]
+pub struct LayoutSchemaCommand {
+  /// Which type to print the schema of.
+  #[arg(short = 'k', long, value_enum, default_value = "render-layout")]
+  kind: SchemaKind,
+  /// An optional path on the filesystem where the schema is written instead of printed.
+  #[arg(short = 'o', long)]
+  output: Option<String>,
+}
+
+/// Checks a layout file without sending it anywhere.
+#[derive(Parser, Deserialize, PartialEq, Debug)]
+pub struct ValidateLayoutCommand {
+  /// The layout file.
+  #[arg(short = 'i', long)]
+  layout_file: String,
+  /// The width to check against; defaults to the width of our devices.
+  #[arg(long)]
+  width: Option<u32>,
+  /// The height to check against; defaults to the height of our devices.
+  #[arg(long)]
+  height: Option<u32>,
+}
+
+/// Prints or writes the JSON Schema of a layout type.
+pub async fn print_schema(command: LayoutSchemaCommand) -> io::Result<()> {
+  let root = match command.kind {
+    SchemaKind::RenderLayout => beetle::rendering::json_schema::SchemaRoot::RenderLayout,
+    SchemaKind::RenderVariant => beetle::rendering::json_schema::SchemaRoot::RenderVariant,
+    SchemaKind::StylizedMessage => beetle::rendering::json_schema::SchemaRoot::StylizedMessage,
+  };
+  let document = serde_json::to_string_pretty(&beetle::rendering::json_schema::document(root))
+    .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+
+  match command.output {
+    Some(path) => {
+      async_std::fs::write(&path, document).await?;
+      println!("wrote schema to {path}");
+    }
+    None => println!("{document}"),
+  }
+
+  Ok(())
+}
+
+/// Checks a layout file, printing everything found. Layouts with errors fail the command.
+pub async fn validate_layout(command: ValidateLayoutCommand) -> io::Result<()> {
+  let contents = async_std::fs::read_to_string(&command.layout_file).await?;
+  let document = serde_json::from_str::<serde_json::Value>(&contents).map_err(|error| {
+    io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("'{}' is not json: {error}", command.layout_file),
+    )
+  })?;
+
+  let dimensions = (
+    command.width.unwrap_or(beetle::rendering::DEVICE_DIMENSIONS.0),
+    command.height.unwrap_or(beetle::rendering::DEVICE_DIMENSIONS.1),
+  );
+  let report = beetle::rendering::validation::report(&document, dimensions);
+
+  for issue in &report.errors {
+    println!("error    {} - {}", issue.path, issue.message);
+  }
+  for issue in &report.warnings {
+    println!("warning  {} - {}", issue.path, issue.message);
+  }
+  for text in &report.truncated {
+    println!(
+      "truncated {} - '{}' is drawn as '{}' within {}px",
+      text.path, text.text, text.rendered, text.available_width
+    );
+  }
+  for overflow in &report.overflows {
+    println!(
+      "overflow {} - {}px past the right, {}px past the bottom",
+      overflow.path, overflow.right, overflow.bottom
+    );
+  }
+
+  if !report.valid {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("'{}' is not a valid layout", command.layout_file),
+    ));
+  }
+
+  println!(
+    "'{}' is valid at {}x{}",
+    command.layout_file, dimensions.0, dimensions.1
+  );
+  Ok(())
+}
@@ -70,6 +70,12 @@ pub async fn send_layout(config: &super::CommandLineConfig, command: SendLayoutC
 
   if let Some(path) = &command.local_path {
     println!("writing image to {path}");
+    let mongo = beetle::mongo::connect_mongo(&config.mongo).await?;
+    if let Err(error) =
+      beetle::rendering::fonts::sync(&beetle::rendering::fonts::collection(&mongo, &config.mongo)).await
+    {
+      log::warn!("unable to load custom fonts, rendering without them - {error}");
+    }
     let local_buffer = parsed_layout.clone().rasterize((400, 300))?;
     let mut file = async_std::fs::File::create(&path).await.map_err(|error| {
       io::Error::new(
@@ -167,9 +173,14 @@ pub async fn send_image(config: &super::CommandLineConfig, command: SendImageCom
     beetle::rendering::RenderLayout::StylizedMessage(beetle::rendering::components::StylizedMessage {
       message: &command.message,
       border: None,
+      border_radius: None,
+      inverted: None,
+      fill: None,
       margin: None,
       padding: None,
       font: None,
+      fallback_fonts: None,
+      icon: None,
       size: None,
     })
     .rasterize((400, 300))?;
@@ -47,8 +47,24 @@ pub mod migrate;
 mod acls;
 pub use acls::{invalidate_acls, print_acls, provision, ProvisionCommand};
 
+/// Commands associated with device credentials.
+mod credentials;
+pub use credentials::{rotate_credentials, RotateCredentialsCommand};
+
 /// Commands associated with device messaging.
 mod messages;
 pub use messages::{
   print_queue_size, send_image, send_layout, send_scannable, SendImageCommand, SendLayoutCommand, SendScannableCommand,
 };
+
+/// Commands associated with the fonts available to layouts.
+mod fonts;
+pub use fonts::{upload_font, UploadFontCommand};
+
+/// Commands that help with authoring layouts; these do not need the config file.
+mod layouts;
+pub use layouts::{print_schema, validate_layout, LayoutSchemaCommand, ValidateLayoutCommand};
+
+/// Commands that go through the web api instead of redis and mongo.
+mod api;
+pub use api::{run_api, ApiCommand};
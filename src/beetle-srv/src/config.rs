@@ -29,6 +29,34 @@ pub struct GoogleConfiguration {
   pub redirect_uri: String,
 }
 
+/// An OpenID Connect provider users can log in with, e.g GitLab or Keycloak. Endpoints are read from
+/// the discovery document of the issuer, unless all three are provided; providers that only speak
+/// oauth (like GitHub) need them.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct OidcProviderConfiguration {
+  /// A short, url-safe identifier for the provider, e.g `gitlab`.
+  pub id: String,
+  /// The name shown to users.
+  pub name: String,
+  /// The issuer url; the discovery document is expected at `/.well-known/openid-configuration`.
+  pub issuer: String,
+  /// The client id.
+  pub client_id: String,
+  /// The client secret.
+  pub client_secret: String,
+  /// The redirect uri, which should point at the `auth/oidc/<id>/complete` route.
+  pub redirect_uri: String,
+  /// The scopes. Defaults to `openid`, `email` and `profile`.
+  pub scopes: Option<Vec<String>>,
+  /// Overrides the authorization endpoint from the discovery document.
+  pub authorization_endpoint: Option<String>,
+  /// Overrides the token endpoint from the discovery document.
+  pub token_endpoint: Option<String>,
+  /// Overrides the userinfo endpoint from the discovery document.
+  pub userinfo_endpoint: Option<String>,
+}
+
 /// Collection configuration.
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
@@ -54,6 +82,52 @@ pub struct MongoCollectionsConfiguration {
 
   /// Storage of device states.
   pub device_states: String,
+
+  /// Storage for everything that was removed when a device was decommissioned. Defaults to
+  /// `device_archives`.
+  pub device_archives: Option<String>,
+
+  /// Storage for the hashed secrets devices use to authenticate with redis. Defaults to
+  /// `device_credentials`.
+  pub device_credentials: Option<String>,
+
+  /// Storage for events like devices going offline and coming back. Defaults to `device_events`.
+  pub device_events: Option<String>,
+
+  /// Storage for webhooks registered by users. Defaults to `webhooks`.
+  pub webhooks: Option<String>,
+
+  /// Storage for the delivery log of webhooks. Defaults to `webhook_deliveries`.
+  pub webhook_deliveries: Option<String>,
+
+  /// Storage for the tokens used to send content to devices. Defaults to `inbound_hooks`.
+  pub inbound_hooks: Option<String>,
+
+  /// Storage for the personal access tokens of users. Defaults to `personal_access_tokens`.
+  pub personal_access_tokens: Option<String>,
+
+  /// Storage for the layout templates saved by users. Defaults to `layout_templates`.
+  pub layout_templates: Option<String>,
+
+  /// Storage for the fonts uploaded for use by layouts. Defaults to `custom_fonts`.
+  pub custom_fonts: Option<String>,
+}
+
+/// Moderation settings applied to everything sent to `Public` devices by users other than the
+/// owner.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct ModerationConfiguration {
+  /// Content containing any of these words will be rejected. Matching is case-insensitive and
+  /// done on whole words.
+  #[serde(default)]
+  pub blocked_words: Vec<String>,
+
+  /// The most amount of requests a single sender can make to a single device per window.
+  pub sender_rate_limit: Option<u32>,
+
+  /// The length of the rate limiting window.
+  pub sender_rate_window_seconds: Option<u64>,
 }
 
 /// The mongodb configuration.
@@ -68,6 +142,50 @@ pub struct MongoConfiguration {
   pub collections: MongoCollectionsConfiguration,
 }
 
+/// The smtp server used to send emails. Connections are made over tls unless explicitly disabled.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct SmtpConfiguration {
+  /// The host; e.g `smtp.mailgun.org`.
+  pub host: String,
+
+  /// The port; typically `465` when using tls.
+  pub port: u16,
+
+  /// The username to authenticate with, if any.
+  pub username: Option<String>,
+
+  /// The password to authenticate with, if any.
+  pub password: Option<String>,
+
+  /// The address emails are sent from.
+  pub from: String,
+
+  /// When true, the connection is made without tls. Only meant for local relays.
+  #[serde(default)]
+  pub insecure: bool,
+}
+
+/// Settings for detecting devices that have gone offline, and telling their owners about it.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct AlertConfiguration {
+  /// The amount of seconds without a check-in before a device is considered offline.
+  pub offline_after_seconds: Option<i64>,
+
+  /// The amount of seconds a device needs to be offline before its owner is notified.
+  pub notify_after_seconds: Option<i64>,
+
+  /// The window used to detect devices going on and offline repeatedly.
+  pub flap_window_seconds: Option<i64>,
+
+  /// The amount of transitions within the window after which notifications are suppressed.
+  pub flap_threshold: Option<usize>,
+
+  /// When provided, owners that have set an email address are notified through this server.
+  pub smtp: Option<SmtpConfiguration>,
+}
+
 /// The configuration specific to maintaining a registration of available ids.
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "snake_case", tag = "kind", content = "content")]
@@ -113,6 +231,13 @@ pub struct RegistrarConfiguration {
   /// If provided, this is the amount of time between device schedule refreshing.
   pub device_schedule_refresh_interval_seconds: Option<i64>,
 
+  /// The amount of seconds a device has to report displaying a render before it is flagged as
+  /// unacknowledged.
+  pub render_acknowledgement_timeout_seconds: Option<i64>,
+
+  /// Offline detection and notification settings. Devices are not tracked without it.
+  pub alerts: Option<AlertConfiguration>,
+
   /// Optional analytics configuration, used for monitoring queue health.
   pub analytics_configuration: Option<RegistrarAnalyticsConfiguration>,
 }
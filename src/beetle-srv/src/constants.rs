@@ -1,4 +1,5 @@
-/// LIST: stores all of the available ids that devices can pop from.
+/// LIST: stores all of the available credentials that devices can pop from. Each entry is the id
+/// and secret of a device, separated by a colon.
 pub const REGISTRAR_AVAILABLE: &str = "ob:r";
 
 /// LIST: devices push into this every time they pop from their individual keys.
@@ -16,5 +17,20 @@ pub const REGISTRAR_JOB_QUEUE: &str = "ob:registrar-jobs";
 /// HASH:  registrar job queue.
 pub const REGISTRAR_JOB_RESULTS: &str = "ob:registrar-job-results";
 
+/// STRING: per-sender, per-device request counters used to rate limit `Public` device content.
+pub const MODERATION_RATE_PREFIX: &str = "ob:moderation-rate";
+
+/// STRING: per-hook request counters used to rate limit inbound hooks.
+pub const INBOUND_HOOK_RATE_PREFIX: &str = "ob:hook-rate";
+
+/// STRING: web sessions, stored as json and expired once they go unused for long enough.
+pub const SESSION_PREFIX: &str = "ob:session";
+
+/// SET: the ids of every session belonging to a user.
+pub const USER_SESSIONS_PREFIX: &str = "ob:user-sessions";
+
+/// STRING: the state of OpenID Connect logins that have been started but not completed.
+pub const OIDC_STATE_PREFIX: &str = "ob:oidc-state";
+
 /// The prefix used for lighting command messages.
 pub const LIGHTING_PREFIX: &str = "lighting";
@@ -17,6 +17,12 @@ pub fn create() -> String {
   shorten(uuid::Uuid::new_v4().to_string())
 }
 
+/// Creates a random secret suitable for use as a device password. Secrets are 64 hexadecimal
+/// characters, and never contain the `:` separator used when handing credentials to devices.
+pub fn create_secret() -> String {
+  format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
 #[cfg(test)]
 mod test {
   use super::shorten;
@@ -17,6 +17,64 @@ where
   format!("ob:{input}")
 }
 
+/// Helper function to create the key that devices pop their new credentials from after they have
+/// been rotated.
+pub fn device_credential_queue_id<S>(input: S) -> String
+where
+  S: std::fmt::Display,
+{
+  format!("ob:{input}:credentials")
+}
+
+/// Returns the hex encoded sha256 digest of a device secret. This is the only form secrets are
+/// stored in, both by redis and by our own credential records.
+pub fn hash_secret<S>(secret: S) -> String
+where
+  S: AsRef<str>,
+{
+  let digest = sha2::Digest::finalize(sha2::Digest::chain_update(
+    <sha2::Sha256 as sha2::Digest>::new(),
+    secret.as_ref().as_bytes(),
+  ));
+  digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The acl entry for a device. This is formatted as an `ACL SETUSER` command that resets whatever
+/// the user previously had, allowing only the passwords with the given hashes. `kramer` does not
+/// support passing hashed passwords, so this is written out by hand.
+pub struct DeviceAclEntry<'a> {
+  /// The id of the device.
+  pub id: &'a str,
+  /// The sha256 digests of every password that should be accepted.
+  pub secret_hashes: &'a [String],
+}
+
+impl<'a> std::fmt::Display for DeviceAclEntry<'a> {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let mut arguments = vec![
+      "ACL".to_string(),
+      "SETUSER".to_string(),
+      self.id.to_string(),
+      "reset".to_string(),
+      "on".to_string(),
+    ];
+    arguments.extend(self.secret_hashes.iter().map(|hash| format!("#{hash}")));
+    arguments.extend([
+      format!("~{}", device_message_queue_id(self.id)),
+      format!("~{}", device_credential_queue_id(self.id)),
+      format!("~{}", crate::constants::REGISTRAR_INCOMING),
+      "+lpop".to_string(),
+      "+blpop".to_string(),
+      "+rpush".to_string(),
+    ]);
+
+    write!(formatter, "*{}\r\n", arguments.len())?;
+    arguments
+      .iter()
+      .try_for_each(|argument| write!(formatter, "${}\r\n{argument}\r\n", argument.len()))
+  }
+}
+
 /// Wraps the configuration we have; the only functionality beyond opening the tcp stream here is
 /// an initial request to the redis instance to authenticate.
 #[cfg(not(feature = "redis-insecure"))]
@@ -77,3 +135,31 @@ pub async fn connect(config: &crate::config::RedisConfiguration) -> Result<Redis
     None => Ok(stream),
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{hash_secret, DeviceAclEntry};
+
+  #[test]
+  fn test_hash_secret() {
+    assert_eq!(
+      hash_secret("abc"),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+  }
+
+  #[test]
+  fn test_device_acl_entry() {
+    let hashes = vec!["aa".to_string()];
+    let entry = DeviceAclEntry {
+      id: "device",
+      secret_hashes: &hashes,
+    };
+    assert_eq!(
+      format!("{entry}"),
+      "*12\r\n$3\r\nACL\r\n$7\r\nSETUSER\r\n$6\r\ndevice\r\n$5\r\nreset\r\n$2\r\non\r\n$3\r\n#aa\r\n\
+       $10\r\n~ob:device\r\n$22\r\n~ob:device:credentials\r\n$5\r\n~ob:i\r\n$5\r\n+lpop\r\n$6\r\n+blpop\r\n\
+       $6\r\n+rpush\r\n"
+    );
+  }
+}
@@ -2,11 +2,108 @@ use crate::schema;
 use serde::Serialize;
 use std::io;
 
-/// The access level a user has to a given device.
-#[derive(Serialize, Debug)]
+/// The access level a user has to a given device. Levels are ordered; each level can do everything
+/// the levels before it can.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
 pub enum AccessLevel {
+  /// The user can only see the device.
+  Viewer,
+
+  /// The user can send content to the device.
+  Contributor,
+
+  /// The user can do everything but change who can access the device and its visibility.
+  Manager,
+
   /// The user can do anything.
-  All,
+  Owner,
+}
+
+impl From<schema::DeviceGuestRole> for AccessLevel {
+  fn from(role: schema::DeviceGuestRole) -> Self {
+    match role {
+      schema::DeviceGuestRole::Manager => Self::Manager,
+      schema::DeviceGuestRole::Contributor => Self::Contributor,
+      schema::DeviceGuestRole::Viewer => Self::Viewer,
+    }
+  }
+}
+
+impl AccessLevel {
+  /// Returns true if this level is allowed to perform the action.
+  pub fn allows(&self, action: DeviceAction) -> bool {
+    *self >= action.required_level()
+  }
+}
+
+/// The things users can do to a device. Every api route and queue payload maps onto one of these.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceAction {
+  /// Looking at device info, its authority model and its album.
+  View,
+
+  /// Sending messages, checklist items, timekeeping, links, lighting, images and album photos.
+  SendContent,
+
+  /// Removing and reordering photos in the album.
+  ManageAlbum,
+
+  /// Clearing whatever is currently rendered.
+  ClearRender,
+
+  /// Showing the registration scannable again.
+  ShowRegistration,
+
+  /// Configuring, running and removing the device schedule.
+  ToggleSchedule,
+
+  /// Renaming the device.
+  Rename,
+
+  /// Approving and rejecting pending content, and managing the moderation settings.
+  Moderate,
+
+  /// Listing the owner and guests of the device.
+  ViewMembers,
+
+  /// Making the device public or private.
+  ChangeVisibility,
+
+  /// Inviting and removing guests.
+  ManageMembers,
+
+  /// Handing the device over to a new owner.
+  TransferOwnership,
+
+  /// Removing the device from the system entirely.
+  Decommission,
+
+  /// Creating and revoking the inbound hooks that send content to the device.
+  ManageHooks,
+}
+
+impl DeviceAction {
+  /// The permission matrix; returns the lowest access level allowed to perform this action.
+  pub fn required_level(&self) -> AccessLevel {
+    match self {
+      Self::View => AccessLevel::Viewer,
+      Self::SendContent => AccessLevel::Contributor,
+      Self::ManageAlbum
+      | Self::ClearRender
+      | Self::ShowRegistration
+      | Self::ToggleSchedule
+      | Self::Rename
+      | Self::Moderate
+      | Self::ViewMembers => AccessLevel::Manager,
+      Self::ChangeVisibility
+      | Self::ManageMembers
+      | Self::TransferOwnership
+      | Self::Decommission
+      | Self::ManageHooks => AccessLevel::Owner,
+    }
+  }
 }
 
 /// Returns the access level that a given user has for a given device, as well as the record that
@@ -52,30 +149,154 @@ pub async fn user_access(
   // against the current user.
   log::trace!("current authority record - {authority_record:?}");
 
-  match authority_record.as_ref().and_then(|rec| rec.authority_model.as_ref()) {
-    Some(schema::DeviceAuthorityModel::Shared { owner, guests }) => {
-      let mut found = false;
-      for guest in guests {
-        if guest == user_id {
-          found = true;
-          break;
-        }
-      }
+  let model = authority_record.as_ref().and_then(|rec| rec.authority_model.as_ref());
 
-      if owner != user_id && !found {
-        return Ok(None);
-      }
+  if model.is_none() {
+    log::warn!("no authority record found for '{device_id}'!");
+  }
+
+  Ok(model_access(model, user_id).map(|level| (level, authority_record)))
+}
+
+/// Resolves the access level a user has under a given authority model, if any.
+fn model_access(model: Option<&schema::DeviceAuthorityModel>, user_id: &String) -> Option<AccessLevel> {
+  let level = match model {
+    Some(schema::DeviceAuthorityModel::Shared { owner, .. })
+    | Some(schema::DeviceAuthorityModel::Public { owner, .. })
+    | Some(schema::DeviceAuthorityModel::Exclusive { owner })
+      if owner == user_id =>
+    {
+      AccessLevel::Owner
     }
-    Some(schema::DeviceAuthorityModel::Exclusive { owner }) => {
-      if owner != user_id {
-        return Ok(None);
+    Some(schema::DeviceAuthorityModel::Shared { guests, roles, .. }) => {
+      if !guests.contains(user_id) {
+        return None;
       }
+
+      roles.get(user_id).copied().unwrap_or_default().into()
     }
-    Some(schema::DeviceAuthorityModel::Public { .. }) => return Ok(Some((AccessLevel::All, authority_record))),
-    None => {
-      log::warn!("no authority record found for '{device_id}'!");
+    Some(schema::DeviceAuthorityModel::Exclusive { .. }) => return None,
+    // Anyone can contribute to a public device, unless they have been given some other role.
+    Some(schema::DeviceAuthorityModel::Public { roles, .. }) => roles.get(user_id).copied().unwrap_or_default().into(),
+    None => AccessLevel::Owner,
+  };
+
+  Some(level)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{model_access, AccessLevel, DeviceAction};
+  use crate::schema;
+
+  fn resolve(record: bson::Document, user_id: &str) -> Option<AccessLevel> {
+    let record = bson::from_document::<schema::DeviceAuthorityRecord>(record).unwrap();
+    model_access(record.authority_model.as_ref(), &user_id.to_string())
+  }
+
+  #[test]
+  fn test_permission_matrix() {
+    let levels = [
+      AccessLevel::Viewer,
+      AccessLevel::Contributor,
+      AccessLevel::Manager,
+      AccessLevel::Owner,
+    ];
+
+    // Each row is an action, followed by whether or not it is allowed for the levels above.
+    let matrix = [
+      (DeviceAction::View, [true, true, true, true]),
+      (DeviceAction::SendContent, [false, true, true, true]),
+      (DeviceAction::ManageAlbum, [false, false, true, true]),
+      (DeviceAction::ClearRender, [false, false, true, true]),
+      (DeviceAction::ShowRegistration, [false, false, true, true]),
+      (DeviceAction::ToggleSchedule, [false, false, true, true]),
+      (DeviceAction::Rename, [false, false, true, true]),
+      (DeviceAction::Moderate, [false, false, true, true]),
+      (DeviceAction::ViewMembers, [false, false, true, true]),
+      (DeviceAction::ChangeVisibility, [false, false, false, true]),
+      (DeviceAction::ManageMembers, [false, false, false, true]),
+      (DeviceAction::TransferOwnership, [false, false, false, true]),
+      (DeviceAction::Decommission, [false, false, false, true]),
+      (DeviceAction::ManageHooks, [false, false, false, true]),
+    ];
+
+    for (action, expected) in matrix {
+      for (level, allowed) in levels.iter().zip(expected) {
+        assert_eq!(level.allows(action), allowed, "{level:?} performing {action:?}");
+      }
     }
   }
 
-  Ok(Some((AccessLevel::All, authority_record)))
+  #[test]
+  fn test_exclusive_access() {
+    let record = bson::doc! {
+      "device_id": "device",
+      "authority_model": { "beetle:kind": "exclusive", "beetle:content": { "owner": "owner" } },
+    };
+
+    assert_eq!(resolve(record.clone(), "owner"), Some(AccessLevel::Owner));
+    assert_eq!(resolve(record, "stranger"), None);
+  }
+
+  #[test]
+  fn test_shared_access() {
+    let record = bson::doc! {
+      "device_id": "device",
+      "authority_model": {
+        "beetle:kind": "shared",
+        "beetle:content": {
+          "owner": "owner",
+          "guests": ["manager", "viewer", "legacy"],
+          "roles": { "manager": "manager", "viewer": "viewer" },
+        },
+      },
+    };
+
+    let owner = resolve(record.clone(), "owner").unwrap();
+    assert_eq!(owner, AccessLevel::Owner);
+    assert!(owner.allows(DeviceAction::Decommission));
+
+    let manager = resolve(record.clone(), "manager").unwrap();
+    assert_eq!(manager, AccessLevel::Manager);
+    assert!(manager.allows(DeviceAction::Rename));
+    assert!(!manager.allows(DeviceAction::ManageMembers));
+
+    let viewer = resolve(record.clone(), "viewer").unwrap();
+    assert_eq!(viewer, AccessLevel::Viewer);
+    assert!(!viewer.allows(DeviceAction::SendContent));
+
+    // Guests added before roles existed fall back to the default role.
+    assert_eq!(resolve(record.clone(), "legacy"), Some(AccessLevel::Contributor));
+    assert_eq!(resolve(record, "stranger"), None);
+  }
+
+  #[test]
+  fn test_public_access() {
+    let record = bson::doc! {
+      "device_id": "device",
+      "authority_model": {
+        "beetle:kind": "public",
+        "beetle:content": {
+          "owner": "owner",
+          "guests": [],
+          "roles": { "manager": "manager" },
+        },
+      },
+    };
+
+    assert_eq!(resolve(record.clone(), "owner"), Some(AccessLevel::Owner));
+    assert_eq!(resolve(record.clone(), "manager"), Some(AccessLevel::Manager));
+
+    let stranger = resolve(record, "stranger").unwrap();
+    assert_eq!(stranger, AccessLevel::Contributor);
+    assert!(stranger.allows(DeviceAction::SendContent));
+    assert!(!stranger.allows(DeviceAction::ClearRender));
+  }
+
+  #[test]
+  fn test_missing_model_access() {
+    let record = bson::doc! { "device_id": "device" };
+    assert_eq!(resolve(record, "anyone"), Some(AccessLevel::Owner));
+  }
 }
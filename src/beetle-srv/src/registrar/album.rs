@@ -0,0 +1,201 @@
+//! Device albums are the list of photos that have been uploaded for a device and retained, to be
+//! cycled through by the slideshow rendering state.
+
+use crate::{rendering, schema};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The most amount of photos a single device album can hold.
+const MAX_ALBUM_LEN: usize = 20;
+
+/// The kinds of changes that can be made to a device album.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DeviceAlbumChange {
+  /// Adds a photo that has already been written to disc.
+  Add {
+    /// The location on disc of the uploaded image.
+    location: String,
+    /// How the photo should be placed on the display.
+    fit: Option<rendering::ImageFit>,
+  },
+
+  /// Removes the photo with the provided id, deleting it from disc.
+  Remove(String),
+
+  /// Moves the photo with the provided id to a new position.
+  Move {
+    /// The id of the photo.
+    id: String,
+    /// The position the photo should be moved to.
+    index: usize,
+  },
+}
+
+/// A request to change the album of a device.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceAlbumRequest {
+  /// The id of the device.
+  pub device_id: String,
+  /// The change being made.
+  pub change: DeviceAlbumChange,
+}
+
+/// Applies the change to a list of photos, returning any photo that was removed from the list and
+/// should be deleted from disc.
+fn apply_change(
+  album: &mut Vec<schema::DeviceAlbumPhoto>,
+  change: &DeviceAlbumChange,
+) -> anyhow::Result<Option<schema::DeviceAlbumPhoto>> {
+  let position = |album: &Vec<schema::DeviceAlbumPhoto>, id: &String| {
+    album
+      .iter()
+      .position(|photo| &photo.id == id)
+      .ok_or_else(|| anyhow::Error::msg(format!("no album photo '{id}'")))
+  };
+
+  match change {
+    DeviceAlbumChange::Add { location, fit } => {
+      if album.len() >= MAX_ALBUM_LEN {
+        return Err(anyhow::Error::msg("album is full"));
+      }
+
+      album.push(schema::DeviceAlbumPhoto {
+        id: crate::identity::create(),
+        location: location.clone(),
+        fit: *fit,
+        added_at: Some(chrono::Utc::now()),
+      });
+
+      Ok(None)
+    }
+    DeviceAlbumChange::Remove(id) => {
+      let index = position(album, id)?;
+      Ok(Some(album.remove(index)))
+    }
+    DeviceAlbumChange::Move { id, index: destination } => {
+      let index = position(album, id)?;
+      let photo = album.remove(index);
+      album.insert((*destination).min(album.len()), photo);
+      Ok(None)
+    }
+  }
+}
+
+/// Processes the album change request, re-rendering the device if it is currently displaying a
+/// slideshow.
+pub(super) async fn apply(
+  mut handle: super::worker::WorkerHandle<'_>,
+  request: &DeviceAlbumRequest,
+) -> anyhow::Result<()> {
+  let device_id = &request.device_id;
+
+  if let DeviceAlbumChange::Add { location, .. } = &request.change {
+    image::io::Reader::open(location)
+      .with_context(|| format!("unable to open '{location}'"))?
+      .decode()
+      .with_context(|| format!("unable to parse '{location}' as an image"))?;
+  }
+
+  let states = handle.device_state_collection()?;
+  let current_state = states
+    .find_one_and_update(
+      bson::doc! { "device_id": device_id },
+      bson::doc! { "$setOnInsert": { "device_id": device_id } },
+      mongodb::options::FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(mongodb::options::ReturnDocument::After)
+        .build(),
+    )
+    .await
+    .with_context(|| format!("unable to load device state for '{device_id}'"))?
+    .ok_or_else(|| anyhow::Error::msg(format!("unable to find device '{device_id}'")))?;
+
+  let mut album = current_state.album;
+  let removed = match apply_change(&mut album, &request.change) {
+    Ok(removed) => removed,
+    Err(error) => {
+      // Photos that were uploaded but could not be added should not stick around on disc.
+      if let DeviceAlbumChange::Add { location, .. } = &request.change {
+        if let Err(error) = async_std::fs::remove_file(location).await {
+          log::warn!("unable to remove rejected album photo '{location}' - {error}");
+        }
+      }
+      return Err(error);
+    }
+  };
+
+  let serialized = bson::to_bson(&album).with_context(|| "unable to serialize album")?;
+  states
+    .update_one(
+      bson::doc! { "device_id": device_id },
+      bson::doc! { "$set": { "album": serialized } },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to store album for '{device_id}'"))?;
+
+  if let Some(photo) = removed {
+    log::info!("removing album photo '{}' from disc for '{device_id}'", photo.id);
+    if let Err(error) = async_std::fs::remove_file(&photo.location).await {
+      log::warn!("unable to remove album photo '{}' - {error}", photo.location);
+    }
+  }
+
+  if let Some(schema::DeviceRenderingState::Slideshow { .. }) = current_state.rendering {
+    let job_id = handle
+      .enqueue_kind(super::jobs::RegistrarJobKind::Renders(
+        super::jobs::RegistrarRenderKinds::CurrentDeviceState(device_id.clone()),
+      ))
+      .await?;
+    log::info!("album change for '{device_id}' queued slideshow render '{job_id}'");
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{apply_change, DeviceAlbumChange, MAX_ALBUM_LEN};
+
+  #[test]
+  fn test_album_changes() {
+    let mut album = vec![];
+    for location in ["a.png", "b.png", "c.png"] {
+      let change = DeviceAlbumChange::Add {
+        location: location.to_string(),
+        fit: None,
+      };
+      assert!(apply_change(&mut album, &change).unwrap().is_none());
+    }
+
+    let last = album[2].id.clone();
+    let change = DeviceAlbumChange::Move {
+      id: last.clone(),
+      index: 0,
+    };
+    apply_change(&mut album, &change).unwrap();
+    assert_eq!(
+      album.iter().map(|photo| photo.location.as_str()).collect::<Vec<&str>>(),
+      vec!["c.png", "a.png", "b.png"]
+    );
+
+    let removed = apply_change(&mut album, &DeviceAlbumChange::Remove(last.clone())).unwrap();
+    assert_eq!(removed.map(|photo| photo.location), Some("c.png".to_string()));
+    assert!(apply_change(&mut album, &DeviceAlbumChange::Remove(last)).is_err());
+  }
+
+  #[test]
+  fn test_album_full() {
+    let change = DeviceAlbumChange::Add {
+      location: "a.png".to_string(),
+      fit: None,
+    };
+    let mut album = vec![];
+    for _ in 0..MAX_ALBUM_LEN {
+      apply_change(&mut album, &change).unwrap();
+    }
+    assert!(apply_change(&mut album, &change).is_err());
+  }
+}
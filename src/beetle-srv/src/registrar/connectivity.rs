@@ -0,0 +1,451 @@
+//! Tracks devices going offline and coming back online based on their `last_seen` timestamp. Every
+//! transition is stored as an event, and owners are notified through the channels they have set
+//! up once a device has been offline for long enough, and again when it returns.
+//!
+//! Devices that keep dropping in and out are considered to be "flapping"; their transitions are
+//! still recorded, but notifications are held back until things settle down.
+
+use crate::schema::{self, DeviceConnectivity, DeviceConnectivityStatus};
+use anyhow::Context;
+use serde::Serialize;
+
+/// The amount of seconds without a check-in before a device is offline, when not configured.
+const DEFAULT_OFFLINE_AFTER_SECONDS: i64 = 120;
+
+/// The amount of seconds offline before owners are notified, when not configured.
+const DEFAULT_NOTIFY_AFTER_SECONDS: i64 = 60 * 10;
+
+/// The window used to count transitions, when not configured.
+const DEFAULT_FLAP_WINDOW_SECONDS: i64 = 60 * 60;
+
+/// The amount of transitions within the window that is considered flapping, when not configured.
+const DEFAULT_FLAP_THRESHOLD: usize = 6;
+
+/// How long the webhook of an owner has to respond to a notification.
+const WEBHOOK_TIMEOUT_SECONDS: u64 = 10;
+
+/// The alert configuration, with defaults filled in.
+#[derive(Debug, Clone, Copy)]
+struct Thresholds {
+  /// How long without a check-in before a device is offline.
+  offline_after: chrono::Duration,
+  /// How long a device needs to be offline before notifying.
+  notify_after: chrono::Duration,
+  /// The window transitions are counted in.
+  flap_window: chrono::Duration,
+  /// The amount of transitions in the window that is considered flapping.
+  flap_threshold: usize,
+}
+
+impl From<&crate::config::AlertConfiguration> for Thresholds {
+  fn from(config: &crate::config::AlertConfiguration) -> Self {
+    Self {
+      offline_after: chrono::Duration::seconds(config.offline_after_seconds.unwrap_or(DEFAULT_OFFLINE_AFTER_SECONDS)),
+      notify_after: chrono::Duration::seconds(config.notify_after_seconds.unwrap_or(DEFAULT_NOTIFY_AFTER_SECONDS)),
+      flap_window: chrono::Duration::seconds(config.flap_window_seconds.unwrap_or(DEFAULT_FLAP_WINDOW_SECONDS)),
+      flap_threshold: config.flap_threshold.unwrap_or(DEFAULT_FLAP_THRESHOLD),
+    }
+  }
+}
+
+/// What should happen to a device after looking at its latest check-in.
+#[derive(Debug, PartialEq)]
+struct Evaluation {
+  /// The connectivity to store.
+  connectivity: DeviceConnectivity,
+  /// True when the status changed.
+  transitioned: bool,
+  /// True when the device is changing status too often to notify about.
+  flapping: bool,
+  /// The status the owner should be notified about, if any.
+  notify: Option<DeviceConnectivityStatus>,
+}
+
+/// The payload posted to webhooks.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct ConnectivityNotification<'a> {
+  /// The id of the device.
+  device_id: &'a str,
+  /// The nickname of the device, if it has one.
+  nickname: Option<&'a str>,
+  /// The status being notified about.
+  status: DeviceConnectivityStatus,
+  /// When the device entered the status.
+  since: chrono::DateTime<chrono::Utc>,
+  /// The last time the device checked in.
+  last_seen: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Returns the collection that connectivity events are stored in.
+pub fn collection(
+  mongo: &mongodb::Client,
+  config: &crate::config::MongoConfiguration,
+) -> mongodb::Collection<schema::DeviceConnectivityEvent> {
+  mongo
+    .database(&config.database)
+    .collection(config.collections.device_events.as_deref().unwrap_or("device_events"))
+}
+
+/// Decides what to do with a device, returning `None` when nothing needs to be stored. Devices
+/// seen for the first time are only given a baseline; nobody wants to hear about a device that
+/// went offline long before tracking started.
+fn evaluate(
+  current: Option<&DeviceConnectivity>,
+  last_seen: Option<chrono::DateTime<chrono::Utc>>,
+  now: chrono::DateTime<chrono::Utc>,
+  thresholds: &Thresholds,
+) -> Option<Evaluation> {
+  let observed = match last_seen {
+    Some(seen) if now - seen < thresholds.offline_after => DeviceConnectivityStatus::Online,
+    _ => DeviceConnectivityStatus::Offline,
+  };
+
+  let Some(current) = current else {
+    return Some(Evaluation {
+      connectivity: DeviceConnectivity {
+        status: observed,
+        since: now,
+        notified: Some(observed),
+        transitions: vec![],
+      },
+      transitioned: false,
+      flapping: false,
+      notify: None,
+    });
+  };
+
+  let mut next = current.clone();
+  let window_start = (now - thresholds.flap_window).timestamp_millis();
+  next.transitions.retain(|at| *at >= window_start);
+
+  let transitioned = observed != current.status;
+  if transitioned {
+    next.status = observed;
+    next.since = now;
+    next.transitions.push(now.timestamp_millis());
+  }
+
+  let flapping = next.transitions.len() >= thresholds.flap_threshold;
+  let notify = match (next.status, next.notified) {
+    _ if flapping => None,
+    (DeviceConnectivityStatus::Offline, notified)
+      if notified != Some(DeviceConnectivityStatus::Offline) && now - next.since >= thresholds.notify_after =>
+    {
+      Some(DeviceConnectivityStatus::Offline)
+    }
+    (DeviceConnectivityStatus::Online, Some(DeviceConnectivityStatus::Offline)) => {
+      Some(DeviceConnectivityStatus::Online)
+    }
+    _ => None,
+  };
+
+  if let Some(status) = notify {
+    next.notified = Some(status);
+  }
+
+  if &next == current {
+    return None;
+  }
+
+  Some(Evaluation {
+    connectivity: next,
+    transitioned,
+    flapping,
+    notify,
+  })
+}
+
+/// Sends the notification through every channel the owner of the device has set up. Failures are
+/// logged; there is no point in holding up the rest of the devices.
+async fn notify(
+  handle: &mut super::worker::WorkerHandle<'_>,
+  smtp: Option<&crate::config::SmtpConfiguration>,
+  diagnostic: &schema::DeviceDiagnostic,
+  connectivity: &DeviceConnectivity,
+) -> anyhow::Result<()> {
+  let device_id = diagnostic.id.as_str();
+  let Some(owner) = handle
+    .device_authority_collection()?
+    .find_one(bson::doc! { "device_id": device_id }, None)
+    .await?
+    .and_then(|record| record.authority_model)
+    .map(|model| match model {
+      schema::DeviceAuthorityModel::Exclusive { owner } => owner,
+      schema::DeviceAuthorityModel::Shared { owner, .. } => owner,
+      schema::DeviceAuthorityModel::Public { owner, .. } => owner,
+    })
+  else {
+    log::debug!("'{device_id}' has no owner to notify");
+    return Ok(());
+  };
+
+  let Some(preferences) = handle
+    .users_collection()?
+    .find_one(bson::doc! { "oid": &owner }, None)
+    .await?
+    .and_then(|user| user.alert_preferences)
+  else {
+    return Ok(());
+  };
+
+  let name = diagnostic.nickname.as_deref().unwrap_or(device_id);
+  let (subject, body) = match connectivity.status {
+    DeviceConnectivityStatus::Offline => (
+      format!("'{name}' is offline"),
+      format!(
+        "Your device '{name}' has not checked in since {}.",
+        diagnostic
+          .last_seen
+          .map(|seen| seen.to_rfc2822())
+          .unwrap_or_else(|| "it was registered".to_string())
+      ),
+    ),
+    DeviceConnectivityStatus::Online => (
+      format!("'{name}' is back online"),
+      format!("Your device '{name}' is checking in again."),
+    ),
+  };
+
+  if let (Some(smtp), Some(to)) = (smtp, preferences.email.as_deref()) {
+    let email = crate::vendor::smtp::Email {
+      to,
+      subject: &subject,
+      body: &body,
+    };
+    if let Err(error) = crate::vendor::smtp::send(smtp, &email).await {
+      log::warn!("unable to email '{owner}' about '{device_id}' - {error}");
+    }
+  }
+
+  if let Some(url) = preferences.webhook_url.as_deref() {
+    let payload = ConnectivityNotification {
+      device_id,
+      nickname: diagnostic.nickname.as_deref(),
+      status: connectivity.status,
+      since: connectivity.since,
+      last_seen: diagnostic.last_seen,
+    };
+
+    let timeout = std::time::Duration::from_secs(WEBHOOK_TIMEOUT_SECONDS);
+    match super::webhooks::public_destination(url).await {
+      Err(error) => log::warn!("refusing to send webhook for '{device_id}' - {error}"),
+      Ok(()) => match surf::post(url).body_json(&payload) {
+        Ok(request) => match async_std::future::timeout(timeout, request).await {
+          Err(_) => log::warn!("webhook for '{device_id}' timed out"),
+          Ok(Ok(response)) if response.status().is_success() => (),
+          Ok(Ok(response)) => log::warn!("webhook for '{device_id}' responded with {}", response.status()),
+          Ok(Err(error)) => log::warn!("unable to send webhook for '{device_id}' - {error}"),
+        },
+        Err(error) => log::warn!("unable to serialize webhook for '{device_id}' - {error}"),
+      },
+    }
+  }
+
+  log::info!("notified '{owner}' that '{device_id}' is {:?}", connectivity.status);
+  Ok(())
+}
+
+/// Looks for devices whose connectivity may have changed, storing transitions and notifying owners.
+pub(super) async fn check(mut handle: super::worker::WorkerHandle<'_>) -> anyhow::Result<()> {
+  let Some(config) = handle.config.alerts.clone() else {
+    return Ok(());
+  };
+
+  let thresholds = Thresholds::from(&config);
+  let now = chrono::Utc::now();
+  let cutoff = (now - thresholds.offline_after).timestamp_millis();
+  let notify_cutoff = (now - thresholds.notify_after).timestamp_millis();
+
+  let diagnostics = handle.device_diagnostic_collection()?;
+  let events = collection(&handle.mongo.client, &handle.mongo.config);
+
+  let mut cursor = diagnostics
+    .find(
+      bson::doc! {
+        "$or": [
+          { "connectivity": bson::Bson::Null },
+          { "connectivity.status": "online", "last_seen": { "$lt": cutoff } },
+          { "connectivity.status": "offline", "last_seen": { "$gte": cutoff } },
+          {
+            "connectivity.status": "offline",
+            "connectivity.notified": { "$ne": "offline" },
+            "connectivity.since": { "$lte": notify_cutoff },
+          },
+          { "connectivity.status": "online", "connectivity.notified": "offline" },
+        ],
+      },
+      None,
+    )
+    .await
+    .with_context(|| "unable to query device connectivity")?;
+
+  while cursor.advance().await? {
+    let diagnostic = cursor.deserialize_current()?;
+    let Some(evaluation) = evaluate(diagnostic.connectivity.as_ref(), diagnostic.last_seen, now, &thresholds) else {
+      continue;
+    };
+
+    let serialized = bson::to_bson(&evaluation.connectivity).with_context(|| "unable to serialize connectivity")?;
+    diagnostics
+      .update_one(
+        bson::doc! { "id": &diagnostic.id },
+        bson::doc! { "$set": { "connectivity": serialized } },
+        None,
+      )
+      .await
+      .with_context(|| format!("unable to store connectivity for '{}'", diagnostic.id))?;
+
+    if evaluation.transitioned {
+      log::info!(
+        "'{}' is now {:?} (flapping: {})",
+        diagnostic.id,
+        evaluation.connectivity.status,
+        evaluation.flapping
+      );
+
+      events
+        .insert_one(
+          schema::DeviceConnectivityEvent {
+            device_id: diagnostic.id.clone(),
+            status: evaluation.connectivity.status,
+            at: now,
+            last_seen: diagnostic.last_seen,
+            flapping: evaluation.flapping,
+          },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to store connectivity event for '{}'", diagnostic.id))?;
+
+      let event = match evaluation.connectivity.status {
+        DeviceConnectivityStatus::Online => schema::WebhookEventPayload::DeviceOnline {
+          device_id: diagnostic.id.clone(),
+        },
+        DeviceConnectivityStatus::Offline => schema::WebhookEventPayload::DeviceOffline {
+          device_id: diagnostic.id.clone(),
+          last_seen: diagnostic.last_seen,
+        },
+      };
+      if let Err(error) = super::webhooks::emit(&mut handle, event).await {
+        log::error!(
+          "unable to emit connectivity webhook event for '{}' - {error}",
+          diagnostic.id
+        );
+      }
+    }
+
+    if evaluation.notify.is_some() {
+      notify(&mut handle, config.smtp.as_ref(), &diagnostic, &evaluation.connectivity).await?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{evaluate, Thresholds};
+  use crate::schema::{DeviceConnectivity, DeviceConnectivityStatus};
+
+  fn thresholds() -> Thresholds {
+    Thresholds {
+      offline_after: chrono::Duration::seconds(60),
+      notify_after: chrono::Duration::seconds(600),
+      flap_window: chrono::Duration::seconds(3600),
+      flap_threshold: 4,
+    }
+  }
+
+  fn seconds_ago(now: chrono::DateTime<chrono::Utc>, amount: i64) -> chrono::DateTime<chrono::Utc> {
+    now - chrono::Duration::seconds(amount)
+  }
+
+  #[test]
+  fn test_baseline_is_silent() {
+    let now = chrono::Utc::now();
+    let evaluation = evaluate(None, Some(seconds_ago(now, 86400)), now, &thresholds()).unwrap();
+    assert_eq!(evaluation.connectivity.status, DeviceConnectivityStatus::Offline);
+    assert_eq!(
+      evaluation.connectivity.notified,
+      Some(DeviceConnectivityStatus::Offline)
+    );
+    assert!(!evaluation.transitioned);
+    assert_eq!(evaluation.notify, None);
+  }
+
+  #[test]
+  fn test_offline_then_back_online() {
+    let now = chrono::Utc::now();
+    let online = DeviceConnectivity {
+      status: DeviceConnectivityStatus::Online,
+      since: seconds_ago(now, 3600),
+      notified: Some(DeviceConnectivityStatus::Online),
+      transitions: vec![],
+    };
+
+    // Still checking in; nothing to do.
+    assert_eq!(
+      evaluate(Some(&online), Some(seconds_ago(now, 5)), now, &thresholds()),
+      None
+    );
+
+    // Gone quiet: a transition, but too early to notify.
+    let offline = evaluate(Some(&online), Some(seconds_ago(now, 90)), now, &thresholds()).unwrap();
+    assert!(offline.transitioned);
+    assert_eq!(offline.connectivity.status, DeviceConnectivityStatus::Offline);
+    assert_eq!(offline.notify, None);
+
+    // Still quiet after the threshold.
+    let later = now + chrono::Duration::seconds(600);
+    let notified = evaluate(
+      Some(&offline.connectivity),
+      Some(seconds_ago(now, 90)),
+      later,
+      &thresholds(),
+    )
+    .unwrap();
+    assert!(!notified.transitioned);
+    assert_eq!(notified.notify, Some(DeviceConnectivityStatus::Offline));
+    assert_eq!(
+      evaluate(
+        Some(&notified.connectivity),
+        Some(seconds_ago(now, 90)),
+        later,
+        &thresholds()
+      ),
+      None
+    );
+
+    // Back online, which the owner hears about since they were told it went offline.
+    let back = evaluate(Some(&notified.connectivity), Some(later), later, &thresholds()).unwrap();
+    assert!(back.transitioned);
+    assert_eq!(back.notify, Some(DeviceConnectivityStatus::Online));
+  }
+
+  #[test]
+  fn test_short_outage_and_flapping() {
+    let now = chrono::Utc::now();
+    let offline = DeviceConnectivity {
+      status: DeviceConnectivityStatus::Offline,
+      since: seconds_ago(now, 120),
+      notified: Some(DeviceConnectivityStatus::Online),
+      transitions: vec![seconds_ago(now, 120).timestamp_millis()],
+    };
+
+    // Owners never heard about the short outage, so they do not hear about the recovery.
+    let back = evaluate(Some(&offline), Some(now), now, &thresholds()).unwrap();
+    assert!(back.transitioned);
+    assert_eq!(back.notify, None);
+
+    let flapping = DeviceConnectivity {
+      since: seconds_ago(now, 900),
+      notified: Some(DeviceConnectivityStatus::Offline),
+      transitions: (1..=3).map(|i| seconds_ago(now, i * 600).timestamp_millis()).collect(),
+      ..offline
+    };
+    let evaluation = evaluate(Some(&flapping), Some(now), now, &thresholds()).unwrap();
+    assert!(evaluation.flapping);
+    assert_eq!(evaluation.notify, None);
+  }
+}
@@ -0,0 +1,241 @@
+//! Devices authenticate with redis using their id and a random secret. The secret is handed to the
+//! device exactly once: either alongside its id when it is popped from the available pool, or on
+//! the device's own credential queue after a rotation. Only sha256 digests of secrets are stored.
+//!
+//! Rotation happens in two steps. Rotating creates a new secret that is accepted in addition to the
+//! current one, giving the device time to pick it up. Finalizing then drops every other secret,
+//! which can only be done once the device has taken its new credentials off the queue.
+
+use crate::schema;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The kinds of credential jobs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DeviceCredentialRequest {
+  /// Creates a new secret for the device with the provided id.
+  Rotate(String),
+
+  /// Revokes every secret other than the one created by the last rotation.
+  Finalize(String),
+}
+
+/// Returns the collection that credential records are stored in.
+pub fn collection(
+  mongo: &mongodb::Client,
+  config: &crate::config::MongoConfiguration,
+) -> mongodb::Collection<schema::DeviceCredentialRecord> {
+  mongo.database(&config.database).collection(
+    config
+      .collections
+      .device_credentials
+      .as_deref()
+      .unwrap_or("device_credentials"),
+  )
+}
+
+/// Returns the secrets that should be accepted after a rotation: the currently active ones, along
+/// with the new one. A previous rotation that was never finalized is replaced.
+fn rotated_hashes(record: &schema::DeviceCredentialRecord, new_hash: &str) -> Vec<String> {
+  record
+    .secret_hashes
+    .iter()
+    .filter(|hash| Some(*hash) != record.pending_secret_hash.as_ref())
+    .cloned()
+    .chain(std::iter::once(new_hash.to_string()))
+    .collect()
+}
+
+/// Sends the acl entry to redis, failing if it was not accepted.
+async fn write_acl<C>(connection: &mut C, id: &str, secret_hashes: &[String]) -> anyhow::Result<()>
+where
+  C: async_std::io::Read + async_std::io::Write + std::marker::Unpin,
+{
+  match kramer::execute(connection, crate::redis::DeviceAclEntry { id, secret_hashes }).await? {
+    kramer::Response::Item(kramer::ResponseValue::String(value)) if value == "OK" => Ok(()),
+    other => Err(anyhow::Error::msg(format!(
+      "unable to write acl entry for '{id}' - {other:?}"
+    ))),
+  }
+}
+
+/// Creates the acl entry and credential record for a new device id, returning the `<id>:<secret>`
+/// entry that should be handed to the device.
+pub async fn provision<C>(
+  connection: &mut C,
+  credentials: &mongodb::Collection<schema::DeviceCredentialRecord>,
+  id: &str,
+) -> anyhow::Result<String>
+where
+  C: async_std::io::Read + async_std::io::Write + std::marker::Unpin,
+{
+  let secret = crate::identity::create_secret();
+  let secret_hashes = vec![crate::redis::hash_secret(&secret)];
+
+  write_acl(connection, id, &secret_hashes).await?;
+
+  credentials
+    .insert_one(
+      schema::DeviceCredentialRecord {
+        device_id: id.to_string(),
+        secret_hashes,
+        pending_secret_hash: None,
+        created_at: Some(chrono::Utc::now()),
+        rotated_at: None,
+      },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to store credentials for '{id}'"))?;
+
+  Ok(format!("{id}:{secret}"))
+}
+
+/// Creates a new secret for the device, pushing it onto the device credential queue.
+pub async fn rotate<C>(
+  connection: &mut C,
+  credentials: &mongodb::Collection<schema::DeviceCredentialRecord>,
+  device_id: &str,
+) -> anyhow::Result<()>
+where
+  C: async_std::io::Read + async_std::io::Write + std::marker::Unpin,
+{
+  // Devices provisioned before secrets were introduced use their id as their password.
+  let record = credentials
+    .find_one(bson::doc! { "device_id": device_id }, None)
+    .await?
+    .unwrap_or_else(|| schema::DeviceCredentialRecord {
+      device_id: device_id.to_string(),
+      secret_hashes: vec![crate::redis::hash_secret(device_id)],
+      ..Default::default()
+    });
+
+  let secret = crate::identity::create_secret();
+  let new_hash = crate::redis::hash_secret(&secret);
+  let secret_hashes = rotated_hashes(&record, &new_hash);
+
+  write_acl(connection, device_id, &secret_hashes).await?;
+
+  let queue = crate::redis::device_credential_queue_id(device_id);
+  kramer::execute(
+    &mut *connection,
+    kramer::Command::Del::<&str, &str>(kramer::Arity::One(&queue)),
+  )
+  .await?;
+  kramer::execute(
+    &mut *connection,
+    kramer::Command::Lists(kramer::ListCommand::Push(
+      (kramer::Side::Right, kramer::Insertion::Always),
+      queue.as_str(),
+      kramer::Arity::One(format!("{device_id}:{secret}")),
+    )),
+  )
+  .await?;
+
+  credentials
+    .update_one(
+      bson::doc! { "device_id": device_id },
+      bson::doc! {
+        "$set": {
+          "secret_hashes": secret_hashes,
+          "pending_secret_hash": new_hash,
+          "rotated_at": bson::to_bson(&chrono::Utc::now())?,
+        },
+      },
+      mongodb::options::UpdateOptions::builder().upsert(true).build(),
+    )
+    .await
+    .with_context(|| format!("unable to store rotated credentials for '{device_id}'"))?;
+
+  log::info!("rotated credentials for '{device_id}', waiting on device to pick them up");
+  Ok(())
+}
+
+/// Revokes every secret for the device other than the one created by the last rotation.
+pub async fn finalize<C>(
+  connection: &mut C,
+  credentials: &mongodb::Collection<schema::DeviceCredentialRecord>,
+  device_id: &str,
+) -> anyhow::Result<()>
+where
+  C: async_std::io::Read + async_std::io::Write + std::marker::Unpin,
+{
+  let pending = credentials
+    .find_one(bson::doc! { "device_id": device_id }, None)
+    .await?
+    .and_then(|record| record.pending_secret_hash)
+    .ok_or_else(|| anyhow::Error::msg(format!("no rotation pending for '{device_id}'")))?;
+
+  let queue = crate::redis::device_credential_queue_id(device_id);
+  match kramer::execute(
+    &mut *connection,
+    kramer::Command::Lists::<&str, &str>(kramer::ListCommand::Len(&queue)),
+  )
+  .await?
+  {
+    kramer::Response::Item(kramer::ResponseValue::Integer(0)) => (),
+    kramer::Response::Item(kramer::ResponseValue::Integer(_)) => {
+      return Err(anyhow::Error::msg(format!(
+        "'{device_id}' has not picked up its new credentials"
+      )))
+    }
+    other => {
+      return Err(anyhow::Error::msg(format!(
+        "strange response from credential queue length - {other:?}"
+      )))
+    }
+  }
+
+  let secret_hashes = vec![pending];
+  write_acl(connection, device_id, &secret_hashes).await?;
+
+  credentials
+    .update_one(
+      bson::doc! { "device_id": device_id },
+      bson::doc! {
+        "$set": { "secret_hashes": secret_hashes },
+        "$unset": { "pending_secret_hash": "" },
+      },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to store finalized credentials for '{device_id}'"))?;
+
+  log::info!("finalized credential rotation for '{device_id}'");
+  Ok(())
+}
+
+/// Processes the credential request.
+pub(super) async fn apply(
+  mut handle: super::worker::WorkerHandle<'_>,
+  request: &DeviceCredentialRequest,
+) -> anyhow::Result<()> {
+  let credentials = collection(&handle.mongo.client, &handle.mongo.config);
+
+  match request {
+    DeviceCredentialRequest::Rotate(device_id) => rotate(handle.redis(), &credentials, device_id).await,
+    DeviceCredentialRequest::Finalize(device_id) => finalize(handle.redis(), &credentials, device_id).await,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::rotated_hashes;
+  use crate::schema;
+
+  #[test]
+  fn test_rotated_hashes() {
+    let mut record = schema::DeviceCredentialRecord {
+      device_id: "device".to_string(),
+      secret_hashes: vec!["current".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(rotated_hashes(&record, "next"), vec!["current", "next"]);
+
+    // Rotating again before the device picked up the last secret replaces it.
+    record.secret_hashes = vec!["current".to_string(), "next".to_string()];
+    record.pending_secret_hash = Some("next".to_string());
+    assert_eq!(rotated_hashes(&record, "again"), vec!["current", "again"]);
+  }
+}
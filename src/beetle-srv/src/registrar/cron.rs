@@ -0,0 +1,287 @@
+//! A small parser for the standard five-field cron syntax (`minute hour day-of-month month
+//! day-of-week`), and the math for finding the next point in time an expression fires at.
+//!
+//! Expressions are evaluated in a timezone: during daylight saving transitions, local times that are
+//! skipped never match, and local times that happen twice only match the first time around.
+
+use std::io;
+
+/// The most amount of time we will look ahead for the next match. Expressions that only match on
+/// leap days falling on a particular weekday can take a while to come around.
+const SEARCH_LIMIT_DAYS: i64 = 366 * 30;
+
+/// A parsed cron expression. Each field is a bitmask of the values it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpression {
+  /// Bit `n` is set if minute `n` matches.
+  minutes: u64,
+  /// Bit `n` is set if hour `n` matches.
+  hours: u64,
+  /// Bit `n` is set if day `n` of the month matches.
+  days_of_month: u64,
+  /// Bit `n` is set if month `n` matches, starting at 1.
+  months: u64,
+  /// Bit `n` is set if weekday `n` matches, starting at 0 for sunday.
+  days_of_week: u64,
+  /// Whether the day of month field was restricted. When both day fields are restricted, either
+  /// one matching is enough.
+  restricted_days_of_month: bool,
+  /// Whether the day of week field was restricted.
+  restricted_days_of_week: bool,
+}
+
+/// Returns an error describing a bad expression.
+fn invalid<S>(message: S) -> io::Error
+where
+  S: std::fmt::Display,
+{
+  io::Error::new(
+    io::ErrorKind::InvalidInput,
+    format!("invalid cron expression - {message}"),
+  )
+}
+
+/// Parses a single number within a field, checking it against the bounds.
+fn parse_value(value: &str, min: u32, max: u32) -> io::Result<u32> {
+  let parsed = value
+    .parse::<u32>()
+    .map_err(|_| invalid(format!("'{value}' is not a number")))?;
+
+  if parsed < min || parsed > max {
+    return Err(invalid(format!("'{parsed}' is not within {min}-{max}")));
+  }
+
+  Ok(parsed)
+}
+
+/// Parses one field of an expression into a bitmask. Fields are comma separated lists of `*`,
+/// single values or ranges, each optionally followed by a `/step`.
+fn parse_field(field: &str, min: u32, max: u32) -> io::Result<u64> {
+  let mut mask = 0u64;
+
+  for part in field.split(',') {
+    let (range, step) = match part.split_once('/') {
+      Some((range, step)) => (range, parse_value(step, 1, max)?),
+      None => (part, 1),
+    };
+
+    let (start, end) = match (range, range.split_once('-')) {
+      ("*", _) => (min, max),
+      (_, Some((start, end))) => (parse_value(start, min, max)?, parse_value(end, min, max)?),
+      // A single value with a step runs to the end of the field, e.g `5/15`.
+      (value, None) if step > 1 => (parse_value(value, min, max)?, max),
+      (value, None) => {
+        let value = parse_value(value, min, max)?;
+        (value, value)
+      }
+    };
+
+    if start > end {
+      return Err(invalid(format!("range '{range}' is backwards")));
+    }
+
+    for value in (start..=end).step_by(step as usize) {
+      mask |= 1 << value;
+    }
+  }
+
+  Ok(mask)
+}
+
+impl CronExpression {
+  /// Parses an expression. Besides the five fields, the `@hourly`, `@daily`, `@weekly`, `@monthly`
+  /// and `@yearly` shorthands are supported.
+  pub fn parse(expression: &str) -> io::Result<Self> {
+    let expression = match expression.trim() {
+      "@hourly" => "0 * * * *",
+      "@daily" | "@midnight" => "0 0 * * *",
+      "@weekly" => "0 0 * * 0",
+      "@monthly" => "0 0 1 * *",
+      "@yearly" | "@annually" => "0 0 1 1 *",
+      other => other,
+    };
+
+    let fields = expression.split_whitespace().collect::<Vec<&str>>();
+    let [minutes, hours, days_of_month, months, days_of_week] = fields.as_slice() else {
+      return Err(invalid(format!("expected 5 fields, found {}", fields.len())));
+    };
+
+    // Both 0 and 7 mean sunday.
+    let mut weekdays = parse_field(days_of_week, 0, 7)?;
+    if weekdays & (1 << 7) != 0 {
+      weekdays = (weekdays | 1) & !(1 << 7);
+    }
+
+    Ok(Self {
+      minutes: parse_field(minutes, 0, 59)?,
+      hours: parse_field(hours, 0, 23)?,
+      days_of_month: parse_field(days_of_month, 1, 31)?,
+      months: parse_field(months, 1, 12)?,
+      days_of_week: weekdays,
+      restricted_days_of_month: *days_of_month != "*",
+      restricted_days_of_week: *days_of_week != "*",
+    })
+  }
+
+  /// Returns true if the expression matches the day.
+  fn matches_day(&self, date: chrono::NaiveDate) -> bool {
+    use chrono::Datelike;
+    let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+    let day_of_week = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+
+    match (self.restricted_days_of_month, self.restricted_days_of_week) {
+      (true, true) => day_of_month || day_of_week,
+      _ => day_of_month && day_of_week,
+    }
+  }
+
+  /// Returns the first point in time strictly after `after` that the expression matches, when
+  /// evaluated in the provided timezone.
+  pub fn next_after<T>(
+    &self,
+    after: chrono::DateTime<chrono::Utc>,
+    timezone: &T,
+  ) -> Option<chrono::DateTime<chrono::Utc>>
+  where
+    T: chrono::TimeZone,
+  {
+    use chrono::{Datelike, Timelike};
+
+    let local = after.with_timezone(timezone).naive_local();
+    let start = local.with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+    let limit = start + chrono::Duration::days(SEARCH_LIMIT_DAYS);
+    let mut current = start;
+
+    while current < limit {
+      let date = current.date();
+
+      if self.months & (1 << date.month()) == 0 {
+        let (year, month) = match date.month() {
+          12 => (date.year() + 1, 1),
+          month => (date.year(), month + 1),
+        };
+        current = chrono::NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+        continue;
+      }
+
+      if !self.matches_day(date) {
+        current = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+        continue;
+      }
+
+      if self.hours & (1 << current.hour()) == 0 {
+        current = current.with_minute(0)? + chrono::Duration::hours(1);
+        continue;
+      }
+
+      if self.minutes & (1 << current.minute()) == 0 {
+        current += chrono::Duration::minutes(1);
+        continue;
+      }
+
+      // Skipped local times have no point in time to fire at; repeated ones fire at the first.
+      match timezone.from_local_datetime(&current).earliest() {
+        Some(time) if time.with_timezone(&chrono::Utc) > after => return Some(time.with_timezone(&chrono::Utc)),
+        _ => current += chrono::Duration::minutes(1),
+      }
+    }
+
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::CronExpression;
+
+  fn at(value: &str) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::parse_from_rfc3339(value).unwrap().into()
+  }
+
+  #[test]
+  fn test_parse() {
+    assert!(CronExpression::parse("*/15 9-17 * * 1-5").is_ok());
+    assert!(CronExpression::parse("0 0 1,15 * *").is_ok());
+    assert!(CronExpression::parse("@daily").is_ok());
+    assert!(CronExpression::parse("* * * *").is_err());
+    assert!(CronExpression::parse("60 * * * *").is_err());
+    assert!(CronExpression::parse("0 5-1 * * *").is_err());
+    assert!(CronExpression::parse("0 0 0 * *").is_err());
+  }
+
+  #[test]
+  fn test_next_after() {
+    let weekdays = CronExpression::parse("30 8 * * 1-5").unwrap();
+    // Friday evening, so the next match is monday morning.
+    assert_eq!(
+      weekdays.next_after(at("2024-03-01T18:00:00Z"), &chrono::Utc),
+      Some(at("2024-03-04T08:30:00Z"))
+    );
+    // Matches are strictly after the provided time.
+    assert_eq!(
+      weekdays.next_after(at("2024-03-04T08:30:00Z"), &chrono::Utc),
+      Some(at("2024-03-05T08:30:00Z"))
+    );
+
+    let quarter = CronExpression::parse("*/15 * * * *").unwrap();
+    assert_eq!(
+      quarter.next_after(at("2024-03-01T10:07:42Z"), &chrono::Utc),
+      Some(at("2024-03-01T10:15:00Z"))
+    );
+
+    let leap = CronExpression::parse("0 0 29 2 *").unwrap();
+    assert_eq!(
+      leap.next_after(at("2024-03-01T00:00:00Z"), &chrono::Utc),
+      Some(at("2028-02-29T00:00:00Z"))
+    );
+
+    assert_eq!(
+      CronExpression::parse("0 0 30 2 *")
+        .unwrap()
+        .next_after(at("2024-03-01T00:00:00Z"), &chrono::Utc),
+      None
+    );
+  }
+
+  #[test]
+  fn test_offsets_and_days() {
+    // 07:00 in new york is noon in utc during the winter, and 11:00 once daylight saving starts.
+    let morning = CronExpression::parse("0 7 * * *").unwrap();
+    let new_york = chrono_tz::America::New_York;
+    assert_eq!(
+      morning.next_after(at("2024-03-01T06:00:00Z"), &new_york),
+      Some(at("2024-03-01T12:00:00Z"))
+    );
+    assert_eq!(
+      morning.next_after(at("2024-03-10T06:00:00Z"), &new_york),
+      Some(at("2024-03-10T11:00:00Z"))
+    );
+
+    // 02:30 does not happen on the day daylight saving starts, and happens twice when it ends.
+    let night = CronExpression::parse("30 2 * * *").unwrap();
+    assert_eq!(
+      night.next_after(at("2024-03-10T05:00:00Z"), &new_york),
+      Some(at("2024-03-11T06:30:00Z"))
+    );
+    let repeated = CronExpression::parse("30 1 * * *").unwrap();
+    assert_eq!(
+      repeated.next_after(at("2024-11-03T04:00:00Z"), &new_york),
+      Some(at("2024-11-03T05:30:00Z"))
+    );
+    assert_eq!(
+      repeated.next_after(at("2024-11-03T05:30:00Z"), &new_york),
+      Some(at("2024-11-04T06:30:00Z"))
+    );
+
+    // Restricting both day fields matches either of them; sundays can be written as 7.
+    let either = CronExpression::parse("0 0 15 * 7").unwrap();
+    assert_eq!(
+      either.next_after(at("2024-03-01T00:00:00Z"), &chrono::Utc),
+      Some(at("2024-03-03T00:00:00Z"))
+    );
+    assert_eq!(
+      either.next_after(at("2024-03-10T00:00:00Z"), &chrono::Utc),
+      Some(at("2024-03-15T00:00:00Z"))
+    );
+  }
+}
@@ -0,0 +1,258 @@
+//! Decommissioning removes a device from the system in one step. Everything stored for the device
+//! is moved into the archive collection, pending renders and messages are dropped, and the device
+//! is removed from every user that had it. Album photos are deleted from disc; the archive only
+//! keeps their metadata.
+//!
+//! By default the device credentials are revoked as well, meaning the hardware will need to
+//! request a fresh id. When resetting the registration instead, the device keeps its id and goes
+//! back to the "initial" registration state; the next time it checks in, it will be sent a new
+//! registration scannable.
+
+use crate::schema;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The request to decommission a device.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceDecommissionRequest {
+  /// The id of the device.
+  pub device_id: String,
+
+  /// When true, the device keeps its credentials and returns to the "initial" registration state
+  /// rather than being removed entirely.
+  #[serde(default)]
+  pub reset_registration: bool,
+}
+
+/// Returns a collection of untyped documents; archives are stored as-is.
+fn raw_collection(handle: &super::worker::WorkerHandle<'_>, name: &str) -> mongodb::Collection<bson::Document> {
+  handle
+    .mongo
+    .client
+    .database(&handle.mongo.config.database)
+    .collection(name)
+}
+
+/// Returns the collections holding documents keyed by `device_id`, all of which are deleted once the
+/// device has been archived. Inbound hooks are deleted separately; they are not archived.
+fn purged_collections(collections: &crate::config::MongoCollectionsConfiguration) -> [&str; 5] {
+  [
+    &collections.device_authorities,
+    &collections.device_states,
+    &collections.device_schedules,
+    &collections.device_histories,
+    collections.device_events.as_deref().unwrap_or("device_events"),
+  ]
+}
+
+/// Returns the redis commands that revoke the credentials of a device: its ACL user, its entry in
+/// the registrar index and its credential queue.
+fn revocation_commands(device_id: &str) -> [kramer::Command<String, String>; 3] {
+  [
+    kramer::Command::Acl(kramer::acl::AclCommand::DelUser(kramer::Arity::One(
+      device_id.to_string(),
+    ))),
+    kramer::Command::Sets(kramer::SetCommand::Rem(
+      crate::constants::REGISTRAR_INDEX.to_string(),
+      kramer::Arity::One(device_id.to_string()),
+    )),
+    kramer::Command::Del(kramer::Arity::One(crate::redis::device_credential_queue_id(device_id))),
+  ]
+}
+
+/// Returns the location on disc of every photo in the album of an archived device state.
+fn album_locations(state: Option<&bson::Document>) -> Vec<String> {
+  state
+    .and_then(|state| state.get_array("album").ok())
+    .map(|album| {
+      album
+        .iter()
+        .filter_map(|photo| photo.as_document())
+        .filter_map(|photo| photo.get_str("location").ok())
+        .map(str::to_string)
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Copies everything stored for the device into the archive collection, then deletes the originals.
+/// The diagnostic record is only deleted when the device is not being reset.
+async fn archive(
+  handle: &mut super::worker::WorkerHandle<'_>,
+  request: &DeviceDecommissionRequest,
+) -> anyhow::Result<()> {
+  let device_id = &request.device_id;
+  let collections = handle.mongo.config.collections.clone();
+  let by_device = bson::doc! { "device_id": device_id };
+
+  let diagnostics = raw_collection(handle, &collections.device_diagnostics);
+  let authorities = raw_collection(handle, &collections.device_authorities);
+  let states = raw_collection(handle, &collections.device_states);
+  let schedules = raw_collection(handle, &collections.device_schedules);
+  let histories = raw_collection(handle, &collections.device_histories);
+
+  let mut record = schema::DeviceArchiveRecord {
+    device_id: device_id.clone(),
+    archived_at: Some(chrono::Utc::now()),
+    diagnostic: diagnostics.find_one(bson::doc! { "id": device_id }, None).await?,
+    authority: authorities.find_one(by_device.clone(), None).await?,
+    state: states.find_one(by_device.clone(), None).await?,
+    schedule: schedules.find_one(by_device.clone(), None).await?,
+    histories: vec![],
+  };
+
+  let mut cursor = histories.find(by_device.clone(), None).await?;
+  while cursor.advance().await? {
+    record.histories.push(cursor.deserialize_current()?);
+  }
+
+  handle
+    .device_archive_collection()?
+    .insert_one(&record, None)
+    .await
+    .with_context(|| format!("unable to archive '{device_id}'"))?;
+
+  for name in purged_collections(&collections) {
+    raw_collection(handle, name)
+      .delete_many(by_device.clone(), None)
+      .await?;
+  }
+  handle.inbound_hook_collection()?.delete_many(by_device, None).await?;
+
+  for location in album_locations(record.state.as_ref()) {
+    log::info!("removing album photo '{location}' from disc for '{device_id}'");
+    if let Err(error) = async_std::fs::remove_file(&location).await {
+      log::warn!("unable to remove album photo '{location}' - {error}");
+    }
+  }
+
+  if request.reset_registration {
+    let registration = bson::to_bson(&schema::DeviceDiagnosticRegistration::Initial)
+      .with_context(|| "unable to serialize registration state")?;
+    diagnostics
+      .update_one(
+        bson::doc! { "id": device_id },
+        bson::doc! {
+          "$set": { "registration_state": registration },
+          "$unset": {
+            "nickname": "",
+            "sent_message_count": "",
+            "telemetry": "",
+            "telemetry_history": "",
+            "connectivity": "",
+          },
+        },
+        None,
+      )
+      .await
+      .with_context(|| format!("unable to reset registration for '{device_id}'"))?;
+  } else {
+    diagnostics.delete_one(bson::doc! { "id": device_id }, None).await?;
+  }
+
+  Ok(())
+}
+
+/// Processes the decommission request.
+pub(super) async fn apply(
+  mut handle: super::worker::WorkerHandle<'_>,
+  request: &DeviceDecommissionRequest,
+) -> anyhow::Result<()> {
+  let device_id = &request.device_id;
+
+  let purged = handle.purge_renders(device_id).await?;
+  handle
+    .command(&kramer::Command::Del::<String, &str>(kramer::Arity::One(
+      crate::redis::device_message_queue_id(device_id),
+    )))
+    .await?;
+  log::info!("decommissioning '{device_id}', dropped {purged} pending render(s) and its message queue");
+
+  if !request.reset_registration {
+    for command in revocation_commands(device_id) {
+      handle.command(&command).await?;
+    }
+    super::credentials::collection(&handle.mongo.client, &handle.mongo.config)
+      .delete_many(bson::doc! { "device_id": device_id }, None)
+      .await
+      .with_context(|| format!("unable to remove credentials for '{device_id}'"))?;
+    log::info!("revoked credentials for '{device_id}'");
+  }
+
+  archive(&mut handle, request).await?;
+
+  let result = handle
+    .users_collection()?
+    .update_many(
+      bson::doc! { format!("devices.{device_id}"): { "$exists": true } },
+      bson::doc! { "$unset": { format!("devices.{device_id}"): "" } },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to remove '{device_id}' from users"))?;
+  log::info!("removed '{device_id}' from {} user(s)", result.modified_count);
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{album_locations, purged_collections, revocation_commands};
+
+  #[test]
+  fn test_revocation_commands() {
+    let commands = revocation_commands("device")
+      .iter()
+      .map(|command| command.to_string())
+      .collect::<Vec<String>>();
+
+    assert_eq!(commands[0], "*3\r\n$3\r\nACL\r\n$7\r\nDELUSER\r\n$6\r\ndevice\r\n");
+    assert!(commands[1].contains("SREM"));
+    assert!(commands[1].ends_with("$6\r\ndevice\r\n"));
+    assert!(commands[2].contains(&crate::redis::device_credential_queue_id("device")));
+  }
+
+  #[test]
+  fn test_purged_collections() {
+    let collections = toml::from_str::<crate::config::MongoCollectionsConfiguration>(
+      r#"
+        migrations = "migrations"
+        users = "users"
+        device_authorities = "authorities"
+        device_diagnostics = "diagnostics"
+        device_schedules = "schedules"
+        device_histories = "histories"
+        device_states = "states"
+      "#,
+    )
+    .unwrap();
+    let purged = purged_collections(&collections);
+
+    assert!(purged.contains(&"schedules"));
+    assert!(purged.contains(&"authorities"));
+    assert!(purged.contains(&"states"));
+    assert!(purged.contains(&"histories"));
+    assert!(!purged.contains(&"diagnostics"));
+    assert!(purged.contains(&"device_events"));
+  }
+
+  #[test]
+  fn test_album_locations() {
+    let state = bson::doc! {
+      "device_id": "device",
+      "album": [
+        { "id": "a", "location": "/tmp/a.png" },
+        { "id": "b", "location": "/tmp/b.png" },
+        { "id": "broken" },
+      ],
+    };
+
+    assert_eq!(
+      album_locations(Some(&state)),
+      vec!["/tmp/a.png".to_string(), "/tmp/b.png".to_string()]
+    );
+    assert!(album_locations(Some(&bson::doc! { "device_id": "device" })).is_empty());
+    assert!(album_locations(None).is_empty());
+  }
+}
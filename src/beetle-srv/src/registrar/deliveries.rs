@@ -0,0 +1,394 @@
+//! Tracks renders after they have been pushed onto a device queue. A render is "delivered" once the
+//! device has emptied its queue, and "displayed" once the device checks in with the render id
+//! stamped on the image it is showing. Renders still waiting on the queue when a newer one is sent
+//! are "superseded". Devices that report the render they display have their renders flagged as
+//! unacknowledged when they are not displayed within the configured timeout; for every other
+//! device, delivery is as far as a render can be tracked.
+//!
+//! Every change is written to both the device history and the job results, so clients polling the
+//! render job can follow along.
+
+use crate::schema::{self, jobs::RenderDelivery, jobs::RenderDeliveryStatus};
+use anyhow::Context;
+
+/// The amount of seconds a device has to display a render, when not configured.
+const DEFAULT_ACKNOWLEDGEMENT_TIMEOUT_SECONDS: i64 = 60 * 5;
+
+/// What was learned about the device renders during a check-in.
+#[derive(Debug, Default)]
+struct Observation<'a> {
+  /// The id of the render the device must have taken off its queue.
+  delivered: Option<&'a str>,
+
+  /// The id of the render the device reported displaying.
+  displayed: Option<&'a str>,
+}
+
+/// Returns the timeout used for acknowledgements.
+fn timeout(config: &crate::config::RegistrarConfiguration) -> chrono::Duration {
+  chrono::Duration::seconds(
+    config
+      .render_acknowledgement_timeout_seconds
+      .unwrap_or(DEFAULT_ACKNOWLEDGEMENT_TIMEOUT_SECONDS),
+  )
+}
+
+/// Returns the delivery moved along based on what was observed, or `None` if nothing changed.
+fn advance(
+  delivery: &RenderDelivery,
+  observation: &Observation<'_>,
+  now: chrono::DateTime<chrono::Utc>,
+  timeout: chrono::Duration,
+) -> Option<RenderDelivery> {
+  let mut next = delivery.clone();
+
+  match delivery.status {
+    RenderDeliveryStatus::Displayed | RenderDeliveryStatus::Unacknowledged | RenderDeliveryStatus::Superseded => {
+      return None
+    }
+    _ if observation.displayed == Some(delivery.id.as_str()) => {
+      next.status = RenderDeliveryStatus::Displayed;
+      next.displayed_at = Some(now);
+    }
+    _ if delivery.expects_display && now - delivery.queued_at > timeout => {
+      next.status = RenderDeliveryStatus::Unacknowledged;
+      return Some(next);
+    }
+    RenderDeliveryStatus::Queued if observation.delivered == Some(delivery.id.as_str()) => {
+      next.status = RenderDeliveryStatus::Delivered;
+    }
+    _ => return None,
+  }
+
+  if next.delivered_at.is_none() {
+    next.delivered_at = Some(now);
+    next.delivery_latency_ms = Some((now - delivery.queued_at).num_milliseconds());
+  }
+
+  Some(next)
+}
+
+/// Returns the delivery settled as a newer render is pushed onto the device queue, or `None` if it
+/// was no longer waiting on the queue. The renderer clears the queue before every push, so a render
+/// that is still queued was either taken off by the device already, or is being cleared out now.
+/// When the device took it is unknown.
+fn supersede(delivery: &RenderDelivery, cleared: bool) -> Option<RenderDelivery> {
+  if delivery.status != RenderDeliveryStatus::Queued {
+    return None;
+  }
+
+  let mut next = delivery.clone();
+  next.status = match cleared {
+    true => RenderDeliveryStatus::Superseded,
+    false => RenderDeliveryStatus::Delivered,
+  };
+  Some(next)
+}
+
+/// Stores the updated delivery on the device history, and as the result of the render job.
+async fn store<C>(
+  connection: &mut C,
+  histories: &mongodb::Collection<schema::DeviceHistoryRecord>,
+  device_id: &str,
+  delivery: &RenderDelivery,
+) -> anyhow::Result<()>
+where
+  C: async_std::io::Read + async_std::io::Write + std::marker::Unpin,
+{
+  let serialized = bson::to_bson(delivery).with_context(|| "unable to serialize render delivery")?;
+  histories
+    .update_one(
+      bson::doc! { "device_id": device_id, "render_deliveries.id": &delivery.id },
+      bson::doc! { "$set": { "render_deliveries.$": serialized } },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to update delivery of render '{}'", delivery.id))?;
+
+  let result = serde_json::to_string(&schema::jobs::JobResult::Success(
+    schema::jobs::SuccessfulJobResult::Render(delivery.clone()),
+  ))?;
+  kramer::execute(
+    connection,
+    kramer::Command::Hashes(kramer::HashCommand::Set(
+      crate::constants::REGISTRAR_JOB_RESULTS,
+      kramer::Arity::One((delivery.id.as_str(), result)),
+      kramer::Insertion::Always,
+    )),
+  )
+  .await?;
+
+  log::info!(
+    "render '{}' for '{device_id}' is now {:?}",
+    delivery.id,
+    delivery.status
+  );
+  Ok(())
+}
+
+/// Applies the observation to every pending delivery of a history record.
+async fn apply_observation<C>(
+  connection: &mut C,
+  histories: &mongodb::Collection<schema::DeviceHistoryRecord>,
+  record: &schema::DeviceHistoryRecord,
+  observation: &Observation<'_>,
+  timeout: chrono::Duration,
+) -> anyhow::Result<()>
+where
+  C: async_std::io::Read + async_std::io::Write + std::marker::Unpin,
+{
+  let now = chrono::Utc::now();
+
+  for delivery in record.render_deliveries.iter().flatten() {
+    if let Some(next) = advance(delivery, observation, now, timeout) {
+      store(connection, histories, &record.device_id, &next).await?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Called by the renderer once it has cleared the device queue, before pushing a new render onto
+/// it. `cleared` is true when anything was still waiting on the queue.
+pub(crate) async fn settle<C>(
+  connection: &mut C,
+  histories: &mongodb::Collection<schema::DeviceHistoryRecord>,
+  device_id: &str,
+  cleared: bool,
+) -> anyhow::Result<()>
+where
+  C: async_std::io::Read + async_std::io::Write + std::marker::Unpin,
+{
+  let Some(record) = histories
+    .find_one(
+      bson::doc! { "device_id": device_id, "render_deliveries.status": "queued" },
+      None,
+    )
+    .await?
+  else {
+    return Ok(());
+  };
+
+  for delivery in record.render_deliveries.iter().flatten() {
+    if let Some(next) = supersede(delivery, cleared) {
+      store(connection, histories, device_id, &next).await?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Called as devices check in. Older renders are settled as newer ones are sent, so only the most
+/// recent one can still be waiting on the device queue.
+pub(super) async fn acknowledge<C>(
+  connection: &mut C,
+  histories: &mongodb::Collection<schema::DeviceHistoryRecord>,
+  config: &crate::config::RegistrarConfiguration,
+  device_id: &str,
+  displayed: Option<&str>,
+) -> anyhow::Result<()>
+where
+  C: async_std::io::Read + async_std::io::Write + std::marker::Unpin,
+{
+  let Some(record) = histories
+    .find_one(
+      bson::doc! {
+        "device_id": device_id,
+        "render_deliveries.status": { "$in": ["queued", "delivered"] },
+      },
+      None,
+    )
+    .await?
+  else {
+    return Ok(());
+  };
+
+  let queue_len = match kramer::execute(
+    &mut *connection,
+    kramer::Command::Lists::<String, &str>(kramer::ListCommand::Len(crate::redis::device_message_queue_id(
+      device_id,
+    ))),
+  )
+  .await?
+  {
+    kramer::Response::Item(kramer::ResponseValue::Integer(amount)) => amount,
+    other => return Err(anyhow::Error::msg(format!("strange device queue length - {other:?}"))),
+  };
+
+  let latest = record
+    .render_deliveries
+    .as_ref()
+    .and_then(|deliveries| deliveries.last())
+    .map(|delivery| delivery.id.as_str());
+
+  let observation = Observation {
+    delivered: latest.filter(|_| queue_len == 0),
+    displayed,
+  };
+
+  apply_observation(connection, histories, &record, &observation, timeout(config)).await
+}
+
+/// Flags renders that have gone too long without being displayed, including those sent to devices
+/// that have stopped checking in altogether.
+pub(super) async fn expire(handle: &mut super::worker::WorkerHandle<'_>) -> anyhow::Result<()> {
+  let timeout = timeout(handle.config);
+  let cutoff = (chrono::Utc::now() - timeout).timestamp_millis();
+  let histories = handle
+    .mongo
+    .client
+    .database(&handle.mongo.config.database)
+    .collection::<schema::DeviceHistoryRecord>(&handle.mongo.config.collections.device_histories);
+
+  let mut cursor = histories
+    .find(
+      bson::doc! {
+        "render_deliveries": {
+          "$elemMatch": {
+            "status": { "$in": ["queued", "delivered"] },
+            "queued_at": { "$lt": cutoff },
+            "expects_display": true,
+          },
+        },
+      },
+      None,
+    )
+    .await
+    .with_context(|| "unable to query expired render deliveries")?;
+
+  while cursor.advance().await? {
+    let record = cursor.deserialize_current()?;
+    apply_observation(handle.redis(), &histories, &record, &Observation::default(), timeout).await?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{advance, supersede, Observation};
+  use crate::schema::jobs::{RenderDelivery, RenderDeliveryStatus};
+
+  fn delivery(status: RenderDeliveryStatus, age_seconds: i64) -> RenderDelivery {
+    RenderDelivery {
+      id: "render".to_string(),
+      status,
+      queued_at: chrono::Utc::now() - chrono::Duration::seconds(age_seconds),
+      delivered_at: None,
+      displayed_at: None,
+      delivery_latency_ms: None,
+      expects_display: true,
+    }
+  }
+
+  #[test]
+  fn test_advance_through_statuses() {
+    let queued = delivery(RenderDeliveryStatus::Queued, 5);
+    let now = chrono::Utc::now();
+    let timeout = chrono::Duration::seconds(60);
+
+    assert_eq!(advance(&queued, &Observation::default(), now, timeout), None);
+
+    let delivered = advance(
+      &queued,
+      &Observation {
+        delivered: Some("render"),
+        displayed: None,
+      },
+      now,
+      timeout,
+    )
+    .unwrap();
+    assert_eq!(delivered.status, RenderDeliveryStatus::Delivered);
+    assert!(delivered.delivery_latency_ms.unwrap() >= 5000);
+
+    let displayed = advance(
+      &delivered,
+      &Observation {
+        delivered: None,
+        displayed: Some("render"),
+      },
+      now,
+      timeout,
+    )
+    .unwrap();
+    assert_eq!(displayed.status, RenderDeliveryStatus::Displayed);
+    assert_eq!(displayed.delivered_at, delivered.delivered_at);
+    assert_eq!(advance(&displayed, &Observation::default(), now, timeout), None);
+  }
+
+  #[test]
+  fn test_advance_unacknowledged() {
+    let now = chrono::Utc::now();
+    let timeout = chrono::Duration::seconds(60);
+
+    let stale = advance(
+      &delivery(RenderDeliveryStatus::Delivered, 120),
+      &Observation::default(),
+      now,
+      timeout,
+    )
+    .unwrap();
+    assert_eq!(stale.status, RenderDeliveryStatus::Unacknowledged);
+
+    // A late report still counts as long as the render has not been flagged yet.
+    let late = advance(
+      &delivery(RenderDeliveryStatus::Queued, 120),
+      &Observation {
+        delivered: None,
+        displayed: Some("render"),
+      },
+      now,
+      timeout,
+    )
+    .unwrap();
+    assert_eq!(late.status, RenderDeliveryStatus::Displayed);
+  }
+
+  #[test]
+  fn test_advance_without_display_reports() {
+    let now = chrono::Utc::now();
+    let timeout = chrono::Duration::seconds(60);
+    let mut stale = delivery(RenderDeliveryStatus::Delivered, 120);
+    stale.expects_display = false;
+
+    // Devices that never report what they display are not expected to.
+    assert_eq!(advance(&stale, &Observation::default(), now, timeout), None);
+
+    stale.status = RenderDeliveryStatus::Queued;
+    let delivered = advance(
+      &stale,
+      &Observation {
+        delivered: Some("render"),
+        displayed: None,
+      },
+      now,
+      timeout,
+    )
+    .unwrap();
+    assert_eq!(delivered.status, RenderDeliveryStatus::Delivered);
+  }
+
+  #[test]
+  fn test_supersede() {
+    let queued = delivery(RenderDeliveryStatus::Queued, 5);
+
+    let taken = supersede(&queued, false).unwrap();
+    assert_eq!(taken.status, RenderDeliveryStatus::Delivered);
+    assert_eq!(taken.delivered_at, None);
+
+    let dropped = supersede(&queued, true).unwrap();
+    assert_eq!(dropped.status, RenderDeliveryStatus::Superseded);
+    assert_eq!(
+      advance(
+        &dropped,
+        &Observation::default(),
+        chrono::Utc::now(),
+        chrono::Duration::seconds(1)
+      ),
+      None
+    );
+
+    assert_eq!(supersede(&delivery(RenderDeliveryStatus::Delivered, 5), true), None);
+  }
+}
@@ -3,9 +3,52 @@
 
 use crate::{schema, vendor::google};
 use anyhow::Context;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::io;
 
+/// The amount of seconds between runs of a schedule `kind`, unless configured otherwise.
+const DEFAULT_REFRESH_INTERVAL_SECONDS: i64 = 60 * 5;
+
+/// The most amount of entries a single schedule can have.
+pub const MAX_ENTRIES: usize = 10;
+
+/// The shortest refresh interval a schedule can be given, in seconds.
+pub const MIN_REFRESH_INTERVAL_SECONDS: u32 = 60;
+
+/// The longest refresh interval a schedule can be given, in seconds.
+pub const MAX_REFRESH_INTERVAL_SECONDS: u32 = 60 * 60 * 24;
+
+/// The kinds of changes that can be made to the schedule of a device.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DeviceScheduleChange {
+  /// Replaces what the schedule does; `None` leaves the schedule in place but stops it from
+  /// rendering anything.
+  SetKind(Option<schema::DeviceScheduleKind>),
+
+  /// Replaces how often the schedule is run; `None` goes back to the registrar default.
+  SetRefreshInterval(Option<u32>),
+
+  /// Adds an entry that fires on its own cron schedule.
+  AddEntry(Box<schema::DeviceScheduleEntry>),
+
+  /// Removes the entry with the provided id.
+  RemoveEntry(String),
+
+  /// Removes the schedule entirely.
+  Delete,
+}
+
+/// A request to change the schedule of a device.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceScheduleRequest {
+  /// The id of the device.
+  pub device_id: String,
+  /// The change being made.
+  pub change: DeviceScheduleChange,
+}
+
 /// TODO: this type is a mirror of the schema defined in our `schedule` module, it is likely we can
 /// bundle this up in the worker through some api for fetching an access token by user ID.
 #[derive(Deserialize, Debug)]
@@ -89,8 +132,265 @@ where
   Ok(())
 }
 
-/// This method is responsible for immediately running any schedule associated with the device id
-/// provded in the job.
+/// Returns how often the `kind` of a schedule runs, in milliseconds.
+fn refresh_interval_millis(config: &crate::config::RegistrarConfiguration, schedule: &schema::DeviceSchedule) -> i64 {
+  let seconds = schedule
+    .refresh_interval_seconds
+    .map(i64::from)
+    .or(config.device_schedule_refresh_interval_seconds)
+    .unwrap_or(DEFAULT_REFRESH_INTERVAL_SECONDS);
+
+  seconds * 1000
+}
+
+/// Returns when the entry fires next, after the provided point in time.
+fn next_fire_at(entry: &schema::DeviceScheduleEntry, after: chrono::DateTime<chrono::Utc>) -> Option<i64> {
+  let expression = super::cron::CronExpression::parse(&entry.cron)
+    .map_err(|error| log::warn!("schedule entry '{}' has a bad expression - {error}", entry.id))
+    .ok()?;
+
+  let timezone = match entry.timezone.as_deref().map(str::parse::<chrono_tz::Tz>) {
+    None => chrono_tz::UTC,
+    Some(Ok(timezone)) => timezone,
+    Some(Err(error)) => {
+      log::warn!("schedule entry '{}' has a bad timezone - {error}", entry.id);
+      return None;
+    }
+  };
+
+  expression
+    .next_after(after, &timezone)
+    .map(|time| time.timestamp_millis())
+}
+
+/// Returns the next time anything on the schedule is due: either the `kind` or the earliest entry.
+fn next_run_at(schedule: &schema::DeviceSchedule, interval_millis: i64, now: i64) -> Option<i64> {
+  let kind = schedule.kind.as_ref().map(|_| match schedule.last_executed {
+    Some(last) => last as i64 + interval_millis,
+    None => now,
+  });
+
+  schedule
+    .entries
+    .iter()
+    .filter_map(|entry| entry.next_fire_at)
+    .chain(kind)
+    .min()
+}
+
+/// Recomputes and stores when the schedule of a device is due next.
+pub(super) async fn reschedule(worker: &mut super::worker::WorkerHandle<'_>, device_id: &str) -> anyhow::Result<()> {
+  let collection = worker.device_schedule_collection()?;
+  let Some(schedule) = collection.find_one(bson::doc! { "device_id": device_id }, None).await? else {
+    return Ok(());
+  };
+
+  let next = next_run_at(
+    &schedule,
+    refresh_interval_millis(worker.config, &schedule),
+    chrono::Utc::now().timestamp_millis(),
+  );
+  log::debug!("schedule['{device_id}'] is next due at {next:?}");
+
+  collection
+    .update_one(
+      bson::doc! { "device_id": device_id },
+      bson::doc! { "$set": { "next_run_at": next } },
+      None,
+    )
+    .await?;
+
+  Ok(())
+}
+
+/// Applies a change to the schedule of a device, creating the schedule if there isn't one yet. The
+/// schedule is run right away whenever what it does changes.
+pub(super) async fn apply(
+  mut worker: super::worker::WorkerHandle<'_>,
+  request: &DeviceScheduleRequest,
+) -> anyhow::Result<()> {
+  let collection = worker.device_schedule_collection()?;
+  let query = bson::doc! { "device_id": &request.device_id };
+  let upsert = mongodb::options::UpdateOptions::builder().upsert(true).build();
+
+  let update = match &request.change {
+    DeviceScheduleChange::Delete => {
+      log::info!("removing device schedule for '{}'", request.device_id);
+      collection
+        .delete_one(query, None)
+        .await
+        .with_context(|| format!("unable to delete device schedule for '{}'", request.device_id))?;
+      return Ok(());
+    }
+    DeviceScheduleChange::SetKind(kind) => bson::doc! { "$set": { "kind": bson::to_bson(kind)? } },
+    DeviceScheduleChange::SetRefreshInterval(interval) => {
+      let interval = interval.map(|seconds| seconds.clamp(MIN_REFRESH_INTERVAL_SECONDS, MAX_REFRESH_INTERVAL_SECONDS));
+      bson::doc! { "$set": { "refresh_interval_seconds": interval } }
+    }
+    DeviceScheduleChange::AddEntry(entry) => {
+      let entry = schema::DeviceScheduleEntry {
+        next_fire_at: next_fire_at(entry, chrono::Utc::now()),
+        last_fired_at: None,
+        ..entry.as_ref().clone()
+      };
+      bson::doc! { "$push": { "entries": bson::to_bson(&entry)? } }
+    }
+    DeviceScheduleChange::RemoveEntry(id) => bson::doc! { "$pull": { "entries": { "id": id } } },
+  };
+
+  log::trace!("applying device schedule update - '{update:?}'");
+  collection
+    .update_one(query, update, upsert)
+    .await
+    .with_context(|| format!("unable to update device schedule for '{}'", request.device_id))?;
+
+  reschedule(&mut worker, &request.device_id).await?;
+
+  if let DeviceScheduleChange::SetKind(_) = request.change {
+    worker
+      .enqueue_kind(super::RegistrarJobKind::RunDeviceSchedule {
+        device_id: request.device_id.clone(),
+        refresh_nonce: None,
+      })
+      .await?;
+  }
+
+  Ok(())
+}
+
+/// Renders the calendar events of a user onto a device.
+async fn render_user_events(
+  worker: &mut super::worker::WorkerHandle<'_>,
+  device_id: &str,
+  user_id: &str,
+) -> anyhow::Result<()> {
+  log::trace!("querying events for device '{device_id}' and user '{user_id}'");
+
+  let users_collection = worker
+    .mongo
+    .client
+    .database(&worker.mongo.config.database)
+    .collection::<UserTokenInfo>(&worker.mongo.config.collections.users);
+
+  let mut partial_user = users_collection
+    .find_one(bson::doc! { "oid": user_id }, None)
+    .await
+    .map_err(|error| {
+      io::Error::new(
+        io::ErrorKind::Other,
+        format!("unable to query users with access tokens - {error}"),
+      )
+    })?
+    .ok_or_else(|| {
+      io::Error::new(
+        io::ErrorKind::Other,
+        format!("unable to find token for user '{}'", user_id),
+      )
+    })?;
+
+  // TODO: figure out how to share this decoding logic between here and the `schedule` module
+  // which uses it when determining if the access token needs refreshing.
+  let key = jsonwebtoken::DecodingKey::from_secret(worker.config.vendor_api_secret.as_bytes());
+  let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+  let decoded_token = jsonwebtoken::decode::<super::users::EncodedUserAccessToken>(
+    &partial_user.latest_token.token.access_token,
+    &key,
+    &validation,
+  )?;
+  partial_user.latest_token.token.access_token = decoded_token.claims.token;
+
+  log::trace!(
+    "querying calendars for token - '{:?}'",
+    partial_user.latest_token.created
+  );
+
+  let primary = google::fetch_primary(&partial_user.latest_token)
+    .await?
+    .ok_or_else(|| {
+      io::Error::new(
+        io::ErrorKind::Other,
+        format!("no primary calendar found for user '{user_id}'"),
+      )
+    })?;
+
+  let events: Vec<google::ParsedEvent> = google::fetch_events(&partial_user.latest_token, &primary)
+    .await?
+    .into_iter()
+    .filter_map(|raw_event| google::parse_event(&raw_event).ok())
+    .collect();
+
+  log::trace!(
+    "found {} events for user '{user_id}' ({:?})",
+    events.len(),
+    partial_user.name
+  );
+
+  worker
+    .enqueue_kind(super::RegistrarJobKind::MutateDeviceState(
+      super::device_state::DeviceStateTransitionRequest {
+        device_id: device_id.to_string(),
+        transition: super::device_state::DeviceStateTransition::SetSchedule(events),
+      },
+    ))
+    .await?;
+
+  Ok(())
+}
+
+/// Performs the action of a schedule entry that has fired.
+async fn fire(
+  worker: &mut super::worker::WorkerHandle<'_>,
+  device_id: &str,
+  action: &schema::DeviceScheduleAction,
+) -> anyhow::Result<()> {
+  match action {
+    schema::DeviceScheduleAction::RenderLayout(layout) => {
+      crate::rendering::validation::check(layout, crate::rendering::DEVICE_DIMENSIONS)?;
+      worker.render(device_id, layout.clone()).await?;
+    }
+    schema::DeviceScheduleAction::Lights(on) => {
+      let layout: crate::rendering::RenderVariant<String> = match on {
+        true => crate::rendering::RenderVariant::on(),
+        false => crate::rendering::RenderVariant::off(),
+      };
+      let config = worker.config;
+      crate::rendering::queue::Queue::new(worker.redis(), &config.vendor_api_secret)
+        .queue(device_id, &crate::rendering::QueuedRenderAuthority::Registrar, layout)
+        .await?;
+    }
+    schema::DeviceScheduleAction::PushMessage { message, origin } => {
+      worker
+        .enqueue_kind(super::RegistrarJobKind::MutateDeviceState(
+          super::device_state::DeviceStateTransitionRequest {
+            device_id: device_id.to_string(),
+            transition: super::device_state::DeviceStateTransition::PushMessage(message.clone(), origin.clone()),
+          },
+        ))
+        .await?;
+    }
+    schema::DeviceScheduleAction::UserEvents { user_oid } => render_user_events(worker, device_id, user_oid).await?,
+    schema::DeviceScheduleAction::RenderTemplate {
+      user_oid,
+      template_id,
+      variables,
+    } => {
+      let template = worker
+        .layout_template_collection()?
+        .find_one(bson::doc! { "id": template_id, "user_id": user_oid }, None)
+        .await?
+        .ok_or_else(|| anyhow::Error::msg(format!("template '{template_id}' no longer exists")))?;
+      let layout = crate::rendering::templates::fill(&template.layout, variables)?;
+      crate::rendering::validation::check(&layout, crate::rendering::DEVICE_DIMENSIONS)?;
+      worker.render(device_id, layout).await?;
+    }
+  }
+
+  Ok(())
+}
+
+/// This method is responsible for running whatever is due on the schedule associated with the
+/// device id provided in the job. Jobs without a nonce were requested directly, and always run the
+/// schedule `kind`.
 pub(super) async fn execute<S, N>(
   mut worker: super::worker::WorkerHandle<'_>,
   device_id: S,
@@ -100,11 +400,11 @@ where
   S: AsRef<str>,
   N: AsRef<str>,
 {
-  let db = worker.mongo.client.database(&worker.mongo.config.database);
+  let device_id = device_id.as_ref();
   let schedules_collection = worker.device_schedule_collection()?;
 
-  let schedule = schedules_collection
-    .find_one(bson::doc! { "device_id": device_id.as_ref() }, None)
+  let mut schedule = schedules_collection
+    .find_one(bson::doc! { "device_id": device_id }, None)
     .await
     .map_err(|error| {
       io::Error::new(
@@ -115,109 +415,161 @@ where
     .ok_or_else(|| {
       io::Error::new(
         io::ErrorKind::Other,
-        format!("unable to find device schedule - '{}'", device_id.as_ref()),
+        format!("unable to find device schedule - '{device_id}'"),
       )
     })?;
 
-  match (nonce, schedule.refresh_nonce.as_ref()) {
+  let forced = match (nonce, schedule.refresh_nonce.as_ref()) {
     (Some(job_nonce), Some(stored_nonce)) if job_nonce.as_ref() != stored_nonce.as_str() => {
       return Ok(None);
     }
-    _ => log::trace!("valid refresh being executed"),
-  }
+    (job_nonce, _) => job_nonce.is_none(),
+  };
+
+  let now = chrono::Utc::now();
+  let now_millis = now.timestamp_millis();
+  let interval = refresh_interval_millis(worker.config, &schedule);
 
-  match schedule.kind {
-    None => {
-      log::info!("nothing to do for device '{}' schedule", device_id.as_ref());
+  // Entries are fired independently of one another; one failing should not hold the rest back.
+  for entry in schedule.entries.iter_mut() {
+    if entry.next_fire_at.map_or(true, |next| next > now_millis) {
+      continue;
     }
-    Some(schema::DeviceScheduleKind::UserEventsBasic { user_oid: user_id }) => {
-      log::trace!(
-        "querying events for device '{}' and user '{}'",
-        device_id.as_ref(),
-        user_id
-      );
 
-      let users_collection = db.collection::<UserTokenInfo>(&worker.mongo.config.collections.users);
+    log::info!("schedule['{device_id}'] entry '{}' is firing", entry.id);
+    if let Err(error) = fire(&mut worker, device_id, &entry.action).await {
+      log::error!("schedule['{device_id}'] entry '{}' failed - {error}", entry.id);
+    }
 
-      let mut partial_user = users_collection
-        .find_one(bson::doc! { "oid": &user_id }, None)
-        .await
-        .map_err(|error| {
-          io::Error::new(
-            io::ErrorKind::Other,
-            format!("unable to query users with access tokens - {error}"),
-          )
-        })?
-        .ok_or_else(|| {
-          io::Error::new(
-            io::ErrorKind::Other,
-            format!("unable to find token for user '{}'", user_id),
-          )
-        })?;
-
-      // TODO: figure out how to share this decoding logic between here and the `schedule` module
-      // which uses it when determining if the access token needs refreshing.
-      let key = jsonwebtoken::DecodingKey::from_secret(worker.config.vendor_api_secret.as_bytes());
-      let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
-      let decoded_token = jsonwebtoken::decode::<super::users::EncodedUserAccessToken>(
-        &partial_user.latest_token.token.access_token,
-        &key,
-        &validation,
-      )?;
-      partial_user.latest_token.token.access_token = decoded_token.claims.token;
-
-      log::trace!(
-        "querying calendars for token - '{:?}'",
-        partial_user.latest_token.created
-      );
-
-      let primary = google::fetch_primary(&partial_user.latest_token)
-        .await?
-        .ok_or_else(|| {
-          io::Error::new(
-            io::ErrorKind::Other,
-            format!("no primary calendar found for user '{user_id}'"),
-          )
-        })?;
-
-      let events: Vec<google::ParsedEvent> = google::fetch_events(&partial_user.latest_token, &primary)
-        .await?
-        .into_iter()
-        .filter_map(|raw_event| google::parse_event(&raw_event).ok())
-        .collect();
+    entry.last_fired_at = Some(now_millis);
+    entry.next_fire_at = next_fire_at(entry, now);
 
-      log::trace!(
-        "found {} events for user '{user_id}' ({:?})",
-        events.len(),
-        partial_user.name
-      );
+    schedules_collection
+      .update_one(
+        bson::doc! { "device_id": device_id, "entries.id": &entry.id },
+        bson::doc! { "$set": {
+          "entries.$.last_fired_at": entry.last_fired_at,
+          "entries.$.next_fire_at": entry.next_fire_at,
+        } },
+        None,
+      )
+      .await
+      .with_context(|| format!("unable to update schedule entry '{}'", entry.id))?;
+  }
 
-      worker
-        .enqueue_kind(super::RegistrarJobKind::MutateDeviceState(
-          super::device_state::DeviceStateTransitionRequest {
-            device_id: device_id.as_ref().to_string(),
-            transition: super::device_state::DeviceStateTransition::SetSchedule(events),
-          },
-        ))
-        .await?;
+  let kind_due = schedule.kind.is_some()
+    && (forced
+      || schedule
+        .last_executed
+        .map_or(true, |last| last as i64 + interval <= now_millis));
+
+  let result = match (&schedule.kind, kind_due) {
+    (Some(schema::DeviceScheduleKind::UserEventsBasic { user_oid }), true) => {
+      render_user_events(&mut worker, device_id, user_oid).await
     }
-  }
+    (None, _) => {
+      log::info!("nothing to do for device '{device_id}' schedule kind");
+      Ok(())
+    }
+    (_, false) => Ok(()),
+  };
 
-  let now = chrono::Utc::now().timestamp_millis();
+  // Failed runs are retried once the refresh interval has passed, like successful ones.
+  if kind_due {
+    schedule.last_executed = Some(now_millis as u64);
+  }
 
+  let next = next_run_at(&schedule, interval, now_millis);
   log::debug!(
-    "setting last executed timestamp for '{}' to {now} (nonce {:?})",
-    device_id.as_ref(),
+    "setting last executed timestamp for '{device_id}' to {:?}, next run at {next:?} (nonce {:?})",
+    schedule.last_executed,
     schedule.refresh_nonce
   );
 
   schedules_collection
     .find_one_and_update(
-      bson::doc! { "device_id": device_id.as_ref() },
-      bson::doc! { "$set": { "last_executed": now, "latest_refresh_nonce": schedule.refresh_nonce } },
+      bson::doc! { "device_id": device_id },
+      bson::doc! { "$set": {
+        "last_executed": schedule.last_executed.map(|last| last as i64),
+        "latest_refresh_nonce": schedule.refresh_nonce,
+        "next_run_at": next,
+      } },
       None,
     )
     .await
-    .map(|_| Some(()))
-    .with_context(|| format!("unable to update device schedule for '{}'", device_id.as_ref()))
+    .with_context(|| format!("unable to update device schedule for '{device_id}'"))?;
+
+  result.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{next_run_at, DeviceScheduleChange, DeviceScheduleRequest};
+  use crate::schema;
+
+  fn entry(next_fire_at: Option<i64>) -> schema::DeviceScheduleEntry {
+    schema::DeviceScheduleEntry {
+      id: "entry".to_string(),
+      cron: "0 8 * * *".to_string(),
+      timezone: None,
+      action: schema::DeviceScheduleAction::Lights(true),
+      next_fire_at,
+      last_fired_at: None,
+    }
+  }
+
+  #[test]
+  fn test_next_run_at() {
+    let mut schedule = schema::DeviceSchedule {
+      device_id: "device".to_string(),
+      entries: vec![entry(Some(5_000)), entry(None)],
+      ..Default::default()
+    };
+    assert_eq!(next_run_at(&schedule, 1_000, 0), Some(5_000));
+
+    // A kind that has never run is due right away; otherwise it is due an interval after it last ran.
+    schedule.kind = Some(schema::DeviceScheduleKind::UserEventsBasic {
+      user_oid: "user".to_string(),
+    });
+    assert_eq!(next_run_at(&schedule, 1_000, 10), Some(10));
+    schedule.last_executed = Some(2_000);
+    assert_eq!(next_run_at(&schedule, 1_000, 10), Some(3_000));
+
+    schedule.kind = None;
+    schedule.entries = vec![entry(None)];
+    assert_eq!(next_run_at(&schedule, 1_000, 10), None);
+  }
+
+  #[test]
+  fn test_schedule_defaults() {
+    let schedule = bson::from_document::<schema::DeviceSchedule>(bson::doc! {
+      "device_id": "device",
+      "last_executed": 1_i64,
+      "kind": { "beetle:kind": "user_events_basic", "beetle:content": { "user_oid": "user" } },
+    })
+    .unwrap();
+
+    assert_eq!(schedule.refresh_interval_seconds, None);
+    assert!(schedule.entries.is_empty());
+    assert_eq!(
+      schedule.kind,
+      Some(schema::DeviceScheduleKind::UserEventsBasic {
+        user_oid: "user".to_string()
+      })
+    );
+  }
+
+  #[test]
+  fn test_change_serialization() {
+    let request = serde_json::from_value::<DeviceScheduleRequest>(serde_json::json!({
+      "device_id": "device",
+      "change": { "beetle:kind": "set_refresh_interval", "beetle:content": 600 },
+    }))
+    .unwrap();
+
+    assert!(matches!(
+      request.change,
+      DeviceScheduleChange::SetRefreshInterval(Some(600))
+    ));
+  }
 }
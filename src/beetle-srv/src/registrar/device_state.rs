@@ -11,6 +11,27 @@ const MAX_DISPLAYED_EVENTS: usize = 4;
 /// The most amount of messages to retain in a list. Older messages are popped off.
 const MAX_MESSAGE_LIST_LEN: usize = 4;
 
+/// The most amount of countdowns to retain in a timekeeping state. Older countdowns are removed.
+const MAX_COUNTDOWN_LIST_LEN: usize = 4;
+
+/// The most amount of items to retain in a checklist. When full, the oldest checked item will be
+/// removed to make room for new ones.
+const MAX_CHECKLIST_LEN: usize = 8;
+
+/// The marker rendered next to checklist items that have not been checked. The bundled fonts do not
+/// include the unicode ballot box glyphs, so plain ascii is used instead.
+const UNCHECKED_MARKER: &str = "[ ]";
+
+/// The marker rendered next to checklist items that have been checked.
+const CHECKED_MARKER: &str = "[x]";
+
+/// The shortest amount of time a slideshow photo will be displayed for. Refreshing the display more
+/// often than this is not worth the flicker.
+const MIN_SLIDESHOW_INTERVAL_SECONDS: u32 = 60;
+
+/// The size of the clock text on the screen.
+const CLOCK_TEXT_SIZE: f32 = 72.0f32;
+
 /// The size of "secondary" text on the screen.
 const SECONDARY_TEXT_SIZE: f32 = 24.0f32;
 
@@ -41,6 +62,30 @@ pub enum DeviceStateTransition {
 
   /// Attemps to add a message to the device state.
   PushMessage(String, schema::DeviceStateMessageOrigin),
+
+  /// Attempts to show (or hide) the clock.
+  SetClock(Option<schema::DeviceRenderingStateClock>),
+
+  /// Attempts to add a countdown to the device state.
+  PushCountdown(schema::DeviceRenderingStateCountdownEntry),
+
+  /// Attempts to remove any countdowns matching the provided label.
+  RemoveCountdown(String),
+
+  /// Attempts to add an item to the checklist.
+  AddChecklistItem(String, schema::DeviceStateMessageOrigin),
+
+  /// Attempts to check (or uncheck) the checklist item with the provided id.
+  SetChecklistItemChecked(String, bool),
+
+  /// Attempts to move the checklist item with the provided id to a new position.
+  MoveChecklistItem(String, usize),
+
+  /// Attempts to remove the checklist item with the provided id.
+  RemoveChecklistItem(String),
+
+  /// Attempts to start cycling through the device album, using the provided interval in seconds.
+  StartSlideshow(u32),
 }
 
 /// The device state transition job kind.
@@ -75,6 +120,14 @@ enum MessageEntryLayout {
   Together,
 }
 
+/// The icon rendered next to the origin of a message.
+fn origin_icon(origin: &schema::DeviceStateMessageOrigin) -> rendering::Icon {
+  match origin {
+    schema::DeviceStateMessageOrigin::Unknown => rendering::Icon::new(rendering::IconGlyph::Message),
+    schema::DeviceStateMessageOrigin::User { .. } => rendering::Icon::new(rendering::IconGlyph::Person),
+  }
+}
+
 /// Pushes some messages onto an accumulator, based on the layout desired.
 fn render_message_entry(
   entry: &schema::DeviceRenderingStateMessageEntry,
@@ -115,11 +168,13 @@ fn render_message_entry(
         schema::DeviceStateMessageOrigin::Unknown => "unknown".to_string(),
         schema::DeviceStateMessageOrigin::User { nickname: value } => value.clone(),
       };
+      origin_component.icon = Some(origin_icon(&entry.origin));
 
       if let Some(ts) = entry.timestamp {
         origin_component.margin = None;
         let mut time_component = rendering::StylizedMessage {
           message: ts.format("%B %d, %H:%M").to_string(),
+          icon: Some(rendering::Icon::new(rendering::IconGlyph::Clock)),
           size: Some(SECONDARY_TEXT_SIZE),
           margin: Some(rendering::OptionalBoundingBox {
             bottom: Some(10),
@@ -145,11 +200,81 @@ fn render_message_entry(
         .timestamp
         .map(|ts| format!("{from_addr} (@ {})", ts.format("%B %d, %H:%M")))
         .unwrap_or(from_addr);
+      origin_component.icon = Some(origin_icon(&entry.origin));
       acc.push(origin_component);
     }
   }
 }
 
+/// Slideshows do not store which photo is currently displayed; instead the photo is picked based on
+/// how many intervals have elapsed. This returns the index of the photo that should currently be
+/// displayed, and the point in time when the next one should be.
+fn slideshow_position(
+  album_len: usize,
+  interval_seconds: u32,
+  now: chrono::DateTime<chrono::Utc>,
+) -> Option<(usize, chrono::DateTime<chrono::Utc>)> {
+  if album_len == 0 {
+    return None;
+  }
+
+  let interval = i64::from(interval_seconds.max(MIN_SLIDESHOW_INTERVAL_SECONDS));
+  let elapsed = now.timestamp().div_euclid(interval);
+  let index = elapsed.rem_euclid(album_len as i64) as usize;
+  let next = chrono::NaiveDateTime::from_timestamp_opt((elapsed + 1) * interval, 0)?.and_utc();
+  Some((index, next))
+}
+
+/// Applies a checklist transition to a list of items, returning the new list. Transitions that do
+/// not apply to checklists, or target items that do not exist, are errors.
+fn transition_checklist(
+  mut items: Vec<schema::DeviceRenderingStateChecklistItem>,
+  transition: &DeviceStateTransition,
+) -> anyhow::Result<Vec<schema::DeviceRenderingStateChecklistItem>> {
+  let position = |items: &Vec<schema::DeviceRenderingStateChecklistItem>, id: &String| {
+    items
+      .iter()
+      .position(|item| &item.id == id)
+      .ok_or_else(|| anyhow::Error::msg(format!("no checklist item '{id}'")))
+  };
+
+  match transition {
+    DeviceStateTransition::AddChecklistItem(content, origin) => {
+      if items.len() >= MAX_CHECKLIST_LEN {
+        let oldest_checked = items
+          .iter()
+          .position(|item| item.checked)
+          .ok_or_else(|| anyhow::Error::msg("checklist is full"))?;
+        items.remove(oldest_checked);
+      }
+
+      items.push(schema::DeviceRenderingStateChecklistItem {
+        id: crate::identity::create(),
+        content: content.clone(),
+        checked: false,
+        origin: origin.clone(),
+        timestamp: Some(chrono::Utc::now()),
+      });
+    }
+    DeviceStateTransition::SetChecklistItemChecked(id, checked) => {
+      let index = position(&items, id)?;
+      items[index].checked = *checked;
+    }
+    DeviceStateTransition::MoveChecklistItem(id, destination) => {
+      let index = position(&items, id)?;
+      let item = items.remove(index);
+      items.insert((*destination).min(items.len()), item);
+    }
+    DeviceStateTransition::RemoveChecklistItem(id) => {
+      let index = position(&items, id)?;
+      items.remove(index);
+    }
+    other => return Err(anyhow::Error::msg(format!("'{other:?}' is not a checklist transition"))),
+  }
+
+  Ok(items)
+}
+
 /// Returns the "marker" for an event, where the marker is the event's stringified starting title.
 /// Note that this also doubles as the _key_ of our `BTreeMap` when iterating over eents, which is
 /// how the events are ultimately ordered.
@@ -166,8 +291,22 @@ fn event_marker(event: &google::ParsedEvent) -> Option<String> {
 /// It is possible that this would be better implemented as an associated method on the
 /// `DeviceRenderingState` type itself, but the goal is to avoid _any_ methods directly built in
 /// the `schema` module (though it is tempting).
-fn render_state(state: &schema::DeviceRenderingState) -> anyhow::Result<rendering::RenderLayout<String>> {
+fn render_state(
+  state: &schema::DeviceRenderingState,
+  album: &[schema::DeviceAlbumPhoto],
+  now: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<rendering::RenderLayout<String>> {
   match state {
+    schema::DeviceRenderingState::Slideshow { interval_seconds } => {
+      let (index, _) = slideshow_position(album.len(), *interval_seconds, now)
+        .ok_or_else(|| anyhow::Error::msg("slideshow album is empty"))?;
+      let photo = &album[index];
+
+      Ok(rendering::RenderLayout::Image(rendering::components::Image {
+        location: photo.location.clone(),
+        fit: photo.fit,
+      }))
+    }
     schema::DeviceRenderingState::ScheduleLayout {
       events,
       messages: message_list,
@@ -214,6 +353,7 @@ fn render_state(state: &schema::DeviceRenderingState) -> anyhow::Result<renderin
 
             messages.push(rendering::components::StylizedMessage {
               message: format!("{formatted_start} - {formatted_end}"),
+              icon: Some(rendering::Icon::new(rendering::IconGlyph::Clock)),
               size: Some(SECONDARY_TEXT_SIZE),
 
               border: Some(rendering::OptionalBoundingBox {
@@ -253,6 +393,7 @@ fn render_state(state: &schema::DeviceRenderingState) -> anyhow::Result<renderin
       for (idx, (title, mut messages)) in events_by_date.into_iter().enumerate() {
         let title_message = rendering::components::StylizedMessage {
           message: title,
+          icon: Some(rendering::Icon::new(rendering::IconGlyph::Calendar)),
           size: Some(SECONDARY_TEXT_SIZE),
           margin: Some(rendering::OptionalBoundingBox {
             top: (idx > 0).then_some(10),
@@ -281,6 +422,85 @@ fn render_state(state: &schema::DeviceRenderingState) -> anyhow::Result<renderin
       let split = rendering::SplitLayout { left, right, ratio: 80 };
       Ok(rendering::RenderLayout::Split(split))
     }
+    schema::DeviceRenderingState::Checklist { items } => {
+      // Checked items are moved to the bottom of the list, but otherwise retain their order.
+      let (checked, unchecked): (Vec<_>, Vec<_>) = items.iter().partition(|item| item.checked);
+
+      let messages = unchecked
+        .into_iter()
+        .chain(checked)
+        .enumerate()
+        .map(|(idx, item)| {
+          let marker = if item.checked { CHECKED_MARKER } else { UNCHECKED_MARKER };
+          rendering::components::StylizedMessage {
+            message: format!("{marker} {}", item.content),
+            font: Some(rendering::FontSelection::DejaVu),
+            size: Some(if item.checked {
+              SECONDARY_TEXT_SIZE
+            } else {
+              PRIMARY_TEXT_SIZE
+            }),
+            margin: Some(rendering::OptionalBoundingBox {
+              top: Some(if idx == 0 { 10 } else { 4 }),
+              left: Some(10),
+              ..Default::default()
+            }),
+            ..Default::default()
+          }
+        })
+        .collect();
+
+      let left = rendering::SplitContents::Messages(messages);
+      let right = rendering::SplitContents::Messages(vec![]);
+      let split = rendering::SplitLayout {
+        left,
+        right,
+        ratio: 100,
+      };
+      Ok(rendering::RenderLayout::Split(split))
+    }
+    schema::DeviceRenderingState::Timekeeping { clock, countdowns } => {
+      let countdowns = countdowns
+        .iter()
+        .map(|entry| rendering::components::Countdown {
+          label: entry.label.clone(),
+          target: entry.target,
+          granularity: entry.granularity,
+          font: None,
+          fallback_fonts: None,
+          size: Some(PRIMARY_TEXT_SIZE),
+        })
+        .collect::<Vec<rendering::components::Countdown<String>>>();
+
+      let clock = clock.as_ref().map(|clock| rendering::components::Clock {
+        format: clock.format.clone(),
+        utc_offset_minutes: clock.utc_offset_minutes,
+        granularity: clock.granularity,
+        font: None,
+        fallback_fonts: None,
+        size: Some(CLOCK_TEXT_SIZE),
+      });
+
+      let split = match (clock, countdowns.is_empty()) {
+        (Some(clock), true) => rendering::SplitLayout {
+          left: rendering::SplitContents::Clock(clock),
+          right: rendering::SplitContents::Messages(vec![]),
+          ratio: 100,
+        },
+        (Some(clock), false) => rendering::SplitLayout {
+          left: rendering::SplitContents::Clock(clock),
+          right: rendering::SplitContents::Countdowns(countdowns),
+          ratio: 45,
+        },
+        (None, _) => rendering::SplitLayout {
+          left: rendering::SplitContents::Countdowns(countdowns),
+          right: rendering::SplitContents::Messages(vec![]),
+          ratio: 100,
+        },
+      };
+
+      Ok(rendering::RenderLayout::Split(split))
+    }
   }
 }
 
@@ -299,12 +519,13 @@ pub(super) async fn render_current(
     .ok_or_else(|| anyhow::Error::msg(format!("no device state found for '{device_id}'")))?;
 
   log::info!("rendering current state for '{device_id}'");
+  let now = chrono::Utc::now();
 
   let layout = current_state
     .rendering
     .as_ref()
     .and_then(|s| {
-      render_state(s)
+      render_state(s, &current_state.album, now)
         .map_err(|error| {
           log::error!("was unable to create layout for state - {error}");
           error
@@ -313,8 +534,28 @@ pub(super) async fn render_current(
     })
     .unwrap_or(rendering::RenderLayout::Clear);
 
+  // Layouts with time-dependent content will need to be rendered again; store when that will be so
+  // the registrar can pick it up during its scheduled work.
+  let scheduled_render = match current_state.rendering {
+    Some(schema::DeviceRenderingState::Slideshow { interval_seconds }) => {
+      slideshow_position(current_state.album.len(), interval_seconds, now).map(|(_, next)| next)
+    }
+    _ => layout.next_change(now),
+  }
+  .map(|next| next.timestamp_millis());
+
   let render_id = handle.render(device_id, layout).await?;
-  log::info!("render '{render_id}' scheduled for device '{device_id}'");
+  log::info!("render '{render_id}' scheduled for device '{device_id}' (next render {scheduled_render:?})");
+
+  states
+    .update_one(
+      bson::doc! { "device_id": &device_id },
+      bson::doc! { "$set": { "scheduled_render": scheduled_render } },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to store next scheduled render for '{device_id}'"))?;
+
   Ok(())
 }
 
@@ -347,6 +588,9 @@ pub(super) async fn attempt_transition(
           device_id: device_id.clone(),
           updated_at: None,
           rendering: None,
+          scheduled_render: None,
+          album: vec![],
+          pending: vec![],
         }));
       }
 
@@ -358,8 +602,14 @@ pub(super) async fn attempt_transition(
   log::trace!("loaded current state for transition - {current_state:?}");
 
   let next_state = match (current_state.rendering, &transition_request.transition) {
-    // push a message onto nothing.
-    (None, DeviceStateTransition::PushMessage(content, origin)) => Some(schema::DeviceRenderingState::MessageList {
+    // push a message onto nothing, or a timekeeping/checklist state (loss of countdowns/items).
+    (
+      None
+      | Some(schema::DeviceRenderingState::Timekeeping { .. })
+      | Some(schema::DeviceRenderingState::Checklist { .. })
+      | Some(schema::DeviceRenderingState::Slideshow { .. }),
+      DeviceStateTransition::PushMessage(content, origin),
+    ) => Some(schema::DeviceRenderingState::MessageList {
       messages: vec![schema::DeviceRenderingStateMessageEntry {
         content: content.clone(),
         origin: origin.clone(),
@@ -408,6 +658,96 @@ pub(super) async fn attempt_transition(
       })
     }
 
+    // set the clock onto an existing timekeeping state.
+    (Some(schema::DeviceRenderingState::Timekeeping { countdowns, .. }), DeviceStateTransition::SetClock(clock)) => {
+      Some(schema::DeviceRenderingState::Timekeeping {
+        clock: clock.clone(),
+        countdowns,
+      })
+    }
+
+    // set the clock onto anything else (loss of messages).
+    (_, DeviceStateTransition::SetClock(clock)) => Some(schema::DeviceRenderingState::Timekeeping {
+      clock: clock.clone(),
+      countdowns: vec![],
+    }),
+
+    // push a countdown onto an existing timekeeping state.
+    (
+      Some(schema::DeviceRenderingState::Timekeeping {
+        clock,
+        countdowns: mut current_list,
+      }),
+      DeviceStateTransition::PushCountdown(entry),
+    ) => {
+      while current_list.len() >= MAX_COUNTDOWN_LIST_LEN {
+        current_list.remove(0);
+      }
+      current_list.push(entry.clone());
+      Some(schema::DeviceRenderingState::Timekeeping {
+        clock,
+        countdowns: current_list,
+      })
+    }
+
+    // push a countdown onto anything else (loss of messages).
+    (_, DeviceStateTransition::PushCountdown(entry)) => Some(schema::DeviceRenderingState::Timekeeping {
+      clock: None,
+      countdowns: vec![entry.clone()],
+    }),
+
+    (
+      Some(schema::DeviceRenderingState::Timekeeping { clock, mut countdowns }),
+      DeviceStateTransition::RemoveCountdown(label),
+    ) => {
+      countdowns.retain(|entry| &entry.label != label);
+      Some(schema::DeviceRenderingState::Timekeeping { clock, countdowns })
+    }
+
+    (current, DeviceStateTransition::RemoveCountdown(label)) => {
+      log::warn!("no countdown '{label}' to remove from device '{device_id}' state");
+      current
+    }
+
+    // any checklist transition onto an existing checklist.
+    (
+      Some(schema::DeviceRenderingState::Checklist { items }),
+      transition @ DeviceStateTransition::AddChecklistItem(..)
+      | transition @ DeviceStateTransition::SetChecklistItemChecked(..)
+      | transition @ DeviceStateTransition::MoveChecklistItem(..)
+      | transition @ DeviceStateTransition::RemoveChecklistItem(..),
+    ) => Some(schema::DeviceRenderingState::Checklist {
+      items: transition_checklist(items, transition)?,
+    }),
+
+    // adding an item onto anything else starts a new checklist (loss of messages).
+    (_, transition @ DeviceStateTransition::AddChecklistItem(..)) => Some(schema::DeviceRenderingState::Checklist {
+      items: transition_checklist(vec![], transition)?,
+    }),
+
+    (
+      _,
+      DeviceStateTransition::SetChecklistItemChecked(id, _)
+      | DeviceStateTransition::MoveChecklistItem(id, _)
+      | DeviceStateTransition::RemoveChecklistItem(id),
+    ) => {
+      return Err(anyhow::Error::msg(format!(
+        "device '{device_id}' has no checklist for item '{id}'"
+      )));
+    }
+
+    (_, DeviceStateTransition::StartSlideshow(interval_seconds)) => {
+      if current_state.album.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+          "device '{device_id}' has no photos in its album"
+        )));
+      }
+
+      Some(schema::DeviceRenderingState::Slideshow {
+        interval_seconds: (*interval_seconds).max(MIN_SLIDESHOW_INTERVAL_SECONDS),
+      })
+    }
+
     (_, DeviceStateTransition::Clear) => {
       log::warn!("clearing device '{device_id}' render state!");
       None
@@ -457,3 +797,75 @@ pub(super) async fn attempt_transition(
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{slideshow_position, transition_checklist, DeviceStateTransition, MAX_CHECKLIST_LEN};
+  use crate::schema;
+
+  fn add(
+    items: Vec<schema::DeviceRenderingStateChecklistItem>,
+    content: &str,
+  ) -> Vec<schema::DeviceRenderingStateChecklistItem> {
+    let transition =
+      DeviceStateTransition::AddChecklistItem(content.to_string(), schema::DeviceStateMessageOrigin::Unknown);
+    transition_checklist(items, &transition).expect("unable to add item")
+  }
+
+  #[test]
+  fn test_checklist_move_and_check() {
+    let items = add(add(add(vec![], "one"), "two"), "three");
+    let three = items[2].id.clone();
+    let items = transition_checklist(items, &DeviceStateTransition::MoveChecklistItem(three.clone(), 0)).unwrap();
+    assert_eq!(
+      items.iter().map(|i| i.content.as_str()).collect::<Vec<&str>>(),
+      vec!["three", "one", "two"]
+    );
+
+    let items = transition_checklist(
+      items,
+      &DeviceStateTransition::SetChecklistItemChecked(three.clone(), true),
+    )
+    .unwrap();
+    assert!(items[0].checked);
+
+    let items = transition_checklist(items, &DeviceStateTransition::RemoveChecklistItem(three.clone())).unwrap();
+    assert_eq!(items.len(), 2);
+    assert!(transition_checklist(items, &DeviceStateTransition::RemoveChecklistItem(three)).is_err());
+  }
+
+  #[test]
+  fn test_checklist_full() {
+    let mut items = (0..MAX_CHECKLIST_LEN).fold(vec![], |acc, i| add(acc, &format!("item {i}")));
+    assert!(transition_checklist(
+      items.clone(),
+      &DeviceStateTransition::AddChecklistItem("overflow".to_string(), schema::DeviceStateMessageOrigin::Unknown)
+    )
+    .is_err());
+
+    items[3].checked = true;
+    let items = add(items, "overflow");
+    assert_eq!(items.len(), MAX_CHECKLIST_LEN);
+    assert!(items.iter().all(|item| item.content != "item 3"));
+  }
+
+  #[test]
+  fn test_slideshow_position() {
+    let at = |seconds: i64| chrono::NaiveDateTime::from_timestamp_opt(seconds, 0).unwrap().and_utc();
+    assert_eq!(slideshow_position(0, 60, at(0)), None);
+    assert_eq!(slideshow_position(3, 60, at(59)), Some((0, at(60))));
+    assert_eq!(slideshow_position(3, 60, at(130)), Some((2, at(180))));
+    assert_eq!(slideshow_position(3, 60, at(180)), Some((0, at(240))));
+    assert_eq!(slideshow_position(3, 1, at(61)), Some((1, at(120))));
+  }
+
+  #[test]
+  fn test_checkbox_markers_have_glyphs() {
+    let font = crate::rendering::FontSelection::DejaVu
+      .renderer()
+      .expect("unable to load font");
+    for character in super::CHECKED_MARKER.chars().chain(super::UNCHECKED_MARKER.chars()) {
+      assert_ne!(font.glyph(character).id().0, 0, "missing glyph for '{character}'");
+    }
+  }
+}
@@ -5,6 +5,9 @@ use crate::schema;
 use serde::Serialize;
 use std::io;
 
+/// The amount of telemetry entries kept on the device diagnostic.
+const TELEMETRY_HISTORY_LEN: i32 = 48;
+
 /// This type is used by mongo when an existing record is _not_ found.
 #[derive(Serialize)]
 struct DeviceDiagnosticSetOnInsert {
@@ -23,6 +26,29 @@ struct DeviceDiagnosticUpsert<'a> {
   /// The timestamp we should now be updating.
   #[serde(with = "chrono::serde::ts_milliseconds")]
   last_seen: chrono::DateTime<chrono::Utc>,
+
+  /// The telemetry sent with this check-in, if any.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  telemetry: Option<&'a schema::DeviceTelemetry>,
+}
+
+/// Parses an item taken from the incoming queue. Devices either push their bare id, or a versioned
+/// check-in message that includes their telemetry.
+fn parse_check_in(raw: String) -> Option<(String, Option<schema::DeviceTelemetry>)> {
+  if !raw.trim_start().starts_with('{') {
+    return Some((raw, None));
+  }
+
+  match serde_json::from_str::<schema::DeviceCheckIn>(&raw) {
+    Ok(schema::DeviceCheckIn::V1 { id, mut telemetry }) => {
+      telemetry.reported_at = Some(chrono::Utc::now());
+      Some((id, Some(telemetry)))
+    }
+    Err(error) => {
+      log::warn!("unable to parse device check-in - {error}");
+      None
+    }
+  }
 }
 
 /// The second main function of our registrar is to keep our server informed of the active devices
@@ -45,8 +71,11 @@ where
   )
   .await?;
 
-  let id = match taken {
-    kramer::Response::Item(kramer::ResponseValue::String(id)) => id,
+  let (id, telemetry) = match taken {
+    kramer::Response::Item(kramer::ResponseValue::String(raw)) => match parse_check_in(raw) {
+      Some(parsed) => parsed,
+      None => return Ok(0),
+    },
     kramer::Response::Item(kramer::ResponseValue::Empty) => return Ok(0),
     other => {
       log::error!("unrecognized inbound registrar queue item - '{other:?}'");
@@ -64,13 +93,14 @@ where
 
   // Attempt to update the diagnostic information in mongo. We only really want to set `last_seen`
   // on every message; to set `first_seen`, we'll take advantage of mongo's `$setOnInsert`
-  // operation.
+  // operation. Telemetry is also appended to the capped history.
   let device_diagnostic = collection
     .find_one_and_update(
       bson::doc! { "id": &id },
       bson::to_document(&DeviceDiagnosticUpsert {
         id: &id,
         last_seen: chrono::Utc::now(),
+        telemetry: telemetry.as_ref(),
       })
       .and_then(|left| {
         bson::to_document(&DeviceDiagnosticSetOnInsert {
@@ -78,11 +108,25 @@ where
         })
         .map(|right| (left, right))
       })
-      .map(|(l, r)| {
-        bson::doc! {
+      .and_then(|(l, r)| {
+        let mut update = bson::doc! {
           "$set": l,
           "$setOnInsert": r
+        };
+
+        if let Some(telemetry) = telemetry.as_ref() {
+          update.insert(
+            "$push",
+            bson::doc! {
+              "telemetry_history": {
+                "$each": [bson::to_bson(telemetry)?],
+                "$slice": -TELEMETRY_HISTORY_LEN,
+              }
+            },
+          );
         }
+
+        Ok(update)
       })
       .map_err(|error| {
         log::warn!("unable to build upsert doc - {error}");
@@ -102,6 +146,16 @@ where
     })?
     .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "upsert failed"))?;
 
+  let histories = mongo_client
+    .database(&mongo_config.database)
+    .collection::<schema::DeviceHistoryRecord>(&mongo_config.collections.device_histories);
+  let displayed = telemetry
+    .as_ref()
+    .and_then(|telemetry| telemetry.last_render_id.as_deref());
+  if let Err(error) = super::deliveries::acknowledge(&mut stream, &histories, &worker.config, &id, displayed).await {
+    log::warn!("unable to acknowledge renders for '{id}' - {error}");
+  }
+
   match &device_diagnostic.registration_state {
     Some(schema::DeviceDiagnosticRegistration::Initial) | None => {
       log::info!(
@@ -175,3 +229,29 @@ where
 
   Ok(1usize)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::parse_check_in;
+
+  #[test]
+  fn test_parse_bare_id() {
+    let (id, telemetry) = parse_check_in("device-id".to_string()).unwrap();
+    assert_eq!(id, "device-id");
+    assert!(telemetry.is_none());
+  }
+
+  #[test]
+  fn test_parse_v1() {
+    let raw = r#"{"beetle:kind":"v1","beetle:content":{"id":"device-id","telemetry":{"firmware_version":"0.2.1","wifi_rssi":-61,"battery_voltage":3.7}}}"#;
+    let (id, telemetry) = parse_check_in(raw.to_string()).unwrap();
+    let telemetry = telemetry.unwrap();
+    assert_eq!(id, "device-id");
+    assert_eq!(telemetry.firmware_version.as_deref(), Some("0.2.1"));
+    assert_eq!(telemetry.wifi_rssi, Some(-61));
+    assert_eq!(telemetry.free_heap, None);
+    assert!(telemetry.reported_at.is_some());
+
+    assert!(parse_check_in(r#"{"beetle:kind":"v9"}"#.to_string()).is_none());
+  }
+}
@@ -1,9 +1,16 @@
 use serde::{Deserialize, Serialize};
 use std::io;
 
+use super::album;
+use super::credentials;
+use super::decommission;
+use super::device_schedule;
 use super::device_state;
+use super::membership;
+use super::moderation;
 use super::ownership;
 use super::rename::DeviceRenameRequest;
+use super::transfer;
 
 /// Rendering jobs specific to the registrar. Eventually this might be expanded to wrap _all_
 /// rendering jobs that currently go directly to the queue.
@@ -44,6 +51,25 @@ pub enum RegistrarJobKind {
   /// These jobs mutate the current "rendered" device state.
   MutateDeviceState(device_state::DeviceStateTransitionRequest),
 
+  /// These jobs add, remove and reorder the photos retained for a device.
+  MutateDeviceAlbum(album::DeviceAlbumRequest),
+
+  /// These jobs hold, approve and reject content sent to public devices, and manage the settings
+  /// used to screen it.
+  Moderation(moderation::DeviceModerationRequest),
+
+  /// These jobs manage invites and the guests of a device.
+  Membership(membership::DeviceMembershipChange),
+
+  /// These jobs start, withdraw and complete the handoff of a device to a new owner.
+  Transfer(transfer::DeviceTransferRequest),
+
+  /// Archives everything stored for a device and removes it from the system.
+  Decommission(decommission::DeviceDecommissionRequest),
+
+  /// These jobs rotate the secrets devices use to authenticate with redis.
+  Credentials(credentials::DeviceCredentialRequest),
+
   /// An immediate attempt to run the schedule for a device.
   RunDeviceSchedule {
     /// The id of the device to refresh based on its schedule.
@@ -53,6 +79,9 @@ pub enum RegistrarJobKind {
     refresh_nonce: Option<String>,
   },
 
+  /// Changes the schedule of a device.
+  Schedule(device_schedule::DeviceScheduleRequest),
+
   /// A job that will simply turn on or off the default schedule for a device, given a user whose
   /// calendar would be used.
   ToggleDefaultSchedule {
@@ -106,16 +135,65 @@ impl RegistrarJob {
   pub fn label(&self) -> &'static str {
     match self.job {
       RegistrarJobKind::MutateDeviceState(_) => "MutateDeviceState",
+      RegistrarJobKind::MutateDeviceAlbum(_) => "MutateDeviceAlbum",
+      RegistrarJobKind::Moderation(_) => "Moderation",
+      RegistrarJobKind::Membership(_) => "Membership",
+      RegistrarJobKind::Transfer(_) => "Transfer",
+      RegistrarJobKind::Decommission(_) => "Decommission",
+      RegistrarJobKind::Credentials(_) => "Credentials",
       RegistrarJobKind::Ownership(_) => "Ownership",
       RegistrarJobKind::OwnershipChange(_) => "OwnershipChange",
       RegistrarJobKind::Rename(_) => "Rename",
       RegistrarJobKind::Renders(_) => "Render",
       RegistrarJobKind::RunDeviceSchedule { .. } => "RunDeviceSchedule",
+      RegistrarJobKind::Schedule(_) => "Schedule",
       RegistrarJobKind::ToggleDefaultSchedule { .. } => "ToggleDefaultSchedule",
       RegistrarJobKind::UserAccessTokenRefresh { .. } => "UserAccessTokenRefresh",
     }
   }
 
+  /// Returns the id of the device this job is for, if it is for a single device.
+  pub fn device_id(&self) -> Option<&str> {
+    let device_id = match &self.job {
+      RegistrarJobKind::MutateDeviceState(request) => &request.device_id,
+      RegistrarJobKind::MutateDeviceAlbum(request) => &request.device_id,
+      RegistrarJobKind::Moderation(request) => &request.device_id,
+      RegistrarJobKind::Membership(
+        membership::DeviceMembershipChange::CreateInvite { device_id, .. }
+        | membership::DeviceMembershipChange::RevokeInvite { device_id, .. }
+        | membership::DeviceMembershipChange::RemoveMember { device_id, .. },
+      ) => device_id,
+      RegistrarJobKind::Membership(_) => return None,
+      RegistrarJobKind::Transfer(
+        transfer::DeviceTransferRequest::Initiate { device_id, .. }
+        | transfer::DeviceTransferRequest::Accept { device_id, .. }
+        | transfer::DeviceTransferRequest::Withdraw { device_id, .. },
+      ) => device_id,
+      RegistrarJobKind::Decommission(request) => &request.device_id,
+      RegistrarJobKind::Credentials(
+        credentials::DeviceCredentialRequest::Rotate(device_id)
+        | credentials::DeviceCredentialRequest::Finalize(device_id),
+      ) => device_id,
+      RegistrarJobKind::Ownership(request) => &request.device_id,
+      RegistrarJobKind::OwnershipChange(ownership::DeviceOwnershipChangeRequest::SetPublicAvailability(
+        device_id,
+        _,
+      )) => device_id,
+      RegistrarJobKind::Rename(request) => &request.device_id,
+      RegistrarJobKind::Renders(
+        RegistrarRenderKinds::RegistrationScannable(device_id)
+        | RegistrarRenderKinds::CurrentDeviceState(device_id)
+        | RegistrarRenderKinds::SendImage { device_id, .. },
+      ) => device_id,
+      RegistrarJobKind::RunDeviceSchedule { device_id, .. } => device_id,
+      RegistrarJobKind::Schedule(request) => &request.device_id,
+      RegistrarJobKind::ToggleDefaultSchedule { device_id, .. } => device_id,
+      RegistrarJobKind::UserAccessTokenRefresh { .. } => return None,
+    };
+
+    Some(device_id)
+  }
+
   /// Serializes and encrypts a job.
   pub fn encrypt(self, config: &crate::config::RegistrarConfiguration) -> io::Result<String> {
     // TODO(job_encryption): using jwt here for ease, not the fact that it is the best. The
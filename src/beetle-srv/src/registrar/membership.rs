@@ -0,0 +1,348 @@
+//! Membership jobs manage who, other than the owner, has access to a device. Guests join by
+//! accepting an invite created by the owner, and leave by being removed or leaving on their own.
+//! Every change here also updates the device map on the affected user records.
+
+use crate::schema;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The most amount of outstanding invites a single device can have.
+const MAX_INVITES_LEN: usize = 10;
+
+/// The kinds of membership changes.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DeviceMembershipChange {
+  /// Adds an invite to a device.
+  CreateInvite {
+    /// The id of the device.
+    device_id: String,
+    /// The invite being added.
+    invite: schema::DeviceInvite,
+  },
+
+  /// Removes an outstanding invite from a device.
+  RevokeInvite {
+    /// The id of the device.
+    device_id: String,
+    /// The code of the invite.
+    code: String,
+  },
+
+  /// Accepts the invite with the provided code, adding the user as a guest on the device.
+  AcceptInvite {
+    /// The code of the invite.
+    code: String,
+    /// The id of the user accepting.
+    user_id: String,
+  },
+
+  /// Declines the invite with the provided code, removing it.
+  DeclineInvite {
+    /// The code of the invite.
+    code: String,
+  },
+
+  /// Removes a guest from a device; this is used both when guests are removed by the owner and
+  /// when guests leave on their own.
+  RemoveMember {
+    /// The id of the device.
+    device_id: String,
+    /// The id of the guest.
+    user_id: String,
+  },
+}
+
+/// Returns the model after adding the user as a guest with the provided role. Exclusive devices
+/// become shared.
+fn add_member(
+  model: schema::DeviceAuthorityModel,
+  user_id: &String,
+  role: schema::DeviceGuestRole,
+) -> anyhow::Result<schema::DeviceAuthorityModel> {
+  match model {
+    schema::DeviceAuthorityModel::Exclusive { owner }
+    | schema::DeviceAuthorityModel::Shared { owner, .. }
+    | schema::DeviceAuthorityModel::Public { owner, .. }
+      if &owner == user_id =>
+    {
+      Err(anyhow::Error::msg("owners cannot join their own device"))
+    }
+    schema::DeviceAuthorityModel::Exclusive { owner } => Ok(schema::DeviceAuthorityModel::Shared {
+      owner,
+      guests: vec![user_id.clone()],
+      roles: [(user_id.clone(), role)].into_iter().collect(),
+    }),
+    schema::DeviceAuthorityModel::Shared {
+      owner,
+      mut guests,
+      mut roles,
+    } => {
+      if !guests.contains(user_id) {
+        guests.push(user_id.clone());
+      }
+      roles.insert(user_id.clone(), role);
+      Ok(schema::DeviceAuthorityModel::Shared { owner, guests, roles })
+    }
+    schema::DeviceAuthorityModel::Public {
+      owner,
+      mut guests,
+      mut roles,
+    } => {
+      if !guests.contains(user_id) {
+        guests.push(user_id.clone());
+      }
+      roles.insert(user_id.clone(), role);
+      Ok(schema::DeviceAuthorityModel::Public { owner, guests, roles })
+    }
+  }
+}
+
+/// Returns the model after removing the user from the guests.
+fn remove_member(
+  model: schema::DeviceAuthorityModel,
+  user_id: &String,
+) -> anyhow::Result<schema::DeviceAuthorityModel> {
+  match model {
+    schema::DeviceAuthorityModel::Exclusive { owner }
+    | schema::DeviceAuthorityModel::Shared { owner, .. }
+    | schema::DeviceAuthorityModel::Public { owner, .. }
+      if &owner == user_id =>
+    {
+      Err(anyhow::Error::msg("owners cannot be removed from their device"))
+    }
+    schema::DeviceAuthorityModel::Exclusive { .. } => Err(anyhow::Error::msg("device has no guests")),
+    schema::DeviceAuthorityModel::Shared {
+      owner,
+      mut guests,
+      mut roles,
+    } => {
+      if !guests.contains(user_id) {
+        return Err(anyhow::Error::msg(format!("'{user_id}' is not a guest")));
+      }
+      guests.retain(|guest| guest != user_id);
+      roles.remove(user_id);
+      Ok(schema::DeviceAuthorityModel::Shared { owner, guests, roles })
+    }
+    schema::DeviceAuthorityModel::Public {
+      owner,
+      mut guests,
+      mut roles,
+    } => {
+      guests.retain(|guest| guest != user_id);
+      roles.remove(user_id);
+      Ok(schema::DeviceAuthorityModel::Public { owner, guests, roles })
+    }
+  }
+}
+
+/// Persists the new authority model for the device.
+async fn store_model(
+  handle: &mut super::worker::WorkerHandle<'_>,
+  device_id: &String,
+  model: &schema::DeviceAuthorityModel,
+) -> anyhow::Result<()> {
+  let serialized = bson::to_bson(model).with_context(|| "unable to serialize authority model")?;
+  handle
+    .device_authority_collection()?
+    .update_one(
+      bson::doc! { "device_id": device_id },
+      bson::doc! { "$set": { "authority_model": serialized } },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to store authority model for '{device_id}'"))?;
+  Ok(())
+}
+
+/// Processes the membership change.
+pub(super) async fn apply(
+  mut handle: super::worker::WorkerHandle<'_>,
+  change: &DeviceMembershipChange,
+) -> anyhow::Result<()> {
+  let authorities = handle.device_authority_collection()?;
+
+  match change {
+    DeviceMembershipChange::CreateInvite { device_id, invite } => {
+      let record = authorities
+        .find_one(bson::doc! { "device_id": device_id }, None)
+        .await?
+        .ok_or_else(|| anyhow::Error::msg(format!("no authority record for '{device_id}'")))?;
+
+      let now = chrono::Utc::now();
+      let mut invites = record
+        .invites
+        .into_iter()
+        .filter(|existing| existing.expires_at > now)
+        .collect::<Vec<schema::DeviceInvite>>();
+
+      if invites.len() >= MAX_INVITES_LEN {
+        return Err(anyhow::Error::msg(format!("'{device_id}' has too many invites")));
+      }
+
+      invites.push(invite.clone());
+      let serialized = bson::to_bson(&invites).with_context(|| "unable to serialize invites")?;
+      authorities
+        .update_one(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$set": { "invites": serialized } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to store invite for '{device_id}'"))?;
+    }
+
+    DeviceMembershipChange::RevokeInvite { device_id, code } => {
+      authorities
+        .update_one(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$pull": { "invites": { "code": code } } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to revoke invite for '{device_id}'"))?;
+    }
+
+    DeviceMembershipChange::DeclineInvite { code } => {
+      authorities
+        .update_one(
+          bson::doc! { "invites.code": code },
+          bson::doc! { "$pull": { "invites": { "code": code } } },
+          None,
+        )
+        .await
+        .with_context(|| "unable to decline invite")?;
+    }
+
+    DeviceMembershipChange::AcceptInvite { code, user_id } => {
+      let record = authorities
+        .find_one(bson::doc! { "invites.code": code }, None)
+        .await?
+        .ok_or_else(|| anyhow::Error::msg("invite not found"))?;
+
+      let invite = record
+        .invites
+        .iter()
+        .find(|invite| &invite.code == code)
+        .ok_or_else(|| anyhow::Error::msg("invite not found"))?;
+
+      if invite.expires_at < chrono::Utc::now() {
+        return Err(anyhow::Error::msg("invite has expired"));
+      }
+
+      let device_id = &record.device_id;
+      let model = record
+        .authority_model
+        .ok_or_else(|| anyhow::Error::msg(format!("'{device_id}' has no authority model")))?;
+      let model = add_member(model, user_id, invite.role)?;
+      let serialized = bson::to_bson(&model).with_context(|| "unable to serialize authority model")?;
+
+      // Invites can only be used once; the invite is pulled in the same write that adds the member,
+      // and only if it is still there, so a failed acceptance leaves it usable and a second one
+      // cannot sneak in.
+      let result = authorities
+        .update_one(
+          bson::doc! { "device_id": device_id, "invites.code": code },
+          bson::doc! {
+            "$set": { "authority_model": serialized },
+            "$pull": { "invites": { "code": code } },
+          },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to store authority model for '{device_id}'"))?;
+
+      if result.matched_count == 0 {
+        return Err(anyhow::Error::msg("invite not found"));
+      }
+
+      let snapshot = handle
+        .device_diagnostic_collection()?
+        .find_one(bson::doc! { "id": device_id }, None)
+        .await?
+        .map(|diagnostic| diagnostic.snapshot())
+        .unwrap_or_default();
+      let serialized = bson::to_bson(&snapshot).with_context(|| "unable to serialize device snapshot")?;
+
+      handle
+        .users_collection()?
+        .update_one(
+          bson::doc! { "oid": user_id },
+          bson::doc! { "$set": { format!("devices.{device_id}"): serialized } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to add '{device_id}' to user '{user_id}'"))?;
+
+      log::info!("user '{user_id}' joined device '{device_id}' as {:?}", invite.role);
+    }
+
+    DeviceMembershipChange::RemoveMember { device_id, user_id } => {
+      let model = authorities
+        .find_one(bson::doc! { "device_id": device_id }, None)
+        .await?
+        .and_then(|record| record.authority_model)
+        .ok_or_else(|| anyhow::Error::msg(format!("'{device_id}' has no authority model")))?;
+
+      let model = remove_member(model, user_id)?;
+      store_model(&mut handle, device_id, &model).await?;
+
+      handle
+        .users_collection()?
+        .update_one(
+          bson::doc! { "oid": user_id },
+          bson::doc! { "$unset": { format!("devices.{device_id}"): "" } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to remove '{device_id}' from user '{user_id}'"))?;
+
+      log::info!("user '{user_id}' removed from device '{device_id}'");
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{add_member, remove_member};
+  use crate::schema;
+
+  #[test]
+  fn test_add_member_to_exclusive() {
+    let model = schema::DeviceAuthorityModel::Exclusive { owner: "owner".into() };
+    let model = add_member(model, &"guest".into(), schema::DeviceGuestRole::Viewer).unwrap();
+
+    match model {
+      schema::DeviceAuthorityModel::Shared { owner, guests, roles } => {
+        assert_eq!(owner, "owner");
+        assert_eq!(guests, vec!["guest".to_string()]);
+        assert_eq!(roles.get("guest"), Some(&schema::DeviceGuestRole::Viewer));
+      }
+      other => panic!("unexpected model {other:?}"),
+    }
+  }
+
+  #[test]
+  fn test_owner_membership() {
+    let model = || schema::DeviceAuthorityModel::Exclusive { owner: "owner".into() };
+    assert!(add_member(model(), &"owner".into(), schema::DeviceGuestRole::Manager).is_err());
+    assert!(remove_member(model(), &"owner".into()).is_err());
+  }
+
+  #[test]
+  fn test_remove_member() {
+    let model = schema::DeviceAuthorityModel::Exclusive { owner: "owner".into() };
+    let model = add_member(model, &"guest".into(), schema::DeviceGuestRole::Manager).unwrap();
+    let model = remove_member(model, &"guest".into()).unwrap();
+
+    match model {
+      schema::DeviceAuthorityModel::Shared { guests, roles, .. } => {
+        assert!(guests.is_empty());
+        assert!(roles.is_empty());
+      }
+      other => panic!("unexpected model {other:?}"),
+    }
+  }
+}
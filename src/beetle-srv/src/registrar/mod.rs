@@ -25,7 +25,10 @@ mod users;
 mod schedule;
 
 /// Functionality associated with manipulating device schedules.
-mod device_schedule;
+pub(crate) mod device_schedule;
+
+/// Parsing and evaluating the cron expressions used by device schedule entries.
+pub(crate) mod cron;
 
 /// This module defines functionality associated with managing the acl pool.
 mod diagnostics;
@@ -37,9 +40,36 @@ pub(crate) use rename::DeviceRenameRequest;
 /// Defines the various jobs that will mutate device state.
 pub(crate) mod device_state;
 
+/// Defines the jobs that manage the photos retained for a device.
+pub(crate) mod album;
+
+/// Defines the screening of content sent to public devices, and the jobs used by owners to manage it.
+pub(crate) mod moderation;
+
+/// Defines the invites and membership jobs for devices shared with other users.
+pub(crate) mod membership;
+
+/// Defines the jobs used to hand a device over to a new owner.
+pub(crate) mod transfer;
+
+/// Defines the job that archives and removes a device.
+pub(crate) mod decommission;
+
+/// Defines how device credentials are created and rotated.
+pub mod credentials;
+
+/// Tracks renders from the device queue to the display.
+pub(crate) mod deliveries;
+
+/// Detects devices going offline and coming back, notifying their owners.
+pub(crate) mod connectivity;
+
+/// Delivers device and job events to user-registered webhooks.
+pub(crate) mod webhooks;
+
 /// Defines rules for what can be done to devices.
 mod access;
-pub use access::{user_access, AccessLevel};
+pub use access::{user_access, AccessLevel, DeviceAction};
 
 /// Just a place to put the types generally associated with background work.
 pub(crate) mod jobs;
@@ -80,6 +110,8 @@ impl Configuration {
       async_std::task::spawn(reporter.work());
     }
 
+    async_std::task::spawn(webhooks::work(mongo.client.clone(), self.mongo.clone()));
+
     Ok(Worker {
       reporting: sink,
       config: self.registrar,
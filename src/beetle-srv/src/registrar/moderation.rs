@@ -0,0 +1,462 @@
+//! Moderation protects `Public` devices from abuse. Content from users other than the owner is
+//! screened against a word blocklist and the owner's sender blocklist, and can optionally be held
+//! until the owner approves it.
+
+use crate::schema;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The most amount of entries that will be held for a single device. Older entries are dropped.
+const MAX_PENDING_LEN: i32 = 20;
+
+/// The outcome of screening something sent to a device.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ModerationVerdict {
+  /// The request can go through as-is.
+  Allow,
+
+  /// The content should be held until the owner approves it.
+  Hold,
+
+  /// The request should be denied; the string is the reason.
+  Reject(&'static str),
+}
+
+/// What a request would put on the display, as far as screening is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenedContent<'a> {
+  /// Nothing new will be displayed, e.g checking off a checklist item.
+  Nothing,
+
+  /// Text that will be displayed; this is checked against the word blocklist.
+  Text(&'a str),
+
+  /// Something that will be displayed but has no text to check, e.g a photo.
+  Opaque,
+}
+
+/// The kinds of changes that can be made to the moderation state of a device.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DeviceModerationChange {
+  /// Holds content until the owner approves it.
+  Hold(schema::DevicePendingEntry),
+
+  /// Approves the pending entry with the provided id, adding it to the device state.
+  Approve(String),
+
+  /// Rejects the pending entry with the provided id.
+  Reject(String),
+
+  /// Prevents the user with the provided id from sending anything to the device. Pending entries
+  /// from them are rejected.
+  BlockSender(String),
+
+  /// Removes the user with the provided id from the sender blocklist.
+  UnblockSender(String),
+
+  /// Turns the approval queue on or off.
+  RequireApproval(bool),
+}
+
+/// A request to change the moderation state of a device.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceModerationRequest {
+  /// The id of the device.
+  pub device_id: String,
+  /// The change being made.
+  pub change: DeviceModerationChange,
+}
+
+/// Returns true if any whole word in the content matches one of the blocked words, ignoring case.
+fn contains_blocked_word<S>(content: &str, blocked_words: &[S]) -> bool
+where
+  S: AsRef<str>,
+{
+  content
+    .split(|character: char| !character.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .any(|word| {
+      blocked_words
+        .iter()
+        .any(|blocked| blocked.as_ref().to_lowercase() == word.to_lowercase())
+    })
+}
+
+/// Returns true when the sender is subject to moderation, which is any user other than the owner
+/// (or a manager) of a `Public` device.
+pub fn is_moderated(record: &schema::DeviceAuthorityRecord, sender: &str) -> bool {
+  match &record.authority_model {
+    Some(schema::DeviceAuthorityModel::Public { owner, roles, .. }) => {
+      owner != sender && roles.get(sender) != Some(&schema::DeviceGuestRole::Manager)
+    }
+    _ => false,
+  }
+}
+
+/// Returns what will be screened for content that can be held.
+pub fn screened(content: &schema::DevicePendingContent) -> ScreenedContent<'_> {
+  match content {
+    schema::DevicePendingContent::Message(text)
+    | schema::DevicePendingContent::ChecklistItem(text)
+    | schema::DevicePendingContent::Link(text) => ScreenedContent::Text(text),
+    schema::DevicePendingContent::Countdown(entry) => ScreenedContent::Text(&entry.label),
+    schema::DevicePendingContent::Clock(clock) => clock
+      .format
+      .as_deref()
+      .map(ScreenedContent::Text)
+      .unwrap_or(ScreenedContent::Opaque),
+    schema::DevicePendingContent::Image { .. } | schema::DevicePendingContent::AlbumPhoto { .. } => {
+      ScreenedContent::Opaque
+    }
+  }
+}
+
+/// Screens something being sent to a device. Requests that would not display anything new are only
+/// checked against the sender blocklist; everything else is held when the owner requires approval.
+pub fn screen<S>(
+  record: &schema::DeviceAuthorityRecord,
+  sender: &str,
+  content: ScreenedContent<'_>,
+  blocked_words: &[S],
+) -> ModerationVerdict
+where
+  S: AsRef<str>,
+{
+  if !is_moderated(record, sender) {
+    return ModerationVerdict::Allow;
+  }
+
+  if record
+    .moderation
+    .blocked_senders
+    .iter()
+    .any(|blocked| blocked == sender)
+  {
+    return ModerationVerdict::Reject("blocked-sender");
+  }
+
+  match content {
+    ScreenedContent::Text(text) if contains_blocked_word(text, blocked_words) => {
+      ModerationVerdict::Reject("blocked-content")
+    }
+    ScreenedContent::Nothing => ModerationVerdict::Allow,
+    ScreenedContent::Text(_) | ScreenedContent::Opaque if record.moderation.require_approval => ModerationVerdict::Hold,
+    ScreenedContent::Text(_) | ScreenedContent::Opaque => ModerationVerdict::Allow,
+  }
+}
+
+/// Deletes the uploaded file held by a pending entry, if it has one. These are only kept around
+/// until the entry is approved.
+async fn discard(entry: &schema::DevicePendingEntry) {
+  let location = match &entry.content {
+    schema::DevicePendingContent::Image { location } | schema::DevicePendingContent::AlbumPhoto { location, .. } => {
+      location
+    }
+    _ => return,
+  };
+
+  if let Err(error) = async_std::fs::remove_file(location).await {
+    log::warn!("unable to remove held file '{location}' - {error}");
+  }
+}
+
+/// Processes the moderation change request.
+pub(super) async fn apply(
+  mut handle: super::worker::WorkerHandle<'_>,
+  request: &DeviceModerationRequest,
+) -> anyhow::Result<()> {
+  let device_id = &request.device_id;
+  let states = handle.device_state_collection()?;
+  let authorities = handle.device_authority_collection()?;
+
+  match &request.change {
+    DeviceModerationChange::Hold(entry) => {
+      let serialized = bson::to_bson(entry).with_context(|| "unable to serialize pending entry")?;
+      let previous = states
+        .find_one_and_update(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$push": { "pending": { "$each": [serialized], "$slice": -MAX_PENDING_LEN } } },
+          mongodb::options::FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(mongodb::options::ReturnDocument::Before)
+            .build(),
+        )
+        .await
+        .with_context(|| format!("unable to hold entry for '{device_id}'"))?;
+
+      // The oldest entries are dropped by the `$slice` above; their files go with them.
+      let previous = previous.map(|state| state.pending).unwrap_or_default();
+      let dropped = (previous.len() + 1).saturating_sub(MAX_PENDING_LEN as usize);
+      for entry in previous.iter().take(dropped) {
+        discard(entry).await;
+      }
+    }
+
+    DeviceModerationChange::Approve(id) => {
+      let previous = states
+        .find_one_and_update(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$pull": { "pending": { "id": id } } },
+          mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(mongodb::options::ReturnDocument::Before)
+            .build(),
+        )
+        .await
+        .with_context(|| format!("unable to approve entry for '{device_id}'"))?
+        .ok_or_else(|| anyhow::Error::msg(format!("no device state for '{device_id}'")))?;
+
+      let entry = previous
+        .pending
+        .into_iter()
+        .find(|entry| &entry.id == id)
+        .ok_or_else(|| anyhow::Error::msg(format!("no pending entry '{id}' for '{device_id}'")))?;
+
+      let transition = |transition| {
+        super::RegistrarJobKind::MutateDeviceState(super::device_state::DeviceStateTransitionRequest {
+          device_id: device_id.clone(),
+          transition,
+        })
+      };
+
+      let job = match entry.content {
+        schema::DevicePendingContent::Message(content) => transition(
+          super::device_state::DeviceStateTransition::PushMessage(content, entry.origin),
+        ),
+        schema::DevicePendingContent::ChecklistItem(content) => transition(
+          super::device_state::DeviceStateTransition::AddChecklistItem(content, entry.origin),
+        ),
+        schema::DevicePendingContent::Clock(clock) => {
+          transition(super::device_state::DeviceStateTransition::SetClock(Some(clock)))
+        }
+        schema::DevicePendingContent::Countdown(countdown) => {
+          transition(super::device_state::DeviceStateTransition::PushCountdown(countdown))
+        }
+        schema::DevicePendingContent::Image { location } => {
+          super::RegistrarJobKind::Renders(super::jobs::RegistrarRenderKinds::SendImage {
+            location,
+            device_id: device_id.clone(),
+          })
+        }
+        schema::DevicePendingContent::AlbumPhoto { location, fit } => {
+          super::RegistrarJobKind::MutateDeviceAlbum(super::album::DeviceAlbumRequest {
+            device_id: device_id.clone(),
+            change: super::album::DeviceAlbumChange::Add { location, fit },
+          })
+        }
+        schema::DevicePendingContent::Link(contents) => {
+          let layout = crate::rendering::RenderLayout::Scannable(crate::rendering::components::Scannable { contents });
+          let render_id = handle.render(device_id, layout).await?;
+          log::info!("approved entry '{id}' for '{device_id}', queued render '{render_id}'");
+          return Ok(());
+        }
+      };
+
+      let job_id = handle.enqueue_kind(job).await?;
+      log::info!("approved entry '{id}' for '{device_id}', queued job '{job_id}'");
+    }
+
+    DeviceModerationChange::Reject(id) => {
+      let previous = states
+        .find_one_and_update(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$pull": { "pending": { "id": id } } },
+          mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(mongodb::options::ReturnDocument::Before)
+            .build(),
+        )
+        .await
+        .with_context(|| format!("unable to reject entry for '{device_id}'"))?;
+
+      for entry in previous.iter().flat_map(|state| &state.pending) {
+        if &entry.id == id {
+          discard(entry).await;
+        }
+      }
+    }
+
+    DeviceModerationChange::BlockSender(sender) => {
+      authorities
+        .update_one(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$addToSet": { "moderation.blocked_senders": sender } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to block sender for '{device_id}'"))?;
+
+      let previous = states
+        .find_one_and_update(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$pull": { "pending": { "sender": sender } } },
+          mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(mongodb::options::ReturnDocument::Before)
+            .build(),
+        )
+        .await
+        .with_context(|| format!("unable to reject entries from blocked sender for '{device_id}'"))?;
+
+      for entry in previous.iter().flat_map(|state| &state.pending) {
+        if &entry.sender == sender {
+          discard(entry).await;
+        }
+      }
+    }
+
+    DeviceModerationChange::UnblockSender(sender) => {
+      authorities
+        .update_one(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$pull": { "moderation.blocked_senders": sender } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to unblock sender for '{device_id}'"))?;
+    }
+
+    DeviceModerationChange::RequireApproval(require_approval) => {
+      authorities
+        .update_one(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$set": { "moderation.require_approval": require_approval } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to update approval setting for '{device_id}'"))?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{screen, screened, ModerationVerdict, ScreenedContent};
+  use crate::schema;
+
+  fn public_record(require_approval: bool) -> schema::DeviceAuthorityRecord {
+    schema::DeviceAuthorityRecord {
+      device_id: "device".to_string(),
+      authority_model: Some(schema::DeviceAuthorityModel::Public {
+        owner: "owner".to_string(),
+        guests: vec![],
+        roles: Default::default(),
+      }),
+      moderation: schema::DeviceModerationSettings {
+        require_approval,
+        blocked_senders: vec!["troll".to_string()],
+      },
+      invites: vec![],
+      transfer: None,
+    }
+  }
+
+  #[test]
+  fn test_screen_owner_and_private_devices() {
+    let words = ["darn"];
+    let record = public_record(true);
+    assert_eq!(
+      screen(&record, "owner", ScreenedContent::Text("darn"), &words),
+      ModerationVerdict::Allow
+    );
+
+    let record = schema::DeviceAuthorityRecord {
+      authority_model: Some(schema::DeviceAuthorityModel::Shared {
+        owner: "owner".to_string(),
+        guests: vec!["troll".to_string()],
+        roles: Default::default(),
+      }),
+      ..public_record(true)
+    };
+    assert_eq!(
+      screen(&record, "troll", ScreenedContent::Text("darn"), &words),
+      ModerationVerdict::Allow
+    );
+  }
+
+  #[test]
+  fn test_screen_public_senders() {
+    let words = ["darn"];
+    let record = public_record(false);
+    assert_eq!(
+      screen(&record, "troll", ScreenedContent::Nothing, &words),
+      ModerationVerdict::Reject("blocked-sender")
+    );
+    assert_eq!(
+      screen(&record, "guest", ScreenedContent::Text("well, DARN it"), &words),
+      ModerationVerdict::Reject("blocked-content")
+    );
+    assert_eq!(
+      screen(&record, "guest", ScreenedContent::Text("darnell"), &words),
+      ModerationVerdict::Allow
+    );
+    assert_eq!(
+      screen(&public_record(true), "guest", ScreenedContent::Text("hello"), &words),
+      ModerationVerdict::Hold
+    );
+    assert_eq!(
+      screen(&public_record(true), "guest", ScreenedContent::Nothing, &words),
+      ModerationVerdict::Allow
+    );
+  }
+
+  #[test]
+  fn test_screen_held_content() {
+    let words = ["darn"];
+    let clock = |format: Option<&str>| {
+      schema::DevicePendingContent::Clock(schema::DeviceRenderingStateClock {
+        format: format.map(String::from),
+        ..Default::default()
+      })
+    };
+    let countdown = |label: &str| {
+      schema::DevicePendingContent::Countdown(schema::DeviceRenderingStateCountdownEntry {
+        label: label.to_string(),
+        target: chrono::Utc::now(),
+        granularity: None,
+      })
+    };
+    let image = schema::DevicePendingContent::Image {
+      location: "/tmp/image.png".to_string(),
+    };
+    let photo = schema::DevicePendingContent::AlbumPhoto {
+      location: "/tmp/photo.png".to_string(),
+      fit: None,
+    };
+
+    let blocked = [
+      schema::DevicePendingContent::Link("https://example.com/darn".to_string()),
+      countdown("darn launch"),
+      clock(Some("%H:%M darn")),
+    ];
+    for content in blocked {
+      assert_eq!(
+        screen(&public_record(false), "guest", screened(&content), &words),
+        ModerationVerdict::Reject("blocked-content"),
+        "{content:?}"
+      );
+    }
+
+    let held = [
+      schema::DevicePendingContent::Link("https://example.com".to_string()),
+      countdown("launch"),
+      clock(Some("%H:%M")),
+      clock(None),
+      image,
+      photo,
+    ];
+    for content in held {
+      assert_eq!(
+        screen(&public_record(true), "guest", screened(&content), &words),
+        ModerationVerdict::Hold,
+        "{content:?}"
+      );
+      assert_eq!(
+        screen(&public_record(false), "guest", screened(&content), &words),
+        ModerationVerdict::Allow,
+        "{content:?}"
+      );
+    }
+  }
+}
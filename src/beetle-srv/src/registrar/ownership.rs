@@ -59,13 +59,15 @@ pub(super) async fn process_change(worker: &mut super::Worker, job: &DeviceOwner
           schema::DeviceAuthorityModel::Public {
             owner: owner.clone(),
             guests: vec![],
+            roles: std::collections::HashMap::new(),
           }
         }
-        (Some(schema::DeviceAuthorityModel::Public { owner, guests }), PublicAvailabilityChange::ToPrivate) => {
+        (Some(schema::DeviceAuthorityModel::Public { owner, guests, roles }), PublicAvailabilityChange::ToPrivate) => {
           log::info!("moving from public to shared");
           schema::DeviceAuthorityModel::Shared {
             owner: owner.clone(),
             guests: guests.clone(),
+            roles: roles.clone(),
           }
         }
         (Some(schema::DeviceAuthorityModel::Shared { owner, .. }), PublicAvailabilityChange::ToPrivate) => {
@@ -82,6 +84,9 @@ pub(super) async fn process_change(worker: &mut super::Worker, job: &DeviceOwner
       let new_model = schema::DeviceAuthorityRecord {
         device_id: id.clone(),
         authority_model: Some(new_state),
+        moderation: model.moderation,
+        invites: model.invites,
+        transfer: model.transfer,
       };
 
       let result = models
@@ -190,7 +195,11 @@ pub(super) async fn register_device(worker: &mut super::Worker, job: &DeviceOwne
       Some(schema::DeviceAuthorityModel::Public {
         owner: original,
         guests: mut public_users,
+        roles,
       }),
+    moderation,
+    invites,
+    transfer,
   }) = authority_model
   {
     log::info!("adding user to the public authority model tracking for device '{device_id}'");
@@ -201,7 +210,11 @@ pub(super) async fn register_device(worker: &mut super::Worker, job: &DeviceOwne
       authority_model: Some(schema::DeviceAuthorityModel::Public {
         owner: original,
         guests: vec![],
+        roles,
       }),
+      moderation,
+      invites,
+      transfer,
     };
 
     let models = mongo
@@ -224,6 +237,7 @@ pub(super) async fn register_device(worker: &mut super::Worker, job: &DeviceOwne
   // Wrap up by updating the diagnostic itself so we can keep track of the original owner.
   let updated_reg = schema::DeviceDiagnosticRegistration::Owned(schema::DeviceDiagnosticOwnership {
     original_owner: job.user_id.clone(),
+    current_owner: None,
   });
   let serialized_registration = bson::to_bson(&updated_reg).map_err(|error| {
     log::warn!("unable to serialize registration_state: {error}");
@@ -2,8 +2,13 @@ use std::io;
 
 /// The main thing our worker will be responsible for is to count the amount of available ids
 /// in our pool that devices will pull down to identify themselves. If that amount reaches a
-/// quantity below a specific threshold, fill it back up.
-pub(super) async fn fill_pool(mut stream: &mut crate::redis::RedisConnection, min: u8) -> io::Result<usize> {
+/// quantity below a specific threshold, fill it back up. Each entry in the pool holds both the id
+/// and the secret the device will authenticate with.
+pub(super) async fn fill_pool(
+  mut stream: &mut crate::redis::RedisConnection,
+  credentials: &mongodb::Collection<crate::schema::DeviceCredentialRecord>,
+  min: u8,
+) -> io::Result<usize> {
   let output = kramer::execute(
     &mut stream,
     kramer::Command::Lists::<&str, bool>(kramer::ListCommand::Len(crate::constants::REGISTRAR_AVAILABLE)),
@@ -30,35 +35,21 @@ pub(super) async fn fill_pool(mut stream: &mut crate::redis::RedisConnection, mi
   }
 
   let ids = (0..min).map(|_| crate::identity::create()).collect::<Vec<String>>();
-  let count = ids.len();
+  let mut entries = Vec::with_capacity(ids.len());
 
   log::info!("creating acl entries for ids {ids:?}");
 
   for id in &ids {
-    let setuser = kramer::acl::SetUser {
-      name: id.clone(),
-      password: Some(id.clone()),
-      commands: Some(vec!["lpop".to_string(), "blpop".to_string()]),
-      keys: Some(crate::redis::device_message_queue_id(id)),
-    };
-
-    let command = kramer::Command::Acl::<String, &str>(kramer::acl::AclCommand::SetUser(setuser));
-
-    if let Err(error) = kramer::execute(&mut stream, &command).await {
-      log::warn!("unable to add acl for id '{}' - {error}", id);
+    match super::credentials::provision(&mut stream, credentials, id).await {
+      Ok(entry) => entries.push(entry),
+      Err(error) => log::warn!("unable to provision credentials for id '{id}' - {error}"),
     }
+  }
 
-    let setuser = kramer::acl::SetUser {
-      name: id.clone(),
-      password: Some(id.clone()),
-      commands: Some(vec!["rpush".to_string()]),
-      keys: Some(crate::constants::REGISTRAR_INCOMING.to_string()),
-    };
-    let command = kramer::Command::Acl::<String, &str>(kramer::acl::AclCommand::SetUser(setuser));
-
-    if let Err(error) = kramer::execute(&mut stream, &command).await {
-      log::warn!("unable to add acl for id '{}' - {error}", id);
-    }
+  let count = entries.len();
+  if count == 0 {
+    log::warn!("unable to provision any of the new ids, pool left as-is");
+    return Ok(0);
   }
 
   log::info!("acl entries for new ids {ids:?} ready, pushing into registration queue",);
@@ -68,7 +59,7 @@ pub(super) async fn fill_pool(mut stream: &mut crate::redis::RedisConnection, mi
     kramer::Command::Lists(kramer::ListCommand::Push(
       (kramer::Side::Left, kramer::Insertion::Always),
       crate::constants::REGISTRAR_AVAILABLE,
-      kramer::Arity::Many(ids),
+      kramer::Arity::Many(entries),
     )),
   )
   .await?;
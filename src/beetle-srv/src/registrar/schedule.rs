@@ -7,9 +7,6 @@ use super::users::EncodedUserAccessToken;
 /// The amount of time to buffer between a token expriting and we refresh it.
 const EXPIRATION_BUFFER: u64 = 1000;
 
-/// The amount of seconds between device schedule refreshes.
-const SCHEDULE_REFRESH_SECONDS: i64 = 60 * 5;
-
 /// This type represents the partial schema from our users collection that we are concerned with
 /// here.
 #[derive(Deserialize, Debug)]
@@ -202,32 +199,27 @@ async fn check_tokens(worker: &mut super::worker::WorkerHandle<'_>) -> io::Resul
 }
 
 /// This is the background method responsible for querying the device schedules collection for any
-/// that have not been run in some time. For these, the worker will queue an execution job and move
+/// that are due to run. For these, the worker will queue an execution job and move
 /// onto the next one.
 async fn check_schedules(worker: &mut super::worker::WorkerHandle<'_>) -> anyhow::Result<()> {
   log::trace!("registrar now checking for any schedules due for a refresh");
 
   let schedules_collection = worker.device_schedule_collection()?;
-  let interval_seconds = worker
-    .config
-    .device_schedule_refresh_interval_seconds
-    .as_ref()
-    .copied()
-    .unwrap_or(SCHEDULE_REFRESH_SECONDS);
-
-  let cutoff = chrono::Utc::now()
-    .checked_sub_signed(chrono::Duration::seconds(interval_seconds))
-    .ok_or_else(|| anyhow::Error::msg("unable to create cutoff date for device schedule refresh"))?
-    .timestamp_millis();
+  let now = chrono::Utc::now().timestamp_millis();
 
+  // Every run stores when the schedule is due next. Schedules that have not run since that was
+  // introduced are picked up once by the time they last ran.
   let mut cursor = schedules_collection
     .find(
-      bson::doc! { "last_executed": { "$lt": cutoff } },
+      bson::doc! { "$or": [
+        { "next_run_at": { "$lte": now } },
+        { "next_run_at": { "$exists": false }, "last_executed": { "$exists": true } },
+      ] },
       mongodb::options::FindOptions::builder().limit(10).build(),
     )
     .await?;
 
-  log::trace!("queried device schedules with cutoff - {cutoff}");
+  log::trace!("queried device schedules due at {now}");
   let mut nonce_updates = vec![];
 
   while let Some(handle_result) = async_std::stream::StreamExt::next(&mut cursor).await {
@@ -293,8 +285,57 @@ async fn check_schedules(worker: &mut super::worker::WorkerHandle<'_>) -> anyhow
   Ok(())
 }
 
+/// Device states that contain time-dependent content (clocks, countdowns) store the point in time
+/// where they should be rendered again. This queues a render for any states that have passed that
+/// point. The `scheduled_render` is cleared here, and will be set again by the render job itself.
+async fn check_scheduled_renders(worker: &mut super::worker::WorkerHandle<'_>) -> anyhow::Result<()> {
+  let states = worker.device_state_collection()?;
+  let now = chrono::Utc::now().timestamp_millis();
+
+  let mut cursor = states
+    .find(
+      bson::doc! { "scheduled_render": { "$lte": now } },
+      mongodb::options::FindOptions::builder().limit(10).build(),
+    )
+    .await?;
+
+  let mut device_ids = vec![];
+
+  while let Some(state_result) = async_std::stream::StreamExt::next(&mut cursor).await {
+    match state_result {
+      Err(error) => log::error!("strange device state problem - {error}"),
+      Ok(state) => device_ids.push(state.device_id),
+    }
+  }
+
+  for device_id in device_ids {
+    log::info!("state['{device_id}'] has time-dependent content due for a render");
+
+    states
+      .update_one(
+        bson::doc! { "device_id": &device_id },
+        bson::doc! { "$unset": { "scheduled_render": "" } },
+        None,
+      )
+      .await?;
+
+    if let Err(error) = worker
+      .enqueue_kind(super::RegistrarJobKind::Renders(
+        super::jobs::RegistrarRenderKinds::CurrentDeviceState(device_id),
+      ))
+      .await
+    {
+      log::error!("unable to queue scheduled device state render - {error}");
+    }
+  }
+
+  Ok(())
+}
+
 /// Queries the user collection, gets refresh tokens.
 pub(super) async fn check_schedule(mut worker: super::worker::WorkerHandle<'_>) -> anyhow::Result<()> {
   check_tokens(&mut worker).await?;
-  check_schedules(&mut worker).await
+  check_scheduled_renders(&mut worker).await?;
+  check_schedules(&mut worker).await?;
+  super::deliveries::expire(&mut worker).await
 }
@@ -0,0 +1,440 @@
+//! Ownership transfers hand a device from its owner to another user. The owner initiates the
+//! transfer and nothing changes until the recipient accepts it, before it expires. Accepting
+//! rewrites the authority model, moves the device between the user records and takes care of any
+//! calendar schedule that was using the previous owner's account.
+
+use crate::schema;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The kinds of ownership transfer jobs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DeviceTransferRequest {
+  /// Starts a transfer, replacing any that was already pending for the device.
+  Initiate {
+    /// The id of the device.
+    device_id: String,
+    /// The transfer being started.
+    transfer: schema::DeviceOwnershipTransfer,
+  },
+
+  /// Completes the transfer; only the recipient can do this.
+  Accept {
+    /// The id of the device.
+    device_id: String,
+    /// The id of the user accepting.
+    user_id: String,
+  },
+
+  /// Drops the pending transfer; either the owner or the recipient can do this.
+  Withdraw {
+    /// The id of the device.
+    device_id: String,
+    /// The id of the user withdrawing.
+    user_id: String,
+  },
+}
+
+/// Returns the model with the owner replaced. The recipient is dropped from the guests, and the
+/// previous owner is added as a manager when they are keeping access.
+fn transfer_model(
+  model: schema::DeviceAuthorityModel,
+  transfer: &schema::DeviceOwnershipTransfer,
+) -> anyhow::Result<schema::DeviceAuthorityModel> {
+  let schema::DeviceOwnershipTransfer {
+    from, to, keep_access, ..
+  } = transfer;
+
+  let (owner, mut guests, mut roles, public) = match model {
+    schema::DeviceAuthorityModel::Exclusive { owner } => (owner, vec![], Default::default(), false),
+    schema::DeviceAuthorityModel::Shared { owner, guests, roles } => (owner, guests, roles, false),
+    schema::DeviceAuthorityModel::Public { owner, guests, roles } => (owner, guests, roles, true),
+  };
+
+  if &owner != from {
+    return Err(anyhow::Error::msg(format!("'{from}' no longer owns the device")));
+  }
+
+  guests.retain(|guest| guest != to);
+  roles.remove(to);
+
+  if *keep_access {
+    guests.push(from.clone());
+    roles.insert(from.clone(), schema::DeviceGuestRole::Manager);
+  }
+
+  let owner = to.clone();
+  Ok(match (public, guests.is_empty()) {
+    (true, _) => schema::DeviceAuthorityModel::Public { owner, guests, roles },
+    (false, false) => schema::DeviceAuthorityModel::Shared { owner, guests, roles },
+    (false, true) => schema::DeviceAuthorityModel::Exclusive { owner },
+  })
+}
+
+/// What happens to a cron entry on the device schedule when the device is transferred.
+#[derive(Debug, PartialEq, Eq)]
+enum EntryTransfer {
+  /// The entry does not belong to the previous owner, or they are keeping access.
+  Keep,
+
+  /// The entry is moved over to the new owner.
+  Reassign,
+
+  /// The entry is removed.
+  Remove,
+}
+
+/// Calendar entries of the previous owner follow the schedule `kind`; they are moved over when the
+/// new owner has connected a calendar and removed otherwise. Templates cannot be moved, so entries
+/// rendering the previous owner's templates are removed unless they are keeping access.
+fn entry_transfer(
+  action: &schema::DeviceScheduleAction,
+  transfer: &schema::DeviceOwnershipTransfer,
+  has_calendar: bool,
+) -> EntryTransfer {
+  match action {
+    schema::DeviceScheduleAction::UserEvents { user_oid } if user_oid == &transfer.from => match has_calendar {
+      true => EntryTransfer::Reassign,
+      false => EntryTransfer::Remove,
+    },
+    schema::DeviceScheduleAction::RenderTemplate { user_oid, .. }
+      if user_oid == &transfer.from && !transfer.keep_access =>
+    {
+      EntryTransfer::Remove
+    }
+    schema::DeviceScheduleAction::RenderLayout(_)
+    | schema::DeviceScheduleAction::PushMessage { .. }
+    | schema::DeviceScheduleAction::Lights(_)
+    | schema::DeviceScheduleAction::UserEvents { .. }
+    | schema::DeviceScheduleAction::RenderTemplate { .. } => EntryTransfer::Keep,
+  }
+}
+
+/// Moves the device schedule over to the new owner when it is built from the previous owner's
+/// calendar. If the new owner has not connected a calendar, the schedule is disabled instead. Cron
+/// entries using the previous owner's account are handled by `entry_transfer`.
+async fn reassign_schedule(
+  handle: &mut super::worker::WorkerHandle<'_>,
+  device_id: &String,
+  transfer: &schema::DeviceOwnershipTransfer,
+) -> anyhow::Result<()> {
+  let schedules = handle.device_schedule_collection()?;
+  let previous = bson::doc! {
+    "device_id": device_id,
+    "kind.beetle:content.user_oid": &transfer.from,
+  };
+
+  let has_calendar = handle
+    .users_collection()?
+    .count_documents(
+      bson::doc! { "oid": &transfer.to, "latest_token": { "$exists": 1 } },
+      None,
+    )
+    .await
+    .with_context(|| "unable to check calendar of new owner")?
+    > 0;
+
+  let update = match has_calendar {
+    true => bson::doc! { "$set": { "kind.beetle:content.user_oid": &transfer.to } },
+    false => bson::doc! { "$set": { "kind": bson::Bson::Null } },
+  };
+
+  let result = schedules
+    .update_one(previous, update, None)
+    .await
+    .with_context(|| format!("unable to reassign schedule for '{device_id}'"))?;
+
+  if result.modified_count > 0 {
+    log::info!("transfer of '{device_id}' updated schedule (reassigned: {has_calendar})");
+  }
+
+  let entries = schedules
+    .find_one(bson::doc! { "device_id": device_id }, None)
+    .await
+    .with_context(|| format!("unable to load schedule for '{device_id}'"))?
+    .map(|schedule| schedule.entries)
+    .unwrap_or_default();
+
+  let (mut reassigned, mut removed) = (vec![], vec![]);
+  for entry in entries {
+    match entry_transfer(&entry.action, transfer, has_calendar) {
+      EntryTransfer::Keep => (),
+      EntryTransfer::Reassign => reassigned.push(entry.id),
+      EntryTransfer::Remove => removed.push(entry.id),
+    }
+  }
+
+  if !reassigned.is_empty() {
+    schedules
+      .update_one(
+        bson::doc! { "device_id": device_id },
+        bson::doc! { "$set": { "entries.$[reassigned].action.beetle:content.user_oid": &transfer.to } },
+        mongodb::options::UpdateOptions::builder()
+          .array_filters(vec![bson::doc! { "reassigned.id": { "$in": reassigned.clone() } }])
+          .build(),
+      )
+      .await
+      .with_context(|| format!("unable to reassign schedule entries for '{device_id}'"))?;
+  }
+
+  if !removed.is_empty() {
+    schedules
+      .update_one(
+        bson::doc! { "device_id": device_id },
+        bson::doc! { "$pull": { "entries": { "id": { "$in": removed.clone() } } } },
+        None,
+      )
+      .await
+      .with_context(|| format!("unable to remove schedule entries for '{device_id}'"))?;
+    super::device_schedule::reschedule(handle, device_id).await?;
+  }
+
+  if !reassigned.is_empty() || !removed.is_empty() {
+    log::info!(
+      "transfer of '{device_id}' reassigned {} and removed {} schedule entries",
+      reassigned.len(),
+      removed.len()
+    );
+  }
+
+  Ok(())
+}
+
+/// Processes the transfer request.
+pub(super) async fn apply(
+  mut handle: super::worker::WorkerHandle<'_>,
+  request: &DeviceTransferRequest,
+) -> anyhow::Result<()> {
+  let authorities = handle.device_authority_collection()?;
+
+  match request {
+    DeviceTransferRequest::Initiate { device_id, transfer } => {
+      if transfer.from == transfer.to {
+        return Err(anyhow::Error::msg("cannot transfer a device to its owner"));
+      }
+
+      handle
+        .users_collection()?
+        .find_one(bson::doc! { "oid": &transfer.to }, None)
+        .await?
+        .ok_or_else(|| anyhow::Error::msg(format!("no user '{}' to transfer to", transfer.to)))?;
+
+      let serialized = bson::to_bson(transfer).with_context(|| "unable to serialize transfer")?;
+      let result = authorities
+        .update_one(
+          bson::doc! { "device_id": device_id, "authority_model.beetle:content.owner": &transfer.from },
+          bson::doc! { "$set": { "transfer": serialized } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to start transfer for '{device_id}'"))?;
+
+      if result.matched_count == 0 {
+        return Err(anyhow::Error::msg(format!(
+          "'{}' does not own '{device_id}'",
+          transfer.from
+        )));
+      }
+
+      log::info!(
+        "'{}' started transfer of '{device_id}' to '{}'",
+        transfer.from,
+        transfer.to
+      );
+    }
+
+    DeviceTransferRequest::Withdraw { device_id, user_id } => {
+      authorities
+        .update_one(
+          bson::doc! {
+            "device_id": device_id,
+            "$or": [{ "transfer.from": user_id }, { "transfer.to": user_id }],
+          },
+          bson::doc! { "$unset": { "transfer": "" } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to withdraw transfer for '{device_id}'"))?;
+    }
+
+    DeviceTransferRequest::Accept { device_id, user_id } => {
+      // Pulling the transfer out first means it can only ever be accepted once.
+      let record = authorities
+        .find_one_and_update(
+          bson::doc! { "device_id": device_id, "transfer.to": user_id },
+          bson::doc! { "$unset": { "transfer": "" } },
+          mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(mongodb::options::ReturnDocument::Before)
+            .build(),
+        )
+        .await?
+        .ok_or_else(|| anyhow::Error::msg(format!("no transfer of '{device_id}' to '{user_id}'")))?;
+
+      let transfer = record
+        .transfer
+        .ok_or_else(|| anyhow::Error::msg(format!("no transfer of '{device_id}'")))?;
+
+      if transfer.expires_at < chrono::Utc::now() {
+        return Err(anyhow::Error::msg("transfer has expired"));
+      }
+
+      let model = record
+        .authority_model
+        .ok_or_else(|| anyhow::Error::msg(format!("'{device_id}' has no authority model")))?;
+      let model = transfer_model(model, &transfer)?;
+      let serialized = bson::to_bson(&model).with_context(|| "unable to serialize authority model")?;
+      authorities
+        .update_one(
+          bson::doc! { "device_id": device_id },
+          bson::doc! { "$set": { "authority_model": serialized } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to store authority model for '{device_id}'"))?;
+
+      let diagnostics = handle.device_diagnostic_collection()?;
+      let snapshot = diagnostics
+        .find_one(bson::doc! { "id": device_id }, None)
+        .await?
+        .map(|diagnostic| diagnostic.snapshot())
+        .unwrap_or_default();
+      let snapshot = bson::to_bson(&snapshot).with_context(|| "unable to serialize device snapshot")?;
+
+      let users = handle.users_collection()?;
+      let device_key = format!("devices.{device_id}");
+      users
+        .update_one(
+          bson::doc! { "oid": &transfer.to },
+          bson::doc! { "$set": { &device_key: snapshot } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to add '{device_id}' to '{}'", transfer.to))?;
+
+      if !transfer.keep_access {
+        users
+          .update_one(
+            bson::doc! { "oid": &transfer.from },
+            bson::doc! { "$unset": { &device_key: "" } },
+            None,
+          )
+          .await
+          .with_context(|| format!("unable to remove '{device_id}' from '{}'", transfer.from))?;
+      }
+
+      diagnostics
+        .update_one(
+          bson::doc! { "id": device_id, "registration_state.beetle:kind": "owned" },
+          bson::doc! { "$set": { "registration_state.beetle:content.current_owner": &transfer.to } },
+          None,
+        )
+        .await
+        .with_context(|| format!("unable to update registration for '{device_id}'"))?;
+
+      reassign_schedule(&mut handle, device_id, &transfer).await?;
+
+      log::info!(
+        "'{device_id}' transferred from '{}' to '{}'",
+        transfer.from,
+        transfer.to
+      );
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{entry_transfer, transfer_model, EntryTransfer};
+  use crate::schema;
+
+  fn transfer(keep_access: bool) -> schema::DeviceOwnershipTransfer {
+    schema::DeviceOwnershipTransfer {
+      from: "owner".into(),
+      to: "next".into(),
+      keep_access,
+      expires_at: chrono::Utc::now(),
+    }
+  }
+
+  #[test]
+  fn test_transfer_exclusive() {
+    let model = || schema::DeviceAuthorityModel::Exclusive { owner: "owner".into() };
+
+    match transfer_model(model(), &transfer(false)).unwrap() {
+      schema::DeviceAuthorityModel::Exclusive { owner } => assert_eq!(owner, "next"),
+      other => panic!("unexpected model {other:?}"),
+    }
+
+    match transfer_model(model(), &transfer(true)).unwrap() {
+      schema::DeviceAuthorityModel::Shared { owner, guests, roles } => {
+        assert_eq!(owner, "next");
+        assert_eq!(guests, vec!["owner".to_string()]);
+        assert_eq!(roles.get("owner"), Some(&schema::DeviceGuestRole::Manager));
+      }
+      other => panic!("unexpected model {other:?}"),
+    }
+  }
+
+  #[test]
+  fn test_transfer_to_guest() {
+    let model = schema::DeviceAuthorityModel::Shared {
+      owner: "owner".into(),
+      guests: vec!["next".into()],
+      roles: [("next".to_string(), schema::DeviceGuestRole::Viewer)]
+        .into_iter()
+        .collect(),
+    };
+
+    match transfer_model(model, &transfer(false)).unwrap() {
+      schema::DeviceAuthorityModel::Exclusive { owner } => assert_eq!(owner, "next"),
+      other => panic!("unexpected model {other:?}"),
+    }
+
+    let model = schema::DeviceAuthorityModel::Exclusive { owner: "other".into() };
+    assert!(transfer_model(model, &transfer(false)).is_err());
+  }
+
+  #[test]
+  fn test_entry_transfer() {
+    let events = |user_oid: &str| schema::DeviceScheduleAction::UserEvents {
+      user_oid: user_oid.into(),
+    };
+    let template = |user_oid: &str| schema::DeviceScheduleAction::RenderTemplate {
+      user_oid: user_oid.into(),
+      template_id: "template".into(),
+      variables: Default::default(),
+    };
+
+    assert_eq!(
+      entry_transfer(&events("owner"), &transfer(false), true),
+      EntryTransfer::Reassign
+    );
+    assert_eq!(
+      entry_transfer(&events("owner"), &transfer(true), false),
+      EntryTransfer::Remove
+    );
+    assert_eq!(
+      entry_transfer(&events("other"), &transfer(false), false),
+      EntryTransfer::Keep
+    );
+
+    assert_eq!(
+      entry_transfer(&template("owner"), &transfer(false), true),
+      EntryTransfer::Remove
+    );
+    assert_eq!(
+      entry_transfer(&template("owner"), &transfer(true), true),
+      EntryTransfer::Keep
+    );
+    assert_eq!(
+      entry_transfer(&template("next"), &transfer(false), true),
+      EntryTransfer::Keep
+    );
+
+    let lights = schema::DeviceScheduleAction::Lights(true);
+    assert_eq!(entry_transfer(&lights, &transfer(false), false), EntryTransfer::Keep);
+  }
+}
@@ -0,0 +1,595 @@
+//! Delivers events to the webhooks users have registered. Emitting an event stores a pending
+//! delivery for every interested webhook; the registrar then works through pending deliveries on
+//! each tick, retrying failures with an exponential backoff. Every attempt is kept on the delivery
+//! itself, which doubles as the delivery log.
+//!
+//! Requests are signed with the secret of the webhook: the `x-beetle-signature` header holds the
+//! hex encoded HMAC-SHA256 of `<x-beetle-timestamp>.<body>`.
+
+use crate::schema;
+use anyhow::Context;
+use hmac::Mac;
+use std::io;
+
+/// The most amount of attempts made for a single delivery.
+const MAX_ATTEMPTS: usize = 6;
+
+/// The delay before the first retry; each retry after that waits twice as long as the last.
+const INITIAL_BACKOFF_SECONDS: i64 = 30;
+
+/// The most amount of deliveries attempted per tick.
+const DELIVERY_BATCH_SIZE: i64 = 20;
+
+/// How long an endpoint has to respond.
+const REQUEST_TIMEOUT_SECONDS: u64 = 10;
+
+/// The most amount of deliveries attempted at the same time.
+const DELIVERY_CONCURRENCY: usize = 5;
+
+/// The most amount of time a single batch of deliveries can take.
+const DELIVERY_BUDGET_SECONDS: u64 = 60;
+
+/// The delay between batches of deliveries.
+const DELIVERY_INTERVAL_SECONDS: u64 = 5;
+
+/// The header holding the signature of the request.
+pub const SIGNATURE_HEADER: &str = "x-beetle-signature";
+
+/// The header holding the timestamp that was signed.
+pub const TIMESTAMP_HEADER: &str = "x-beetle-timestamp";
+
+/// Returns the collection that webhooks are stored in.
+pub fn collection(
+  mongo: &mongodb::Client,
+  config: &crate::config::MongoConfiguration,
+) -> mongodb::Collection<schema::Webhook> {
+  mongo
+    .database(&config.database)
+    .collection(config.collections.webhooks.as_deref().unwrap_or("webhooks"))
+}
+
+/// Returns the collection that webhook deliveries are stored in.
+pub fn deliveries_collection(
+  mongo: &mongodb::Client,
+  config: &crate::config::MongoConfiguration,
+) -> mongodb::Collection<schema::WebhookDelivery> {
+  mongo.database(&config.database).collection(
+    config
+      .collections
+      .webhook_deliveries
+      .as_deref()
+      .unwrap_or("webhook_deliveries"),
+  )
+}
+
+/// Returns the hex encoded signature of a request body.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+  let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+  mac.update(format!("{timestamp}.{body}").as_bytes());
+  mac
+    .finalize()
+    .into_bytes()
+    .iter()
+    .map(|byte| format!("{byte:02x}"))
+    .collect()
+}
+
+/// Returns true when the address is reachable on the public internet. Anything else (loopback,
+/// private ranges, link-local and friends) would let users aim requests at our own network.
+fn public_address(address: std::net::IpAddr) -> bool {
+  match address {
+    std::net::IpAddr::V4(address) => {
+      let [first, second, ..] = address.octets();
+      // The shared address space (100.64.0.0/10) used by carrier-grade nat.
+      let shared = first == 100 && (second & 0xc0) == 64;
+      !(address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_unspecified()
+        || address.is_broadcast()
+        || address.is_multicast()
+        || address.is_documentation()
+        || shared
+        || first == 0)
+    }
+    std::net::IpAddr::V6(address) => match address.to_ipv4_mapped() {
+      Some(mapped) => public_address(mapped.into()),
+      None => {
+        let first = address.segments()[0];
+        // Unique local (fc00::/7) and link-local (fe80::/10) addresses.
+        let local = (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80;
+        !(address.is_loopback() || address.is_unspecified() || address.is_multicast() || local)
+      }
+    },
+  }
+}
+
+/// Resolves the host of an http(s) url, failing unless every address it resolves to is public.
+/// This is checked when urls are registered and again before every request, since what a name
+/// resolves to can change in between.
+pub(crate) async fn public_destination(url: &str) -> io::Result<()> {
+  let parsed =
+    url::Url::parse(url).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, format!("bad url - {error}")))?;
+
+  if !matches!(parsed.scheme(), "http" | "https") {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput, "url is not http(s)"));
+  }
+
+  let port = parsed.port_or_known_default().unwrap_or(80);
+  let addresses = match parsed.host() {
+    Some(url::Host::Ipv4(address)) => vec![address.into()],
+    Some(url::Host::Ipv6(address)) => vec![address.into()],
+    Some(url::Host::Domain(domain)) => async_std::net::ToSocketAddrs::to_socket_addrs(&(domain, port))
+      .await?
+      .map(|address| address.ip())
+      .collect(),
+    None => vec![],
+  };
+
+  if addresses.is_empty() {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput, "url host does not resolve"));
+  }
+
+  match addresses.into_iter().find(|address| !public_address(*address)) {
+    Some(address) => Err(io::Error::new(
+      io::ErrorKind::PermissionDenied,
+      format!("url resolves to non-public address '{address}'"),
+    )),
+    None => Ok(()),
+  }
+}
+
+/// Returns how long to wait before the next attempt, given how many have been made.
+fn backoff(attempts: usize) -> chrono::Duration {
+  let exponent = attempts.saturating_sub(1).min(10) as u32;
+  chrono::Duration::seconds(INITIAL_BACKOFF_SECONDS * 2i64.pow(exponent))
+}
+
+/// Returns the events that a finished job should emit.
+pub(super) fn job_events(
+  job: &super::RegistrarJob,
+  result: &io::Result<schema::jobs::JobResult>,
+) -> Vec<schema::WebhookEventPayload> {
+  let Some(device_id) = job.device_id().map(str::to_string) else {
+    return vec![];
+  };
+
+  let mut events = vec![schema::WebhookEventPayload::JobCompleted {
+    job_id: job.id.clone(),
+    device_id: device_id.clone(),
+    label: job.label().to_string(),
+    result: match result {
+      Ok(result) => result.clone(),
+      Err(error) => schema::jobs::JobResult::Failure(error.to_string()),
+    },
+  }];
+
+  if !matches!(result, Ok(schema::jobs::JobResult::Success(_))) {
+    return events;
+  }
+
+  match &job.job {
+    super::RegistrarJobKind::MutateDeviceState(super::device_state::DeviceStateTransitionRequest {
+      transition: super::device_state::DeviceStateTransition::PushMessage(content, origin),
+      ..
+    }) => events.push(schema::WebhookEventPayload::MessagePosted {
+      device_id,
+      content: content.clone(),
+      origin: origin.clone(),
+    }),
+    super::RegistrarJobKind::Ownership(request) => events.push(schema::WebhookEventPayload::OwnershipChanged {
+      device_id,
+      owner: request.user_id.clone(),
+    }),
+    super::RegistrarJobKind::Transfer(super::transfer::DeviceTransferRequest::Accept { user_id, .. }) => {
+      events.push(schema::WebhookEventPayload::OwnershipChanged {
+        device_id,
+        owner: user_id.clone(),
+      })
+    }
+    _ => (),
+  }
+
+  events
+}
+
+/// Stores a pending delivery of the event for every webhook interested in it. Webhooks are only
+/// considered when the user that registered them can currently access the device.
+pub(super) async fn emit(
+  handle: &mut super::worker::WorkerHandle<'_>,
+  payload: schema::WebhookEventPayload,
+) -> anyhow::Result<usize> {
+  let device_id = payload.device_id().to_string();
+  let members = match handle
+    .device_authority_collection()?
+    .find_one(bson::doc! { "device_id": &device_id }, None)
+    .await?
+    .and_then(|record| record.authority_model)
+  {
+    Some(schema::DeviceAuthorityModel::Exclusive { owner }) => vec![owner],
+    Some(schema::DeviceAuthorityModel::Shared { owner, guests, .. })
+    | Some(schema::DeviceAuthorityModel::Public { owner, guests, .. }) => {
+      std::iter::once(owner).chain(guests).collect()
+    }
+    None => return Ok(0),
+  };
+
+  let kind = bson::to_bson(&payload.kind()).with_context(|| "unable to serialize event kind")?;
+  let mut cursor = collection(&handle.mongo.client, &handle.mongo.config)
+    .find(bson::doc! { "user_id": { "$in": members }, "events": kind }, None)
+    .await
+    .with_context(|| format!("unable to find webhooks for '{device_id}'"))?;
+
+  let event = schema::WebhookEvent {
+    id: uuid::Uuid::new_v4().to_string(),
+    created_at: chrono::Utc::now(),
+    payload,
+  };
+
+  let deliveries = deliveries_collection(&handle.mongo.client, &handle.mongo.config);
+  let mut count = 0;
+  while cursor.advance().await? {
+    let webhook = cursor.deserialize_current()?;
+    if !webhook.device_ids.is_empty() && !webhook.device_ids.contains(&device_id) {
+      continue;
+    }
+
+    deliveries
+      .insert_one(
+        schema::WebhookDelivery {
+          id: uuid::Uuid::new_v4().to_string(),
+          webhook_id: webhook.id,
+          user_id: webhook.user_id,
+          event: event.clone(),
+          status: schema::WebhookDeliveryStatus::Pending,
+          next_attempt_at: event.created_at.timestamp_millis(),
+          attempts: vec![],
+        },
+        None,
+      )
+      .await
+      .with_context(|| "unable to store webhook delivery")?;
+    count += 1;
+  }
+
+  if count > 0 {
+    log::info!("queued {count} webhook deliveries for {:?}", event.payload.kind());
+  }
+
+  Ok(count)
+}
+
+/// Posts the event to the webhook, returning the attempt made. Nothing is sent unless the url
+/// resolves to public addresses.
+async fn attempt(webhook: &schema::Webhook, delivery: &schema::WebhookDelivery) -> schema::WebhookDeliveryAttempt {
+  if let Err(error) = public_destination(&webhook.url).await {
+    return schema::WebhookDeliveryAttempt {
+      at: chrono::Utc::now(),
+      status_code: None,
+      error: Some(error.to_string()),
+    };
+  }
+
+  post(webhook, delivery).await
+}
+
+/// Sends the signed request for a delivery, returning the attempt made.
+async fn post(webhook: &schema::Webhook, delivery: &schema::WebhookDelivery) -> schema::WebhookDeliveryAttempt {
+  let at = chrono::Utc::now();
+  let failed = |status_code: Option<u16>, error: String| schema::WebhookDeliveryAttempt {
+    at,
+    status_code,
+    error: Some(error),
+  };
+
+  let body = match serde_json::to_string(&delivery.event) {
+    Ok(body) => body,
+    Err(error) => return failed(None, format!("unable to serialize event - {error}")),
+  };
+  let timestamp = at.timestamp();
+
+  let request = surf::post(&webhook.url)
+    .header(SIGNATURE_HEADER, sign(&webhook.secret, timestamp, &body))
+    .header(TIMESTAMP_HEADER, timestamp.to_string())
+    .header("x-beetle-delivery", delivery.id.as_str())
+    .content_type(surf::http::mime::JSON)
+    .body_string(body);
+
+  let timeout = std::time::Duration::from_secs(REQUEST_TIMEOUT_SECONDS);
+  match async_std::future::timeout(timeout, request).await {
+    Err(_) => failed(None, "timed out".to_string()),
+    Ok(Err(error)) => failed(None, error.to_string()),
+    Ok(Ok(response)) if response.status().is_success() => schema::WebhookDeliveryAttempt {
+      at,
+      status_code: Some(response.status().into()),
+      error: None,
+    },
+    Ok(Ok(response)) => failed(Some(response.status().into()), "unsuccessful response".to_string()),
+  }
+}
+
+/// Attempts a single delivery, recording the outcome on it. The delivery is claimed first by moving
+/// its next attempt out, so a batch that outlives its budget is never picked up twice.
+async fn deliver_one(
+  webhooks: mongodb::Collection<schema::Webhook>,
+  deliveries: mongodb::Collection<schema::WebhookDelivery>,
+  delivery: schema::WebhookDelivery,
+) -> anyhow::Result<()> {
+  let now = chrono::Utc::now();
+  let next_attempt_at = (now + backoff(delivery.attempts.len() + 1)).timestamp_millis();
+
+  let claimed = deliveries
+    .update_one(
+      bson::doc! { "id": &delivery.id, "status": "pending", "next_attempt_at": delivery.next_attempt_at },
+      bson::doc! { "$set": { "next_attempt_at": next_attempt_at } },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to claim webhook delivery '{}'", delivery.id))?;
+
+  if claimed.matched_count == 0 {
+    log::debug!("webhook delivery '{}' was claimed elsewhere", delivery.id);
+    return Ok(());
+  }
+
+  let webhook = webhooks
+    .find_one(bson::doc! { "id": &delivery.webhook_id }, None)
+    .await?;
+
+  let (attempt, status) = match webhook {
+    Some(webhook) => {
+      let attempt = attempt(&webhook, &delivery).await;
+      let status = match (&attempt.error, delivery.attempts.len() + 1) {
+        (None, _) => schema::WebhookDeliveryStatus::Delivered,
+        (Some(_), count) if count >= MAX_ATTEMPTS => schema::WebhookDeliveryStatus::Failed,
+        (Some(_), _) => schema::WebhookDeliveryStatus::Pending,
+      };
+      (attempt, status)
+    }
+    None => (
+      schema::WebhookDeliveryAttempt {
+        at: now,
+        status_code: None,
+        error: Some("webhook was removed".to_string()),
+      },
+      schema::WebhookDeliveryStatus::Failed,
+    ),
+  };
+
+  if let Some(error) = attempt.error.as_ref() {
+    log::warn!("webhook delivery '{}' failed ({status:?}) - {error}", delivery.id);
+  }
+
+  deliveries
+    .update_one(
+      bson::doc! { "id": &delivery.id },
+      bson::doc! {
+        "$set": {
+          "status": bson::to_bson(&status)?,
+          "next_attempt_at": next_attempt_at,
+        },
+        "$push": { "attempts": bson::to_bson(&attempt)? },
+      },
+      None,
+    )
+    .await
+    .with_context(|| format!("unable to record attempt of webhook delivery '{}'", delivery.id))?;
+
+  Ok(())
+}
+
+/// Attempts every delivery that is due, a few at a time.
+async fn deliver(mongo: &mongodb::Client, config: &crate::config::MongoConfiguration) -> anyhow::Result<()> {
+  let webhooks = collection(mongo, config);
+  let deliveries = deliveries_collection(mongo, config);
+  let now = chrono::Utc::now();
+
+  let mut cursor = deliveries
+    .find(
+      bson::doc! { "status": "pending", "next_attempt_at": { "$lte": now.timestamp_millis() } },
+      mongodb::options::FindOptions::builder()
+        .sort(bson::doc! { "next_attempt_at": 1 })
+        .limit(DELIVERY_BATCH_SIZE)
+        .build(),
+    )
+    .await
+    .with_context(|| "unable to query pending webhook deliveries")?;
+
+  let mut due = vec![];
+  while cursor.advance().await? {
+    due.push(cursor.deserialize_current()?);
+  }
+
+  let mut due = due.into_iter().peekable();
+  while due.peek().is_some() {
+    let running = due
+      .by_ref()
+      .take(DELIVERY_CONCURRENCY)
+      .map(|delivery| {
+        let id = delivery.id.clone();
+        let task = async_std::task::spawn(deliver_one(webhooks.clone(), deliveries.clone(), delivery));
+        (id, task)
+      })
+      .collect::<Vec<_>>();
+
+    for (id, task) in running {
+      if let Err(error) = task.await {
+        log::error!("unable to deliver webhook '{id}' - {error}");
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Works through pending deliveries for as long as the registrar runs. This happens on its own task
+/// so that slow endpoints never hold up the registrar tick; each batch is also given a budget, after
+/// which whatever is still in flight is left to finish on its own.
+pub(super) async fn work(mongo: mongodb::Client, config: crate::config::MongoConfiguration) {
+  let budget = std::time::Duration::from_secs(DELIVERY_BUDGET_SECONDS);
+  let interval = std::time::Duration::from_secs(DELIVERY_INTERVAL_SECONDS);
+
+  loop {
+    match async_std::future::timeout(budget, deliver(&mongo, &config)).await {
+      Err(_) => log::warn!("webhook deliveries ran over their budget"),
+      Ok(Err(error)) => log::error!("failed webhook deliveries - {error}"),
+      Ok(Ok(())) => (),
+    }
+
+    async_std::task::sleep(interval).await;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{attempt, backoff, post, public_address, public_destination, sign, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+  use crate::schema;
+  use async_std::io::prelude::{BufReadExt, ReadExt, WriteExt};
+
+  /// Accepts a single http request, responding with the provided status. Returns the headers (in
+  /// lowercase) and body that were received.
+  async fn receive(listener: async_std::net::TcpListener, status: u16) -> (Vec<(String, String)>, String) {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut reader = async_std::io::BufReader::new(stream);
+    let mut headers = vec![];
+
+    loop {
+      let mut line = String::new();
+      reader.read_line(&mut line).await.unwrap();
+      let line = line.trim_end();
+      if line.is_empty() {
+        break;
+      }
+      if let Some((name, value)) = line.split_once(':') {
+        headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+      }
+    }
+
+    let length = headers
+      .iter()
+      .find(|(name, _)| name == "content-length")
+      .and_then(|(_, value)| value.parse::<usize>().ok())
+      .unwrap_or_default();
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).await.unwrap();
+
+    let response = format!("HTTP/1.1 {status} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+    reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+
+    (headers, String::from_utf8(body).unwrap())
+  }
+
+  fn fixtures(port: u16) -> (schema::Webhook, schema::WebhookDelivery) {
+    let webhook = schema::Webhook {
+      id: "webhook".to_string(),
+      user_id: "user".to_string(),
+      url: format!("http://127.0.0.1:{port}/hooks"),
+      secret: "secret".to_string(),
+      events: vec![schema::WebhookEventKind::DeviceOnline],
+      device_ids: vec![],
+      created_at: None,
+    };
+    let delivery = schema::WebhookDelivery {
+      id: "delivery".to_string(),
+      webhook_id: webhook.id.clone(),
+      user_id: webhook.user_id.clone(),
+      event: schema::WebhookEvent {
+        id: "event".to_string(),
+        created_at: chrono::Utc::now(),
+        payload: schema::WebhookEventPayload::DeviceOnline {
+          device_id: "device".to_string(),
+        },
+      },
+      status: schema::WebhookDeliveryStatus::Pending,
+      next_attempt_at: 0,
+      attempts: vec![],
+    };
+    (webhook, delivery)
+  }
+
+  #[test]
+  fn test_signed_delivery() {
+    async_std::task::block_on(async {
+      let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let (webhook, delivery) = fixtures(listener.local_addr().unwrap().port());
+      let receiver = async_std::task::spawn(receive(listener, 200));
+
+      let result = post(&webhook, &delivery).await;
+      assert_eq!(result.status_code, Some(200));
+      assert_eq!(result.error, None);
+
+      let (headers, body) = receiver.await;
+      let header = |name: &str| {
+        headers
+          .iter()
+          .find(|(key, _)| key == name)
+          .map(|(_, value)| value.clone())
+          .unwrap()
+      };
+      let timestamp = header(TIMESTAMP_HEADER).parse::<i64>().unwrap();
+      assert_eq!(header(SIGNATURE_HEADER), sign("secret", timestamp, &body));
+      assert!(body.contains("\"beetle:kind\":\"device_online\""));
+    });
+  }
+
+  #[test]
+  fn test_failed_delivery() {
+    async_std::task::block_on(async {
+      let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let (webhook, delivery) = fixtures(listener.local_addr().unwrap().port());
+      let receiver = async_std::task::spawn(receive(listener, 500));
+
+      let result = post(&webhook, &delivery).await;
+      assert_eq!(result.status_code, Some(500));
+      assert!(result.error.is_some());
+      receiver.await;
+    });
+  }
+
+  #[test]
+  fn test_private_delivery() {
+    async_std::task::block_on(async {
+      let (webhook, delivery) = fixtures(8080);
+      let result = attempt(&webhook, &delivery).await;
+      assert_eq!(result.status_code, None);
+      assert!(result.error.unwrap().contains("non-public"));
+    });
+  }
+
+  #[test]
+  fn test_public_address() {
+    let parse = |address: &str| address.parse::<std::net::IpAddr>().unwrap();
+    assert!(public_address(parse("93.184.216.34")));
+    assert!(public_address(parse("2606:2800:220:1::")));
+    assert!(!public_address(parse("127.0.0.1")));
+    assert!(!public_address(parse("10.1.2.3")));
+    assert!(!public_address(parse("192.168.1.1")));
+    assert!(!public_address(parse("169.254.169.254")));
+    assert!(!public_address(parse("100.64.0.1")));
+    assert!(!public_address(parse("0.0.0.0")));
+    assert!(!public_address(parse("::1")));
+    assert!(!public_address(parse("fd00::1")));
+    assert!(!public_address(parse("fe80::1")));
+    assert!(!public_address(parse("::ffff:127.0.0.1")));
+  }
+
+  #[test]
+  fn test_public_destination() {
+    async_std::task::block_on(async {
+      assert!(public_destination("http://127.0.0.1:8080/hooks").await.is_err());
+      assert!(public_destination("http://[::1]/hooks").await.is_err());
+      assert!(public_destination("http://169.254.169.254/latest/meta-data")
+        .await
+        .is_err());
+      assert!(public_destination("ftp://93.184.216.34/hooks").await.is_err());
+      assert!(public_destination("https://93.184.216.34/hooks").await.is_ok());
+    });
+  }
+
+  #[test]
+  fn test_backoff() {
+    assert_eq!(backoff(1), chrono::Duration::seconds(30));
+    assert_eq!(backoff(2), chrono::Duration::seconds(60));
+    assert_eq!(backoff(5), chrono::Duration::seconds(480));
+  }
+}
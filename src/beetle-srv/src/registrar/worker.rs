@@ -69,11 +69,15 @@ pub(super) struct WorkerHandle<'a> {
 
   /// A reference to the active redis connection. It would be nice if this itself was some
   /// container instead, the way our mongo client is.
-  #[allow(unused)]
   redis: &'a mut crate::redis::RedisConnection,
 }
 
 impl<'a> WorkerHandle<'a> {
+  /// Returns the active redis connection, for functionality that is shared outside the worker.
+  pub(super) fn redis(&mut self) -> &mut crate::redis::RedisConnection {
+    self.redis
+  }
+
   /// Pushes a render layout onto the queue for a device.
   pub(super) async fn render<I, S>(
     &mut self,
@@ -156,8 +160,87 @@ impl<'a> WorkerHandle<'a> {
     )
   }
 
+  /// Returns the mongodb collection for our users.
+  pub fn users_collection(&mut self) -> io::Result<mongodb::Collection<schema::User>> {
+    Ok(
+      self
+        .mongo
+        .client
+        .database(&self.mongo.config.database)
+        .collection(&self.mongo.config.collections.users),
+    )
+  }
+
+  /// Returns the mongodb collection for our device diagnostics.
+  pub fn device_diagnostic_collection(&mut self) -> io::Result<mongodb::Collection<schema::DeviceDiagnostic>> {
+    Ok(
+      self
+        .mongo
+        .client
+        .database(&self.mongo.config.database)
+        .collection(&self.mongo.config.collections.device_diagnostics),
+    )
+  }
+
+  /// Returns the mongodb collection for our device authority records.
+  pub fn device_authority_collection(&mut self) -> io::Result<mongodb::Collection<schema::DeviceAuthorityRecord>> {
+    Ok(
+      self
+        .mongo
+        .client
+        .database(&self.mongo.config.database)
+        .collection(&self.mongo.config.collections.device_authorities),
+    )
+  }
+
+  /// Returns the mongodb collection where decommissioned devices are archived.
+  pub fn device_archive_collection(&mut self) -> io::Result<mongodb::Collection<schema::DeviceArchiveRecord>> {
+    let collections = &self.mongo.config.collections;
+    Ok(
+      self
+        .mongo
+        .client
+        .database(&self.mongo.config.database)
+        .collection(collections.device_archives.as_deref().unwrap_or("device_archives")),
+    )
+  }
+
+  /// Returns the mongodb collection of inbound hooks.
+  pub fn inbound_hook_collection(&mut self) -> io::Result<mongodb::Collection<schema::InboundHook>> {
+    let collections = &self.mongo.config.collections;
+    Ok(
+      self
+        .mongo
+        .client
+        .database(&self.mongo.config.database)
+        .collection(collections.inbound_hooks.as_deref().unwrap_or("inbound_hooks")),
+    )
+  }
+
+  /// Returns the mongodb collection of layout templates.
+  pub fn layout_template_collection(&mut self) -> io::Result<mongodb::Collection<schema::LayoutTemplate>> {
+    let collections = &self.mongo.config.collections;
+    Ok(
+      self
+        .mongo
+        .client
+        .database(&self.mongo.config.database)
+        .collection(collections.layout_templates.as_deref().unwrap_or("layout_templates")),
+    )
+  }
+
+  /// Removes everything waiting in the rendering queue for the device.
+  pub(super) async fn purge_renders<I>(&mut self, device_id: I) -> io::Result<usize>
+  where
+    I: AsRef<str>,
+  {
+    crate::rendering::queue::Queue::new(self.redis, &self.config.vendor_api_secret)
+      .purge(device_id)
+      .await
+  }
+
   /// The smallest wrapped around kramer redis command execution using our reference to redis.
-  async fn command<S, V>(&mut self, command: &kramer::Command<S, V>) -> io::Result<kramer::Response>
+  pub(super) async fn command<S, V>(&mut self, command: &kramer::Command<S, V>) -> io::Result<kramer::Response>
   where
     S: std::fmt::Display,
     V: std::fmt::Display,
@@ -212,6 +295,7 @@ impl Worker {
         // Attempt to fill our id pool if necessary.
         let amount = pool::fill_pool(
           &mut redis_connection,
+          &super::credentials::collection(&self.mongo.client, &self.mongo.config),
           self.config.registration_pool_minimum.unwrap_or(DEFAULT_POOL_MINIMUM),
         )
         .await?;
@@ -246,6 +330,10 @@ impl Worker {
           log::error!("failed scheduled registrar workflow - {error}");
         }
 
+        if let Err(error) = super::connectivity::check(self.handle(&mut redis_connection)).await {
+          log::error!("failed device connectivity check - {error}");
+        }
+
         let mut processed_job_count = 0u8;
         if pending_job_count > 0 {
           log::trace!("attempting to process {pending_job_count} job(s)");
@@ -417,6 +505,72 @@ async fn work_jobs(
         .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
     }
 
+    RegistrarJobKind::Moderation(request) => {
+      log::info!(
+        "job[{}] processing moderation change for '{}'",
+        job_container.id,
+        request.device_id
+      );
+
+      super::moderation::apply(worker.handle(redis_connection), request)
+        .await
+        .map(|_| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal))
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+
+    RegistrarJobKind::Membership(change) => {
+      log::info!("job[{}] processing membership change", job_container.id);
+
+      super::membership::apply(worker.handle(redis_connection), change)
+        .await
+        .map(|_| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal))
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+
+    RegistrarJobKind::Transfer(request) => {
+      log::info!("job[{}] processing ownership transfer", job_container.id);
+
+      super::transfer::apply(worker.handle(redis_connection), request)
+        .await
+        .map(|_| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal))
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+
+    RegistrarJobKind::Decommission(request) => {
+      log::info!(
+        "job[{}] processing decommission for '{}'",
+        job_container.id,
+        request.device_id
+      );
+
+      super::decommission::apply(worker.handle(redis_connection), request)
+        .await
+        .map(|_| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal))
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+
+    RegistrarJobKind::Credentials(request) => {
+      log::info!("job[{}] processing credential request", job_container.id);
+
+      super::credentials::apply(worker.handle(redis_connection), request)
+        .await
+        .map(|_| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal))
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+
+    RegistrarJobKind::MutateDeviceAlbum(request) => {
+      log::info!(
+        "job[{}] processing album change for '{}'",
+        job_container.id,
+        request.device_id
+      );
+
+      super::album::apply(worker.handle(redis_connection), request)
+        .await
+        .map(|_| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal))
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+
     RegistrarJobKind::Renders(super::jobs::RegistrarRenderKinds::SendImage { location, device_id }) => {
       log::debug!("attempting to send '{location:?}' to device {device_id}");
       send_image(worker.handle(redis_connection), device_id, location).await
@@ -499,6 +653,19 @@ async fn work_jobs(
       execution_result.map(|_| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal))
     }
 
+    RegistrarJobKind::Schedule(request) => {
+      log::info!(
+        "job[{}] processing schedule change for '{}'",
+        job_container.id,
+        request.device_id
+      );
+
+      super::device_schedule::apply(worker.handle(redis_connection), request)
+        .await
+        .map(|_| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal))
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+
     RegistrarJobKind::ToggleDefaultSchedule {
       user_id,
       device_id,
@@ -536,6 +703,12 @@ async fn work_jobs(
     }
   };
 
+  for event in super::webhooks::job_events(&job_container, &result) {
+    if let Err(error) = super::webhooks::emit(&mut worker.handle(redis_connection), event).await {
+      log::error!("unable to emit webhook event for job '{}' - {error}", job_container.id);
+    }
+  }
+
   let serialized_result = match result {
     Ok(job_result) => serde_json::to_string(&job_result),
     Err(job_error) => {
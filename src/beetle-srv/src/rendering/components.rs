@@ -4,7 +4,33 @@ use qrencode as qrcode;
 use serde::{Deserialize, Serialize};
 use std::io;
 
-use super::fonts;
+use super::{fonts, icons};
+
+/// The space between an icon and the text next to it.
+const ICON_SPACING: i32 = 6;
+
+/// The largest icons are drawn at; this is the height of the tallest image we render.
+pub const MAX_ICON_SIZE: u32 = super::constants::MAX_HEIGHT;
+
+/// The largest margin, padding or border applied to a single side of a message, and the largest
+/// border radius; anything beyond this would not fit on any image we render anyway.
+const MAX_BOX_SIDE: i32 = super::constants::MAX_WIDTH as i32;
+
+/// Adds up the parts of a component's size, saturating instead of overflowing.
+fn box_sum(parts: &[i32]) -> i32 {
+  parts.iter().fold(0, |sum, part| sum.saturating_add(*part))
+}
+
+/// The margin (or padding) on the left, right, top and bottom.
+fn box_sides(bounding: Option<&OptionalBoundingBox>) -> (i32, i32, i32, i32) {
+  let side = |value: Option<i32>| value.unwrap_or(0).clamp(-MAX_BOX_SIDE, MAX_BOX_SIDE);
+  (
+    side(bounding.and_then(|b| b.left)),
+    side(bounding.and_then(|b| b.right)),
+    side(bounding.and_then(|b| b.top)),
+    side(bounding.and_then(|b| b.bottom)),
+  )
+}
 
 /// A bounding box where everything is optional.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -30,15 +56,31 @@ pub struct StylizedMessage<S> {
   /// The font to use.
   pub font: Option<fonts::FontSelection>,
 
+  /// Fonts used, in order, for characters the font does not have a glyph for. DejaVu is always
+  /// tried last.
+  pub fallback_fonts: Option<Vec<fonts::FontSelection>>,
+
+  /// An icon drawn before the text, if any.
+  pub icon: Option<Icon>,
+
   /// The amount of padding, if any.
   pub padding: Option<OptionalBoundingBox>,
 
   /// The amount of padding, if any.
   pub margin: Option<OptionalBoundingBox>,
 
-  /// The amount of border, if any.
+  /// The width of the border on each side, if any. Borders are drawn outside of the padding.
   pub border: Option<OptionalBoundingBox>,
 
+  /// Rounds the corners of the box by this many pixels.
+  pub border_radius: Option<u32>,
+
+  /// Draws white text (and icons) on a black box.
+  pub inverted: Option<bool>,
+
+  /// Fills the box inside of the border. Inverted boxes are filled with black unless this is set.
+  pub fill: Option<GrayLevel>,
+
   /// The scale to apply to our font.
   pub size: Option<f32>,
 }
@@ -52,10 +94,81 @@ where
     Self {
       message: S::default(),
       font: Some(font),
+      fallback_fonts: None,
+      icon: None,
       size: None,
       margin: None,
       padding: None,
       border: None,
+      border_radius: None,
+      inverted: None,
+      fill: None,
+    }
+  }
+}
+
+/// The shades of gray our displays can show.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GrayLevel {
+  /// No ink at all.
+  White,
+
+  /// A third of the way to black.
+  LightGray,
+
+  /// Two thirds of the way to black.
+  DarkGray,
+
+  /// Full ink.
+  Black,
+}
+
+impl GrayLevel {
+  /// The pixel value of this shade.
+  pub fn luma(&self) -> image::Luma<u8> {
+    match self {
+      Self::White => image::Luma([255]),
+      Self::LightGray => image::Luma([170]),
+      Self::DarkGray => image::Luma([85]),
+      Self::Black => image::Luma([0]),
+    }
+  }
+}
+
+/// Fills a rectangle whose corners are rounded by the provided radius. Whatever falls outside of the
+/// image is clipped.
+fn fill_rounded_rect<C>(image: &mut C, origin: (i32, i32), size: (i32, i32), radius: i32, color: image::Luma<u8>)
+where
+  C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+{
+  let (image_width, image_height) = image.dimensions();
+  let (width, height) = (size.0.max(0), size.1.max(0));
+  let radius = f64::from(radius.min(width / 2).min(height / 2).max(0));
+
+  // Only the rows and columns that land on the image are visited. The math is done in i64 (and
+  // f64) so that large rectangles, or ones placed far off the image, cannot overflow.
+  let visible = |start: i32, length: i32, limit: u32| {
+    let first = (-i64::from(start)).clamp(0, i64::from(length));
+    let last = (i64::from(limit) - i64::from(start)).clamp(0, i64::from(length));
+    first..last
+  };
+
+  for y in visible(origin.1, height, image_height) {
+    for x in visible(origin.0, width, image_width) {
+      // Pixels in the corners are only drawn if they fall within the circle of that corner.
+      let (cx, cy) = (x as f64 + 0.5, y as f64 + 0.5);
+      let dx = cx - cx.clamp(radius, f64::from(width) - radius);
+      let dy = cy - cy.clamp(radius, f64::from(height) - radius);
+      if dx * dx + dy * dy > radius * radius {
+        continue;
+      }
+
+      image.draw_pixel(
+        (i64::from(origin.0) + x) as u32,
+        (i64::from(origin.1) + y) as u32,
+        color,
+      );
     }
   }
 }
@@ -79,90 +192,782 @@ pub(crate) struct StylizedMessageBounding {
   pub(crate) constraints: Option<StylizedMessageBoundingConstraints>,
 }
 
+/// A message that has been laid out within a bounding box, but not yet drawn.
+pub(crate) struct FittedMessage {
+  /// The text that will actually be drawn, which may have been truncated.
+  pub(crate) text: String,
+
+  /// Whether the text had to be truncated to fit within the constraints.
+  pub(crate) truncated: bool,
+
+  /// The size of the text alone.
+  text_dimensions: (i32, i32),
+
+  /// The width and height of the icon drawn before the text; zero without one.
+  icon_size: i32,
+
+  /// The size of everything drawn, including padding and margins.
+  pub(crate) dimensions: (i32, i32),
+}
+
 impl<S> StylizedMessage<S>
 where
   S: std::convert::AsRef<str>,
 {
-  /// Render the text within this bounding box.
-  pub(super) fn draw_within<C>(&self, bounds: &StylizedMessageBounding, image: &mut C) -> io::Result<(i32, i32)>
-  where
-    C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
-  {
-    let df = fonts::FontSelection::default();
-    // Get our font from the embedded ttf data.
-    let font = self.font.as_ref().unwrap_or(&df).renderer()?;
-    let scale = rusttype::Scale {
+  /// The scale our font is rendered at.
+  fn scale(&self) -> rusttype::Scale {
+    rusttype::Scale {
       x: self.size.unwrap_or(80f32),
       y: self.size.unwrap_or(80f32),
-    };
+    }
+  }
 
-    let mb = self.margin.as_ref().and_then(|m| m.bottom).unwrap_or(0);
-    let mt = self.margin.as_ref().and_then(|m| m.top).unwrap_or(0);
-    let ml = self.margin.as_ref().and_then(|m| m.left).unwrap_or(0);
-    let mr = self.margin.as_ref().and_then(|m| m.right).unwrap_or(0);
+  /// The width of the border on the left, right, top and bottom.
+  fn border_widths(&self) -> (i32, i32, i32, i32) {
+    let border = self.border.as_ref();
+    let side = |value: Option<i32>| value.unwrap_or(0).clamp(0, MAX_BOX_SIDE);
+    (
+      side(border.and_then(|b| b.left)),
+      side(border.and_then(|b| b.right)),
+      side(border.and_then(|b| b.top)),
+      side(border.and_then(|b| b.bottom)),
+    )
+  }
+
+  /// The font chain our text is drawn with.
+  fn fonts(&self) -> io::Result<fonts::FontChain> {
+    fonts::FontChain::new(self.font.as_ref(), self.fallback_fonts.as_deref())
+  }
 
-    let pl = self.padding.as_ref().and_then(|m| m.left).unwrap_or(0);
-    let pr = self.padding.as_ref().and_then(|m| m.right).unwrap_or(0);
-    let pt = self.padding.as_ref().and_then(|m| m.top).unwrap_or(0);
-    let pb = self.padding.as_ref().and_then(|m| m.bottom).unwrap_or(0);
+  /// Lays out the text within the bounding box, truncating it if it is constrained.
+  fn fit_with(&self, fonts: &fonts::FontChain, bounds: &StylizedMessageBounding) -> io::Result<FittedMessage> {
+    let scale = self.scale();
 
-    let top = bounds.top + mt;
-    let left = bounds.left + ml;
+    let (ml, mr, mt, mb) = box_sides(self.margin.as_ref());
+    let (pl, pr, pt, pb) = box_sides(self.padding.as_ref());
+    let (bl, br, bt, bb) = self.border_widths();
 
-    let mut rendered_text = std::borrow::Cow::from(self.message.as_ref());
     let mut message = self.message.as_ref();
-    let mut text_dimensions = imageproc::drawing::text_size(scale, &font, message);
+    let mut text_dimensions = fonts.text_size(scale, message);
+    let mut text = message.to_string();
+    let mut truncated = false;
 
-    if let Some(StylizedMessageBoundingConstraints::MaxWidth(max_width)) = bounds.constraints {
-      let mut trunc_size = 0;
-      let mut cloned = message.to_string();
+    // Unless sized, icons match the height of the (untruncated) text, or the font size without any.
+    let icon_size = self
+      .icon
+      .as_ref()
+      .map_or(0, |icon| match (icon.size, text_dimensions.1) {
+        (Some(size), _) => size.min(MAX_ICON_SIZE) as i32,
+        (None, 0) => scale.y.round() as i32,
+        (None, height) => height,
+      });
+    let icon_width = if self.icon.is_some() {
+      icon_size + ICON_SPACING
+    } else {
+      0
+    };
 
-      while (text_dimensions.0 + ml + pl) > max_width {
-        (!message.is_empty()).then_some(()).ok_or_else(|| {
-          io::Error::new(
+    if let Some(StylizedMessageBoundingConstraints::MaxWidth(max_width)) = bounds.constraints {
+      while box_sum(&[text_dimensions.0, icon_width, ml, bl, br, pl]) > max_width {
+        let Some((last, _)) = message.char_indices().last() else {
+          return Err(io::Error::new(
             io::ErrorKind::Other,
             format!("unable to render '{}' within {max_width}", self.message.as_ref()),
-          )
-        })?;
+          ));
+        };
 
-        (message, _) = message.split_at(message.len() - 1);
-        text_dimensions = imageproc::drawing::text_size(scale, &font, message);
-        trunc_size += 1;
+        message = &message[..last];
+        text_dimensions = fonts.text_size(scale, message);
+        truncated = true;
       }
 
-      if trunc_size > 0 {
-        (message, _) = message.split_at(message.len() - 3);
-        cloned = format!("{message}...");
+      if truncated {
+        let keep = message.chars().count().saturating_sub(3);
+        text = format!("{}...", message.chars().take(keep).collect::<String>());
       }
+    }
+
+    Ok(FittedMessage {
+      text,
+      truncated,
+      text_dimensions,
+      icon_size,
+      dimensions: (
+        box_sum(&[text_dimensions.0, icon_width, ml, mr, bl, br, pl, pr]),
+        box_sum(&[text_dimensions.1.max(icon_size), mt, mb, bt, bb, pt, pb]),
+      ),
+    })
+  }
+
+  /// Lays out the text within the bounding box without drawing it.
+  pub(crate) fn fit(&self, bounds: &StylizedMessageBounding) -> io::Result<FittedMessage> {
+    self.fit_with(&self.fonts()?, bounds)
+  }
+
+  /// Render the text within this bounding box.
+  pub(super) fn draw_within<C>(&self, bounds: &StylizedMessageBounding, image: &mut C) -> io::Result<(i32, i32)>
+  where
+    C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+  {
+    let fonts = self.fonts()?;
+    let fitted = self.fit_with(&fonts, bounds)?;
+    let text_dimensions = fitted.text_dimensions;
+    let content_height = text_dimensions.1.max(fitted.icon_size);
+    let icon_width = if self.icon.is_some() {
+      fitted.icon_size + ICON_SPACING
+    } else {
+      0
+    };
+
+    let (ml, _, mt, _) = box_sides(self.margin.as_ref());
+    let (pl, pr, pt, pb) = box_sides(self.padding.as_ref());
+    let (bl, br, bt, bb) = self.border_widths();
+    let inverted = self.inverted.unwrap_or(false);
+    let foreground = if inverted { GrayLevel::White } else { GrayLevel::Black };
+    let fill = self.fill.or(inverted.then_some(GrayLevel::Black));
+
+    let top = bounds.top.saturating_add(mt);
+    let left = bounds.left.saturating_add(ml);
+    let radius = self.border_radius.unwrap_or(0).min(MAX_BOX_SIDE as u32) as i32;
+    let box_size = (
+      box_sum(&[bl, pl, icon_width, text_dimensions.0, pr, br]),
+      box_sum(&[bt, pt, content_height, pb, bb]),
+    );
+    let has_border = bl + br + bt + bb > 0;
 
-      rendered_text = std::borrow::Cow::from(cloned);
+    // The border is drawn as a black box, with the inside of it filled back in on top.
+    if has_border {
+      fill_rounded_rect(image, (left, top), box_size, radius, GrayLevel::Black.luma());
     }
 
-    // Currently, this will _not_ take into account how wide the text is.
-    if let Some(border) = self.border.as_ref() {
-      let bh = text_dimensions.1 + pt + pb;
-      let bw = text_dimensions.0 + pl + pr;
-      let bl = left + border.left.as_ref().copied().unwrap_or(0);
+    if has_border || fill.is_some() {
+      let inner_size = (box_size.0 - bl - br, box_size.1 - bt - bb);
+      let inner_radius = radius - bl.max(br).max(bt).max(bb);
+      let inner_fill = fill.unwrap_or(GrayLevel::White).luma();
+      fill_rounded_rect(image, (left + bl, top + bt), inner_size, inner_radius, inner_fill);
+    }
+
+    let content_left = left + bl + pl;
+    let content_top = top + bt + pt;
 
-      let bounding_rect = imageproc::rect::Rect::at(left, top).of_size(bw as u32, bh as u32);
-      let inner_rect = imageproc::rect::Rect::at(bl, top).of_size(bw as u32, bh as u32);
-      imageproc::drawing::draw_filled_rect_mut(image, bounding_rect, image::Luma([0]));
-      imageproc::drawing::draw_filled_rect_mut(image, inner_rect, image::Luma([255]));
+    if let Some(icon) = self.icon.as_ref() {
+      let icon_top = content_top + (content_height - fitted.icon_size) / 2;
+      icon.draw_at(
+        fitted.icon_size as u32,
+        content_left,
+        icon_top,
+        foreground.luma(),
+        image,
+      );
     }
 
-    imageproc::drawing::draw_text_mut(
+    fonts.draw(
       image,
-      image::Luma([0]),
-      left + pl,
-      top + pt,
-      scale,
-      &font,
-      &rendered_text,
+      foreground.luma(),
+      content_left + icon_width,
+      content_top + (content_height - text_dimensions.1) / 2,
+      self.scale(),
+      &fitted.text,
     );
 
-    let height = text_dimensions.1 + mt + mb + pt + pb;
-    let width = text_dimensions.0 + ml + mr + pl + pr;
-    Ok((width, height))
+    Ok(fitted.dimensions)
+  }
+}
+
+/// The glyphs bundled with the renderer that icons can be drawn with.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IconGlyph {
+  /// A clock face.
+  Clock,
+
+  /// A calendar page.
+  Calendar,
+
+  /// The silhouette of a person.
+  Person,
+
+  /// A full battery.
+  Battery,
+
+  /// A wireless signal.
+  Wifi,
+
+  /// A speech bubble.
+  Message,
+
+  /// A light bulb.
+  Light,
+}
+
+/// A small glyph drawn inline, before the text of a message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Icon {
+  /// The glyph to draw.
+  pub glyph: IconGlyph,
+
+  /// The width and height of the icon, in pixels. When omitted, icons are as tall as the text
+  /// they are placed next to.
+  pub size: Option<u32>,
+}
+
+impl Icon {
+  /// Creates an icon that will be sized to match its text.
+  pub fn new(glyph: IconGlyph) -> Self {
+    Self { glyph, size: None }
+  }
+
+  /// Draws the icon with its top-left corner at the provided location. Whatever falls outside of
+  /// the image is clipped.
+  pub(super) fn draw_at<C>(&self, size: u32, left: i32, top: i32, color: image::Luma<u8>, image: &mut C)
+  where
+    C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+  {
+    let (width, height) = image.dimensions();
+
+    // Only the rows and columns that land on the image are visited. The math is done in i64 so
+    // that large icons, or ones placed far off the image, cannot overflow.
+    let visible = |start: i32, limit: u32| {
+      let first = (-i64::from(start)).clamp(0, i64::from(size));
+      let last = (i64::from(limit) - i64::from(start)).clamp(0, i64::from(size));
+      first..last
+    };
+
+    for y in visible(top, height) {
+      for x in visible(left, width) {
+        if icons::filled(self.glyph, size, x as u32, y as u32) {
+          image.draw_pixel((i64::from(left) + x) as u32, (i64::from(top) + y) as u32, color);
+        }
+      }
+    }
+  }
+}
+
+/// The smallest unit of time a time-dependent component is concerned with. This is used both when
+/// formatting the component's text, and when determining how often it needs to be re-rendered.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeGranularity {
+  /// Changes every minute.
+  Minutes,
+
+  /// Changes every hour.
+  Hours,
+
+  /// Changes every day.
+  #[default]
+  Days,
+}
+
+impl TimeGranularity {
+  /// The amount of seconds in a single unit of this granularity.
+  pub fn seconds(&self) -> i64 {
+    match self {
+      Self::Minutes => 60,
+      Self::Hours => 60 * 60,
+      Self::Days => 60 * 60 * 24,
+    }
+  }
+}
+
+/// The format clocks are rendered with when none is provided, or when theirs cannot be used.
+const DEFAULT_CLOCK_FORMAT: &str = "%H:%M";
+
+/// Returns an error when a `strftime`-style clock format contains a specifier chrono does not
+/// understand (e.g `%Q`); formatting with these would otherwise fail when the clock is drawn.
+pub fn check_clock_format(format: &str) -> io::Result<()> {
+  let mut items = chrono::format::StrftimeItems::new(format);
+  if items.any(|item| matches!(item, chrono::format::Item::Error)) {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("'{format}' is not a valid clock format"),
+    ));
+  }
+  Ok(())
+}
+
+/// Renders the current date and/or time.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct Clock<S> {
+  /// A `strftime`-style format string. When omitted, the clock will render as `%H:%M`.
+  pub format: Option<S>,
+
+  /// We do not carry a timezone database around with us; clocks are rendered using a fixed offset
+  /// from utc, in minutes.
+  pub utc_offset_minutes: Option<i32>,
+
+  /// How often this clock changes. When omitted, clocks are assumed to change every minute.
+  pub granularity: Option<TimeGranularity>,
+
+  /// The font to use.
+  pub font: Option<fonts::FontSelection>,
+
+  /// Fonts used, in order, for characters the font does not have a glyph for.
+  pub fallback_fonts: Option<Vec<fonts::FontSelection>>,
+
+  /// The scale to apply to our font.
+  pub size: Option<f32>,
+}
+
+impl<S> Clock<S>
+where
+  S: std::convert::AsRef<str>,
+{
+  /// Returns the fixed offset this clock is rendered in.
+  fn offset(&self) -> chrono::FixedOffset {
+    self
+      .utc_offset_minutes
+      .and_then(|minutes| minutes.checked_mul(60))
+      .and_then(chrono::FixedOffset::east_opt)
+      .unwrap_or_else(|| chrono::FixedOffset::east_opt(0).expect("zero offset"))
+  }
+
+  /// Builds the text that would be displayed at a given point in time. Formats that cannot be
+  /// used are replaced with the default one.
+  pub fn text(&self, now: chrono::DateTime<chrono::Utc>) -> String {
+    use std::fmt::Write;
+
+    let local = now.with_timezone(&self.offset());
+    let format = self.format.as_ref().map(|f| f.as_ref()).unwrap_or(DEFAULT_CLOCK_FORMAT);
+    let mut text = String::new();
+    if write!(text, "{}", local.format(format)).is_err() {
+      log::warn!("unable to render clock with format '{format}', using the default");
+      text.clear();
+      let _ = write!(text, "{}", local.format(DEFAULT_CLOCK_FORMAT));
+    }
+    text
+  }
+
+  /// Returns the next point in time where the text of this clock will be different. This is the
+  /// next whole unit of our granularity in the clock's offset (e.g the next local midnight).
+  pub fn next_change(&self, now: chrono::DateTime<chrono::Utc>) -> chrono::DateTime<chrono::Utc> {
+    let unit = self.granularity.unwrap_or(TimeGranularity::Minutes).seconds();
+    let offset = self.offset().local_minus_utc() as i64;
+    let local = now.timestamp() + offset;
+    let next = (local.div_euclid(unit) + 1) * unit - offset;
+    now + chrono::Duration::seconds(next - now.timestamp())
+  }
+
+  /// Creates the message that will actually be drawn.
+  pub(super) fn stylized(&self, now: chrono::DateTime<chrono::Utc>) -> StylizedMessage<String> {
+    StylizedMessage {
+      message: self.text(now),
+      font: self.font.clone(),
+      fallback_fonts: self.fallback_fonts.clone(),
+      size: self.size,
+      ..StylizedMessage::default()
+    }
+  }
+}
+
+/// Renders the amount of time remaining until some point in time, e.g "3 days until launch".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Countdown<S> {
+  /// The text rendered after the amount of time remaining.
+  pub label: S,
+
+  /// The point in time we are counting down to.
+  pub target: chrono::DateTime<chrono::Utc>,
+
+  /// The smallest unit of time displayed. When omitted, only the days remaining are rendered.
+  pub granularity: Option<TimeGranularity>,
+
+  /// The font to use.
+  pub font: Option<fonts::FontSelection>,
+
+  /// Fonts used, in order, for characters the font does not have a glyph for.
+  pub fallback_fonts: Option<Vec<fonts::FontSelection>>,
+
+  /// The scale to apply to our font.
+  pub size: Option<f32>,
+}
+
+impl<S> Countdown<S>
+where
+  S: std::convert::AsRef<str>,
+{
+  /// Builds the text that would be displayed at a given point in time. Amounts are always rounded
+  /// down; once the target has passed, the countdown will remain at zero.
+  pub fn text(&self, now: chrono::DateTime<chrono::Utc>) -> String {
+    let remaining = self.target.timestamp().saturating_sub(now.timestamp()).max(0);
+    let days = remaining / TimeGranularity::Days.seconds();
+    let hours = (remaining % TimeGranularity::Days.seconds()) / TimeGranularity::Hours.seconds();
+    let minutes = (remaining % TimeGranularity::Hours.seconds()) / TimeGranularity::Minutes.seconds();
+    let label = self.label.as_ref();
+
+    match self.granularity.unwrap_or_default() {
+      TimeGranularity::Days if days == 1 => format!("1 day {label}"),
+      TimeGranularity::Days => format!("{days} days {label}"),
+      TimeGranularity::Hours => format!("{days}d {hours}h {label}"),
+      TimeGranularity::Minutes => format!("{days}d {hours}h {minutes}m {label}"),
+    }
+  }
+
+  /// Returns the next point in time where the text of this countdown will be different, if any.
+  /// Countdowns that have already reached their target will never change again.
+  pub fn next_change(&self, now: chrono::DateTime<chrono::Utc>) -> Option<chrono::DateTime<chrono::Utc>> {
+    let unit = self.granularity.unwrap_or_default().seconds();
+    let remaining = self.target.timestamp().saturating_sub(now.timestamp());
+
+    if remaining <= 0 {
+      return None;
+    }
+
+    Some(now + chrono::Duration::seconds(remaining % unit + 1))
+  }
+
+  /// Creates the message that will actually be drawn.
+  pub(super) fn stylized(&self, now: chrono::DateTime<chrono::Utc>) -> StylizedMessage<String> {
+    StylizedMessage {
+      message: self.text(now),
+      font: self.font.clone(),
+      fallback_fonts: self.fallback_fonts.clone(),
+      size: self.size,
+      ..StylizedMessage::default()
+    }
+  }
+}
+
+/// The height of a chart, not including its label, when one is not provided.
+const DEFAULT_CHART_HEIGHT: u32 = 60;
+
+/// The tallest a chart can be drawn; this is the height of the tallest image we render.
+pub const MAX_CHART_HEIGHT: u32 = super::constants::MAX_HEIGHT;
+
+/// The size of the label drawn above a chart.
+const CHART_LABEL_SIZE: f32 = 20.0;
+
+/// The size of the labels on the axis of line and bar charts.
+const CHART_AXIS_LABEL_SIZE: f32 = 14.0;
+
+/// The space between the axis labels of a chart and the axis itself.
+const CHART_AXIS_SPACING: i32 = 4;
+
+/// The kinds of charts that can be drawn from a series of values.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChartKind {
+  /// A line through every value, with an axis labelled by its range.
+  Line,
+
+  /// A line through every value without an axis, ending in a dot at the latest value.
+  Sparkline,
+
+  /// A bar for every value, growing from zero, with an axis labelled by its range.
+  Bar,
+
+  /// A single bar filled to the latest value. Unless provided, the range is zero to one hundred.
+  Progress,
+}
+
+/// Draws a series of numbers; values that are not finite are skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Chart<S> {
+  /// How the values are drawn.
+  pub kind: ChartKind,
+
+  /// The values, oldest first.
+  pub values: Vec<f64>,
+
+  /// Text drawn above the chart, if any.
+  pub label: Option<S>,
+
+  /// The value at the bottom of the chart. When omitted, the chart is scaled to the values.
+  pub min: Option<f64>,
+
+  /// The value at the top of the chart. When omitted, the chart is scaled to the values.
+  pub max: Option<f64>,
+
+  /// The height of the chart in pixels, not including the label.
+  pub height: Option<u32>,
+
+  /// The shade of bars. Bars are always outlined in black, so lighter shades remain visible on
+  /// displays without gray.
+  pub fill: Option<GrayLevel>,
+
+  /// The amount of margin, if any.
+  pub margin: Option<OptionalBoundingBox>,
+}
+
+/// Draws a black, one pixel outline of a rectangle. Whatever falls outside of the image is clipped.
+fn draw_outline<C>(image: &mut C, origin: (i32, i32), size: (i32, i32))
+where
+  C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+{
+  if size.0 <= 0 || size.1 <= 0 {
+    return;
+  }
+
+  let (left, top) = (origin.0 as f32, origin.1 as f32);
+  let (right, bottom) = ((origin.0 + size.0 - 1) as f32, (origin.1 + size.1 - 1) as f32);
+  let color = GrayLevel::Black.luma();
+  imageproc::drawing::draw_line_segment_mut(image, (left, top), (right, top), color);
+  imageproc::drawing::draw_line_segment_mut(image, (left, bottom), (right, bottom), color);
+  imageproc::drawing::draw_line_segment_mut(image, (left, top), (left, bottom), color);
+  imageproc::drawing::draw_line_segment_mut(image, (right, top), (right, bottom), color);
+}
+
+/// Formats a value for the axis of a chart.
+fn axis_label(value: f64) -> String {
+  if value.fract() == 0.0 {
+    format!("{value:.0}")
+  } else {
+    format!("{value:.1}")
+  }
+}
+
+impl<S> Chart<S>
+where
+  S: std::convert::AsRef<str>,
+{
+  /// The values that can be drawn.
+  fn finite_values(&self) -> Vec<f64> {
+    self.values.iter().copied().filter(|value| value.is_finite()).collect()
+  }
+
+  /// Returns the values at the bottom and top of the chart. Unless provided, these are the lowest
+  /// and highest values; bar charts always include zero. The range is never empty.
+  pub fn range(&self) -> (f64, f64) {
+    let values = self.finite_values();
+    let (lowest, highest) = values
+      .iter()
+      .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), value| {
+        (low.min(*value), high.max(*value))
+      });
+
+    let (lowest, highest) = match self.kind {
+      ChartKind::Progress => (0.0, 100.0),
+      ChartKind::Bar => (lowest.min(0.0), highest.max(0.0)),
+      ChartKind::Line | ChartKind::Sparkline if values.is_empty() => (0.0, 1.0),
+      ChartKind::Line | ChartKind::Sparkline => (lowest, highest),
+    };
+
+    let low = self.min.filter(|value| value.is_finite()).unwrap_or(lowest);
+    let high = self.max.filter(|value| value.is_finite()).unwrap_or(highest);
+
+    // Flat series are drawn through the middle of the chart.
+    match high > low {
+      true => (low, high),
+      false => (low - 1.0, low + 1.0),
+    }
+  }
+
+  /// The amount of margin on the left, right, top and bottom.
+  fn margins(&self) -> (i32, i32, i32, i32) {
+    box_sides(self.margin.as_ref())
+  }
+
+  /// The height the chart itself is drawn with, not including the label.
+  fn chart_height(&self) -> i32 {
+    self.height.unwrap_or(DEFAULT_CHART_HEIGHT).clamp(1, MAX_CHART_HEIGHT) as i32
+  }
+
+  /// The width the chart itself is drawn with; charts take up all of the width available to them.
+  fn width(&self, bounds: &StylizedMessageBounding) -> i32 {
+    let (ml, mr, _, _) = self.margins();
+    let available = match bounds.constraints {
+      Some(StylizedMessageBoundingConstraints::MaxWidth(max_width)) => max_width,
+      None => super::constants::DEVICE_DIMENSIONS.0 as i32 - bounds.left,
+    };
+    available.saturating_sub(ml).saturating_sub(mr).max(0)
+  }
+
+  /// The message drawn above the chart, if any.
+  fn label_message(&self) -> Option<StylizedMessage<&str>> {
+    self.label.as_ref().map(|label| StylizedMessage {
+      message: label.as_ref(),
+      size: Some(CHART_LABEL_SIZE),
+      ..StylizedMessage::default()
+    })
+  }
+
+  /// Returns the width and height of everything drawn, including the label and margins.
+  pub(crate) fn fit(&self, bounds: &StylizedMessageBounding) -> io::Result<(i32, i32)> {
+    let (ml, mr, mt, mb) = self.margins();
+    let width = self.width(bounds);
+    let label_height = match self.label_message() {
+      Some(label) => {
+        let label_bounds = StylizedMessageBounding {
+          left: bounds.left + ml,
+          top: bounds.top + mt,
+          constraints: Some(StylizedMessageBoundingConstraints::MaxWidth(width)),
+        };
+        label.fit(&label_bounds)?.dimensions.1
+      }
+      None => 0,
+    };
+    let height = self.chart_height();
+
+    Ok((box_sum(&[width, ml, mr]), box_sum(&[label_height, height, mt, mb])))
+  }
+
+  /// Draws the chart within this bounding box, returning the space taken up.
+  pub(super) fn draw_within<C>(&self, bounds: &StylizedMessageBounding, image: &mut C) -> io::Result<(i32, i32)>
+  where
+    C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+  {
+    let dimensions = self.fit(bounds)?;
+    let (ml, _, mt, _) = self.margins();
+    let left = bounds.left + ml;
+    let mut top = bounds.top + mt;
+    let width = self.width(bounds);
+    let height = self.chart_height();
+
+    if let Some(label) = self.label_message() {
+      let label_bounds = StylizedMessageBounding {
+        left,
+        top,
+        constraints: Some(StylizedMessageBoundingConstraints::MaxWidth(width)),
+      };
+      top += label.draw_within(&label_bounds, image)?.1;
+    }
+
+    let range = self.range();
+    let values = self.finite_values();
+
+    match self.kind {
+      ChartKind::Progress => {
+        let value = values.last().copied().unwrap_or(range.0);
+        let ratio = ((value - range.0) / (range.1 - range.0)).clamp(0.0, 1.0);
+        let filled = ((width - 4).max(0) as f64 * ratio).round() as i32;
+        let fill = self.fill.unwrap_or(GrayLevel::Black).luma();
+        draw_outline(image, (left, top), (width, height));
+        fill_rounded_rect(image, (left + 2, top + 2), (filled, height - 4), 0, fill);
+      }
+      ChartKind::Sparkline => {
+        if let Some((x, y)) = draw_series(image, &values, range, (left + 1, top + 1), (width - 2, height - 2)) {
+          fill_rounded_rect(image, (x - 1, y - 1), (3, 3), 0, GrayLevel::Black.luma());
+        }
+      }
+      ChartKind::Line | ChartKind::Bar => {
+        let gutter = draw_axis(image, range, (left, top), (width, height))?;
+        let plot_origin = (left + gutter + 1, top);
+        let plot_size = (width - gutter - 1, height - 1);
+
+        if self.kind == ChartKind::Bar {
+          let fill = self.fill.unwrap_or(GrayLevel::Black);
+          draw_bars(image, &values, range, plot_origin, plot_size, fill);
+        } else {
+          draw_series(image, &values, range, plot_origin, plot_size);
+        }
+      }
+    }
+
+    Ok(dimensions)
+  }
+}
+
+/// Returns the row a value is drawn at within an area of the provided height.
+fn project(value: f64, range: (f64, f64), top: i32, height: i32) -> i32 {
+  let ratio = ((value - range.0) / (range.1 - range.0)).clamp(0.0, 1.0);
+  top + ((1.0 - ratio) * (height - 1).max(0) as f64).round() as i32
+}
+
+/// Draws the labels for the top and bottom of the range on the left, followed by the vertical and
+/// horizontal axis lines. Returns the width taken up by the labels.
+fn draw_axis<C>(image: &mut C, range: (f64, f64), origin: (i32, i32), size: (i32, i32)) -> io::Result<i32>
+where
+  C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+{
+  let labels = [axis_label(range.1), axis_label(range.0)].map(|text| StylizedMessage {
+    message: text,
+    size: Some(CHART_AXIS_LABEL_SIZE),
+    ..StylizedMessage::default()
+  });
+  let unbounded = StylizedMessageBounding {
+    left: 0,
+    top: 0,
+    constraints: None,
+  };
+  let [high, low] = labels;
+  let high_size = high.fit(&unbounded)?.dimensions;
+  let low_size = low.fit(&unbounded)?.dimensions;
+  let gutter = high_size.0.max(low_size.0) + CHART_AXIS_SPACING;
+
+  let at = |top| StylizedMessageBounding {
+    left: origin.0,
+    top,
+    constraints: None,
+  };
+  high.draw_within(&at(origin.1), image)?;
+  low.draw_within(&at(origin.1 + size.1 - low_size.1), image)?;
+
+  let color = GrayLevel::Black.luma();
+  let axis_left = (origin.0 + gutter) as f32;
+  let (top, bottom) = (origin.1 as f32, (origin.1 + size.1 - 1) as f32);
+  let right = (origin.0 + size.0 - 1) as f32;
+  imageproc::drawing::draw_line_segment_mut(image, (axis_left, top), (axis_left, bottom), color);
+  imageproc::drawing::draw_line_segment_mut(image, (axis_left, bottom), (right, bottom), color);
+
+  Ok(gutter)
+}
+
+/// Draws a two pixel thick line through the values, spread evenly across the area. Returns the
+/// location of the last value, if any were drawn.
+fn draw_series<C>(
+  image: &mut C,
+  values: &[f64],
+  range: (f64, f64),
+  origin: (i32, i32),
+  size: (i32, i32),
+) -> Option<(i32, i32)>
+where
+  C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+{
+  if size.0 <= 0 || size.1 <= 0 {
+    return None;
+  }
+
+  let points = values
+    .iter()
+    .enumerate()
+    .map(|(index, value)| {
+      let x = match values.len() {
+        1 => origin.0 + (size.0 - 1) / 2,
+        count => origin.0 + (index as i32 * (size.0 - 1)) / (count as i32 - 1),
+      };
+      (x, project(*value, range, origin.1, size.1))
+    })
+    .collect::<Vec<(i32, i32)>>();
+
+  let color = GrayLevel::Black.luma();
+  for pair in points.windows(2) {
+    let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+    for offset in [0, 1] {
+      let start = (x1 as f32, (y1 + offset) as f32);
+      let end = (x2 as f32, (y2 + offset) as f32);
+      imageproc::drawing::draw_line_segment_mut(image, start, end, color);
+    }
+  }
+
+  points.last().copied()
+}
+
+/// Draws a bar for every value, growing from zero (or whichever end of the range is closest to it).
+fn draw_bars<C>(image: &mut C, values: &[f64], range: (f64, f64), origin: (i32, i32), size: (i32, i32), fill: GrayLevel)
+where
+  C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+{
+  if values.is_empty() || size.0 <= 0 || size.1 <= 0 {
+    return;
+  }
+
+  let slot = size.0 as f64 / values.len() as f64;
+  let gap = (slot / 4.0).floor() as i32;
+  let bar_width = ((slot.floor() as i32) - gap).max(1);
+  let baseline = project(0.0, range, origin.1, size.1);
+
+  for (index, value) in values.iter().enumerate() {
+    let x = origin.0 + (index as f64 * slot).round() as i32 + gap / 2;
+    let y = project(*value, range, origin.1, size.1);
+    let (bar_top, bar_height) = (y.min(baseline), (y - baseline).abs() + 1);
+
+    fill_rounded_rect(image, (x, bar_top), (bar_width, bar_height), 0, fill.luma());
+    draw_outline(image, (x, bar_top), (bar_width, bar_height));
   }
 }
 
@@ -195,3 +1000,578 @@ where
     )
   }
 }
+
+/// Controls how an image is placed within the dimensions it is being rendered to.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFit {
+  /// Scales the image to fill the whole area, cropping whatever overflows.
+  Cover,
+
+  /// Scales the image to fit entirely within the area, leaving blank space around it.
+  #[default]
+  Contain,
+
+  /// Does not scale the image at all, placing it in the center and cropping whatever overflows.
+  Center,
+}
+
+/// An image that has been stored somewhere on disc.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Image<S> {
+  /// The location on disc of the raw image data.
+  pub location: S,
+
+  /// How the image should be placed.
+  pub fit: Option<ImageFit>,
+}
+
+impl<S> Image<S>
+where
+  S: AsRef<str>,
+{
+  /// Attempts to load and decode the image, producing a grayscale image of exactly the provided
+  /// dimensions.
+  pub(super) fn grayscale(&self, dimensions: (u32, u32)) -> io::Result<image::GrayImage> {
+    let location = self.location.as_ref();
+    log::info!("attempting to fit and take grayscale of '{location}'");
+
+    let source = image::io::Reader::open(location)
+      .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?
+      .decode()
+      .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+
+    Ok(self.fit_within(&source, dimensions))
+  }
+
+  /// Places the source image onto a white canvas of the provided dimensions based on our fit.
+  fn fit_within(&self, source: &image::DynamicImage, dimensions: (u32, u32)) -> image::GrayImage {
+    let (width, height) = dimensions;
+    let mut canvas = image::GrayImage::from_pixel(width, height, image::Luma([255]));
+    let filter = image::imageops::FilterType::CatmullRom;
+
+    let placed = match self.fit.unwrap_or_default() {
+      ImageFit::Cover => source.resize_to_fill(width, height, filter),
+      ImageFit::Contain => source.resize(width, height, filter),
+      ImageFit::Center => source.clone(),
+    }
+    .to_luma8();
+
+    let x = (i64::from(width) - i64::from(placed.width())) / 2;
+    let y = (i64::from(height) - i64::from(placed.height())) / 2;
+    image::imageops::overlay(&mut canvas, &placed, x, y);
+    canvas
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    Chart, ChartKind, Clock, Countdown, GrayLevel, Icon, IconGlyph, Image, ImageFit, OptionalBoundingBox,
+    StylizedMessage, StylizedMessageBounding, TimeGranularity,
+  };
+
+  fn sides(left: i32, right: i32, top: i32, bottom: i32) -> Option<OptionalBoundingBox> {
+    Some(OptionalBoundingBox {
+      left: Some(left),
+      right: Some(right),
+      top: Some(top),
+      bottom: Some(bottom),
+    })
+  }
+
+  /// An empty message with 10 pixels of padding on every side, so the box is 20x20 plus borders.
+  fn padded() -> StylizedMessage<&'static str> {
+    StylizedMessage {
+      message: "",
+      padding: sides(10, 10, 10, 10),
+      ..StylizedMessage::default()
+    }
+  }
+
+  /// Draws the message onto a white image, with the box starting at (5, 5).
+  fn boxed(message: StylizedMessage<&'static str>) -> image::GrayImage {
+    let mut image = image::GrayImage::from_pixel(60, 50, image::Luma([255]));
+    let bounds = StylizedMessageBounding {
+      left: 5,
+      top: 5,
+      constraints: None,
+    };
+    message.draw_within(&bounds, &mut image).unwrap();
+    image
+  }
+
+  fn luma(image: &image::GrayImage, x: u32, y: u32) -> u8 {
+    image.get_pixel(x, y).0[0]
+  }
+
+  #[test]
+  fn test_border_left_only() {
+    let image = boxed(StylizedMessage {
+      border: Some(OptionalBoundingBox {
+        left: Some(2),
+        ..Default::default()
+      }),
+      ..padded()
+    });
+    assert_eq!(luma(&image, 5, 15), 0);
+    assert_eq!(luma(&image, 6, 15), 0);
+    assert_eq!(luma(&image, 7, 15), 255);
+    assert_eq!(luma(&image, 26, 15), 255);
+    assert_eq!(luma(&image, 15, 5), 255);
+    assert_eq!(luma(&image, 15, 24), 255);
+  }
+
+  #[test]
+  fn test_border_all_sides() {
+    // The box spans 5..28 horizontally and 5..32 vertically.
+    let image = boxed(StylizedMessage {
+      border: sides(1, 2, 3, 4),
+      ..padded()
+    });
+    assert_eq!(luma(&image, 5, 15), 0);
+    assert_eq!(luma(&image, 6, 15), 255);
+    assert_eq!(luma(&image, 25, 15), 255);
+    assert_eq!(luma(&image, 26, 15), 0);
+    assert_eq!(luma(&image, 27, 15), 0);
+    assert_eq!(luma(&image, 28, 15), 255);
+    assert_eq!(luma(&image, 15, 7), 0);
+    assert_eq!(luma(&image, 15, 8), 255);
+    assert_eq!(luma(&image, 15, 27), 255);
+    assert_eq!(luma(&image, 15, 28), 0);
+    assert_eq!(luma(&image, 15, 31), 0);
+    assert_eq!(luma(&image, 15, 32), 255);
+    assert_eq!(luma(&image, 4, 15), 255);
+  }
+
+  #[test]
+  fn test_border_dimensions() {
+    let bounds = StylizedMessageBounding {
+      left: 0,
+      top: 0,
+      constraints: None,
+    };
+    let message = StylizedMessage {
+      border: sides(1, 2, 3, 4),
+      ..padded()
+    };
+    assert_eq!(message.fit(&bounds).unwrap().dimensions, (23, 27));
+  }
+
+  #[test]
+  fn test_border_radius() {
+    let image = boxed(StylizedMessage {
+      border: sides(2, 2, 2, 2),
+      border_radius: Some(8),
+      ..padded()
+    });
+    // The corners are cut off, the middle of each edge is not.
+    assert_eq!(luma(&image, 5, 5), 255);
+    assert_eq!(luma(&image, 28, 28), 255);
+    assert_eq!(luma(&image, 5, 17), 0);
+    assert_eq!(luma(&image, 17, 5), 0);
+    assert_eq!(luma(&image, 17, 17), 255);
+
+    let square = boxed(StylizedMessage {
+      border: sides(2, 2, 2, 2),
+      ..padded()
+    });
+    assert_eq!(luma(&square, 5, 5), 0);
+  }
+
+  #[test]
+  fn test_border_radius_fill() {
+    let image = boxed(StylizedMessage {
+      fill: Some(GrayLevel::LightGray),
+      border_radius: Some(8),
+      ..padded()
+    });
+    // Without a border, the fill itself has the rounded corners.
+    assert_eq!(luma(&image, 5, 5), 255);
+    assert_eq!(luma(&image, 24, 24), 255);
+    assert_eq!(luma(&image, 5, 15), 170);
+    assert_eq!(luma(&image, 15, 15), 170);
+  }
+
+  #[test]
+  fn test_border_radius_inverted() {
+    let image = boxed(StylizedMessage {
+      inverted: Some(true),
+      border_radius: Some(8),
+      ..padded()
+    });
+    assert_eq!(luma(&image, 5, 5), 255);
+    assert_eq!(luma(&image, 24, 5), 255);
+    assert_eq!(luma(&image, 15, 5), 0);
+    assert_eq!(luma(&image, 15, 15), 0);
+  }
+
+  #[test]
+  fn test_border_radius_sides() {
+    // The box is 25x28, starting at (5, 5); the inside is 20x20, starting at (6, 7).
+    let image = boxed(StylizedMessage {
+      border: sides(1, 4, 2, 6),
+      border_radius: Some(8),
+      ..padded()
+    });
+    assert_eq!(luma(&image, 5, 5), 255);
+    assert_eq!(luma(&image, 5, 19), 0);
+    assert_eq!(luma(&image, 28, 19), 0);
+    assert_eq!(luma(&image, 25, 19), 255);
+    assert_eq!(luma(&image, 17, 6), 0);
+    assert_eq!(luma(&image, 17, 7), 255);
+    assert_eq!(luma(&image, 17, 26), 255);
+    assert_eq!(luma(&image, 17, 30), 0);
+    assert_eq!(luma(&image, 29, 32), 255);
+  }
+
+  #[test]
+  fn test_oversized_box() {
+    let huge = Some(OptionalBoundingBox {
+      left: Some(i32::MAX),
+      right: Some(i32::MAX),
+      top: Some(i32::MIN),
+      bottom: Some(i32::MAX),
+    });
+    let message = StylizedMessage {
+      border: huge.clone(),
+      padding: huge.clone(),
+      margin: huge,
+      border_radius: Some(u32::MAX),
+      ..padded()
+    };
+    let mut image = image::GrayImage::from_pixel(60, 50, image::Luma([255]));
+    let bounds = StylizedMessageBounding {
+      left: 5,
+      top: 5,
+      constraints: None,
+    };
+    assert!(message.draw_within(&bounds, &mut image).is_ok());
+
+    super::fill_rounded_rect(
+      &mut image,
+      (i32::MIN, i32::MIN),
+      (i32::MAX, i32::MAX),
+      i32::MAX,
+      image::Luma([0]),
+    );
+    super::fill_rounded_rect(&mut image, (-10, -10), (i32::MAX, i32::MAX), 0, image::Luma([0]));
+    assert_eq!(luma(&image, 59, 49), 0);
+  }
+
+  #[test]
+  fn test_inverted() {
+    let image = boxed(StylizedMessage {
+      inverted: Some(true),
+      icon: Some(Icon {
+        glyph: IconGlyph::Calendar,
+        size: Some(16),
+      }),
+      ..padded()
+    });
+    // The icon starts at (15, 15); its first row is blank, its third is solid.
+    assert_eq!(luma(&image, 5, 5), 0);
+    assert_eq!(luma(&image, 15, 15), 0);
+    assert_eq!(luma(&image, 15, 17), 255);
+    assert_eq!(luma(&image, 4, 4), 255);
+  }
+
+  #[test]
+  fn test_fill() {
+    let image = boxed(StylizedMessage {
+      fill: Some(GrayLevel::LightGray),
+      ..padded()
+    });
+    assert_eq!(luma(&image, 5, 5), 170);
+    assert_eq!(luma(&image, 24, 24), 170);
+    assert_eq!(luma(&image, 25, 25), 255);
+
+    let image = boxed(StylizedMessage {
+      fill: Some(GrayLevel::DarkGray),
+      inverted: Some(true),
+      border: sides(1, 1, 1, 1),
+      ..padded()
+    });
+    assert_eq!(luma(&image, 5, 5), 0);
+    assert_eq!(luma(&image, 6, 6), 85);
+  }
+
+  fn wide_black_image() -> image::DynamicImage {
+    image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(40, 20, image::Luma([0])))
+  }
+
+  fn fitted(fit: ImageFit, dimensions: (u32, u32)) -> image::GrayImage {
+    let image = Image {
+      location: "",
+      fit: Some(fit),
+    };
+    image.fit_within(&wide_black_image(), dimensions)
+  }
+
+  #[test]
+  fn test_image_fit_cover() {
+    let result = fitted(ImageFit::Cover, (20, 20));
+    assert_eq!(result.dimensions(), (20, 20));
+    assert!(result.pixels().all(|pixel| pixel.0[0] == 0));
+  }
+
+  #[test]
+  fn test_image_fit_contain() {
+    let result = fitted(ImageFit::Contain, (20, 20));
+    assert_eq!(result.dimensions(), (20, 20));
+    assert_eq!(result.get_pixel(10, 0).0[0], 255);
+    assert_eq!(result.get_pixel(10, 10).0[0], 0);
+    assert_eq!(result.get_pixel(10, 19).0[0], 255);
+  }
+
+  #[test]
+  fn test_image_fit_center() {
+    let result = fitted(ImageFit::Center, (60, 10));
+    assert_eq!(result.dimensions(), (60, 10));
+    assert_eq!(result.get_pixel(5, 5).0[0], 255);
+    assert_eq!(result.get_pixel(30, 5).0[0], 0);
+    assert_eq!(result.get_pixel(55, 5).0[0], 255);
+  }
+
+  #[test]
+  fn test_icon_fit() {
+    let bounds = StylizedMessageBounding {
+      left: 0,
+      top: 0,
+      constraints: None,
+    };
+    let mut message = StylizedMessage {
+      message: "hello",
+      size: Some(24.0),
+      ..StylizedMessage::default()
+    };
+    let plain = message.fit(&bounds).unwrap();
+
+    message.icon = Some(Icon {
+      glyph: IconGlyph::Clock,
+      size: Some(40),
+    });
+    let with_icon = message.fit(&bounds).unwrap();
+    assert_eq!(with_icon.dimensions.0, plain.dimensions.0 + 40 + super::ICON_SPACING);
+    assert_eq!(with_icon.dimensions.1, 40);
+
+    // Oversized icons are clamped rather than overflowing.
+    message.icon = Some(Icon {
+      glyph: IconGlyph::Clock,
+      size: Some(u32::MAX),
+    });
+    let clamped = message.fit(&bounds).unwrap();
+    assert_eq!(clamped.dimensions.1, super::MAX_ICON_SIZE as i32);
+  }
+
+  #[test]
+  fn test_icon_clipping() {
+    let icon = Icon::new(IconGlyph::Calendar);
+
+    // Icons that are huge, or placed far off the image, are clipped without overflowing.
+    let mut image = image::GrayImage::new(8, 8);
+    icon.draw_at(u32::MAX, i32::MIN, i32::MIN, image::Luma([0]), &mut image);
+    icon.draw_at(u32::MAX, i32::MAX, i32::MAX, image::Luma([0]), &mut image);
+
+    // Only the middle of a 32px calendar drawn at (-8, -8) lands on the image; each sprite pixel
+    // covers two image pixels.
+    let mut image = image::GrayImage::from_pixel(8, 8, image::Luma([255]));
+    icon.draw_at(32, -8, -8, image::Luma([0]), &mut image);
+    assert_eq!(image.get_pixel(0, 0).0, [0]);
+    assert_eq!(image.get_pixel(0, 2).0, [255]);
+    assert_eq!(image.get_pixel(0, 4).0, [255]);
+    assert_eq!(image.get_pixel(4, 4).0, [0]);
+  }
+
+  fn chart(kind: ChartKind, values: &[f64]) -> Chart<&'static str> {
+    Chart {
+      kind,
+      values: values.to_vec(),
+      label: None,
+      min: None,
+      max: None,
+      height: Some(20),
+      fill: None,
+      margin: None,
+    }
+  }
+
+  #[test]
+  fn test_chart_range() {
+    assert_eq!(chart(ChartKind::Line, &[2.0, 4.0, 3.0]).range(), (2.0, 4.0));
+    assert_eq!(chart(ChartKind::Bar, &[3.0, 5.0]).range(), (0.0, 5.0));
+    assert_eq!(chart(ChartKind::Bar, &[-3.0, -1.0]).range(), (-3.0, 0.0));
+    assert_eq!(chart(ChartKind::Sparkline, &[5.0, 5.0, f64::NAN]).range(), (4.0, 6.0));
+    assert_eq!(chart(ChartKind::Line, &[]).range(), (0.0, 1.0));
+    assert_eq!(chart(ChartKind::Progress, &[40.0]).range(), (0.0, 100.0));
+
+    let capped = Chart {
+      max: Some(10.0),
+      ..chart(ChartKind::Progress, &[4.0])
+    };
+    assert_eq!(capped.range(), (0.0, 10.0));
+  }
+
+  /// Draws the chart onto a white image, 40 pixels wide starting at (5, 5).
+  fn charted(chart: Chart<&'static str>) -> image::GrayImage {
+    let mut image = image::GrayImage::from_pixel(50, 30, image::Luma([255]));
+    let bounds = StylizedMessageBounding {
+      left: 5,
+      top: 5,
+      constraints: Some(super::StylizedMessageBoundingConstraints::MaxWidth(40)),
+    };
+    assert_eq!(chart.draw_within(&bounds, &mut image).unwrap(), (40, 20));
+    image
+  }
+
+  #[test]
+  fn test_progress_pixels() {
+    // The inside of the bar is 36 pixels wide, starting at 7.
+    let image = charted(chart(ChartKind::Progress, &[10.0, 50.0]));
+    assert_eq!(luma(&image, 5, 5), 0);
+    assert_eq!(luma(&image, 44, 24), 0);
+    assert_eq!(luma(&image, 6, 15), 255);
+    assert_eq!(luma(&image, 7, 15), 0);
+    assert_eq!(luma(&image, 24, 15), 0);
+    assert_eq!(luma(&image, 25, 15), 255);
+
+    let image = charted(Chart {
+      fill: Some(GrayLevel::LightGray),
+      ..chart(ChartKind::Progress, &[100.0])
+    });
+    assert_eq!(luma(&image, 42, 15), 170);
+  }
+
+  #[test]
+  fn test_sparkline_pixels() {
+    // Rising from the bottom left to the top right of the 38x18 area inside of (6, 6).
+    let image = charted(chart(ChartKind::Sparkline, &[0.0, 1.0]));
+    assert_eq!(luma(&image, 6, 23), 0);
+    assert_eq!(luma(&image, 43, 6), 0);
+    assert_eq!(luma(&image, 43, 23), 255);
+    assert_eq!(luma(&image, 6, 6), 255);
+  }
+
+  #[test]
+  fn test_bar_pixels() {
+    let image = charted(chart(ChartKind::Bar, &[1.0, 0.0]));
+    // The axis runs along the bottom, and nothing is drawn above the right half.
+    assert_eq!(luma(&image, 44, 24), 0);
+    assert_eq!(luma(&image, 40, 10), 255);
+    assert!((5..45).any(|x| luma(&image, x, 6) == 0));
+  }
+
+  #[test]
+  fn test_oversized_chart() {
+    let bounds = StylizedMessageBounding {
+      left: 5,
+      top: 5,
+      constraints: Some(super::StylizedMessageBoundingConstraints::MaxWidth(40)),
+    };
+
+    for kind in [ChartKind::Progress, ChartKind::Sparkline, ChartKind::Bar] {
+      let tall = Chart {
+        height: Some(u32::MAX),
+        label: Some("tall"),
+        margin: Some(OptionalBoundingBox {
+          left: Some(i32::MIN),
+          right: Some(i32::MAX),
+          top: Some(i32::MAX),
+          bottom: Some(i32::MAX),
+        }),
+        ..chart(kind, &[1.0, 2.0])
+      };
+      let mut image = image::GrayImage::from_pixel(50, 30, image::Luma([255]));
+      let (_, height) = tall.draw_within(&bounds, &mut image).unwrap();
+      assert!(height >= super::MAX_CHART_HEIGHT as i32);
+    }
+
+    let clamped = Chart {
+      height: Some(u32::MAX),
+      ..chart(ChartKind::Bar, &[1.0])
+    };
+    assert_eq!(clamped.fit(&bounds).unwrap().1, super::MAX_CHART_HEIGHT as i32);
+  }
+
+  fn at(input: &str) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::parse_from_rfc3339(input)
+      .expect("invalid test date")
+      .with_timezone(&chrono::Utc)
+  }
+
+  fn countdown(granularity: Option<TimeGranularity>) -> Countdown<&'static str> {
+    Countdown {
+      label: "until launch",
+      target: at("2023-06-10T12:00:00Z"),
+      granularity,
+      font: None,
+      fallback_fonts: None,
+      size: None,
+    }
+  }
+
+  #[test]
+  fn test_countdown_days() {
+    let countdown = countdown(None);
+    assert_eq!(countdown.text(at("2023-06-07T11:00:00Z")), "3 days until launch");
+    assert_eq!(countdown.text(at("2023-06-09T06:00:00Z")), "1 day until launch");
+    assert_eq!(countdown.text(at("2023-06-11T00:00:00Z")), "0 days until launch");
+  }
+
+  #[test]
+  fn test_countdown_minutes() {
+    let countdown = countdown(Some(TimeGranularity::Minutes));
+    assert_eq!(countdown.text(at("2023-06-07T10:45:30Z")), "3d 1h 14m until launch");
+  }
+
+  #[test]
+  fn test_countdown_next_change() {
+    let countdown = countdown(None);
+    assert_eq!(
+      countdown.next_change(at("2023-06-07T11:00:00Z")),
+      Some(at("2023-06-07T12:00:01Z"))
+    );
+    assert_eq!(countdown.next_change(at("2023-06-10T12:00:00Z")), None);
+  }
+
+  #[test]
+  fn test_clock_next_change() {
+    let clock = Clock::<&str> {
+      utc_offset_minutes: Some(-300),
+      granularity: Some(TimeGranularity::Days),
+      ..Default::default()
+    };
+    assert_eq!(
+      clock.next_change(at("2023-06-07T11:00:00Z")),
+      at("2023-06-08T05:00:00Z")
+    );
+
+    let clock = Clock::<&str>::default();
+    assert_eq!(
+      clock.next_change(at("2023-06-07T11:00:30Z")),
+      at("2023-06-07T11:01:00Z")
+    );
+    assert_eq!(clock.text(at("2023-06-07T11:00:30Z")), "11:00");
+
+    // Offsets that cannot be represented are rendered in utc.
+    let clock = Clock::<&str> {
+      utc_offset_minutes: Some(i32::MAX),
+      ..Default::default()
+    };
+    assert_eq!(clock.text(at("2023-06-07T11:00:30Z")), "11:00");
+  }
+
+  #[test]
+  fn test_clock_bad_format() {
+    assert!(super::check_clock_format("%Y-%m-%d %H:%M").is_ok());
+    assert!(super::check_clock_format("%Q").is_err());
+    assert!(super::check_clock_format("%H:%M %").is_err());
+
+    let clock = Clock::<&str> {
+      format: Some("%Q"),
+      ..Default::default()
+    };
+    assert_eq!(clock.text(at("2023-06-07T11:00:30Z")), "11:00");
+  }
+}
@@ -2,3 +2,5 @@
 pub(super) const MAX_WIDTH: u32 = 1200;
 /// The max height of anything that we can render.
 pub(super) const MAX_HEIGHT: u32 = 1200;
+/// The dimensions of the display on our devices.
+pub const DEVICE_DIMENSIONS: (u32, u32) = (400, 300);
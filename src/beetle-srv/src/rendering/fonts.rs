@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io;
 
 /// Teko @ `<https://fonts.google.com/specimen/Teko?preview.text=2:00PM&preview.text_type=custom>`
@@ -13,7 +14,33 @@ const DEJAVU_SANS: &[u8] = include_bytes!("../../DejaVuSans.ttf");
 /// Roboto Sans @ `<https://fonts.google.com/specimen/Roboto>`
 const ROBOTO_SANS: &[u8] = include_bytes!("../../Roboto-Regular.ttf");
 
-#[derive(Deserialize, Serialize, Default, Debug, Clone)]
+/// The longest name a custom font can be given.
+pub const MAX_CUSTOM_NAME_LEN: usize = 32;
+
+/// Custom fonts are stored as a single mongo document, which cannot be larger than 16mb.
+pub const MAX_CUSTOM_FONT_BYTES: usize = 15 * 1024 * 1024;
+
+/// A parsed font, along with the digest of the bytes it was parsed from so changes to custom fonts
+/// can be noticed.
+#[derive(Clone)]
+struct CachedFont {
+  /// The parsed font.
+  font: rusttype::Font<'static>,
+
+  /// The hex encoded sha256 digest of the font data; empty for embedded fonts.
+  digest: String,
+}
+
+/// Every font parsed so far, keyed by the cache key of its selection. Parsing is not free, and the
+/// same handful of fonts are used for every render.
+static CACHE: std::sync::OnceLock<std::sync::RwLock<HashMap<String, CachedFont>>> = std::sync::OnceLock::new();
+
+/// Returns the font cache.
+fn cache() -> &'static std::sync::RwLock<HashMap<String, CachedFont>> {
+  CACHE.get_or_init(Default::default)
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
 /// Enumerates the fonts available to us.
 pub enum FontSelection {
@@ -29,28 +56,367 @@ pub enum FontSelection {
 
   /// See constant.
   DejaVu,
+
+  /// A font uploaded with `beetle-cli upload-font`, by name.
+  Custom(String),
+}
+
+/// Returns true if the name can be given to a custom font.
+pub fn valid_custom_name(name: &str) -> bool {
+  !name.is_empty()
+    && name.len() <= MAX_CUSTOM_NAME_LEN
+    && name.chars().all(|character| {
+      character.is_ascii_lowercase() || character.is_ascii_digit() || character == '-' || character == '_'
+    })
+}
+
+/// Parses the bytes of a custom font and makes it available to every render under the provided
+/// name. Fonts that were already registered with the same data are left alone.
+pub fn register(name: &str, bytes: Vec<u8>, digest: &str) -> io::Result<()> {
+  let key = FontSelection::Custom(name.to_string()).cache_key();
+
+  if registered_digest(name).as_deref() == Some(digest) {
+    return Ok(());
+  }
+
+  let font = rusttype::Font::try_from_vec(bytes)
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("'{name}' is not a ttf or otf font")))?;
+
+  let mut fonts = cache()
+    .write()
+    .map_err(|_| io::Error::new(io::ErrorKind::Other, "font cache poisoned"))?;
+  fonts.insert(
+    key,
+    CachedFont {
+      font,
+      digest: digest.to_string(),
+    },
+  );
+  Ok(())
+}
+
+/// Returns the digest of the data a custom font was registered with, if it has been.
+pub fn registered_digest(name: &str) -> Option<String> {
+  let key = FontSelection::Custom(name.to_string()).cache_key();
+  let fonts = cache().read().ok()?;
+  fonts.get(&key).map(|cached| cached.digest.clone())
+}
+
+/// Returns the hex encoded sha256 digest of font data.
+pub fn digest(bytes: &[u8]) -> String {
+  let digest = sha2::Digest::finalize(sha2::Digest::chain_update(<sha2::Sha256 as sha2::Digest>::new(), bytes));
+  digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Returns the collection that custom fonts are stored in.
+pub fn collection(
+  mongo: &mongodb::Client,
+  config: &crate::config::MongoConfiguration,
+) -> mongodb::Collection<crate::schema::CustomFont> {
+  mongo
+    .database(&config.database)
+    .collection(config.collections.custom_fonts.as_deref().unwrap_or("custom_fonts"))
+}
+
+/// Checks the font data and stores it under the provided name, replacing whatever font was there.
+pub async fn upload(
+  fonts: &mongodb::Collection<crate::schema::CustomFont>,
+  name: &str,
+  bytes: Vec<u8>,
+) -> io::Result<crate::schema::CustomFont> {
+  if !valid_custom_name(name) {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("font names must be 1-{MAX_CUSTOM_NAME_LEN} lowercase letters, digits, '-' or '_'"),
+    ));
+  }
+
+  if bytes.len() > MAX_CUSTOM_FONT_BYTES {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("fonts cannot be larger than {MAX_CUSTOM_FONT_BYTES} bytes"),
+    ));
+  }
+
+  if rusttype::Font::try_from_bytes(&bytes).is_none() {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("'{name}' is not a ttf or otf font"),
+    ));
+  }
+
+  let font = crate::schema::CustomFont {
+    name: name.to_string(),
+    digest: digest(&bytes),
+    data: bson::Binary {
+      subtype: bson::spec::BinarySubtype::Generic,
+      bytes,
+    },
+    uploaded_at: Some(chrono::Utc::now()),
+  };
+
+  fonts
+    .replace_one(
+      bson::doc! { "name": name },
+      &font,
+      mongodb::options::ReplaceOptions::builder().upsert(true).build(),
+    )
+    .await
+    .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("unable to store font - {error}")))?;
+
+  Ok(font)
+}
+
+/// Registers every stored custom font that has not been registered yet, or has changed since it
+/// was. Only the digests are read for fonts that are already up to date.
+pub async fn sync(fonts: &mongodb::Collection<crate::schema::CustomFont>) -> io::Result<usize> {
+  let mut cursor = fonts
+    .clone_with_type::<bson::Document>()
+    .find(
+      bson::doc! {},
+      mongodb::options::FindOptions::builder()
+        .projection(bson::doc! { "name": 1, "digest": 1 })
+        .build(),
+    )
+    .await
+    .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("unable to list fonts - {error}")))?;
+
+  let mut stale = vec![];
+  while let Some(document) = async_std::stream::StreamExt::next(&mut cursor).await {
+    let document = document.map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+    let (Ok(name), Ok(digest)) = (document.get_str("name"), document.get_str("digest")) else {
+      log::warn!("skipping malformed font document - {document:?}");
+      continue;
+    };
+
+    if registered_digest(name).as_deref() != Some(digest) {
+      stale.push(name.to_string());
+    }
+  }
+
+  let mut count = 0;
+  for name in stale {
+    let font = fonts
+      .find_one(bson::doc! { "name": &name }, None)
+      .await
+      .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("unable to load font - {error}")))?;
+
+    let Some(font) = font else {
+      continue;
+    };
+
+    match register(&font.name, font.data.bytes, &font.digest) {
+      Ok(()) => count += 1,
+      Err(error) => log::warn!("unable to register font '{name}' - {error}"),
+    }
+  }
+
+  Ok(count)
 }
 
 impl FontSelection {
-  /// Returns the compile-time memory location of the font matching the selection.
-  pub fn bytes(&self) -> &'static [u8] {
+  /// The key this font is cached by.
+  fn cache_key(&self) -> String {
+    match self {
+      Self::Barlow => "barlow".to_string(),
+      Self::DejaVu => "deja_vu".to_string(),
+      Self::Roboto => "roboto".to_string(),
+      Self::Teko => "teko".to_string(),
+      Self::Custom(name) => format!("custom:{name}"),
+    }
+  }
+
+  /// Returns the compile-time memory location of the font matching the selection. Custom fonts are
+  /// not embedded.
+  pub fn bytes(&self) -> Option<&'static [u8]> {
     match self {
-      Self::Barlow => BARLOW,
-      Self::DejaVu => DEJAVU_SANS,
-      Self::Roboto => ROBOTO_SANS,
-      Self::Teko => TEKO,
+      Self::Barlow => Some(BARLOW),
+      Self::DejaVu => Some(DEJAVU_SANS),
+      Self::Roboto => Some(ROBOTO_SANS),
+      Self::Teko => Some(TEKO),
+      Self::Custom(_) => None,
     }
   }
 
-  /// Creates the font rendering object.
-  pub fn renderer(&self) -> io::Result<rusttype::Font> {
-    let font = Vec::from(self.bytes());
+  /// Returns the font rendering object, parsing it the first time it is used. Custom fonts must
+  /// have been registered first.
+  pub fn renderer(&self) -> io::Result<rusttype::Font<'static>> {
+    let key = self.cache_key();
+
+    if let Some(cached) = cache().read().ok().and_then(|fonts| fonts.get(&key).cloned()) {
+      return Ok(cached.font);
+    }
+
+    let bytes = self
+      .bytes()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("font '{key}' has not been loaded")))?;
 
-    rusttype::Font::try_from_vec(font).ok_or_else(|| {
+    let font = rusttype::Font::try_from_bytes(bytes).ok_or_else(|| {
       io::Error::new(
         io::ErrorKind::Other,
         "Unable to build valid font context for rasterizing",
       )
-    })
+    })?;
+
+    if let Ok(mut fonts) = cache().write() {
+      fonts.insert(
+        key,
+        CachedFont {
+          font: font.clone(),
+          digest: String::new(),
+        },
+      );
+    }
+
+    Ok(font)
+  }
+}
+
+/// A font along with the fonts used for characters it does not have a glyph for. The chain always
+/// ends with DejaVu, which adds greek and cyrillic to the latin the other embedded fonts cover.
+/// Anything else (symbols, CJK) needs an uploaded custom font in the chain.
+pub(crate) struct FontChain {
+  /// The fonts, in order of preference.
+  fonts: Vec<rusttype::Font<'static>>,
+}
+
+impl FontChain {
+  /// Builds the chain for a component. Fonts that are not available (e.g custom fonts that were
+  /// removed) are skipped, as long as something is left to render with.
+  pub(crate) fn new(primary: Option<&FontSelection>, fallbacks: Option<&[FontSelection]>) -> io::Result<Self> {
+    let default = FontSelection::default();
+    let primary = primary.unwrap_or(&default);
+    let selections = std::iter::once(primary)
+      .chain(fallbacks.unwrap_or_default())
+      .chain(std::iter::once(&FontSelection::DejaVu));
+
+    let mut seen = vec![];
+    let mut fonts = vec![];
+    for selection in selections {
+      if seen.contains(&selection) {
+        continue;
+      }
+      seen.push(selection);
+
+      match selection.renderer() {
+        Ok(font) => fonts.push(font),
+        Err(error) if selection == primary => log::warn!("falling back from font - {error}"),
+        Err(error) => log::warn!("skipping fallback font - {error}"),
+      }
+    }
+
+    Ok(Self { fonts })
+  }
+
+  /// Splits the text into runs that can each be drawn with a single font of the chain. Characters
+  /// no font has a glyph for stay with the first font, which draws them as blanks.
+  fn runs<'a>(&self, text: &'a str) -> Vec<(&rusttype::Font<'static>, &'a str)> {
+    let mut runs: Vec<(usize, usize, usize)> = vec![];
+
+    for (offset, character) in text.char_indices() {
+      let index = match character.is_whitespace() {
+        // Whitespace stays with whatever run it is in, so spaces do not break runs apart.
+        true => runs.last().map(|(index, _, _)| *index).unwrap_or(0),
+        false => self
+          .fonts
+          .iter()
+          .position(|font| font.glyph(character).id().0 != 0)
+          .unwrap_or(0),
+      };
+
+      let end = offset + character.len_utf8();
+      match runs.last_mut() {
+        Some((current, _, run_end)) if *current == index => *run_end = end,
+        _ => runs.push((index, offset, end)),
+      }
+    }
+
+    runs
+      .into_iter()
+      .map(|(index, start, end)| (&self.fonts[index], &text[start..end]))
+      .collect()
+  }
+
+  /// Returns the width and height of the text, the same way `imageproc::drawing::text_size` does
+  /// for a single font.
+  pub(crate) fn text_size(&self, scale: rusttype::Scale, text: &str) -> (i32, i32) {
+    self
+      .runs(text)
+      .into_iter()
+      .fold((0, 0), |(width, height), (font, run)| {
+        let (w, h) = imageproc::drawing::text_size(scale, font, run);
+        (width + w, height.max(h))
+      })
+  }
+
+  /// Draws the text, switching fonts between runs.
+  pub(crate) fn draw<C>(
+    &self,
+    image: &mut C,
+    color: image::Luma<u8>,
+    x: i32,
+    y: i32,
+    scale: rusttype::Scale,
+    text: &str,
+  ) where
+    C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+  {
+    let mut left = x;
+    for (font, run) in self.runs(text) {
+      imageproc::drawing::draw_text_mut(image, color, left, y, scale, font, run);
+      left += imageproc::drawing::text_size(scale, font, run).0;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{register, FontChain, FontSelection, DEJAVU_SANS};
+
+  /// Returns the runs of the text, along with the position in the chain of the font each uses.
+  fn runs<'a>(chain: &FontChain, text: &'a str) -> Vec<(usize, &'a str)> {
+    chain
+      .runs(text)
+      .into_iter()
+      .map(|(font, run)| {
+        let index = chain.fonts.iter().position(|candidate| std::ptr::eq(candidate, font));
+        (index.unwrap(), run)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_fallback_runs() {
+    // Barlow does not have cyrillic glyphs, which DejaVu does.
+    let chain = FontChain::new(Some(&FontSelection::Barlow), None).unwrap();
+    assert_eq!(
+      runs(&chain, "hello привет world"),
+      vec![(0, "hello "), (1, "привет "), (0, "world")]
+    );
+
+    let single = FontChain::new(Some(&FontSelection::DejaVu), None).unwrap();
+    assert_eq!(runs(&single, "hello привет world"), vec![(0, "hello привет world")]);
+
+    // No embedded font has the checkbox glyph; it stays with the primary font and is left blank.
+    assert_eq!(runs(&chain, "todo ☐"), vec![(0, "todo ☐")]);
+  }
+
+  #[test]
+  fn test_custom_fallback_runs() {
+    register("fallback-test", DEJAVU_SANS.to_vec(), "fallback-test").unwrap();
+    let fallbacks = [FontSelection::Custom("fallback-test".to_string())];
+    let chain = FontChain::new(Some(&FontSelection::Barlow), Some(&fallbacks)).unwrap();
+
+    // Uploaded fallbacks are tried before DejaVu.
+    assert_eq!(chain.fonts.len(), 3);
+    assert_eq!(runs(&chain, "hi привет"), vec![(0, "hi "), (1, "привет")]);
+  }
+
+  #[test]
+  fn test_missing_custom_font() {
+    let selection = FontSelection::Custom("missing".to_string());
+    assert!(selection.renderer().is_err());
+    let chain = FontChain::new(Some(&selection), None).unwrap();
+    assert_eq!(chain.runs("hi").len(), 1);
   }
 }
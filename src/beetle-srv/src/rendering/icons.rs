@@ -0,0 +1,215 @@
+//! The sprites behind the `Icon` component. Each glyph is a 16x16 bitmap, where `#` is drawn and
+//! `.` is left blank; sprites are scaled to the size of the icon when drawn. These are kept as text
+//! so they can be edited without any tooling.
+
+use super::components::IconGlyph;
+
+/// The width and height of every sprite.
+pub(super) const SPRITE_SIZE: u32 = 16;
+
+/// A single sprite.
+type Sprite = [&'static str; SPRITE_SIZE as usize];
+
+/// See `IconGlyph::Clock`.
+const CLOCK: Sprite = [
+  ".....######.....",
+  "...##......##...",
+  "..#..........#..",
+  ".#......#.....#.",
+  ".#......#.....#.",
+  "#.......#......#",
+  "#.......#......#",
+  "#.......#......#",
+  "#.......#####..#",
+  "#..............#",
+  "#..............#",
+  ".#............#.",
+  ".#............#.",
+  "..#..........#..",
+  "...##......##...",
+  ".....######.....",
+];
+
+/// See `IconGlyph::Calendar`.
+const CALENDAR: Sprite = [
+  "...#.......#....",
+  "...#.......#....",
+  "################",
+  "#..............#",
+  "################",
+  "#..............#",
+  "#.##..##..##...#",
+  "#.##..##..##...#",
+  "#..............#",
+  "#.##..##..##...#",
+  "#.##..##..##...#",
+  "#..............#",
+  "#.##..##.......#",
+  "#.##..##.......#",
+  "#..............#",
+  "################",
+];
+
+/// See `IconGlyph::Person`.
+const PERSON: Sprite = [
+  "......####......",
+  ".....######.....",
+  "....########....",
+  "....########....",
+  "....########....",
+  "....########....",
+  ".....######.....",
+  "......####......",
+  "................",
+  "...##########...",
+  "..############..",
+  ".##############.",
+  ".##############.",
+  ".##############.",
+  ".##############.",
+  "................",
+];
+
+/// See `IconGlyph::Battery`.
+const BATTERY: Sprite = [
+  "................",
+  "................",
+  "................",
+  ".#############..",
+  ".#...........#..",
+  ".#.#########.#..",
+  ".#.#########.###",
+  ".#.#########.###",
+  ".#.#########.###",
+  ".#.#########.###",
+  ".#.#########.#..",
+  ".#...........#..",
+  ".#############..",
+  "................",
+  "................",
+  "................",
+];
+
+/// See `IconGlyph::Wifi`.
+const WIFI: Sprite = [
+  "................",
+  "................",
+  "....########....",
+  "..##........##..",
+  ".#............#.",
+  "#....######....#",
+  "...##......##...",
+  "..#..........#..",
+  ".....######.....",
+  "....#......#....",
+  "................",
+  ".......##.......",
+  "......####......",
+  ".......##.......",
+  "................",
+  "................",
+];
+
+/// See `IconGlyph::Message`.
+const MESSAGE: Sprite = [
+  "................",
+  ".##############.",
+  "#..............#",
+  "#..##########..#",
+  "#..............#",
+  "#..##########..#",
+  "#..............#",
+  "#..######......#",
+  "#..............#",
+  ".#####...######.",
+  ".....#..#.......",
+  ".....#.#........",
+  ".....##.........",
+  ".....#..........",
+  "................",
+  "................",
+];
+
+/// See `IconGlyph::Light`.
+const LIGHT: Sprite = [
+  ".....######.....",
+  "...##......##...",
+  "..#..........#..",
+  ".#............#.",
+  ".#............#.",
+  ".#............#.",
+  ".#............#.",
+  "..#..........#..",
+  "...#........#...",
+  "....#......#....",
+  ".....######.....",
+  ".....######.....",
+  ".....#....#.....",
+  ".....######.....",
+  "......####......",
+  "................",
+];
+
+/// Returns the sprite of a glyph.
+fn sprite(glyph: IconGlyph) -> &'static Sprite {
+  match glyph {
+    IconGlyph::Clock => &CLOCK,
+    IconGlyph::Calendar => &CALENDAR,
+    IconGlyph::Person => &PERSON,
+    IconGlyph::Battery => &BATTERY,
+    IconGlyph::Wifi => &WIFI,
+    IconGlyph::Message => &MESSAGE,
+    IconGlyph::Light => &LIGHT,
+  }
+}
+
+/// Returns true if the pixel of the glyph, scaled to `size`, should be drawn. Scaling is done by
+/// sampling the nearest pixel of the sprite, which keeps the edges sharp on the device.
+pub(super) fn filled(glyph: IconGlyph, size: u32, x: u32, y: u32) -> bool {
+  // Done in u64 so that large sizes cannot overflow.
+  let size = u64::from(size.max(1));
+  let sprite_size = u64::from(SPRITE_SIZE);
+  let row = (u64::from(y) * sprite_size / size).min(sprite_size - 1) as usize;
+  let column = (u64::from(x) * sprite_size / size).min(sprite_size - 1) as usize;
+  sprite(glyph)[row].as_bytes()[column] == b'#'
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{filled, sprite, SPRITE_SIZE};
+  use crate::rendering::components::IconGlyph;
+
+  const GLYPHS: [IconGlyph; 7] = [
+    IconGlyph::Clock,
+    IconGlyph::Calendar,
+    IconGlyph::Person,
+    IconGlyph::Battery,
+    IconGlyph::Wifi,
+    IconGlyph::Message,
+    IconGlyph::Light,
+  ];
+
+  #[test]
+  fn test_sprites_are_square() {
+    for glyph in GLYPHS {
+      for row in sprite(glyph) {
+        assert_eq!(row.len(), SPRITE_SIZE as usize, "bad row in {glyph:?} - '{row}'");
+        assert!(
+          row.chars().all(|c| c == '#' || c == '.'),
+          "bad row in {glyph:?} - '{row}'"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_scaling() {
+    // The top-left corner of the calendar is blank, the left edge below the rings is not.
+    assert!(!filled(IconGlyph::Calendar, 32, 0, 0));
+    assert!(filled(IconGlyph::Calendar, 32, 0, 5));
+    assert!(filled(IconGlyph::Calendar, 8, 0, 7));
+    assert!(!filled(IconGlyph::Calendar, 8, 7, 7));
+    assert!(filled(IconGlyph::Calendar, 16, 15, 15));
+    assert!(filled(IconGlyph::Calendar, u32::MAX, u32::MAX - 1, u32::MAX - 1));
+  }
+}
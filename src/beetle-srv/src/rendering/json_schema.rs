@@ -0,0 +1,445 @@
+//! JSON Schema documents describing the layout types, so they can be authored (and checked) outside
+//! of this crate. Every enum here is serialized with the `beetle:kind` / `beetle:content` adjacent
+//! tagging, which is expressed as a `oneOf` of objects whose `beetle:kind` is a constant.
+//!
+//! The schemas are kept next to the types by hand; the tests at the bottom of this file make sure
+//! every kind the types serialize to is accepted by the schema.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// The JSON Schema dialect our documents are written in.
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// The name of the tag field used by every enum.
+pub const KIND_FIELD: &str = "beetle:kind";
+
+/// The name of the content field used by every enum.
+pub const CONTENT_FIELD: &str = "beetle:content";
+
+/// The types a schema document can be generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaRoot {
+  /// `RenderLayout`, the type read by `beetle-cli send-layout`.
+  RenderLayout,
+
+  /// `RenderVariant`, which wraps a layout or a lighting change.
+  RenderVariant,
+
+  /// `StylizedMessage`, the text component.
+  StylizedMessage,
+}
+
+impl SchemaRoot {
+  /// The name of the definition this root refers to.
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::RenderLayout => "render_layout",
+      Self::RenderVariant => "render_variant",
+      Self::StylizedMessage => "stylized_message",
+    }
+  }
+}
+
+/// A reference to one of our definitions.
+fn reference(name: &str) -> Value {
+  json!({ "$ref": format!("#/$defs/{name}") })
+}
+
+/// Allows `null` in addition to the schema; used for `Option` fields.
+fn nullable(schema: Value) -> Value {
+  json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+/// An object with the provided properties. Properties marked as required must be present; the
+/// others are `Option` fields and can be omitted or `null`.
+fn object(description: &str, properties: &[(&str, Value, bool)]) -> Value {
+  let required = properties
+    .iter()
+    .filter_map(|(name, _, required)| required.then_some(*name))
+    .collect::<Vec<&str>>();
+  let properties = properties
+    .iter()
+    .map(|(name, schema, required)| {
+      let schema = match required {
+        true => schema.clone(),
+        false => nullable(schema.clone()),
+      };
+      (name.to_string(), schema)
+    })
+    .collect::<Map<String, Value>>();
+
+  json!({
+    "description": description,
+    "type": "object",
+    "properties": properties,
+    "required": required,
+  })
+}
+
+/// An enum serialized with our adjacent tagging. Variants without content are `None`.
+fn tagged(description: &str, variants: &[(&str, Option<Value>)]) -> Value {
+  let branches = variants
+    .iter()
+    .map(|(kind, content)| {
+      let mut properties = Map::new();
+      properties.insert(KIND_FIELD.to_string(), json!({ "const": kind }));
+      let mut required = vec![KIND_FIELD];
+
+      if let Some(content) = content {
+        properties.insert(CONTENT_FIELD.to_string(), content.clone());
+        required.push(CONTENT_FIELD);
+      }
+
+      json!({ "type": "object", "properties": properties, "required": required })
+    })
+    .collect::<Vec<Value>>();
+
+  json!({ "description": description, "oneOf": branches })
+}
+
+/// A string that must be one of the provided values; used for unit-only enums serialized without
+/// tagging.
+fn string_enum(description: &str, values: &[&str]) -> Value {
+  json!({ "description": description, "type": "string", "enum": values })
+}
+
+/// Every definition shared by our documents.
+pub(super) fn definitions() -> Map<String, Value> {
+  let string = json!({ "type": "string" });
+  let size = json!({ "type": "number", "exclusiveMinimum": 0 });
+  let fallbacks = json!({ "type": "array", "items": reference("font_selection") });
+  let mut definitions = Map::new();
+  let mut define = |name: &str, schema: Value| definitions.insert(name.to_string(), schema);
+
+  define(
+    "font_selection",
+    tagged(
+      "One of the fonts embedded in the renderer, or an uploaded font by name.",
+      &[
+        ("roboto", None),
+        ("teko", None),
+        ("barlow", None),
+        ("deja_vu", None),
+        (
+          "custom",
+          Some(json!({ "type": "string", "minLength": 1, "maxLength": super::fonts::MAX_CUSTOM_NAME_LEN })),
+        ),
+      ],
+    ),
+  );
+  let bound = json!({ "type": "integer" });
+  define(
+    "optional_bounding_box",
+    object(
+      "An amount of space on each side, in pixels.",
+      &[
+        ("left", bound.clone(), false),
+        ("right", bound.clone(), false),
+        ("top", bound.clone(), false),
+        ("bottom", bound, false),
+      ],
+    ),
+  );
+  define(
+    "time_granularity",
+    string_enum(
+      "The smallest unit of time a component is concerned with.",
+      &["minutes", "hours", "days"],
+    ),
+  );
+  define(
+    "gray_level",
+    string_enum(
+      "One of the shades of gray displays can show.",
+      &["white", "light_gray", "dark_gray", "black"],
+    ),
+  );
+  define(
+    "icon_glyph",
+    string_enum(
+      "One of the glyphs bundled with the renderer.",
+      &["clock", "calendar", "person", "battery", "wifi", "message", "light"],
+    ),
+  );
+  define(
+    "icon",
+    object(
+      "A small glyph drawn before the text of a message.",
+      &[
+        ("glyph", reference("icon_glyph"), true),
+        (
+          "size",
+          json!({ "type": "integer", "minimum": 1, "maximum": super::components::MAX_ICON_SIZE }),
+          false,
+        ),
+      ],
+    ),
+  );
+  define(
+    "stylized_message",
+    object(
+      "Text drawn with a font.",
+      &[
+        ("message", string.clone(), true),
+        ("font", reference("font_selection"), false),
+        ("fallback_fonts", fallbacks.clone(), false),
+        ("icon", reference("icon"), false),
+        ("padding", reference("optional_bounding_box"), false),
+        ("margin", reference("optional_bounding_box"), false),
+        ("border", reference("optional_bounding_box"), false),
+        ("border_radius", json!({ "type": "integer", "minimum": 0 }), false),
+        ("inverted", json!({ "type": "boolean" }), false),
+        ("fill", reference("gray_level"), false),
+        ("size", size.clone(), false),
+      ],
+    ),
+  );
+  define(
+    "clock",
+    object(
+      "The current date and/or time.",
+      &[
+        ("format", string.clone(), false),
+        ("utc_offset_minutes", json!({ "type": "integer" }), false),
+        ("granularity", reference("time_granularity"), false),
+        ("font", reference("font_selection"), false),
+        ("fallback_fonts", fallbacks.clone(), false),
+        ("size", size.clone(), false),
+      ],
+    ),
+  );
+  define(
+    "countdown",
+    object(
+      "The time remaining until some point in time.",
+      &[
+        ("label", string.clone(), true),
+        ("target", json!({ "type": "string", "format": "date-time" }), true),
+        ("granularity", reference("time_granularity"), false),
+        ("font", reference("font_selection"), false),
+        ("fallback_fonts", fallbacks, false),
+        ("size", size, false),
+      ],
+    ),
+  );
+  define("scannable", object("A qr code.", &[("contents", string.clone(), true)]));
+  define(
+    "image_fit",
+    string_enum(
+      "How an image is placed within the area it is rendered to.",
+      &["cover", "contain", "center"],
+    ),
+  );
+  define(
+    "image",
+    object(
+      "An image stored on the disk of the renderer.",
+      &[
+        ("location", string.clone(), true),
+        ("fit", reference("image_fit"), false),
+      ],
+    ),
+  );
+  define(
+    "chart_kind",
+    string_enum(
+      "How the values of a chart are drawn.",
+      &["line", "sparkline", "bar", "progress"],
+    ),
+  );
+  define(
+    "chart",
+    object(
+      "A series of numbers drawn as a chart; the range is scaled to the values unless provided.",
+      &[
+        ("kind", reference("chart_kind"), true),
+        (
+          "values",
+          json!({ "type": "array", "items": { "type": "number" } }),
+          true,
+        ),
+        ("label", string.clone(), false),
+        ("min", json!({ "type": "number" }), false),
+        ("max", json!({ "type": "number" }), false),
+        (
+          "height",
+          json!({ "type": "integer", "minimum": 1, "maximum": super::components::MAX_CHART_HEIGHT }),
+          false,
+        ),
+        ("fill", reference("gray_level"), false),
+        ("margin", reference("optional_bounding_box"), false),
+      ],
+    ),
+  );
+  define(
+    "split_contents",
+    tagged(
+      "What one side of a split renders.",
+      &[
+        (
+          "messages",
+          Some(json!({ "type": "array", "items": reference("stylized_message") })),
+        ),
+        ("scannable", Some(reference("scannable"))),
+        ("clock", Some(reference("clock"))),
+        (
+          "countdowns",
+          Some(json!({ "type": "array", "items": reference("countdown") })),
+        ),
+        ("charts", Some(json!({ "type": "array", "items": reference("chart") }))),
+      ],
+    ),
+  );
+  define(
+    "split_layout",
+    object(
+      "Content on the left and content on the right.",
+      &[
+        ("left", reference("split_contents"), true),
+        ("right", reference("split_contents"), true),
+        (
+          "ratio",
+          json!({ "type": "integer", "minimum": 0, "maximum": 100 }),
+          true,
+        ),
+      ],
+    ),
+  );
+  define(
+    "render_layout",
+    tagged(
+      "Something rasterized and sent to the display of a device.",
+      &[
+        ("clear", None),
+        ("raw", Some(string)),
+        ("image", Some(reference("image"))),
+        ("stylized_message", Some(reference("stylized_message"))),
+        ("split", Some(reference("split_layout"))),
+        ("scannable", Some(reference("scannable"))),
+        ("clock", Some(reference("clock"))),
+        ("countdown", Some(reference("countdown"))),
+        ("chart", Some(reference("chart"))),
+      ],
+    ),
+  );
+  define(
+    "lighting_layout",
+    string_enum("A change to the lights of a device.", &["off", "on"]),
+  );
+
+  let created = json!({ "type": "string", "format": "date-time" });
+  let container = |inner: &str| {
+    object(
+      "Wraps a layout along with when it was created.",
+      &[("layout", reference(inner), true), ("created", created.clone(), false)],
+    )
+  };
+  define(
+    "render_variant",
+    tagged(
+      "A layout or a lighting change, as it is queued for a device.",
+      &[
+        ("layout", Some(container("render_layout"))),
+        ("lighting", Some(container("lighting_layout"))),
+      ],
+    ),
+  );
+
+  definitions
+}
+
+/// Builds the full schema document for one of our types.
+pub fn document(root: SchemaRoot) -> Value {
+  let name = root.name();
+  json!({
+    "$schema": DIALECT,
+    "title": name,
+    "$ref": format!("#/$defs/{name}"),
+    "$defs": definitions(),
+  })
+}
+
+/// Returns the kinds accepted by a tagged definition.
+pub(super) fn kinds(definition: &Value) -> Vec<&str> {
+  definition
+    .get("oneOf")
+    .and_then(Value::as_array)
+    .map(|branches| {
+      branches
+        .iter()
+        .filter_map(|branch| branch.pointer(&format!("/properties/{KIND_FIELD}/const"))?.as_str())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{definitions, document, kinds, SchemaRoot, KIND_FIELD};
+
+  /// Every reference used anywhere in a document points at a definition.
+  fn references(value: &serde_json::Value, found: &mut Vec<String>) {
+    match value {
+      serde_json::Value::Object(fields) => {
+        if let Some(serde_json::Value::String(target)) = fields.get("$ref") {
+          found.push(target.trim_start_matches("#/$defs/").to_string());
+        }
+        fields.values().for_each(|value| references(value, found));
+      }
+      serde_json::Value::Array(values) => values.iter().for_each(|value| references(value, found)),
+      _ => (),
+    }
+  }
+
+  #[test]
+  fn test_references_resolve() {
+    let document = document(SchemaRoot::RenderVariant);
+    let mut found = vec![];
+    references(&document, &mut found);
+    assert!(!found.is_empty());
+    for name in found {
+      assert!(document["$defs"].get(&name).is_some(), "missing definition '{name}'");
+    }
+  }
+
+  #[test]
+  fn test_kinds_match_serialization() {
+    let layouts: Vec<crate::rendering::RenderLayout<String>> = vec![
+      crate::rendering::RenderLayout::Clear,
+      crate::rendering::RenderLayout::Raw("a".to_string()),
+      crate::rendering::RenderLayout::StylizedMessage(crate::rendering::StylizedMessage::default()),
+      crate::rendering::RenderLayout::Scannable(crate::rendering::components::Scannable {
+        contents: "a".to_string(),
+      }),
+      crate::rendering::RenderLayout::Clock(crate::rendering::components::Clock::default()),
+      crate::rendering::RenderLayout::Chart(crate::rendering::Chart {
+        kind: crate::rendering::ChartKind::Sparkline,
+        values: vec![1.0, 2.0],
+        label: None,
+        min: None,
+        max: None,
+        height: None,
+        fill: None,
+        margin: None,
+      }),
+    ];
+
+    let definitions = definitions();
+    let accepted = kinds(&definitions["render_layout"]);
+    assert_eq!(accepted.len(), 9);
+    for layout in layouts {
+      let value = serde_json::to_value(&layout).unwrap();
+      assert!(accepted.contains(&value[KIND_FIELD].as_str().unwrap()));
+    }
+
+    for font in [
+      crate::rendering::FontSelection::DejaVu,
+      crate::rendering::FontSelection::Custom("noto-emoji".to_string()),
+    ] {
+      let value = serde_json::to_value(font).unwrap();
+      assert!(kinds(&definitions["font_selection"]).contains(&value[KIND_FIELD].as_str().unwrap()));
+    }
+  }
+}
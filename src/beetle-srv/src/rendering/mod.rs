@@ -6,22 +6,40 @@
 use serde::{Deserialize, Serialize};
 use std::io;
 
-/// Defines all of the binary-included fonts available.
-mod fonts;
+/// Defines all of the binary-included fonts available, along with uploaded custom fonts.
+pub mod fonts;
 pub use fonts::FontSelection;
 
 /// Defines layout constants like padding and margins.
 mod constants;
+pub use constants::DEVICE_DIMENSIONS;
 
 /// Defines the components that can be used within a layout.
 pub mod components;
-pub use components::{OptionalBoundingBox, StylizedMessage};
+pub use components::{
+  Chart, ChartKind, GrayLevel, Icon, IconGlyph, ImageFit, OptionalBoundingBox, StylizedMessage, TimeGranularity,
+};
+
+/// The sprites drawn by icon components.
+mod icons;
 
 /// The rendering queue module contains the central business logic for taking a layout and adding
 /// it to the queue of things to be rendered and sent to devices.
 pub(crate) mod queue;
 pub use queue::{Queue, QueuedRenderAuthority};
 
+/// Stamps render ids onto rasterized images, and reads them back out.
+pub mod receipt;
+
+/// Layouts with placeholders that are filled in right before they are rendered.
+pub mod templates;
+
+/// JSON Schema documents describing our layouts.
+pub mod json_schema;
+
+/// Checks layouts against the schema and the dimensions of a device.
+pub mod validation;
+
 /// The renderer itself is responsible for periodically popping from the queue and doing the
 /// things.
 mod renderer;
@@ -36,6 +54,90 @@ pub enum SplitContents<S> {
 
   /// Embeds a qr code onto the split side.
   Scannable(components::Scannable<S>),
+
+  /// Renders the current date and/or time.
+  Clock(components::Clock<S>),
+
+  /// A list of countdowns, rendered one after another.
+  Countdowns(Vec<components::Countdown<S>>),
+
+  /// A list of charts, rendered one after another.
+  Charts(Vec<components::Chart<S>>),
+}
+
+impl<S> SplitContents<S>
+where
+  S: std::convert::AsRef<str>,
+{
+  /// Returns true if anything on this side refers to files on disk.
+  fn references_files(&self) -> bool {
+    match self {
+      Self::Messages(_) | Self::Scannable(_) | Self::Clock(_) | Self::Countdowns(_) | Self::Charts(_) => false,
+    }
+  }
+
+  /// Draws the contents of one side of a split, starting at the top of the image.
+  fn draw_within<C>(
+    &self,
+    left: i32,
+    max_width: i32,
+    now: chrono::DateTime<chrono::Utc>,
+    image: &mut C,
+  ) -> io::Result<()>
+  where
+    C: imageproc::drawing::Canvas<Pixel = image::Luma<u8>>,
+  {
+    let bounds = |top| components::StylizedMessageBounding {
+      left,
+      top,
+      constraints: Some(components::StylizedMessageBoundingConstraints::MaxWidth(max_width)),
+    };
+
+    match self {
+      Self::Messages(messages) => {
+        let mut top = 0;
+        for m in messages {
+          let (_, h) = m.draw_within(&bounds(top), image)?;
+          top += h;
+        }
+      }
+      Self::Clock(clock) => {
+        clock.stylized(now).draw_within(&bounds(0), image)?;
+      }
+      Self::Countdowns(countdowns) => {
+        let mut top = 0;
+        for countdown in countdowns {
+          let (_, h) = countdown.stylized(now).draw_within(&bounds(top), image)?;
+          top += h;
+        }
+      }
+      Self::Charts(charts) => {
+        let mut top = 0;
+        for chart in charts {
+          let (_, h) = chart.draw_within(&bounds(top), image)?;
+          top += h;
+        }
+      }
+      Self::Scannable(_) => {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          "qr codes cannot be rendered within a split",
+        ))
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Returns the next point in time where what would be drawn for these contents will change, if
+  /// ever.
+  fn next_change(&self, now: chrono::DateTime<chrono::Utc>) -> Option<chrono::DateTime<chrono::Utc>> {
+    match self {
+      Self::Clock(clock) => Some(clock.next_change(now)),
+      Self::Countdowns(countdowns) => countdowns.iter().filter_map(|c| c.next_change(now)).min(),
+      Self::Messages(_) | Self::Scannable(_) | Self::Charts(_) => None,
+    }
+  }
 }
 
 /// An layout that has content on the left and right.
@@ -60,9 +162,13 @@ pub enum RenderLayout<S> {
   /// Clears the screen.
   Clear,
 
-  /// Holds a location on disc to raw image data.
+  /// Holds a location on disc to raw image data. This is rendered the same as an `Image` with the
+  /// default fit.
   Raw(S),
 
+  /// An image stored on disc, with options for how it should be placed.
+  Image(components::Image<S>),
+
   /// A single styleized message. Will be rendered in the middle of the display being rasterized
   /// to.
   StylizedMessage(components::StylizedMessage<S>),
@@ -72,12 +178,53 @@ pub enum RenderLayout<S> {
 
   /// A single qr code that will be rendered to the whole dimensions.
   Scannable(components::Scannable<S>),
+
+  /// Renders the current date and/or time.
+  Clock(components::Clock<S>),
+
+  /// Renders the time remaining until some point in time.
+  Countdown(components::Countdown<S>),
+
+  /// A chart drawn across the whole width of the display.
+  Chart(components::Chart<S>),
 }
 
 impl<S> RenderLayout<S>
 where
   S: std::convert::AsRef<str>,
 {
+  /// Layouts that contain time-dependent components will need to be rasterized again whenever the
+  /// content of those components change. This returns the next point in time where that is the
+  /// case, if any.
+  pub fn next_change(&self, now: chrono::DateTime<chrono::Utc>) -> Option<chrono::DateTime<chrono::Utc>> {
+    match self {
+      Self::Clock(clock) => Some(clock.next_change(now)),
+      Self::Countdown(countdown) => countdown.next_change(now),
+      Self::Split(SplitLayout { left, right, .. }) => match (left.next_change(now), right.next_change(now)) {
+        (Some(l), Some(r)) => Some(l.min(r)),
+        (l, r) => l.or(r),
+      },
+      Self::Clear | Self::Raw(_) | Self::Image(_) | Self::StylizedMessage(_) | Self::Scannable(_) | Self::Chart(_) => {
+        None
+      }
+    }
+  }
+
+  /// Returns true if the layout refers to files on disk anywhere, including within splits. Layouts
+  /// saved or scheduled by users cannot, since the files would be read by the renderer.
+  pub fn references_files(&self) -> bool {
+    match self {
+      Self::Raw(_) | Self::Image(_) => true,
+      Self::Split(SplitLayout { left, right, .. }) => left.references_files() || right.references_files(),
+      Self::Clear
+      | Self::StylizedMessage(_)
+      | Self::Scannable(_)
+      | Self::Clock(_)
+      | Self::Countdown(_)
+      | Self::Chart(_) => false,
+    }
+  }
+
   /// Turn this layout into a rasterized image.
   pub fn rasterize(self, dimensions: (u32, u32)) -> io::Result<Vec<u8>> {
     let mut image = image::GrayImage::new(dimensions.0, dimensions.1);
@@ -98,15 +245,12 @@ where
 
     match self {
       Self::Raw(location) => {
-        log::info!("attempting to ctop and take grayscale of '{}'", location.as_ref());
-        let mut raw_image = image::io::Reader::open(location.as_ref())
-          .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?
-          .decode()
-          .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
-
-        raw_image = raw_image.resize(dimensions.0, dimensions.1, image::imageops::FilterType::CatmullRom);
-        image = raw_image.grayscale().to_luma8();
-        log::info!("grayscale of '{}' successful", location.as_ref());
+        let raw = components::Image { location, fit: None };
+        image = raw.grayscale(dimensions)?;
+      }
+
+      Self::Image(stored) => {
+        image = stored.grayscale(dimensions)?;
       }
 
       Self::Clear => (),
@@ -117,42 +261,40 @@ where
 
       Self::Split(SplitLayout { left, right, ratio }) => {
         let left_max = (dimensions.0 as f32 * (ratio as f32 / 100f32)).round() as u32;
+        let now = chrono::Utc::now();
+        left.draw_within(0, left_max as i32, now, &mut image)?;
+        right.draw_within(left_max as i32, (dimensions.0 - left_max) as i32, now, &mut image)?;
+      }
 
-        match left {
-          SplitContents::Messages(messages) => {
-            let mut top = 0;
-            for m in messages {
-              let bounds = components::StylizedMessageBounding {
-                left: 0,
-                top,
-                constraints: Some(components::StylizedMessageBoundingConstraints::MaxWidth(
-                  left_max as i32,
-                )),
-              };
-              let (_, h) = m.draw_within(&bounds, &mut image)?;
-              top += h;
-            }
-          }
-          _ => todo!(),
-        }
+      Self::Clock(clock) => {
+        let bounding = components::StylizedMessageBounding {
+          left: 10,
+          top: 10,
+          constraints: None,
+        };
+        clock.stylized(chrono::Utc::now()).draw_within(&bounding, &mut image)?;
+      }
 
-        match right {
-          SplitContents::Messages(messages) => {
-            let mut top = 0;
-            for m in messages {
-              let bounds = components::StylizedMessageBounding {
-                left: left_max as i32,
-                top,
-                constraints: Some(components::StylizedMessageBoundingConstraints::MaxWidth(
-                  (dimensions.0 - left_max) as i32,
-                )),
-              };
-              let (_, h) = m.draw_within(&bounds, &mut image)?;
-              top += h;
-            }
-          }
-          _ => todo!(),
-        }
+      Self::Countdown(countdown) => {
+        let bounding = components::StylizedMessageBounding {
+          left: 10,
+          top: 10,
+          constraints: None,
+        };
+        countdown
+          .stylized(chrono::Utc::now())
+          .draw_within(&bounding, &mut image)?;
+      }
+
+      Self::Chart(chart) => {
+        let bounding = components::StylizedMessageBounding {
+          left: 10,
+          top: 10,
+          constraints: Some(components::StylizedMessageBoundingConstraints::MaxWidth(
+            dimensions.0 as i32 - 20,
+          )),
+        };
+        chart.draw_within(&bounding, &mut image)?;
       }
 
       // If we're just a stylized image, draw us.
@@ -256,9 +398,14 @@ impl<S> RenderVariant<S> {
     #[allow(deprecated)]
     let layout = RenderLayout::StylizedMessage(components::StylizedMessage {
       border: None,
+      border_radius: None,
+      inverted: None,
+      fill: None,
       margin: None,
       padding: None,
       font: Some(fonts::FontSelection::Barlow),
+      fallback_fonts: None,
+      icon: None,
       message,
       size: None,
     });
@@ -61,6 +61,58 @@ where
     Queue { connection, secret }
   }
 
+  /// Removes every render waiting in the queue for the device, returning how many were removed.
+  /// Entries that cannot be decoded are left alone for the renderer to deal with.
+  pub async fn purge<S>(&mut self, device_id: S) -> io::Result<usize>
+  where
+    S: AsRef<str>,
+  {
+    let entries = match kramer::execute(
+      &mut self.connection,
+      kramer::Command::Lists::<&str, &str>(kramer::ListCommand::Range(crate::constants::RENDERING_QUEUE, 0, -1)),
+    )
+    .await?
+    {
+      kramer::Response::Array(entries) => entries,
+      kramer::Response::Item(kramer::ResponseValue::Empty) => vec![],
+      other => {
+        return Err(io::Error::new(
+          io::ErrorKind::Other,
+          format!("strange response from rendering queue range - {other:?}"),
+        ))
+      }
+    };
+
+    let key = jsonwebtoken::DecodingKey::from_secret(self.secret.as_bytes());
+    let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+    let mut removed = 0;
+
+    for entry in entries {
+      let kramer::ResponseValue::String(token) = entry else {
+        continue;
+      };
+
+      let matches = jsonwebtoken::decode::<QueuedRenderEncrypted<serde_json::Value>>(&token, &key, &validation)
+        .map(|decoded| decoded.claims.job.device_id == device_id.as_ref())
+        .unwrap_or_default();
+
+      if !matches {
+        continue;
+      }
+
+      if let kramer::Response::Item(kramer::ResponseValue::Integer(amount)) = kramer::execute(
+        &mut self.connection,
+        kramer::Command::Lists(kramer::ListCommand::Rem(crate::constants::RENDERING_QUEUE, &token, 0)),
+      )
+      .await?
+      {
+        removed += amount as usize;
+      }
+    }
+
+    Ok(removed)
+  }
+
   /// Creates a queued render, serializes it, and adds it to the redis list for popping later.
   pub async fn queue<S, T>(
     &mut self,
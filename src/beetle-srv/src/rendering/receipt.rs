@@ -0,0 +1,76 @@
+//! Rendered images are stamped with the id of the render that produced them, using a `tEXt` chunk
+//! in the png. Decoders skip chunks they do not care about, so devices that do not know about the
+//! stamp keep working; devices that do can report the id back when they check in.
+
+/// The keyword of the text chunk holding the render id.
+const RENDER_ID_KEYWORD: &[u8] = b"beetle:render_id";
+
+/// The signature every png starts with.
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Iterates over the `(kind, data, offset)` of each chunk in a png, where the offset is the
+/// position of the chunk within the buffer.
+fn chunks(png: &[u8]) -> impl Iterator<Item = (&[u8], &[u8], usize)> {
+  let mut offset = PNG_SIGNATURE.len();
+  let valid = png.starts_with(PNG_SIGNATURE);
+
+  std::iter::from_fn(move || {
+    let header = png.get(offset..offset + 8).filter(|_| valid)?;
+    let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let data = png.get(offset + 8..offset + 8 + len)?;
+    let start = offset;
+    offset += len + 12;
+    Some((&header[4..8], data, start))
+  })
+}
+
+/// Adds the render id to the png, just before its terminating chunk. Buffers that are not pngs
+/// are returned as-is.
+pub fn stamp(png: Vec<u8>, render_id: &str) -> Vec<u8> {
+  let Some(end) = chunks(&png)
+    .find(|(kind, _, _)| kind == b"IEND")
+    .map(|(_, _, offset)| offset)
+  else {
+    return png;
+  };
+
+  let mut chunk = Vec::with_capacity(RENDER_ID_KEYWORD.len() + render_id.len() + 13);
+  chunk.extend_from_slice(&((RENDER_ID_KEYWORD.len() + 1 + render_id.len()) as u32).to_be_bytes());
+  chunk.extend_from_slice(b"tEXt");
+  chunk.extend_from_slice(RENDER_ID_KEYWORD);
+  chunk.push(0);
+  chunk.extend_from_slice(render_id.as_bytes());
+  let crc = crc32fast::hash(&chunk[4..]);
+  chunk.extend_from_slice(&crc.to_be_bytes());
+
+  let mut stamped = png;
+  stamped.splice(end..end, chunk);
+  stamped
+}
+
+/// Returns the render id stamped on the png, if there is one.
+pub fn render_id(png: &[u8]) -> Option<String> {
+  chunks(png)
+    .filter(|(kind, _, _)| kind == b"tEXt")
+    .find_map(|(_, data, _)| data.strip_prefix(RENDER_ID_KEYWORD)?.strip_prefix(&[0]))
+    .and_then(|id| String::from_utf8(id.to_vec()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{render_id, stamp};
+
+  #[test]
+  fn test_stamp_round_trip() {
+    let png = super::super::RenderLayout::<String>::Clear.rasterize((20, 10)).unwrap();
+    assert_eq!(render_id(&png), None);
+
+    let stamped = stamp(png, "render-id");
+    assert_eq!(render_id(&stamped).as_deref(), Some("render-id"));
+
+    let decoded = image::load_from_memory(&stamped).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (20, 10));
+
+    assert_eq!(stamp(b"lighting:on".to_vec(), "render-id"), b"lighting:on".to_vec());
+  }
+}
@@ -110,13 +110,25 @@ impl Worker {
 
         let queue_id = crate::redis::device_message_queue_id(&queued_render.device_id);
 
-        if let Err(error) = self.clear_pending(&mut c, &queue_id).await {
-          log::error!("unable to clear stale renders for '{queue_id}' - {error:?}");
+        match self.clear_pending(&mut c, &queue_id).await {
+          Ok(cleared) => {
+            let histories = self.histories_collection()?;
+            let settled =
+              crate::registrar::deliveries::settle(&mut c, &histories, &queued_render.device_id, cleared > 0).await;
+
+            if let Err(error) = settled {
+              log::warn!("unable to settle earlier renders for '{queue_id}' - {error}");
+            }
+          }
+          Err(error) => log::error!("unable to clear stale renders for '{queue_id}' - {error:?}"),
         }
 
         // Actually attempt to rasterize the layout into bytes and send it along to the device via
         // the device redis queue.
-        let queue_error = match self.send_layout(&mut c, &queue_id, queued_render.layout.clone()).await {
+        let queue_error = match self
+          .send_layout(&mut c, &queue_id, &queued_render.id, queued_render.layout.clone())
+          .await
+        {
           Ok(_) => None,
           Err(error) => {
             log::warn!("unable to send layout - {error:}");
@@ -176,13 +188,52 @@ impl Worker {
           );
         }
 
+        // Layouts are stamped with their render id; start tracking them until the device reports
+        // displaying them. Lighting commands are never acknowledged, and are done once sent.
+        let delivery = match (&queue_error, &queued_render.layout) {
+          (None, super::RenderVariant::Layout(_)) => Some(schema::jobs::RenderDelivery {
+            id: queued_render.id.clone(),
+            status: schema::jobs::RenderDeliveryStatus::Queued,
+            queued_at: chrono::Utc::now(),
+            delivered_at: None,
+            displayed_at: None,
+            delivery_latency_ms: None,
+            expects_display: self.reports_display(&queued_render.device_id).await,
+          }),
+          _ => None,
+        };
+
+        if let Some(delivery) = delivery.as_ref() {
+          let delivery_doc = bson::to_bson(delivery).map_err(|error| {
+            log::warn!("unable to encode delivery as bson! - {error}");
+            io::Error::new(io::ErrorKind::Other, "serialization error".to_string())
+          })?;
+
+          if let Err(error) = histories
+            .update_one(
+              bson::doc! { "device_id": &queued_render.device_id },
+              bson::doc! { "$push": { "render_deliveries": { "$each": [delivery_doc], "$slice": -10 } } },
+              mongodb::options::UpdateOptions::builder().upsert(true).build(),
+            )
+            .await
+          {
+            log::warn!(
+              "render[{}] unable to track delivery to device '{}' - {error}",
+              queued_render.id,
+              queued_render.device_id
+            );
+          }
+        }
+
         // Lastly, update our job results hash with an entry for this render attempt. This is how
         // clients know the render has been processed in the background.
-        let serialized_result = serde_json::to_string(
-          &queue_error
-            .map(schema::jobs::JobResult::Failure)
-            .unwrap_or_else(|| schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal)),
-        )
+        let serialized_result = serde_json::to_string(&match (queue_error, delivery) {
+          (Some(error), _) => schema::jobs::JobResult::Failure(error),
+          (None, Some(delivery)) => {
+            schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Render(delivery))
+          }
+          (None, None) => schema::jobs::JobResult::Success(schema::jobs::SuccessfulJobResult::Terminal),
+        })
         .map_err(|error| {
           log::warn!("unable to complete serialization of render result - {error}");
           io::Error::new(io::ErrorKind::Other, "result-failure")
@@ -231,6 +282,48 @@ impl Worker {
     )
   }
 
+  /// Returns true when the device has recently checked in with the id of the render it displays,
+  /// meaning it can be expected to report the next one as well.
+  async fn reports_display(&self, device_id: &str) -> bool {
+    let Some(mongo) = self.connections.0.as_ref() else {
+      return false;
+    };
+
+    let found = mongo
+      .database(&self.config.0.mongo.database)
+      .collection::<bson::Document>(&self.config.0.mongo.collections.device_diagnostics)
+      .find_one(
+        bson::doc! { "id": device_id, "telemetry_history.last_render_id": { "$type": "string" } },
+        None,
+      )
+      .await;
+
+    match found {
+      Ok(found) => found.is_some(),
+      Err(error) => {
+        log::warn!("unable to check display reports of '{device_id}' - {error}");
+        false
+      }
+    }
+  }
+
+  /// Makes sure every custom font uploaded since the last render is available.
+  async fn sync_fonts(&mut self) -> io::Result<()> {
+    let mongo = self
+      .connections
+      .0
+      .as_ref()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no mongo connection".to_string()))?;
+
+    let count = super::fonts::sync(&super::fonts::collection(mongo, &self.config.0.mongo)).await?;
+
+    if count > 0 {
+      log::info!("registered {count} new or changed custom font(s)");
+    }
+
+    Ok(())
+  }
+
   /// While the `tick` method is responsible for dealing with redis connections _and_ checking for
   /// a new layout, this function is solely responsible for dealing with the process of queuing
   /// that new layout onto the device queue.
@@ -238,6 +331,7 @@ impl Worker {
     &mut self,
     connection: &mut crate::redis::RedisConnection,
     queue_id: &str,
+    render_id: &str,
     layout: super::RenderVariant<S>,
   ) -> io::Result<()>
   where
@@ -258,7 +352,14 @@ impl Worker {
         log::info!("pushed lighting command onto queue - '{res:?}'");
       }
       super::RenderVariant::Layout(layout_container) => {
-        let formatted_buffer = layout_container.layout.rasterize((400, 300))?;
+        // Layouts referring to fonts that are missing will fall back to the embedded ones, so this
+        // is not worth failing the render over.
+        if let Err(error) = self.sync_fonts().await {
+          log::warn!("unable to sync custom fonts - {error}");
+        }
+
+        let formatted_buffer =
+          super::receipt::stamp(layout_container.layout.rasterize(super::DEVICE_DIMENSIONS)?, render_id);
 
         if let Some(ref location) = self.config.0.registrar.rasterize_storage {
           let mut path = std::path::PathBuf::new();
@@ -294,12 +395,13 @@ impl Worker {
   }
 
   /// Given a queue id, the goal of this method is to remove all things in it. This does check the
-  /// length before doing so, which is nice for logging purposes.
+  /// length before doing so, which is nice for logging purposes, and returns the amount of entries
+  /// that were removed.
   async fn clear_pending(
     &mut self,
     mut connection: &mut crate::redis::RedisConnection,
     queue_id: &str,
-  ) -> io::Result<()> {
+  ) -> io::Result<i64> {
     log::info!("clearing all pending renders for '{queue_id}'");
     let len = kramer::Command::<&str, &str>::Lists(kramer::ListCommand::Len(queue_id));
     let res = kramer::execute(&mut connection, &len).await?;
@@ -315,13 +417,13 @@ impl Worker {
 
     if count <= 0 {
       log::info!("queue '{queue_id} had {count} stale messages, ignoring");
-      return Ok(());
+      return Ok(0);
     }
 
     log::info!("queue '{queue_id}' has {count} stale messages, deleting");
     let del = kramer::Command::<&str, &str>::Lists(kramer::ListCommand::Trim(queue_id, count, 0));
 
-    kramer::execute(connection, &del).await.map(|_| ()).map_err(|error| {
+    kramer::execute(connection, &del).await.map(|_| count).map_err(|error| {
       io::Error::new(
         io::ErrorKind::Other,
         format!("failed deletion of stale messages on '{queue_id}' - {error:?}"),
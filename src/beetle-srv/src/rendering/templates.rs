@@ -0,0 +1,209 @@
+//! Layout templates are render layouts whose strings hold `{{placeholders}}`. Templates are filled
+//! in with variables right before they are queued, whether that is from the api, an inbound hook or
+//! a device schedule.
+
+use std::collections::HashMap;
+use std::io;
+
+/// The longest a placeholder name can be.
+const MAX_PLACEHOLDER_LEN: usize = 32;
+
+/// Returns true if the name is allowed between the braces of a placeholder.
+fn valid_name(name: &str) -> bool {
+  !name.is_empty()
+    && name.len() <= MAX_PLACEHOLDER_LEN
+    && name
+      .chars()
+      .all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+/// Calls `visit` with the name of every placeholder in the string, replacing each placeholder with
+/// whatever is returned. Braces around anything that is not a valid name are left alone.
+fn substitute<F>(input: &str, mut visit: F) -> io::Result<String>
+where
+  F: FnMut(&str) -> io::Result<Option<String>>,
+{
+  let mut output = String::with_capacity(input.len());
+  let mut rest = input;
+
+  while let Some(start) = rest.find("{{") {
+    let Some(end) = rest[start + 2..].find("}}") else {
+      break;
+    };
+
+    let name = rest[start + 2..start + 2 + end].trim();
+    output.push_str(&rest[..start]);
+
+    match valid_name(name) {
+      true => match visit(name)? {
+        Some(value) => output.push_str(&value),
+        None => output.push_str(&rest[start..start + end + 4]),
+      },
+      false => output.push_str(&rest[start..start + end + 4]),
+    }
+
+    rest = &rest[start + end + 4..];
+  }
+
+  output.push_str(rest);
+  Ok(output)
+}
+
+/// Walks every string value in the json representation of a layout.
+fn walk<F>(value: &mut serde_json::Value, visit: &mut F) -> io::Result<()>
+where
+  F: FnMut(&str) -> io::Result<Option<String>>,
+{
+  match value {
+    serde_json::Value::String(contents) => *contents = substitute(contents, &mut *visit)?,
+    serde_json::Value::Array(values) => {
+      for value in values {
+        walk(value, visit)?;
+      }
+    }
+    serde_json::Value::Object(fields) => {
+      for (_, value) in fields.iter_mut() {
+        walk(value, visit)?;
+      }
+    }
+    _ => (),
+  }
+
+  Ok(())
+}
+
+/// Collects every string value in the json representation of a layout, leaving out the tags that
+/// name each variant.
+fn collect(value: &serde_json::Value, found: &mut Vec<String>) {
+  match value {
+    serde_json::Value::String(contents) => found.push(contents.clone()),
+    serde_json::Value::Array(values) => values.iter().for_each(|value| collect(value, found)),
+    serde_json::Value::Object(fields) => fields
+      .iter()
+      .filter(|(key, _)| key.as_str() != super::json_schema::KIND_FIELD)
+      .for_each(|(_, value)| collect(value, found)),
+    _ => (),
+  }
+}
+
+/// Returns all of the text in a layout, joined by spaces. This is what gets screened by moderation
+/// once a template has been filled in.
+pub fn text(layout: &super::RenderLayout<String>) -> io::Result<String> {
+  let value = serde_json::to_value(layout).map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+  let mut found = vec![];
+  collect(&value, &mut found);
+  Ok(found.join(" "))
+}
+
+/// Serializes the layout, passes its strings through `visit` and parses it back.
+fn transform<F>(layout: &super::RenderLayout<String>, mut visit: F) -> io::Result<super::RenderLayout<String>>
+where
+  F: FnMut(&str) -> io::Result<Option<String>>,
+{
+  let mut value = serde_json::to_value(layout).map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+  walk(&mut value, &mut visit)?;
+  serde_json::from_value(value).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Returns the sorted, unique names of every placeholder in the layout.
+pub fn placeholders(layout: &super::RenderLayout<String>) -> io::Result<Vec<String>> {
+  let mut names = vec![];
+  transform(layout, |name| {
+    names.push(name.to_string());
+    Ok(None)
+  })?;
+
+  names.sort();
+  names.dedup();
+  Ok(names)
+}
+
+/// Replaces every placeholder in the layout with its variable. Every placeholder must have one;
+/// extra variables are ignored.
+pub fn fill(
+  layout: &super::RenderLayout<String>,
+  variables: &HashMap<String, String>,
+) -> io::Result<super::RenderLayout<String>> {
+  transform(layout, |name| match variables.get(name) {
+    Some(value) => Ok(Some(value.clone())),
+    None => Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("missing template variable '{name}'"),
+    )),
+  })
+}
+
+/// Checks that a layout can be used as a template, returning its placeholders. The layout is filled
+/// with sample data and rasterized to make sure it renders at all.
+pub fn validate(layout: &super::RenderLayout<String>) -> io::Result<Vec<String>> {
+  if layout.references_files() {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      "templates cannot refer to files",
+    ));
+  }
+
+  let names = placeholders(layout)?;
+  let samples = names
+    .iter()
+    .map(|name| (name.clone(), format!("sample {name}")))
+    .collect::<HashMap<String, String>>();
+
+  let filled = fill(layout, &samples)?;
+  super::validation::check(&filled, super::DEVICE_DIMENSIONS)?;
+  filled.rasterize(super::DEVICE_DIMENSIONS)?;
+  Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{fill, placeholders, text, validate};
+  use std::collections::HashMap;
+
+  fn layout(message: &str) -> crate::rendering::RenderLayout<String> {
+    serde_json::from_value(serde_json::json!({
+      "beetle:kind": "stylized_message",
+      "beetle:content": { "message": message, "font": { "beetle:kind": "barlow" }, "size": 24.0 },
+    }))
+    .unwrap()
+  }
+
+  #[test]
+  fn test_placeholders() {
+    let template = layout("{{ greeting }}, {{name}}! {{name}} {{not a name}} {{");
+    assert_eq!(
+      placeholders(&template).unwrap(),
+      vec!["greeting".to_string(), "name".to_string()]
+    );
+  }
+
+  #[test]
+  fn test_fill() {
+    let template = layout("{{greeting}}, {{name}}!");
+    let mut variables = HashMap::new();
+    variables.insert("greeting".to_string(), "hello".to_string());
+    assert!(fill(&template, &variables).is_err());
+
+    variables.insert("name".to_string(), "{{greeting}}".to_string());
+    let filled = fill(&template, &variables).unwrap();
+    assert_eq!(placeholders(&filled).unwrap(), vec!["greeting".to_string()]);
+    match filled {
+      crate::rendering::RenderLayout::StylizedMessage(message) => assert_eq!(message.message, "hello, {{greeting}}!"),
+      other => panic!("unexpected layout {other:?}"),
+    }
+  }
+
+  #[test]
+  fn test_validate() {
+    assert!(validate(&layout("hi {{name}}")).is_ok());
+    assert!(validate(&crate::rendering::RenderLayout::Raw("/etc/passwd".to_string())).is_err());
+  }
+
+  #[test]
+  fn test_text() {
+    let mut variables = HashMap::new();
+    variables.insert("name".to_string(), "world".to_string());
+    let filled = fill(&layout("hello {{name}}"), &variables).unwrap();
+    assert_eq!(text(&filled).unwrap(), "hello world");
+  }
+}
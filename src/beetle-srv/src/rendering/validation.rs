@@ -0,0 +1,697 @@
+//! Checks layouts before they are sent anywhere. Documents are first checked against our JSON
+//! Schema, which lets us point at exactly which field is wrong, and are then laid out against the
+//! dimensions of a device to find text that would be truncated and elements that would not fit.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::io;
+
+use super::json_schema::{self, CONTENT_FIELD, KIND_FIELD};
+
+/// Where root level text components are drawn from, matching `RenderLayout::rasterize`.
+const ROOT_OFFSET: i32 = 10;
+
+/// A problem found at some location within a layout document.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct LayoutIssue {
+  /// A JSON pointer to the value in question.
+  pub path: String,
+
+  /// What is wrong with it.
+  pub message: String,
+}
+
+/// Text that will not be drawn in full.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct TruncatedText {
+  /// A JSON pointer to the component holding the text.
+  pub path: String,
+
+  /// The text as it was provided.
+  pub text: String,
+
+  /// The text as it will be drawn.
+  pub rendered: String,
+
+  /// The width available to the text, in pixels.
+  pub available_width: i32,
+}
+
+/// An element that extends past the edges of the device.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Overflow {
+  /// A JSON pointer to the element.
+  pub path: String,
+
+  /// How far past the right edge the element extends, in pixels.
+  pub right: i32,
+
+  /// How far past the bottom edge the element extends, in pixels.
+  pub bottom: i32,
+}
+
+/// Everything found while checking a layout.
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct LayoutReport {
+  /// Whether the layout can be rendered at all; this is false whenever there are errors.
+  pub valid: bool,
+
+  /// The width and height the layout was checked against.
+  pub dimensions: (u32, u32),
+
+  /// Problems that keep the layout from being rendered.
+  pub errors: Vec<LayoutIssue>,
+
+  /// Problems that do not keep the layout from being rendered, e.g fields that are ignored.
+  pub warnings: Vec<LayoutIssue>,
+
+  /// Text that will be cut short.
+  pub truncated: Vec<TruncatedText>,
+
+  /// Elements that will not fit on the device.
+  pub overflows: Vec<Overflow>,
+}
+
+/// Appends a segment to a JSON pointer, escaping it.
+fn child(path: &str, segment: &str) -> String {
+  format!("{path}/{}", segment.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Returns the name JSON Schema uses for the type of a value.
+fn type_name(value: &Value) -> &'static str {
+  match value {
+    Value::Null => "null",
+    Value::Bool(_) => "boolean",
+    Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+    Value::Number(_) => "number",
+    Value::String(_) => "string",
+    Value::Array(_) => "array",
+    Value::Object(_) => "object",
+  }
+}
+
+/// Checks a value against one of the schemas produced by `json_schema`. Only the keywords those
+/// schemas use are supported.
+fn check_schema(
+  value: &Value,
+  schema: &Value,
+  definitions: &Map<String, Value>,
+  path: &str,
+  report: &mut LayoutReport,
+) {
+  let error = |report: &mut LayoutReport, message: String| {
+    report.errors.push(LayoutIssue {
+      path: path.to_string(),
+      message,
+    })
+  };
+
+  if let Some(target) = schema.get("$ref").and_then(Value::as_str) {
+    let name = target.trim_start_matches("#/$defs/");
+    match definitions.get(name) {
+      Some(definition) => check_schema(value, definition, definitions, path, report),
+      None => error(report, format!("unknown definition '{name}'")),
+    }
+    return;
+  }
+
+  if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+    // `anyOf` is only used for nullable fields, where the first option is the interesting one.
+    if value.is_null() || options.is_empty() {
+      return;
+    }
+    check_schema(value, &options[0], definitions, path, report);
+    return;
+  }
+
+  if let Some(branches) = schema.get("oneOf").and_then(Value::as_array) {
+    let kinds = json_schema::kinds(schema);
+    let kind = match value.get(KIND_FIELD) {
+      Some(Value::String(kind)) => kind,
+      Some(_) | None => {
+        error(
+          report,
+          format!("expected an object with a '{KIND_FIELD}' of {}", kinds.join(", ")),
+        );
+        return;
+      }
+    };
+
+    match kinds.iter().position(|candidate| candidate == kind) {
+      Some(index) => check_schema(value, &branches[index], definitions, path, report),
+      None => report.errors.push(LayoutIssue {
+        path: child(path, KIND_FIELD),
+        message: format!("unknown kind '{kind}', expected one of {}", kinds.join(", ")),
+      }),
+    }
+    return;
+  }
+
+  if let Some(expected) = schema.get("const") {
+    if value != expected {
+      error(report, format!("expected {expected}"));
+    }
+    return;
+  }
+
+  if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+    let actual = type_name(value);
+    if actual != expected && !(expected == "number" && actual == "integer") {
+      error(report, format!("expected {expected}, found {actual}"));
+      return;
+    }
+  }
+
+  if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+    if !allowed.contains(value) {
+      let allowed = allowed.iter().map(Value::to_string).collect::<Vec<String>>();
+      error(report, format!("expected one of {}", allowed.join(", ")));
+    }
+  }
+
+  if let Some(number) = value.as_f64() {
+    if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+      if number < minimum {
+        error(report, format!("must be at least {minimum}"));
+      }
+    }
+    if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+      if number > maximum {
+        error(report, format!("must be at most {maximum}"));
+      }
+    }
+    if let Some(minimum) = schema.get("exclusiveMinimum").and_then(Value::as_f64) {
+      if number <= minimum {
+        error(report, format!("must be greater than {minimum}"));
+      }
+    }
+  }
+
+  if let Some(length) = value.as_str().map(|text| text.chars().count() as u64) {
+    if let Some(minimum) = schema.get("minLength").and_then(Value::as_u64) {
+      if length < minimum {
+        error(report, format!("must be at least {minimum} characters"));
+      }
+    }
+    if let Some(maximum) = schema.get("maxLength").and_then(Value::as_u64) {
+      if length > maximum {
+        error(report, format!("must be at most {maximum} characters"));
+      }
+    }
+  }
+
+  if let (Some("date-time"), Some(text)) = (schema.get("format").and_then(Value::as_str), value.as_str()) {
+    if chrono::DateTime::parse_from_rfc3339(text).is_err() {
+      error(report, format!("'{text}' is not an rfc3339 date-time"));
+    }
+  }
+
+  if let (Some(items), Some(values)) = (schema.get("items"), value.as_array()) {
+    for (index, item) in values.iter().enumerate() {
+      check_schema(item, items, definitions, &child(path, &index.to_string()), report);
+    }
+  }
+
+  if let (Some(properties), Some(fields)) = (schema.get("properties").and_then(Value::as_object), value.as_object()) {
+    let required = schema
+      .get("required")
+      .and_then(Value::as_array)
+      .map(|required| required.iter().filter_map(Value::as_str).collect::<Vec<&str>>())
+      .unwrap_or_default();
+
+    for name in required {
+      if !fields.contains_key(name) {
+        error(report, format!("missing required field '{name}'"));
+      }
+    }
+
+    for (name, field) in fields {
+      match properties.get(name) {
+        Some(property) => check_schema(field, property, definitions, &child(path, name), report),
+        None => report.warnings.push(LayoutIssue {
+          path: child(path, name),
+          message: format!("unknown field '{name}' is ignored"),
+        }),
+      }
+    }
+  }
+}
+
+/// Lays out a message, recording truncation and overflow.
+fn measure_message<S>(
+  message: &super::StylizedMessage<S>,
+  bounds: &super::components::StylizedMessageBounding,
+  path: &str,
+  report: &mut LayoutReport,
+) -> i32
+where
+  S: AsRef<str>,
+{
+  if let Some(size) = message.icon.as_ref().and_then(|icon| icon.size) {
+    if size > report.dimensions.1 {
+      report.errors.push(LayoutIssue {
+        path: child(&child(path, "icon"), "size"),
+        message: format!("a {size}px icon is taller than the display"),
+      });
+      return 0;
+    }
+  }
+
+  let fitted = match message.fit(bounds) {
+    Ok(fitted) => fitted,
+    Err(error) => {
+      report.errors.push(LayoutIssue {
+        path: path.to_string(),
+        message: error.to_string(),
+      });
+      return 0;
+    }
+  };
+
+  if fitted.truncated {
+    let available_width = match bounds.constraints {
+      Some(super::components::StylizedMessageBoundingConstraints::MaxWidth(width)) => width,
+      None => report.dimensions.0 as i32,
+    };
+    report.truncated.push(TruncatedText {
+      path: path.to_string(),
+      text: message.message.as_ref().to_string(),
+      rendered: fitted.text.clone(),
+      available_width,
+    });
+  }
+
+  record_overflow(bounds, fitted.dimensions, path, report);
+  fitted.dimensions.1
+}
+
+/// Records an overflow if something of the provided size does not fit on the display.
+fn record_overflow(
+  bounds: &super::components::StylizedMessageBounding,
+  dimensions: (i32, i32),
+  path: &str,
+  report: &mut LayoutReport,
+) {
+  let right = bounds.left + dimensions.0 - report.dimensions.0 as i32;
+  let bottom = bounds.top + dimensions.1 - report.dimensions.1 as i32;
+  if right > 0 || bottom > 0 {
+    report.overflows.push(Overflow {
+      path: path.to_string(),
+      right: right.max(0),
+      bottom: bottom.max(0),
+    });
+  }
+}
+
+/// Lays out a chart, recording overflow and warning about charts without anything to draw.
+fn measure_chart(
+  chart: &super::Chart<String>,
+  bounds: &super::components::StylizedMessageBounding,
+  path: &str,
+  report: &mut LayoutReport,
+) -> i32 {
+  if let Some(height) = chart.height.filter(|height| *height > report.dimensions.1) {
+    report.errors.push(LayoutIssue {
+      path: child(path, "height"),
+      message: format!("a {height}px chart is taller than the display"),
+    });
+    return 0;
+  }
+
+  if !chart.values.iter().any(|value| value.is_finite()) {
+    report.warnings.push(LayoutIssue {
+      path: format!("{path}/values"),
+      message: "the chart has no values to draw".to_string(),
+    });
+  }
+
+  match chart.fit(bounds) {
+    Ok(dimensions) => {
+      record_overflow(bounds, dimensions, path, report);
+      dimensions.1
+    }
+    Err(error) => {
+      report.errors.push(LayoutIssue {
+        path: path.to_string(),
+        message: error.to_string(),
+      });
+      0
+    }
+  }
+}
+
+/// Lays out one side of a split.
+fn measure_split_contents(
+  contents: &super::SplitContents<String>,
+  left: i32,
+  max_width: i32,
+  path: &str,
+  report: &mut LayoutReport,
+) {
+  let now = chrono::Utc::now();
+  let content_path = child(path, CONTENT_FIELD);
+  let bounds = |top| super::components::StylizedMessageBounding {
+    left,
+    top,
+    constraints: Some(super::components::StylizedMessageBoundingConstraints::MaxWidth(
+      max_width,
+    )),
+  };
+
+  match contents {
+    super::SplitContents::Messages(messages) => {
+      let mut top = 0;
+      for (index, message) in messages.iter().enumerate() {
+        top += measure_message(message, &bounds(top), &child(&content_path, &index.to_string()), report);
+      }
+    }
+    super::SplitContents::Countdowns(countdowns) => {
+      let mut top = 0;
+      for (index, countdown) in countdowns.iter().enumerate() {
+        let message = countdown.stylized(now);
+        top += measure_message(
+          &message,
+          &bounds(top),
+          &child(&content_path, &index.to_string()),
+          report,
+        );
+      }
+    }
+    super::SplitContents::Charts(charts) => {
+      let mut top = 0;
+      for (index, chart) in charts.iter().enumerate() {
+        top += measure_chart(chart, &bounds(top), &child(&content_path, &index.to_string()), report);
+      }
+    }
+    super::SplitContents::Clock(clock) => {
+      if clock_format_valid(clock, &content_path, report) {
+        measure_message(&clock.stylized(now), &bounds(0), &content_path, report);
+      }
+    }
+    super::SplitContents::Scannable(_) => report.errors.push(LayoutIssue {
+      path: path.to_string(),
+      message: "qr codes cannot be rendered within a split".to_string(),
+    }),
+  }
+}
+
+/// Records an error for clock formats that cannot be rendered, returning whether the format is usable.
+fn clock_format_valid(clock: &super::components::Clock<String>, path: &str, report: &mut LayoutReport) -> bool {
+  let format = match clock.format.as_ref() {
+    Some(format) => format,
+    None => return true,
+  };
+
+  match super::components::check_clock_format(format) {
+    Ok(()) => true,
+    Err(error) => {
+      report.errors.push(LayoutIssue {
+        path: child(path, "format"),
+        message: error.to_string(),
+      });
+      false
+    }
+  }
+}
+
+/// Lays out a layout that is known to match our schema.
+fn measure(layout: &super::RenderLayout<String>, report: &mut LayoutReport) {
+  let now = chrono::Utc::now();
+  let path = format!("/{CONTENT_FIELD}");
+  let root = super::components::StylizedMessageBounding {
+    left: ROOT_OFFSET,
+    top: ROOT_OFFSET,
+    constraints: None,
+  };
+
+  match layout {
+    super::RenderLayout::StylizedMessage(message) => {
+      measure_message(message, &root, &path, report);
+    }
+    super::RenderLayout::Clock(clock) => {
+      if clock_format_valid(clock, &path, report) {
+        measure_message(&clock.stylized(now), &root, &path, report);
+      }
+    }
+    super::RenderLayout::Countdown(countdown) => {
+      measure_message(&countdown.stylized(now), &root, &path, report);
+    }
+    super::RenderLayout::Chart(chart) => {
+      let bounds = super::components::StylizedMessageBounding {
+        constraints: Some(super::components::StylizedMessageBoundingConstraints::MaxWidth(
+          report.dimensions.0 as i32 - ROOT_OFFSET * 2,
+        )),
+        ..root
+      };
+      measure_chart(chart, &bounds, &path, report);
+    }
+    super::RenderLayout::Split(super::SplitLayout { left, right, ratio }) => {
+      let width = report.dimensions.0;
+      let left_max = (width as f32 * (*ratio as f32 / 100f32)).round() as u32;
+      measure_split_contents(left, 0, left_max as i32, &child(&path, "left"), report);
+      measure_split_contents(
+        right,
+        left_max as i32,
+        width.saturating_sub(left_max) as i32,
+        &child(&path, "right"),
+        report,
+      );
+    }
+    super::RenderLayout::Scannable(scannable) => {
+      if let Err(error) = scannable.grayscale(report.dimensions) {
+        report.errors.push(LayoutIssue {
+          path,
+          message: format!("unable to build qr code - {error}"),
+        });
+      }
+    }
+    super::RenderLayout::Raw(_) | super::RenderLayout::Image(_) => report.warnings.push(LayoutIssue {
+      path,
+      message: "images are read from the disk of the renderer and are not checked".to_string(),
+    }),
+    super::RenderLayout::Clear => (),
+  }
+}
+
+/// Checks a `RenderLayout` document against the dimensions of a device.
+pub fn report(document: &Value, dimensions: (u32, u32)) -> LayoutReport {
+  let mut report = LayoutReport {
+    dimensions,
+    ..LayoutReport::default()
+  };
+
+  if dimensions.0 > super::constants::MAX_WIDTH || dimensions.1 > super::constants::MAX_HEIGHT {
+    report.errors.push(LayoutIssue {
+      path: String::new(),
+      message: "dimensions exceed reasonable resolution".to_string(),
+    });
+    return report;
+  }
+
+  let definitions = json_schema::definitions();
+  if let Some(schema) = definitions.get(json_schema::SchemaRoot::RenderLayout.name()) {
+    check_schema(document, schema, &definitions, "", &mut report);
+  }
+
+  if report.errors.is_empty() {
+    match serde_json::from_value::<super::RenderLayout<String>>(document.clone()) {
+      Ok(layout) => measure(&layout, &mut report),
+      Err(error) => report.errors.push(LayoutIssue {
+        path: String::new(),
+        message: error.to_string(),
+      }),
+    }
+  }
+
+  report.valid = report.errors.is_empty();
+  report
+}
+
+/// Checks a layout before it is rasterized, failing with the first error found. Layouts that did
+/// not come through the validation api (templates and schedules) go through this instead.
+pub fn check(layout: &super::RenderLayout<String>, dimensions: (u32, u32)) -> io::Result<()> {
+  let document = serde_json::to_value(layout).map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+
+  match report(&document, dimensions).errors.first() {
+    Some(issue) => Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("invalid layout at '{}' - {}", issue.path, issue.message),
+    )),
+    None => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{check, report};
+
+  #[test]
+  fn test_schema_errors() {
+    let result = report(
+      &serde_json::json!({
+        "beetle:kind": "split",
+        "beetle:content": {
+          "left": { "beetle:kind": "messages", "beetle:content": [{ "message": 1, "szie": 2 }] },
+          "right": { "beetle:kind": "banner" },
+          "ratio": 120,
+        },
+      }),
+      (400, 300),
+    );
+
+    assert!(!result.valid);
+    let paths = result
+      .errors
+      .iter()
+      .map(|issue| issue.path.as_str())
+      .collect::<Vec<&str>>();
+    assert_eq!(
+      paths,
+      vec![
+        "/beetle:content/left/beetle:content/0/message",
+        "/beetle:content/right/beetle:kind",
+        "/beetle:content/ratio",
+      ]
+    );
+    assert_eq!(result.warnings[0].path, "/beetle:content/left/beetle:content/0/szie");
+  }
+
+  #[test]
+  fn test_truncation_and_overflow() {
+    let result = report(
+      &serde_json::json!({
+        "beetle:kind": "split",
+        "beetle:content": {
+          "left": {
+            "beetle:kind": "messages",
+            "beetle:content": [{ "message": "a message that is far too long for half", "size": 40.0 }],
+          },
+          "right": {
+            "beetle:kind": "messages",
+            "beetle:content": [
+              { "message": "a", "size": 100.0 },
+              { "message": "b", "size": 100.0 },
+              { "message": "c", "size": 100.0 },
+              { "message": "d", "size": 100.0 },
+            ],
+          },
+          "ratio": 50,
+        },
+      }),
+      (400, 300),
+    );
+
+    assert!(result.valid);
+    assert_eq!(result.truncated.len(), 1);
+    assert_eq!(result.truncated[0].path, "/beetle:content/left/beetle:content/0");
+    assert!(result.truncated[0].rendered.ends_with("..."));
+    assert!(!result.overflows.is_empty());
+    assert!(result
+      .overflows
+      .iter()
+      .all(|overflow| overflow.right == 0 && overflow.bottom > 0));
+    assert_eq!(
+      result.overflows.last().map(|overflow| overflow.path.as_str()),
+      Some("/beetle:content/right/beetle:content/3")
+    );
+  }
+
+  #[test]
+  fn test_charts() {
+    let chart = serde_json::json!({ "kind": "bar", "values": [1, 2, 3] });
+    let result = report(
+      &serde_json::json!({
+        "beetle:kind": "split",
+        "beetle:content": {
+          "left": { "beetle:kind": "charts", "beetle:content": vec![chart; 6] },
+          "right": {
+            "beetle:kind": "charts",
+            "beetle:content": [{ "kind": "sparkline", "values": [], "label": "empty" }],
+          },
+          "ratio": 50,
+        },
+      }),
+      (400, 300),
+    );
+
+    assert!(result.valid, "{:?}", result.errors);
+    assert_eq!(result.overflows.len(), 1);
+    assert_eq!(result.overflows[0].path, "/beetle:content/left/beetle:content/5");
+    assert_eq!(result.overflows[0].right, 0);
+    assert_eq!(result.overflows[0].bottom, 60);
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].path, "/beetle:content/right/beetle:content/0/values");
+  }
+
+  #[test]
+  fn test_check_split_scannable() {
+    let layout = serde_json::from_value::<crate::rendering::RenderLayout<String>>(serde_json::json!({
+      "beetle:kind": "split",
+      "beetle:content": {
+        "left": { "beetle:kind": "scannable", "beetle:content": { "contents": "https://example.com" } },
+        "right": { "beetle:kind": "messages", "beetle:content": [{ "message": "hi", "size": 24.0 }] },
+        "ratio": 50,
+      },
+    }))
+    .unwrap();
+
+    assert!(check(&layout, (400, 300)).is_err());
+    assert!(layout.rasterize((400, 300)).is_err());
+  }
+
+  #[test]
+  fn test_oversized_icon() {
+    let message = |size: u32| {
+      serde_json::json!({
+        "beetle:kind": "stylized_message",
+        "beetle:content": { "message": "hi", "size": 24.0, "icon": { "glyph": "clock", "size": size } },
+      })
+    };
+
+    assert!(report(&message(32), (400, 300)).valid);
+
+    let result = report(&message(301), (400, 300));
+    assert!(!result.valid);
+    assert_eq!(result.errors[0].path, "/beetle:content/icon/size");
+
+    let result = report(&message(u32::MAX), (400, 300));
+    assert!(!result.valid);
+    assert_eq!(result.errors[0].path, "/beetle:content/icon/size");
+  }
+
+  #[test]
+  fn test_oversized_chart() {
+    let chart = |height: u32| {
+      serde_json::json!({
+        "beetle:kind": "chart",
+        "beetle:content": { "kind": "bar", "values": [1, 2], "height": height },
+      })
+    };
+
+    assert!(report(&chart(100), (400, 300)).valid);
+
+    let result = report(&chart(301), (400, 300));
+    assert!(!result.valid);
+    assert_eq!(result.errors[0].path, "/beetle:content/height");
+
+    let result = report(&chart(u32::MAX), (400, 300));
+    assert!(!result.valid);
+    assert_eq!(result.errors[0].path, "/beetle:content/height");
+  }
+
+  #[test]
+  fn test_bad_clock_format() {
+    let clock = |format: &str| {
+      serde_json::json!({
+        "beetle:kind": "clock",
+        "beetle:content": { "format": format },
+      })
+    };
+
+    assert!(report(&clock("%H:%M"), (400, 300)).valid);
+
+    let result = report(&clock("%Q"), (400, 300));
+    assert!(!result.valid);
+    assert_eq!(result.errors[0].path, "/beetle:content/format");
+  }
+}
@@ -0,0 +1,45 @@
+//! The schema of personal access tokens; named, scoped tokens users create to talk to the api from
+//! scripts and the cli, instead of through a browser session.
+
+use serde::{Deserialize, Serialize};
+
+/// What a personal access token can be used for.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PersonalAccessTokenScope {
+  /// Requests that only read, e.g `GET`.
+  Read,
+
+  /// Requests that make changes, e.g `POST`.
+  Write,
+}
+
+/// A token that is accepted in place of the session cookie. Only the hash of the token is stored;
+/// the token itself is returned once, when it is created.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct PersonalAccessToken {
+  /// The id of this token.
+  pub id: String,
+
+  /// The id of the user this token acts as.
+  pub user_id: String,
+
+  /// A name to tell tokens apart.
+  pub name: String,
+
+  /// The hex encoded sha256 digest of the token.
+  pub token_hash: String,
+
+  /// What this token can be used for.
+  pub scopes: Vec<PersonalAccessTokenScope>,
+
+  /// When this token was created.
+  pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When this token was last used.
+  pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When this token stops working, if ever.
+  pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
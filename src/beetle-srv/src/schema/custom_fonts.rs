@@ -0,0 +1,22 @@
+//! The schema of fonts uploaded for use by layouts, which are referenced by name through
+//! `FontSelection::Custom`.
+
+use serde::{Deserialize, Serialize};
+
+/// An uploaded ttf or otf font.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct CustomFont {
+  /// The name layouts refer to this font by.
+  pub name: String,
+
+  /// The hex encoded sha256 digest of the font data. Renderers use this to notice a font was
+  /// replaced without re-downloading every font.
+  pub digest: String,
+
+  /// The font data itself.
+  pub data: bson::Binary,
+
+  /// When this font was uploaded.
+  pub uploaded_at: Option<chrono::DateTime<chrono::Utc>>,
+}
@@ -1,4 +1,4 @@
-use crate::vendor::google;
+use crate::{rendering, vendor::google};
 use serde::{Deserialize, Serialize};
 
 /// Entries in our device rendering state that are messages.
@@ -13,6 +13,111 @@ pub struct DeviceRenderingStateMessageEntry {
   pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+/// Entries in our device rendering state that belong to a checklist.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceRenderingStateChecklistItem {
+  /// A unique id for this item, used by transitions that target a specific item.
+  pub id: String,
+  /// The string to be rendered.
+  pub content: String,
+  /// Whether or not this item has been checked off.
+  pub checked: bool,
+  /// Who/what added this item.
+  pub origin: DeviceStateMessageOrigin,
+  /// The timestamp the item was added to our list.
+  pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Entries in our device rendering state that count down to some point in time.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceRenderingStateCountdownEntry {
+  /// The text rendered after the time remaining, e.g "until launch".
+  pub label: String,
+  /// The point in time being counted down to.
+  pub target: chrono::DateTime<chrono::Utc>,
+  /// The smallest unit of time that will be displayed.
+  pub granularity: Option<rendering::TimeGranularity>,
+}
+
+/// The clock settings of our device rendering state.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceRenderingStateClock {
+  /// A `strftime`-style format string.
+  pub format: Option<String>,
+  /// The offset from utc, in minutes, that the clock will be rendered in.
+  pub utc_offset_minutes: Option<i32>,
+  /// How often the clock changes; this should match the format.
+  pub granularity: Option<rendering::TimeGranularity>,
+}
+
+/// A photo that has been uploaded and retained in a device album.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceAlbumPhoto {
+  /// A unique id for this photo, used to remove or move it.
+  pub id: String,
+  /// The location on disc of the image data.
+  pub location: String,
+  /// How the photo should be placed on the display.
+  pub fit: Option<rendering::ImageFit>,
+  /// The timestamp the photo was added to the album.
+  pub added_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// The content of a pending entry; what will be added to the device state once approved.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DevicePendingContent {
+  /// A message to push onto the message list.
+  Message(String),
+
+  /// An item to add to the checklist.
+  ChecklistItem(String),
+
+  /// A clock to show.
+  Clock(DeviceRenderingStateClock),
+
+  /// A countdown to add.
+  Countdown(DeviceRenderingStateCountdownEntry),
+
+  /// A link to render as a QR code.
+  Link(String),
+
+  /// An uploaded image to send to the device. The file is deleted once it has been sent, or when
+  /// the entry is rejected.
+  Image {
+    /// The location on disc of the uploaded image.
+    location: String,
+  },
+
+  /// An uploaded photo to add to the device album.
+  AlbumPhoto {
+    /// The location on disc of the uploaded image.
+    location: String,
+    /// How the photo should be placed on the display.
+    fit: Option<rendering::ImageFit>,
+  },
+}
+
+/// Content sent to a device by another user that is being held until the owner approves it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DevicePendingEntry {
+  /// A unique id for this entry, used to approve or reject it.
+  pub id: String,
+  /// The id of the user that sent this.
+  pub sender: String,
+  /// Who/what sent this, as it will be displayed.
+  pub origin: DeviceStateMessageOrigin,
+  /// The content being held.
+  pub content: DevicePendingContent,
+  /// The timestamp the entry was held.
+  pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+}
+
 /// This schema is the long-lived representation of what is being rendered to a device.
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
@@ -31,6 +136,27 @@ pub enum DeviceRenderingState {
     /// The list of messages.
     messages: Vec<DeviceRenderingStateMessageEntry>,
   },
+
+  /// A shared to-do list.
+  Checklist {
+    /// The items in the list, in the order they were added (or moved to).
+    items: Vec<DeviceRenderingStateChecklistItem>,
+  },
+
+  /// A clock and/or list of countdowns. These are re-rendered by the registrar as time passes.
+  Timekeeping {
+    /// The clock, if one should be displayed.
+    clock: Option<DeviceRenderingStateClock>,
+
+    /// The list of countdowns.
+    countdowns: Vec<DeviceRenderingStateCountdownEntry>,
+  },
+
+  /// Cycles through the photos in the device album.
+  Slideshow {
+    /// The amount of time each photo is displayed for.
+    interval_seconds: u32,
+  },
 }
 
 /// This schema is the long-lived representation of what is being rendered to a device.
@@ -45,6 +171,19 @@ pub struct DeviceState {
 
   /// The render state.
   pub(crate) rendering: Option<DeviceRenderingState>,
+
+  /// For states containing time-dependent content, this is the timestamp (in milliseconds) after
+  /// which the registrar should render the state again.
+  pub(crate) scheduled_render: Option<i64>,
+
+  /// The photos retained for this device. These live alongside the rendering state, but are not
+  /// affected by transitions between rendering states.
+  #[serde(default)]
+  pub(crate) album: Vec<DeviceAlbumPhoto>,
+
+  /// Content from other users waiting on approval from the owner of a `Public` device.
+  #[serde(default)]
+  pub(crate) pending: Vec<DevicePendingEntry>,
 }
 
 /// The various kinds of origins messages can come from.
@@ -0,0 +1,55 @@
+//! The schema of inbound hooks; tokens created by device owners that let scripts and other services
+//! send content to a single device without a browser session.
+
+use serde::{Deserialize, Serialize};
+
+/// The things an inbound hook can be allowed to do.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InboundHookPermission {
+  /// Pushing messages onto the device.
+  Message,
+
+  /// Turning the device lights on and off.
+  Lights,
+
+  /// Sending images to the device.
+  Image,
+
+  /// Rendering layout templates of the user that created the hook.
+  Template,
+}
+
+/// A token that can send content to a device. Only the hash of the token is stored; the token
+/// itself is returned once, when the hook is created.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct InboundHook {
+  /// The id of this hook.
+  pub id: String,
+
+  /// The id of the device content is sent to.
+  pub device_id: String,
+
+  /// The id of the user that created this hook. Requests are made on their behalf, and stop
+  /// working once they no longer have access to the device.
+  pub user_id: String,
+
+  /// A name for this hook, which is also shown as the origin of its messages.
+  pub label: String,
+
+  /// The hex encoded sha256 digest of the token.
+  pub token_hash: String,
+
+  /// What this hook is allowed to do.
+  pub permissions: Vec<InboundHookPermission>,
+
+  /// The most amount of requests allowed per minute.
+  pub rate_limit: u32,
+
+  /// When this hook was created.
+  pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When this hook was last used.
+  pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
@@ -11,6 +11,61 @@ pub enum SuccessfulJobResult {
 
   /// This job caused other jobs to immediately run.
   Percolated(Vec<String>),
+
+  /// This job was a render sent to a device; the delivery is updated as the device reports back.
+  Render(RenderDelivery),
+}
+
+/// The steps a render goes through after being rasterized and pushed onto the device queue.
+#[derive(Deserialize, Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderDeliveryStatus {
+  /// The render is waiting on the device queue.
+  Queued,
+
+  /// The device has taken the render off of its queue.
+  Delivered,
+
+  /// The device reported the render as the one on its display.
+  Displayed,
+
+  /// The device never reported displaying the render in time.
+  Unacknowledged,
+
+  /// A newer render was sent before the device took this one off of its queue.
+  Superseded,
+}
+
+/// Tracks a single render from the device queue to the display.
+#[derive(Deserialize, Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct RenderDelivery {
+  /// The id of the render.
+  pub id: String,
+
+  /// Where the render is at.
+  pub status: RenderDeliveryStatus,
+
+  /// When the render was pushed onto the device queue.
+  #[serde(with = "chrono::serde::ts_milliseconds")]
+  pub queued_at: chrono::DateTime<chrono::Utc>,
+
+  /// When the device took the render off its queue.
+  #[serde(default, with = "chrono::serde::ts_milliseconds_option")]
+  pub delivered_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When the device reported displaying the render.
+  #[serde(default, with = "chrono::serde::ts_milliseconds_option")]
+  pub displayed_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// The amount of milliseconds between queuing and delivery.
+  #[serde(default)]
+  pub delivery_latency_ms: Option<i64>,
+
+  /// Whether the device reports the renders it displays. Renders sent to devices that do not are
+  /// never flagged as unacknowledged; delivery is as far as they can be tracked.
+  #[serde(default)]
+  pub expects_display: bool,
 }
 
 /// The enumerated result set of all background jobs.
@@ -0,0 +1,31 @@
+//! The schema of layout templates; render layouts saved by users whose strings can hold
+//! `{{placeholders}}` that are filled in whenever the template is rendered.
+
+use serde::{Deserialize, Serialize};
+
+/// A saved layout with placeholders.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct LayoutTemplate {
+  /// The id of this template.
+  pub id: String,
+
+  /// The id of the user that saved this template. Only they can render it.
+  pub user_id: String,
+
+  /// A name for this template.
+  pub name: String,
+
+  /// The layout itself.
+  pub layout: crate::rendering::RenderLayout<String>,
+
+  /// The names of the placeholders found in the layout when it was saved. Each needs a variable
+  /// whenever the template is rendered.
+  pub variables: Vec<String>,
+
+  /// When this template was created.
+  pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When this template was last changed.
+  pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
@@ -5,11 +5,38 @@ use serde::{Deserialize, Serialize};
 
 /// The device state is a bit beefy.
 mod device_state;
-pub use device_state::{DeviceRenderingState, DeviceRenderingStateMessageEntry, DeviceState, DeviceStateMessageOrigin};
+pub use device_state::{
+  DeviceAlbumPhoto, DevicePendingContent, DevicePendingEntry, DeviceRenderingState, DeviceRenderingStateChecklistItem,
+  DeviceRenderingStateClock, DeviceRenderingStateCountdownEntry, DeviceRenderingStateMessageEntry, DeviceState,
+  DeviceStateMessageOrigin,
+};
 
 /// The general schema related to the background jobs used.
 pub(crate) mod jobs;
 
+/// Webhooks registered by users, and their deliveries.
+mod webhooks;
+pub use webhooks::{
+  Webhook, WebhookDelivery, WebhookDeliveryAttempt, WebhookDeliveryStatus, WebhookEvent, WebhookEventKind,
+  WebhookEventPayload,
+};
+
+/// Tokens that let other services send content to a device.
+mod inbound_hooks;
+pub use inbound_hooks::{InboundHook, InboundHookPermission};
+
+/// Tokens that users can authenticate with in place of a session.
+mod access_tokens;
+pub use access_tokens::{PersonalAccessToken, PersonalAccessTokenScope};
+
+/// Layouts saved by users, filled in with variables whenever they are rendered.
+mod layout_templates;
+pub use layout_templates::LayoutTemplate;
+
+/// Fonts uploaded for use by layouts.
+mod custom_fonts;
+pub use custom_fonts::CustomFont;
+
 /// The "snapshot in time" of device information we want stored on our user documents themselves.
 #[derive(Deserialize, Serialize, Debug, Default)]
 #[serde(rename_all = "snake_case")]
@@ -37,6 +64,44 @@ pub struct User {
 
   /// A list of device ids this user has access to.
   pub devices: Option<std::collections::HashMap<String, UserDeviceSnapshot>>,
+
+  /// Where to notify this user about the devices they own.
+  #[serde(default)]
+  pub alert_preferences: Option<UserAlertPreferences>,
+
+  /// The accounts at OpenID Connect providers linked to this user.
+  #[serde(default)]
+  pub identities: Vec<UserIdentity>,
+
+  /// Email addresses a provider has verified as belonging to this user. Logging in through any
+  /// provider with one of these links that account to this user.
+  #[serde(default)]
+  pub verified_emails: Vec<String>,
+}
+
+/// An account at an OpenID Connect provider.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct UserIdentity {
+  /// The id of the provider, per configuration.
+  pub provider: String,
+
+  /// The id of the account at the provider.
+  pub subject: String,
+
+  /// The email address of the account, if the provider shared one.
+  pub email: Option<String>,
+}
+
+/// The channels a user wants to be notified through when their devices go offline or come back.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct UserAlertPreferences {
+  /// An email address to send notifications to.
+  pub email: Option<String>,
+
+  /// A url that notifications will be posted to as json.
+  pub webhook_url: Option<String>,
 }
 
 /// Mongo + serde + chrono don't work perfectly together; for now these are serialized into a user
@@ -45,6 +110,22 @@ fn format_datetime(datetime: &chrono::DateTime<chrono::Utc>) -> String {
   datetime.format("%b %d, %Y %H:%M:%S").to_string()
 }
 
+/// The roles users other than the owner can be given on a device. Users without an explicit role
+/// are contributors.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceGuestRole {
+  /// Can do everything but change who can access the device and its visibility.
+  Manager,
+
+  /// Can send content to the device.
+  #[default]
+  Contributor,
+
+  /// Can only see the device.
+  Viewer,
+}
+
 /// The various kinds of authority models supported for devices.
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
@@ -62,6 +143,10 @@ pub enum DeviceAuthorityModel {
 
     /// A list of other user ids that can manage this device.
     guests: Vec<String>,
+
+    /// The roles given to guests, keyed by user id.
+    #[serde(default)]
+    roles: std::collections::HashMap<String, DeviceGuestRole>,
   },
 
   /// When a device is in an "open" model, anyone can send things to it. We will retain the list of
@@ -73,6 +158,10 @@ pub enum DeviceAuthorityModel {
     /// The guest list here is cosmetic; users in this list will be re-added to the shared list,
     /// but otherwise are not special.
     guests: Vec<String>,
+
+    /// The roles given to users, keyed by user id. These are retained when moving to "shared".
+    #[serde(default)]
+    roles: std::collections::HashMap<String, DeviceGuestRole>,
   },
 }
 
@@ -84,6 +173,105 @@ pub struct DeviceHistoryRecord {
   pub(crate) device_id: String,
   /// This list of all renders for this device.
   pub(crate) render_history: Option<Vec<crate::rendering::queue::QueuedRender<String>>>,
+  /// The delivery status of the most recent renders sent to this device.
+  #[serde(default)]
+  pub(crate) render_deliveries: Option<Vec<jobs::RenderDelivery>>,
+}
+
+/// Everything that was stored for a device at the time it was decommissioned. The documents are
+/// kept as-is so that nothing is lost to schema changes.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceArchiveRecord {
+  /// The id of the device.
+  pub device_id: String,
+
+  /// When the device was decommissioned.
+  pub archived_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// The diagnostic record.
+  pub diagnostic: Option<bson::Document>,
+
+  /// The authority record.
+  pub authority: Option<bson::Document>,
+
+  /// The device state.
+  pub state: Option<bson::Document>,
+
+  /// The device schedule.
+  pub schedule: Option<bson::Document>,
+
+  /// The render histories.
+  pub histories: Vec<bson::Document>,
+}
+
+/// The credentials a device uses to authenticate with redis. Secrets are only ever stored as sha256
+/// digests.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceCredentialRecord {
+  /// The id of the device.
+  pub device_id: String,
+
+  /// The digests of every secret currently accepted for the device. This holds more than one
+  /// entry while a rotation is waiting on the device to pick up its new secret.
+  pub secret_hashes: Vec<String>,
+
+  /// The digest of the secret created by a rotation that has not been finalized yet.
+  pub pending_secret_hash: Option<String>,
+
+  /// When the device was first given credentials.
+  pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When the credentials were last rotated.
+  pub rotated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Owner-managed settings that apply to content sent by other users to a `Public` device.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "snake_case", default)]
+pub struct DeviceModerationSettings {
+  /// When true, messages from other users are held until the owner approves them.
+  pub require_approval: bool,
+
+  /// A list of user ids that are not allowed to send anything to the device.
+  pub blocked_senders: Vec<String>,
+}
+
+/// An invitation for someone to join a device as a guest. The code is shared by the owner, and
+/// whoever accepts it first becomes a guest with the given role.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceInvite {
+  /// The code used to accept or decline this invite.
+  pub code: String,
+
+  /// The role the guest will be given.
+  pub role: DeviceGuestRole,
+
+  /// The id of the user that created this invite.
+  pub created_by: String,
+
+  /// After this point in time, the invite can no longer be accepted.
+  pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A pending handoff of a device from its owner to another user. Nothing changes until the
+/// recipient accepts it.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceOwnershipTransfer {
+  /// The id of the current owner.
+  pub from: String,
+
+  /// The id of the user that will become the owner.
+  pub to: String,
+
+  /// When true, the current owner stays on as a manager of the device.
+  pub keep_access: bool,
+
+  /// After this point in time, the transfer can no longer be accepted.
+  pub expires_at: chrono::DateTime<chrono::Utc>,
 }
 
 /// The schema of our records that are stored in `device_authorities` collection.
@@ -95,6 +283,18 @@ pub struct DeviceAuthorityRecord {
 
   /// The model.
   pub(crate) authority_model: Option<DeviceAuthorityModel>,
+
+  /// Moderation settings, which only apply while the device is `Public`.
+  #[serde(default)]
+  pub(crate) moderation: DeviceModerationSettings,
+
+  /// Outstanding invites for this device.
+  #[serde(default)]
+  pub(crate) invites: Vec<DeviceInvite>,
+
+  /// An ownership transfer waiting on the recipient.
+  #[serde(default)]
+  pub(crate) transfer: Option<DeviceOwnershipTransfer>,
 }
 
 /// At the end of the day, what devices a user has access to is still being maintained on the user
@@ -104,6 +304,11 @@ pub struct DeviceAuthorityRecord {
 pub struct DeviceDiagnosticOwnership {
   /// The if of the user that first registered this device.
   pub original_owner: String,
+
+  /// The id of the user that currently owns this device, if ownership has been transferred since
+  /// it was first registered.
+  #[serde(default)]
+  pub current_owner: Option<String>,
 }
 
 /// This type represents the different states of "registration" a device may be in. This
@@ -125,7 +330,7 @@ pub enum DeviceDiagnosticRegistration {
 }
 
 /// The different kinds of things that can happen on a schedule for a device.
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
 pub enum DeviceScheduleKind {
   /// The most basic kind of schedule. The `String` held by this variant is the user id for whom we
@@ -136,6 +341,69 @@ pub enum DeviceScheduleKind {
   },
 }
 
+/// The things a schedule entry can do when it fires.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DeviceScheduleAction {
+  /// Renders a layout stored on the entry.
+  RenderLayout(crate::rendering::RenderLayout<String>),
+
+  /// Adds a message to the device state.
+  PushMessage {
+    /// The message.
+    message: String,
+    /// Who the message is shown as coming from.
+    origin: DeviceStateMessageOrigin,
+  },
+
+  /// Turns the lights on or off.
+  Lights(bool),
+
+  /// Renders the calendar events of a user, the same way the `UserEventsBasic` schedule does.
+  UserEvents {
+    /// The id of our user.
+    user_oid: String,
+  },
+
+  /// Fills in and renders a layout template. The template is loaded when the entry fires, so
+  /// changes to it are picked up.
+  RenderTemplate {
+    /// The id of the user that owns the template.
+    user_oid: String,
+    /// The id of the template.
+    template_id: String,
+    /// The values of the placeholders in the template.
+    #[serde(default)]
+    variables: std::collections::HashMap<String, String>,
+  },
+}
+
+/// Something that happens whenever a cron expression matches.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceScheduleEntry {
+  /// The id of this entry.
+  pub id: String,
+
+  /// A five-field cron expression.
+  pub cron: String,
+
+  /// The IANA timezone the cron expression is evaluated in, e.g `America/New_York`. Defaults to
+  /// utc.
+  #[serde(default)]
+  pub timezone: Option<String>,
+
+  /// What happens when the entry fires.
+  pub action: DeviceScheduleAction,
+
+  /// The next time the entry fires, in milliseconds. Entries whose expression can never match
+  /// will not have one.
+  pub next_fire_at: Option<i64>,
+
+  /// The last time the entry fired, in milliseconds.
+  pub last_fired_at: Option<i64>,
+}
+
 /// A schedule of things to render for a specific device.
 #[derive(Deserialize, Serialize, Debug, Default)]
 #[serde(rename_all = "snake_case")]
@@ -155,6 +423,19 @@ pub struct DeviceSchedule {
 
   /// The underlying schedule implementation.
   pub kind: Option<DeviceScheduleKind>,
+
+  /// How often the schedule is run, in seconds. Schedules without one use the interval from the
+  /// registrar configuration.
+  #[serde(default)]
+  pub refresh_interval_seconds: Option<u32>,
+
+  /// Things that happen on their own cron schedule, alongside the `kind`.
+  #[serde(default)]
+  pub entries: Vec<DeviceScheduleEntry>,
+
+  /// The next time anything on this schedule is due, in milliseconds. This is what the registrar
+  /// polls for; it is `null` when nothing is scheduled.
+  pub next_run_at: Option<i64>,
 }
 
 /// This type is serialized into our mongoDB instance for every device and updated periodically
@@ -181,6 +462,125 @@ pub struct DeviceDiagnostic {
 
   /// The state of this device's registration.
   pub registration_state: Option<DeviceDiagnosticRegistration>,
+
+  /// The telemetry sent with the most recent check-in, if the device sends any.
+  #[serde(default)]
+  pub telemetry: Option<DeviceTelemetry>,
+
+  /// A rolling window of the telemetry sent with recent check-ins, oldest first.
+  #[serde(default)]
+  pub telemetry_history: Vec<DeviceTelemetry>,
+
+  /// Whether the device is online, as last determined by the registrar.
+  #[serde(default)]
+  pub connectivity: Option<DeviceConnectivity>,
+}
+
+/// Whether a device is checking in regularly.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceConnectivityStatus {
+  /// The device has checked in recently.
+  Online,
+
+  /// The device has not checked in for a while.
+  Offline,
+}
+
+/// The connectivity tracked for a device, used to detect transitions and decide when to notify.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceConnectivity {
+  /// The current status.
+  pub status: DeviceConnectivityStatus,
+
+  /// When the device entered the current status.
+  #[serde(with = "chrono::serde::ts_milliseconds")]
+  pub since: chrono::DateTime<chrono::Utc>,
+
+  /// The status the owner was last told about.
+  pub notified: Option<DeviceConnectivityStatus>,
+
+  /// The timestamps of recent transitions, in milliseconds, used to detect flapping.
+  #[serde(default)]
+  pub transitions: Vec<i64>,
+}
+
+/// A persisted record of a device going offline or coming back online.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceConnectivityEvent {
+  /// The id of the device.
+  pub device_id: String,
+
+  /// The status the device moved into.
+  pub status: DeviceConnectivityStatus,
+
+  /// When the transition was detected.
+  #[serde(with = "chrono::serde::ts_milliseconds")]
+  pub at: chrono::DateTime<chrono::Utc>,
+
+  /// The last time the device checked in, as of the transition.
+  #[serde(with = "chrono::serde::ts_milliseconds_option")]
+  pub last_seen: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// True when the device was changing status often enough that notifications were suppressed.
+  #[serde(default)]
+  pub flapping: bool,
+}
+
+/// The health information a device can send along with its check-in. Every field is optional;
+/// devices only report what their hardware is capable of measuring.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceTelemetry {
+  /// The version of the firmware running on the device.
+  #[serde(default)]
+  pub firmware_version: Option<String>,
+
+  /// The kind of board the firmware is running on.
+  #[serde(default)]
+  pub board: Option<String>,
+
+  /// The amount of free heap memory, in bytes.
+  #[serde(default)]
+  pub free_heap: Option<u32>,
+
+  /// The signal strength of the wifi connection, in dBm.
+  #[serde(default)]
+  pub wifi_rssi: Option<i32>,
+
+  /// The battery voltage, for devices running on battery power.
+  #[serde(default)]
+  pub battery_voltage: Option<f32>,
+
+  /// The amount of seconds since the device was last started.
+  #[serde(default)]
+  pub uptime_seconds: Option<u64>,
+
+  /// The id of the render currently on the device display.
+  #[serde(default)]
+  pub last_render_id: Option<String>,
+
+  /// When the server received this telemetry; anything sent by the device here is ignored.
+  #[serde(default, with = "chrono::serde::ts_milliseconds_option")]
+  pub reported_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// The versioned message devices push onto the incoming queue when checking in. Devices that only
+/// push their id are still supported.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum DeviceCheckIn {
+  /// The first version of the check-in message.
+  V1 {
+    /// The id of the device.
+    id: String,
+
+    /// The health information of the device.
+    #[serde(default)]
+    telemetry: DeviceTelemetry,
+  },
 }
 
 impl DeviceDiagnostic {
@@ -220,3 +620,25 @@ impl std::fmt::Display for DeviceDiagnostic {
     )
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::DeviceModerationSettings;
+
+  #[test]
+  fn test_partial_moderation_settings() {
+    let settings = bson::from_document::<DeviceModerationSettings>(bson::doc! {
+      "blocked_senders": ["user"],
+    })
+    .unwrap();
+    assert!(!settings.require_approval);
+    assert_eq!(settings.blocked_senders, vec!["user".to_string()]);
+
+    let settings = bson::from_document::<DeviceModerationSettings>(bson::doc! {
+      "require_approval": true,
+    })
+    .unwrap();
+    assert!(settings.require_approval);
+    assert!(settings.blocked_senders.is_empty());
+  }
+}
@@ -0,0 +1,194 @@
+//! The schema of user-registered webhooks, the events they can subscribe to, and the log of every
+//! attempt made to deliver those events.
+
+use serde::{Deserialize, Serialize};
+
+/// The kinds of events a webhook can subscribe to.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+  /// A background job for a device finished, successfully or not.
+  JobCompleted,
+
+  /// A device started checking in again.
+  DeviceOnline,
+
+  /// A device stopped checking in.
+  DeviceOffline,
+
+  /// A message was added to a device.
+  MessagePosted,
+
+  /// A device was given a new owner.
+  OwnershipChanged,
+}
+
+/// The contents of an event, as they are posted to webhooks.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case", tag = "beetle:kind", content = "beetle:content")]
+pub enum WebhookEventPayload {
+  /// A background job for a device finished.
+  JobCompleted {
+    /// The id of the job.
+    job_id: String,
+    /// The id of the device.
+    device_id: String,
+    /// The kind of job.
+    label: String,
+    /// How the job went.
+    result: super::jobs::JobResult,
+  },
+
+  /// A device started checking in again.
+  DeviceOnline {
+    /// The id of the device.
+    device_id: String,
+  },
+
+  /// A device stopped checking in.
+  DeviceOffline {
+    /// The id of the device.
+    device_id: String,
+    /// The last time the device checked in.
+    #[serde(with = "chrono::serde::ts_milliseconds_option")]
+    last_seen: Option<chrono::DateTime<chrono::Utc>>,
+  },
+
+  /// A message was added to a device.
+  MessagePosted {
+    /// The id of the device.
+    device_id: String,
+    /// The message.
+    content: String,
+    /// Who sent it.
+    origin: super::DeviceStateMessageOrigin,
+  },
+
+  /// A device was given a new owner.
+  OwnershipChanged {
+    /// The id of the device.
+    device_id: String,
+    /// The id of the new owner.
+    owner: String,
+  },
+}
+
+impl WebhookEventPayload {
+  /// Returns the kind of this event.
+  pub fn kind(&self) -> WebhookEventKind {
+    match self {
+      Self::JobCompleted { .. } => WebhookEventKind::JobCompleted,
+      Self::DeviceOnline { .. } => WebhookEventKind::DeviceOnline,
+      Self::DeviceOffline { .. } => WebhookEventKind::DeviceOffline,
+      Self::MessagePosted { .. } => WebhookEventKind::MessagePosted,
+      Self::OwnershipChanged { .. } => WebhookEventKind::OwnershipChanged,
+    }
+  }
+
+  /// Returns the id of the device this event is about.
+  pub fn device_id(&self) -> &str {
+    match self {
+      Self::JobCompleted { device_id, .. }
+      | Self::DeviceOnline { device_id }
+      | Self::DeviceOffline { device_id, .. }
+      | Self::MessagePosted { device_id, .. }
+      | Self::OwnershipChanged { device_id, .. } => device_id,
+    }
+  }
+}
+
+/// A single event, which is the body of every webhook request.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct WebhookEvent {
+  /// A unique id for this event; shared by every delivery of it.
+  pub id: String,
+
+  /// When the event happened.
+  pub created_at: chrono::DateTime<chrono::Utc>,
+
+  /// What happened.
+  pub payload: WebhookEventPayload,
+}
+
+/// An endpoint registered by a user to receive events about their devices.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct Webhook {
+  /// The id of this webhook.
+  pub id: String,
+
+  /// The id of the user that registered this webhook.
+  pub user_id: String,
+
+  /// Where events are posted to.
+  pub url: String,
+
+  /// The secret used to sign every request.
+  pub secret: String,
+
+  /// The kinds of events sent to this webhook.
+  pub events: Vec<WebhookEventKind>,
+
+  /// When not empty, only events for these devices are sent.
+  #[serde(default)]
+  pub device_ids: Vec<String>,
+
+  /// When this webhook was registered.
+  pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Where a delivery is at.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+  /// The delivery is waiting on its next attempt.
+  Pending,
+
+  /// The endpoint accepted the delivery.
+  Delivered,
+
+  /// Every attempt failed; the delivery will not be retried.
+  Failed,
+}
+
+/// A single attempt at delivering an event.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct WebhookDeliveryAttempt {
+  /// When the attempt was made.
+  pub at: chrono::DateTime<chrono::Utc>,
+
+  /// The status code the endpoint responded with, if it responded at all.
+  pub status_code: Option<u16>,
+
+  /// What went wrong, if anything.
+  pub error: Option<String>,
+}
+
+/// The delivery of an event to a single webhook, along with the log of every attempt.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct WebhookDelivery {
+  /// The id of this delivery.
+  pub id: String,
+
+  /// The id of the webhook being delivered to.
+  pub webhook_id: String,
+
+  /// The id of the user that owns the webhook.
+  pub user_id: String,
+
+  /// The event being delivered.
+  pub event: WebhookEvent,
+
+  /// Where the delivery is at.
+  pub status: WebhookDeliveryStatus,
+
+  /// When the next attempt should be made, in milliseconds.
+  pub next_attempt_at: i64,
+
+  /// Every attempt made so far.
+  #[serde(default)]
+  pub attempts: Vec<WebhookDeliveryAttempt>,
+}
@@ -59,11 +59,18 @@ pub async fn get_device_id(
         }
       };
 
+      // Pool entries hold the id and secret separated by a colon; older entries only hold the id,
+      // which doubles as the password.
+      let (mock_device_id, secret) = match mock_device_id.split_once(':') {
+        Some((id, secret)) => (id.to_string(), secret.to_string()),
+        None => (mock_device_id.clone(), mock_device_id),
+      };
+
       log::info!("device id taken - {mock_device_id:?}");
 
       match kramer::execute(
         &mut connection,
-        kramer::Command::<&str, &str>::Auth(kramer::AuthCredentials::User((&mock_device_id, &mock_device_id))),
+        kramer::Command::<&str, &str>::Auth(kramer::AuthCredentials::User((&mock_device_id, &secret))),
       )
       .await
       {
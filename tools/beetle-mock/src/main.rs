@@ -65,6 +65,8 @@ async fn run_background(args: CommandLineArguments, s: async_std::channel::Sende
   })?;
 
   let mut interval = async_std::stream::interval(std::time::Duration::from_millis(500));
+  let started = std::time::Instant::now();
+  let mut last_render_id = None;
 
   loop {
     log::info!("mock starting image queue pop");
@@ -147,6 +149,8 @@ async fn run_background(args: CommandLineArguments, s: async_std::channel::Sende
     }
 
     if !image_buffer.is_empty() {
+      last_render_id = beetle::rendering::receipt::render_id(&image_buffer).or(last_render_id);
+
       if let Err(error) = save_image(&args, &image_buffer) {
         log::warn!("unable to save image - {error}");
       }
@@ -158,12 +162,24 @@ async fn run_background(args: CommandLineArguments, s: async_std::channel::Sende
 
     log::info!("writing message '{mock_device_id}' for keep-alive");
 
+    let check_in = serde_json::to_string(&beetle::schema::DeviceCheckIn::V1 {
+      id: mock_device_id.clone(),
+      telemetry: beetle::schema::DeviceTelemetry {
+        firmware_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        board: Some("beetle-mock".to_string()),
+        uptime_seconds: Some(started.elapsed().as_secs()),
+        last_render_id: last_render_id.clone(),
+        ..Default::default()
+      },
+    })
+    .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+
     let response = kramer::execute(
       &mut connection,
       kramer::Command::<&str, &str>::Lists(kramer::ListCommand::Push(
         (kramer::Side::Right, kramer::Insertion::Always),
         beetle::constants::REGISTRAR_INCOMING,
-        kramer::Arity::One(&mock_device_id),
+        kramer::Arity::One(&check_in),
       )),
     )
     .await;